@@ -75,6 +75,7 @@ fn bench_fence_parsing(c: &mut Criterion) {
                     break;
                 }
             }
+            len
         });
     });
 
@@ -258,6 +259,7 @@ fn bench_iteration_patterns(c: &mut Criterion) {
             for &item in black_box(DATA) {
                 sum += item;
             }
+            sum
         });
     });
 
@@ -277,6 +279,7 @@ fn bench_iteration_patterns(c: &mut Criterion) {
                     break;
                 }
             }
+            found
         });
     });
 
@@ -314,7 +317,7 @@ fn bench_regex_like_operations(c: &mut Criterion) {
     c.bench_function("split_once", |b| {
         b.iter(|| {
             let s = black_box("line 42");
-            let _ = s.split_once(' ').map(|(a, b)| (a, b));
+            let _ = s.split_once(' ');
         });
     });
 }
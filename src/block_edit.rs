@@ -0,0 +1,179 @@
+use std::path::Path;
+
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::{
+    block_selector::BlockSelector,
+    file_write::write_if_unchanged,
+    modernize::unified_diff,
+    preview_validator::{
+        collect_mermaid_blocks, content_hash, replace_block_content, validate_mermaid_block,
+        MermaidBlock, PreviewIssue, ValidationOptions,
+    },
+    renderer_registry::RendererRegistry,
+};
+
+#[derive(Debug, Clone)]
+pub struct EditBlockOptions {
+    pub validation: ValidationOptions,
+    /// Write even when the block changed since it was read or the new
+    /// diagram does not validate.
+    pub force: bool,
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockEdit {
+    pub target: String,
+    pub block_index: u32,
+    /// Whether the file was rewritten.
+    pub written: bool,
+    /// Whether the new diagram validates for the target.
+    pub valid: bool,
+    /// The block's content hash before the edit.
+    pub previous_hash: String,
+    /// The block's content hash on disk after the call, i.e. the previous
+    /// hash when nothing was written; pass it as `expectedHash` to edit the
+    /// block again.
+    pub content_hash: String,
+    /// The edit was written despite a hash mismatch or an invalid diagram.
+    pub forced: bool,
+    pub issues: Vec<PreviewIssue>,
+    /// Unified diff of the edit.
+    pub diff: String,
+}
+
+/// Replaces the content lines of the block `selector` names in the markdown
+/// file at `path` with `source`, when the block still hashes to `expected_hash` and
+/// `source` validates for the target.
+///
+/// Fences, the block's indentation and the file's line endings are kept,
+/// and the file is replaced atomically. A hash mismatch is an error unless
+/// `options.force` is set; an invalid diagram is reported with its issues
+/// and left unwritten unless `options.force` is set.
+pub async fn edit_mermaid_block(
+    path: &Path,
+    selector: &BlockSelector,
+    expected_hash: &str,
+    source: &str,
+    options: &EditBlockOptions,
+    renderers: &RendererRegistry,
+) -> Result<BlockEdit, String> {
+    let label = path.display().to_string();
+    let markdown = tokio::fs::read_to_string(path)
+        .await
+        .map_err(|err| format!("Failed to read markdown file {label}: {err}"))?;
+    let block_index = selector.resolve(&label, &markdown)?;
+    let (blocks, _) = collect_mermaid_blocks(&markdown);
+    let block = &blocks[block_index as usize - 1];
+
+    let previous_hash = content_hash(&block.content);
+    let hash_matches = previous_hash == expected_hash;
+    if !hash_matches && !options.force {
+        return Err(format!(
+            "Block #{block_index} in {label} changed since it was read (content hash {previous_hash}, expected {expected_hash}); read it again with getMermaidBlock or pass force"
+        ));
+    }
+    if contains_fence(source) {
+        return Err("The new source must not contain code fences".to_string());
+    }
+
+    let content = indented(source, fence_indent(&markdown, block));
+    let edited = replace_block_content(&markdown, block, &content);
+    let result = validate_mermaid_block(&edited, block_index, &options.validation, renderers).await;
+
+    let write = result.valid || options.force;
+    let hash = if write {
+        write_if_unchanged(path, &label, &markdown, &edited)
+            .await
+            .map_err(|err| err.to_string())?;
+        // Hash the block as it now reads from the file, which can differ
+        // from `content` in trailing blank lines.
+        let (edited_blocks, _) = collect_mermaid_blocks(&edited);
+        edited_blocks.get(block_index as usize - 1).map_or_else(
+            || content_hash(&content),
+            |block| content_hash(&block.content),
+        )
+    } else {
+        previous_hash.clone()
+    };
+    Ok(BlockEdit {
+        target: result.target,
+        block_index,
+        written: write,
+        valid: result.valid,
+        previous_hash,
+        content_hash: hash,
+        forced: write && !(result.valid && hash_matches),
+        issues: result.issues,
+        diff: unified_diff(&label, &markdown, &edited),
+    })
+}
+
+/// Whether `source` has a line that would open or close a ```, ~~~ or :::
+/// block and so end the block it is written into.
+pub(crate) fn contains_fence(source: &str) -> bool {
+    source.lines().any(|line| {
+        let line = line.trim_start();
+        line.starts_with("```") || line.starts_with("~~~") || line.starts_with(":::")
+    })
+}
+
+/// The indentation of `block`'s opening fence.
+pub(crate) fn fence_indent<'a>(markdown: &'a str, block: &MermaidBlock) -> &'a str {
+    markdown
+        .lines()
+        .nth(block.start_line as usize - 1)
+        .map_or("", |line| &line[..line.len() - line.trim_start().len()])
+}
+
+/// `source` with `indent` added to every non-blank line, so a block nested
+/// in a list stays nested. Source that already carries the indentation, as
+/// returned by getMermaidBlock, is kept as is.
+pub(crate) fn indented(source: &str, indent: &str) -> String {
+    let lines = source.lines().collect::<Vec<_>>();
+    let carries_indent = lines
+        .iter()
+        .all(|line| line.trim().is_empty() || line.starts_with(indent));
+    if carries_indent {
+        return lines.join("\n");
+    }
+    lines
+        .iter()
+        .map(|line| {
+            if line.trim().is_empty() {
+                line.to_string()
+            } else {
+                format!("{indent}{line}")
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn indented_keeps_nested_blocks_nested() {
+        assert_eq!(
+            indented("flowchart TD\n\n  A-->B", "  "),
+            "  flowchart TD\n\n    A-->B"
+        );
+        assert_eq!(
+            indented("  flowchart TD\n    A-->B", "  "),
+            "  flowchart TD\n    A-->B"
+        );
+        assert_eq!(indented("flowchart TD\nA-->B", ""), "flowchart TD\nA-->B");
+    }
+
+    #[test]
+    fn every_fence_kind_is_refused() {
+        assert!(contains_fence("graph TD\n  ```"));
+        assert!(contains_fence("graph TD\n~~~"));
+        assert!(contains_fence("::: mermaid\ngraph TD"));
+        assert!(!contains_fence("graph TD\n  A-->B"));
+    }
+}
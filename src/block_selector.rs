@@ -0,0 +1,177 @@
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+use crate::preview_validator::{block_section, collect_mermaid_blocks, heading_slug, MermaidBlock};
+
+/// Ids listed at most when a `blockId` is not found.
+const LISTED_IDS: usize = 20;
+
+/// How a block tool names its block. Exactly one field must be set.
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockSelector {
+    /// 1-based block index; negative values count from the end (-1 is the
+    /// last block).
+    #[serde(default)]
+    pub block_index: Option<i64>,
+    /// Stable block id from scanMermaidBlocks, e.g.
+    /// `architecture/data-flow#3f2a9c1d`.
+    #[serde(default)]
+    pub block_id: Option<String>,
+    /// Heading text, anchor (`#data-flow`) or heading path
+    /// (`architecture/data-flow`) of the section holding the block.
+    #[serde(default)]
+    pub heading: Option<String>,
+}
+
+impl BlockSelector {
+    pub fn index(block_index: i64) -> Self {
+        BlockSelector {
+            block_index: Some(block_index),
+            ..BlockSelector::default()
+        }
+    }
+
+    /// Resolves the selector to a 1-based block index of `markdown`, the
+    /// content of the file named `path` in messages. Selectors matching no
+    /// block or several blocks are errors listing the candidates.
+    pub fn resolve(&self, path: &str, markdown: &str) -> Result<u32, String> {
+        let (blocks, _) = collect_mermaid_blocks(markdown);
+        match (&self.block_index, &self.block_id, &self.heading) {
+            (Some(index), None, None) => resolve_index(*index, &blocks, path),
+            (None, Some(id), None) => blocks
+                .iter()
+                .find(|block| block.id == *id)
+                .map(|block| block.index)
+                .ok_or_else(|| {
+                    let mut ids = blocks
+                        .iter()
+                        .take(LISTED_IDS)
+                        .map(|block| block.id.as_str())
+                        .collect::<Vec<_>>();
+                    if blocks.len() > LISTED_IDS {
+                        ids.push("...");
+                    }
+                    format!(
+                        "Mermaid block id {id} was not found in {path}; block ids: {}",
+                        if ids.is_empty() {
+                            "none".to_string()
+                        } else {
+                            ids.join(", ")
+                        }
+                    )
+                }),
+            (None, None, Some(heading)) => resolve_heading(heading, &blocks, path),
+            _ => Err("Pass exactly one of blockIndex, blockId or heading".to_string()),
+        }
+    }
+}
+
+fn resolve_index(index: i64, blocks: &[MermaidBlock], path: &str) -> Result<u32, String> {
+    let count = blocks.len() as i64;
+    let resolved = if index < 0 { count + 1 + index } else { index };
+    if resolved < 1 || resolved > count {
+        return Err(format!(
+            "Mermaid block index {index} was not found ({count} block(s) in {path})"
+        ));
+    }
+    Ok(resolved as u32)
+}
+
+/// Blocks whose heading path ends with the slugs of `heading`.
+fn resolve_heading(heading: &str, blocks: &[MermaidBlock], path: &str) -> Result<u32, String> {
+    let slugs = heading
+        .split('/')
+        .map(heading_slug)
+        .filter(|slug| !slug.is_empty())
+        .collect::<Vec<_>>();
+    let wanted = slugs.iter().map(String::as_str).collect::<Vec<_>>();
+    if wanted.is_empty() {
+        return Err(format!("Heading {heading:?} has no anchor to match"));
+    }
+    let candidates = blocks
+        .iter()
+        .filter(|block| {
+            block_section(&block.id)
+                .split('/')
+                .collect::<Vec<_>>()
+                .ends_with(&wanted)
+        })
+        .collect::<Vec<_>>();
+    match candidates.as_slice() {
+        [block] => Ok(block.index),
+        [] => Err(format!(
+            "No Mermaid block under heading {heading:?} in {path}"
+        )),
+        _ => Err(format!(
+            "Heading {heading:?} matches {} Mermaid blocks: {}; pass blockId or blockIndex",
+            candidates.len(),
+            candidates
+                .iter()
+                .map(|block| format!(
+                    "{} (block #{}, lines {}-{})",
+                    block.id, block.index, block.start_line, block.end_line
+                ))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MARKDOWN: &str = "# Architecture\n## Data flow\n```mermaid\ngraph TD\n```\n```mermaid\ngraph LR\n```\n## Deploy\n```mermaid\ngraph TD\n```\n";
+
+    fn heading(heading: &str) -> BlockSelector {
+        BlockSelector {
+            heading: Some(heading.to_string()),
+            ..BlockSelector::default()
+        }
+    }
+
+    #[test]
+    fn selectors_resolve_to_block_indexes() {
+        assert_eq!(BlockSelector::index(-1).resolve("doc.md", MARKDOWN), Ok(3));
+        assert_eq!(BlockSelector::index(1).resolve("doc.md", MARKDOWN), Ok(1));
+        assert!(BlockSelector::index(-4)
+            .resolve("doc.md", MARKDOWN)
+            .is_err());
+        assert!(BlockSelector::index(0).resolve("doc.md", MARKDOWN).is_err());
+        let (blocks, _) = collect_mermaid_blocks(MARKDOWN);
+        let id = BlockSelector {
+            block_id: Some(blocks[1].id.clone()),
+            ..BlockSelector::default()
+        };
+        assert_eq!(id.resolve("doc.md", MARKDOWN), Ok(2));
+        assert_eq!(heading("Deploy").resolve("doc.md", MARKDOWN), Ok(3));
+        assert_eq!(
+            heading("architecture/#deploy").resolve("doc.md", MARKDOWN),
+            Ok(3)
+        );
+        assert!(BlockSelector::default()
+            .resolve("doc.md", MARKDOWN)
+            .is_err());
+    }
+
+    #[test]
+    fn ambiguous_and_unknown_selectors_list_candidates() {
+        let error = heading("Data flow")
+            .resolve("doc.md", MARKDOWN)
+            .unwrap_err();
+        assert!(error.contains("matches 2 Mermaid blocks"));
+        let (blocks, _) = collect_mermaid_blocks(MARKDOWN);
+        assert!(error.contains(&format!("{} (block #1, lines 3-5)", blocks[0].id)));
+        assert!(error.contains(&format!("{} (block #2, lines 6-8)", blocks[1].id)));
+
+        let unknown = BlockSelector {
+            block_id: Some("deploy#1".to_string()),
+            ..BlockSelector::default()
+        };
+        let error = unknown.resolve("doc.md", MARKDOWN).unwrap_err();
+        assert!(error.contains(&blocks[2].id));
+        assert!(blocks[2].id.starts_with("architecture/deploy#"));
+        assert!(heading("Intro").resolve("doc.md", MARKDOWN).is_err());
+    }
+}
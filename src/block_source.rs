@@ -0,0 +1,128 @@
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::{
+    preview_target::BlockSyntax,
+    preview_validator::{collect_mermaid_blocks, content_hash},
+};
+
+/// Context lines `getMermaidBlock` returns at most on each side.
+pub const MAX_CONTEXT_LINES: u32 = 50;
+
+/// One line of the markdown file with its 1-based line number.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct NumberedLine {
+    pub line: u32,
+    pub text: String,
+}
+
+/// The exact source of one Mermaid block.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MermaidBlockSource {
+    pub index: u32,
+    pub id: String,
+    /// Line of the opening fence.
+    pub start_line: u32,
+    /// Line of the closing fence.
+    pub end_line: u32,
+    pub syntax: BlockSyntax,
+    pub info_string: String,
+    /// The lines between the fences, joined with `\n`.
+    pub content: String,
+    /// SHA-256 of `content`, as reported by diagram status resources.
+    pub content_hash: String,
+    /// `content` line by line with file line numbers.
+    pub lines: Vec<NumberedLine>,
+    /// Markdown lines before the opening fence, when context was requested.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub context_before: Vec<NumberedLine>,
+    /// Markdown lines after the closing fence, when context was requested.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub context_after: Vec<NumberedLine>,
+}
+
+/// Returns block `block_index` of `markdown` with up to `context_lines`
+/// (capped at [`MAX_CONTEXT_LINES`]) surrounding lines on each side.
+pub fn mermaid_block_source(
+    path: &str,
+    markdown: &str,
+    block_index: u32,
+    context_lines: u32,
+) -> Result<MermaidBlockSource, String> {
+    let (blocks, _) = collect_mermaid_blocks(markdown);
+    let block_count = blocks.len();
+    let block = blocks
+        .into_iter()
+        .find(|block| block.index == block_index)
+        .ok_or_else(|| {
+            format!(
+                "Mermaid block index {block_index} was not found ({} block(s) in {path})",
+                block_count
+            )
+        })?;
+
+    let file_lines = markdown.lines().collect::<Vec<_>>();
+    let numbered = |first: u32, last: u32| {
+        (first..=last)
+            .filter_map(|line| {
+                let text = file_lines.get(line.checked_sub(1)? as usize)?;
+                Some(NumberedLine {
+                    line,
+                    text: text.to_string(),
+                })
+            })
+            .collect::<Vec<_>>()
+    };
+    let context_lines = context_lines.min(MAX_CONTEXT_LINES);
+    let before_start = block.start_line.saturating_sub(context_lines).max(1);
+
+    Ok(MermaidBlockSource {
+        index: block.index,
+        id: block.id,
+        start_line: block.start_line,
+        end_line: block.end_line,
+        syntax: block.syntax,
+        info_string: block.info_string,
+        content_hash: content_hash(&block.content),
+        lines: numbered(block.start_line + 1, block.end_line - 1),
+        context_before: numbered(before_start, block.start_line - 1),
+        context_after: numbered(block.end_line + 1, block.end_line + context_lines),
+        content: block.content,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn block_lines_and_context_carry_file_line_numbers() {
+        let markdown = "# Title\n\nIntro\n\n```mermaid title\nflowchart TD\n  A-->B\n```\nAfter\n";
+        let block = mermaid_block_source("doc.md", markdown, 1, 2).unwrap();
+        assert_eq!(block.info_string, "mermaid title");
+        assert_eq!(block.content, "flowchart TD\n  A-->B");
+        assert_eq!(block.content_hash, content_hash("flowchart TD\n  A-->B"));
+        assert_eq!(
+            block.lines,
+            vec![
+                NumberedLine {
+                    line: 6,
+                    text: "flowchart TD".to_string()
+                },
+                NumberedLine {
+                    line: 7,
+                    text: "  A-->B".to_string()
+                },
+            ]
+        );
+        let before = block.context_before.iter().map(|line| line.line);
+        assert_eq!(before.collect::<Vec<_>>(), vec![3, 4]);
+        // Only one line follows the block.
+        assert_eq!(block.context_after.len(), 1);
+        assert_eq!(block.context_after[0].text, "After");
+
+        let error = mermaid_block_source("doc.md", markdown, 2, 0).unwrap_err();
+        assert!(error.contains("1 block(s) in doc.md"));
+    }
+}
@@ -1,20 +1,34 @@
-use std::{env, process::Stdio, time::Duration};
+use std::{
+    env,
+    ffi::{OsStr, OsString},
+    process::Stdio,
+    sync::OnceLock,
+    thread,
+    time::{Duration, Instant},
+};
 
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use thiserror::Error;
 use tokio::{
     io::{AsyncReadExt, AsyncWriteExt},
     process::Command,
+    sync::Semaphore,
     time,
 };
 
+use crate::logging::{log, LogLevel};
+
+const LOGGER: &str = "renderer";
+
 const DEFAULT_TIMEOUT_SECS: u64 = 30;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum OutputFormat {
     Svg,
+    #[default]
     Png,
 }
 
@@ -34,12 +48,6 @@ impl OutputFormat {
     }
 }
 
-impl Default for OutputFormat {
-    fn default() -> Self {
-        OutputFormat::Png
-    }
-}
-
 #[derive(Debug, Error)]
 pub enum RenderError {
     #[error("mermaid-cli process exited with code {code}")]
@@ -94,6 +102,26 @@ impl RenderError {
     }
 }
 
+/// mermaid-cli processes allowed to run at once across the whole process,
+/// from `MERMAID_MAX_RENDERS` (default: the number of CPUs). Every render
+/// waits for a slot, so parallel callers such as directory validation share
+/// one limit.
+pub fn max_concurrent_renders() -> usize {
+    static MAX: OnceLock<usize> = OnceLock::new();
+    *MAX.get_or_init(|| {
+        env::var("MERMAID_MAX_RENDERS")
+            .ok()
+            .and_then(|value| value.trim().parse::<usize>().ok())
+            .filter(|max| *max > 0)
+            .unwrap_or_else(|| thread::available_parallelism().map_or(4, usize::from))
+    })
+}
+
+fn render_slots() -> &'static Semaphore {
+    static SLOTS: OnceLock<Semaphore> = OnceLock::new();
+    SLOTS.get_or_init(|| Semaphore::new(max_concurrent_renders()))
+}
+
 pub fn timeout_from_env() -> Duration {
     env::var("MERMAID_TIMEOUT")
         .ok()
@@ -119,7 +147,18 @@ pub async fn render_diagram(
     format: OutputFormat,
     timeout: Duration,
 ) -> Result<Vec<u8>, RenderError> {
-    let mut command = Command::new(mermaid_cli_command());
+    render_diagram_with_cli(&mermaid_cli_command(), diagram, format, timeout).await
+}
+
+/// Renders `diagram` with the mermaid-cli executable at `cli` instead of the
+/// one configured through `MERMAID_CLI`.
+pub async fn render_diagram_with_cli(
+    cli: &OsStr,
+    diagram: &str,
+    format: OutputFormat,
+    timeout: Duration,
+) -> Result<Vec<u8>, RenderError> {
+    let mut command = Command::new(cli);
     command
         .arg("-i")
         .arg("/dev/stdin")
@@ -132,12 +171,40 @@ pub async fn render_diagram(
         command.arg("-b").arg("transparent");
     }
 
+    // Dropping the render, e.g. when a request is cancelled, kills mmdc.
     command
         .stdin(Stdio::piped())
         .stdout(Stdio::piped())
-        .stderr(Stdio::piped());
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
 
-    let mut child = command.spawn().map_err(RenderError::spawn)?;
+    let _slot = render_slots()
+        .acquire()
+        .await
+        .expect("render slots are never closed");
+    let started = Instant::now();
+    let mut child = command.spawn().map_err(|err| {
+        log(
+            LogLevel::Error,
+            LOGGER,
+            json!({
+                "event": "spawnFailed",
+                "command": cli.to_string_lossy(),
+                "error": err.to_string(),
+            }),
+        );
+        RenderError::spawn(err)
+    })?;
+    log(
+        LogLevel::Debug,
+        LOGGER,
+        json!({
+            "event": "spawn",
+            "command": cli.to_string_lossy(),
+            "pid": child.id(),
+            "format": format.as_str(),
+        }),
+    );
     let mut stdin = child.stdin.take().ok_or_else(|| RenderError::Io {
         message: "Failed to open mermaid-cli stdin".to_string(),
     })?;
@@ -172,6 +239,15 @@ pub async fn render_diagram(
         _ = time::sleep(timeout) => {
             let _ = child.kill().await;
             let _ = child.wait().await;
+            log(
+                LogLevel::Warning,
+                LOGGER,
+                json!({
+                    "event": "timeout",
+                    "command": cli.to_string_lossy(),
+                    "durationMs": started.elapsed().as_millis() as u64,
+                }),
+            );
             Err(RenderError::timeout(timeout))
         }
         status = child.wait() => {
@@ -206,6 +282,16 @@ pub async fn render_diagram(
         Ok(status) => status,
         Err(err) => return Err(err),
     };
+    log(
+        LogLevel::Info,
+        LOGGER,
+        json!({
+            "event": "exit",
+            "command": cli.to_string_lossy(),
+            "exitCode": status.code(),
+            "durationMs": started.elapsed().as_millis() as u64,
+        }),
+    );
 
     if !status.success() {
         let code = status.code().unwrap_or(-1);
@@ -215,8 +301,8 @@ pub async fn render_diagram(
     Ok(stdout_bytes)
 }
 
-fn mermaid_cli_command() -> String {
-    env::var("MERMAID_CLI").unwrap_or_else(|_| "mmdc".to_string())
+pub fn mermaid_cli_command() -> OsString {
+    env::var_os("MERMAID_CLI").unwrap_or_else(|| OsString::from("mmdc"))
 }
 
 #[cfg(test)]
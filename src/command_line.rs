@@ -0,0 +1,649 @@
+use std::{
+    collections::HashMap,
+    env,
+    ffi::OsStr,
+    path::{Path, PathBuf},
+};
+
+use clap::ValueEnum;
+use futures::{stream, StreamExt};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncReadExt;
+
+use crate::{
+    cli_runner::{max_concurrent_renders, OutputFormat, RenderError},
+    directory::{
+        drop_no_blocks_issue, find_markdown_files, read_issue, relative, DirectoryFilter,
+        FileReport,
+    },
+    git_changes::{ChangedFile, ChangedReport},
+    junit::junit_xml,
+    preview_target::PreviewTarget,
+    preview_validator::{
+        count_errors, scan_markdown_for_targets, validate_markdown, FileProgress, PreviewIssue,
+        PreviewScanResult, PreviewValidationResult, ValidationOptions,
+    },
+    renderer_registry::RendererRegistry,
+    sarif::sarif_log,
+    server::normalize_diagram,
+    workflow_commands::workflow_commands,
+};
+
+/// Files checked in a directory when no include glob is given.
+pub const DEFAULT_CHECK_INCLUDE: &[&str] =
+    &["**/*.md", "**/*.markdown", "**/*.mmd", "**/*.mermaid"];
+
+/// Issue codes caused by the environment rather than by a diagram.
+const ENVIRONMENT_CODES: &[&str] = &[
+    "markdown_read_error",
+    "renderer_unavailable",
+    "validation_cancelled",
+];
+
+/// How a command ended. Scripts tell broken diagrams apart from a broken
+/// setup by the exit code; `2` is left to usage errors reported by clap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitStatus {
+    /// Every diagram is valid.
+    Valid,
+    /// At least one diagram is invalid.
+    Invalid,
+    /// An input could not be read, or the renderer could not run.
+    Environment,
+}
+
+impl ExitStatus {
+    pub fn code(&self) -> u8 {
+        match self {
+            ExitStatus::Valid => 0,
+            ExitStatus::Invalid => 1,
+            ExitStatus::Environment => 3,
+        }
+    }
+}
+
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize, JsonSchema,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportFormat {
+    /// One line per issue, then a summary.
+    #[default]
+    Text,
+    /// The report as pretty-printed JSON.
+    Json,
+    /// A SARIF 2.1.0 log for code-scanning dashboards.
+    Sarif,
+    /// JUnit XML with one test case per Mermaid block.
+    Junit,
+    /// GitHub Actions workflow commands that annotate pull requests.
+    Github,
+}
+
+/// One input of `check`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CheckInput {
+    File(PathBuf),
+    /// Standard input, reported under `name`.
+    Stdin {
+        name: String,
+    },
+}
+
+impl CheckInput {
+    pub fn name(&self) -> String {
+        match self {
+            CheckInput::File(path) => path.strip_prefix(".").unwrap_or(path).display().to_string(),
+            CheckInput::Stdin { name } => name.clone(),
+        }
+    }
+
+    /// `.mmd` and `.mermaid` inputs hold one bare diagram; anything else is
+    /// markdown.
+    pub fn is_diagram(&self) -> bool {
+        let path = match self {
+            CheckInput::File(path) => path.as_path(),
+            CheckInput::Stdin { name } => Path::new(name),
+        };
+        is_diagram_path(path)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CheckReport {
+    pub target: String,
+    pub mermaid_block_count: u32,
+    pub error_count: u32,
+    pub valid: bool,
+    pub files: Vec<FileReport<PreviewValidationResult>>,
+    /// Content of each checked file by reported path, for reporters that
+    /// quote or fix the source.
+    #[serde(skip)]
+    pub sources: HashMap<String, String>,
+    /// Indices of the blocks checked in each file, for files where only
+    /// some blocks were; absent files had every block checked.
+    #[serde(skip)]
+    pub checked_blocks: HashMap<String, Vec<u32>>,
+}
+
+impl CheckReport {
+    fn new(
+        target: PreviewTarget,
+        files: Vec<FileReport<PreviewValidationResult>>,
+        sources: HashMap<String, String>,
+    ) -> Self {
+        let mermaid_block_count = files
+            .iter()
+            .map(|file| file.result.mermaid_block_count)
+            .sum();
+        let error_count = files
+            .iter()
+            .map(|file| file.result.error_count)
+            .sum::<u32>();
+        CheckReport {
+            target: target.as_str().to_string(),
+            mermaid_block_count,
+            error_count,
+            valid: error_count == 0,
+            files,
+            sources,
+            checked_blocks: HashMap::new(),
+        }
+    }
+
+    /// Groups the blocks of a changed-blocks report on `changed` by file.
+    pub fn from_changes(
+        target: PreviewTarget,
+        changes: ChangedReport,
+        changed: &[ChangedFile],
+    ) -> Self {
+        let mut files: Vec<FileReport<PreviewValidationResult>> = Vec::new();
+        let mut checked_blocks: HashMap<String, Vec<u32>> = HashMap::new();
+        for block in changes.blocks {
+            checked_blocks
+                .entry(block.path.clone())
+                .or_default()
+                .push(block.block_index);
+            let index = match files.iter().position(|file| file.path == block.path) {
+                Some(index) => index,
+                None => {
+                    files.push(FileReport {
+                        path: block.path.clone(),
+                        result: PreviewValidationResult {
+                            target: target.as_str().to_string(),
+                            valid: true,
+                            error_count: 0,
+                            mermaid_block_count: 0,
+                            renderer_version: None,
+                            issues: Vec::new(),
+                            compatibility: Vec::new(),
+                        },
+                    });
+                    files.len() - 1
+                }
+            };
+            let result = &mut files[index].result;
+            result.mermaid_block_count += 1;
+            result.error_count += count_errors(&block.issues);
+            result.valid = result.error_count == 0;
+            result.issues.extend(block.issues);
+        }
+        let sources = changed
+            .iter()
+            .map(|file| (file.path.clone(), file.markdown.clone()))
+            .collect();
+        CheckReport {
+            checked_blocks,
+            ..CheckReport::new(target, files, sources)
+        }
+    }
+
+    /// A report of one scanned file, whose issues come from the scanner.
+    pub fn from_scan(path: &str, result: PreviewScanResult, source: String) -> Self {
+        let target = PreviewTarget::parse(&result.target).unwrap_or_default();
+        let file = FileReport {
+            path: path.to_string(),
+            result: PreviewValidationResult {
+                target: result.target,
+                valid: result.error_count == 0,
+                error_count: result.error_count,
+                mermaid_block_count: result.mermaid_block_count,
+                renderer_version: None,
+                issues: result.issues,
+                compatibility: result.compatibility,
+            },
+        };
+        CheckReport::new(
+            target,
+            vec![file],
+            HashMap::from([(path.to_string(), source)]),
+        )
+    }
+
+    /// Reports paths under `root` relative to it, with `/` separators.
+    pub fn relative_to(mut self, root: &Path) -> Self {
+        for file in &mut self.files {
+            if let Some(path) = relative(root, Path::new(&file.path)) {
+                if let Some(source) = self.sources.remove(&file.path) {
+                    self.sources.insert(path.clone(), source);
+                }
+                if let Some(blocks) = self.checked_blocks.remove(&file.path) {
+                    self.checked_blocks.insert(path.clone(), blocks);
+                }
+                file.path = path;
+            }
+        }
+        self
+    }
+
+    pub fn exit_status(&self) -> ExitStatus {
+        let environment = self.files.iter().any(|file| {
+            file.result
+                .issues
+                .iter()
+                .any(|issue| ENVIRONMENT_CODES.contains(&issue.code.as_str()))
+        });
+        if environment {
+            ExitStatus::Environment
+        } else if self.valid {
+            ExitStatus::Valid
+        } else {
+            ExitStatus::Invalid
+        }
+    }
+}
+
+/// Expands file, directory and glob arguments, and `-` for stdin, into the
+/// inputs to check. Directories and globs skip hidden and ignored files;
+/// directories use the include globs of `filter`, or
+/// [`DEFAULT_CHECK_INCLUDE`].
+pub fn expand_inputs(
+    args: &[String],
+    stdin_name: &str,
+    filter: &DirectoryFilter,
+) -> Result<Vec<CheckInput>, String> {
+    let mut inputs = Vec::new();
+    for arg in args {
+        if arg == "-" {
+            if inputs
+                .iter()
+                .any(|input| matches!(input, CheckInput::Stdin { .. }))
+            {
+                return Err("- can only be given once".to_string());
+            }
+            inputs.push(CheckInput::Stdin {
+                name: stdin_name.to_string(),
+            });
+            continue;
+        }
+        let path = Path::new(arg);
+        if path.is_file() {
+            inputs.push(CheckInput::File(path.to_path_buf()));
+        } else if path.is_dir() {
+            let filter = DirectoryFilter {
+                include: if filter.include.is_empty() {
+                    DEFAULT_CHECK_INCLUDE
+                        .iter()
+                        .map(|glob| glob.to_string())
+                        .collect()
+                } else {
+                    filter.include.clone()
+                },
+                exclude: filter.exclude.clone(),
+            };
+            inputs.extend(
+                find_markdown_files(path, &filter)?
+                    .into_iter()
+                    .map(CheckInput::File),
+            );
+        } else if is_glob(arg) {
+            let (root, pattern) = split_glob(arg);
+            let filter = DirectoryFilter {
+                include: vec![pattern],
+                exclude: filter.exclude.clone(),
+            };
+            let files = if root.is_dir() {
+                find_markdown_files(&root, &filter)?
+            } else {
+                Vec::new()
+            };
+            if files.is_empty() {
+                return Err(format!("No files match {arg}"));
+            }
+            inputs.extend(files.into_iter().map(CheckInput::File));
+        } else {
+            return Err(format!("{arg}: no such file or directory"));
+        }
+    }
+    inputs.dedup();
+    Ok(inputs)
+}
+
+/// Fails when the renderer for `options` cannot be resolved or its command
+/// cannot be found, before any input is validated.
+pub fn check_renderer(
+    options: &ValidationOptions,
+    renderers: &RendererRegistry,
+) -> Result<(), String> {
+    let renderer = renderers.resolve(options.mermaid_version.as_deref(), options.target)?;
+    if command_exists(&renderer.command) {
+        Ok(())
+    } else {
+        Err(format!(
+            "mermaid-cli not found: {} (set MERMAID_CLI or install @mermaid-js/mermaid-cli)",
+            renderer.command.to_string_lossy()
+        ))
+    }
+}
+
+/// Validates `inputs` concurrently within the shared render limit. Markdown
+/// without Mermaid blocks is no error here.
+pub async fn check_inputs(
+    inputs: &[CheckInput],
+    options: &ValidationOptions,
+    renderers: &RendererRegistry,
+) -> CheckReport {
+    let target = options.target;
+    let mut stdin = None;
+    if inputs
+        .iter()
+        .any(|input| matches!(input, CheckInput::Stdin { .. }))
+    {
+        let mut content = String::new();
+        stdin = Some(
+            tokio::io::stdin()
+                .read_to_string(&mut content)
+                .await
+                .map(|_| content),
+        );
+    }
+
+    let stdin = &stdin;
+    // Progress is reported per file; the blocks of concurrent files would
+    // interleave.
+    let file_options = &ValidationOptions {
+        control: options.control.without_progress(),
+        ..options.clone()
+    };
+    let total = inputs.len() as u32;
+    let files = stream::iter(inputs.to_vec())
+        .map(|input| async move {
+            let content = match &input {
+                CheckInput::File(path) => tokio::fs::read_to_string(path).await,
+                CheckInput::Stdin { .. } => match stdin {
+                    Some(Ok(content)) => Ok(content.clone()),
+                    Some(Err(err)) => Err(std::io::Error::new(err.kind(), err.to_string())),
+                    None => Ok(String::new()),
+                },
+            };
+            let result = match &content {
+                Ok(content) if input.is_diagram() => {
+                    validate_diagram(content, file_options, renderers).await
+                }
+                Ok(markdown) => {
+                    let mut result = validate_markdown(markdown, file_options, renderers).await;
+                    result.error_count -= drop_no_blocks_issue(&mut result.issues);
+                    result.valid = result.error_count == 0;
+                    result
+                }
+                Err(err) => PreviewValidationResult {
+                    target: target.as_str().to_string(),
+                    valid: false,
+                    error_count: 1,
+                    mermaid_block_count: 0,
+                    renderer_version: None,
+                    issues: vec![read_issue(
+                        Path::new(&input.name()),
+                        std::io::Error::new(err.kind(), err.to_string()),
+                    )],
+                    compatibility: Vec::new(),
+                },
+            };
+            let report = FileReport {
+                path: input.name(),
+                result,
+            };
+            (report, content.ok())
+        })
+        .buffered(max_concurrent_renders())
+        .zip(stream::iter(1..))
+        .map(|((report, content), completed)| {
+            options.control.report(FileProgress {
+                path: report.path.clone(),
+                completed,
+                total,
+            });
+            (report, content)
+        })
+        .collect::<Vec<_>>()
+        .await;
+    let mut sources = HashMap::new();
+    let files = files
+        .into_iter()
+        .map(|(report, content)| {
+            if let Some(content) = content {
+                sources.insert(report.path.clone(), content);
+            }
+            report
+        })
+        .collect();
+    CheckReport::new(target, files, sources)
+}
+
+/// Validates a bare diagram as a markdown file holding just that block,
+/// with lines counted from the diagram's first line.
+pub async fn validate_diagram(
+    diagram: &str,
+    options: &ValidationOptions,
+    renderers: &RendererRegistry,
+) -> PreviewValidationResult {
+    let mut result = validate_markdown(&fence_diagram(diagram), options, renderers).await;
+    unfence_lines(&mut result.issues);
+    result
+}
+
+/// Scans a bare diagram like [`validate_diagram`] validates it.
+pub fn scan_diagram(diagram: &str, target: PreviewTarget) -> PreviewScanResult {
+    let mut result = scan_markdown_for_targets(&fence_diagram(diagram), target, &[]);
+    unfence_lines(&mut result.issues);
+    for block in &mut result.blocks {
+        block.start_line = 1;
+        block.end_line = block.line_count;
+    }
+    result
+}
+
+/// The diagram of `source`: a bare diagram, a fenced block or the first
+/// Mermaid block of a markdown document.
+pub fn diagram_source(source: &str) -> Result<String, String> {
+    normalize_diagram(source)
+}
+
+/// The output format implied by the extension of `output`, if any.
+pub fn format_for_output(output: &Path) -> Option<OutputFormat> {
+    let extension = output.extension()?.to_str()?;
+    if extension.eq_ignore_ascii_case("svg") {
+        Some(OutputFormat::Svg)
+    } else if extension.eq_ignore_ascii_case("png") {
+        Some(OutputFormat::Png)
+    } else {
+        None
+    }
+}
+
+/// Exit status for a failed render: the renderer rejecting the diagram is
+/// an invalid diagram, failing to run it is an environment failure.
+pub fn render_error_status(err: &RenderError) -> ExitStatus {
+    match err {
+        RenderError::ProcessExit { .. } | RenderError::Timeout { .. } => ExitStatus::Invalid,
+        RenderError::Spawn { .. } | RenderError::Io { .. } => ExitStatus::Environment,
+    }
+}
+
+/// One line per issue, prefixed with `path:line:column`.
+pub fn issue_lines(path: &str, issues: &[PreviewIssue]) -> Vec<String> {
+    issues
+        .iter()
+        .map(|issue| {
+            let location = match (issue.line, issue.column) {
+                (Some(line), Some(column)) => format!("{path}:{line}:{column}"),
+                (Some(line), None) => format!("{path}:{line}"),
+                _ => path.to_string(),
+            };
+            let mut line = format!(
+                "{location}: {} [{}] {}",
+                issue.severity, issue.code, issue.message
+            );
+            if let Some(block_index) = issue.block_index {
+                line.push_str(&format!(" (block #{block_index})"));
+            }
+            line
+        })
+        .collect()
+}
+
+/// Issues of every file followed by a summary line.
+pub fn check_text(report: &CheckReport) -> String {
+    let mut lines = report
+        .files
+        .iter()
+        .flat_map(|file| issue_lines(&file.path, &file.result.issues))
+        .collect::<Vec<_>>();
+    lines.push(format!(
+        "{} file(s), {} block(s), {} error(s)",
+        report.files.len(),
+        report.mermaid_block_count,
+        report.error_count
+    ));
+    lines.join("\n")
+}
+
+/// `report` in `format`. SARIF paths are relative to `root` when given.
+pub fn format_report(report: &CheckReport, format: ReportFormat, root: Option<&Path>) -> String {
+    match format {
+        ReportFormat::Text => check_text(report),
+        ReportFormat::Json => to_json(report),
+        ReportFormat::Sarif => to_json(&sarif_log(report, root)),
+        ReportFormat::Junit => junit_xml(report),
+        ReportFormat::Github => workflow_commands(report),
+    }
+}
+
+/// The blocks of a scanned file, its issues and a summary line.
+pub fn scan_text(path: &str, result: &PreviewScanResult) -> String {
+    let mut lines = result
+        .blocks
+        .iter()
+        .map(|block| {
+            format!(
+                "#{} {} lines {}-{} ({}): {}",
+                block.index,
+                block.diagram_type.as_deref().unwrap_or("unknown"),
+                block.start_line,
+                block.end_line,
+                block.id,
+                block.first_line
+            )
+        })
+        .collect::<Vec<_>>();
+    lines.extend(issue_lines(path, &result.issues));
+    lines.push(format!(
+        "{} block(s), {} error(s)",
+        result.mermaid_block_count, result.error_count
+    ));
+    lines.join("\n")
+}
+
+fn to_json<T: Serialize>(value: &T) -> String {
+    serde_json::to_string_pretty(value).expect("reports serialize to JSON")
+}
+
+pub(crate) fn is_diagram_path(path: &Path) -> bool {
+    path.extension()
+        .and_then(OsStr::to_str)
+        .is_some_and(|extension| {
+            extension.eq_ignore_ascii_case("mmd") || extension.eq_ignore_ascii_case("mermaid")
+        })
+}
+
+pub(crate) fn is_glob(arg: &str) -> bool {
+    arg.contains(['*', '?', '[', '{'])
+}
+
+/// Splits a glob into the directory before its first wildcard component
+/// and the pattern relative to that directory.
+pub(crate) fn split_glob(glob: &str) -> (PathBuf, String) {
+    let parts = glob.split('/').collect::<Vec<_>>();
+    let literal = parts.iter().take_while(|part| !is_glob(part)).count();
+    let root = parts[..literal].join("/");
+    let root = if root.is_empty() {
+        if glob.starts_with('/') { "/" } else { "." }.to_string()
+    } else {
+        root
+    };
+    (PathBuf::from(root), parts[literal..].join("/"))
+}
+
+/// Wraps `diagram` in a backtick fence longer than any backtick run in it.
+pub(crate) fn fence_diagram(diagram: &str) -> String {
+    let longest_run = diagram
+        .split(|ch| ch != '`')
+        .map(str::len)
+        .max()
+        .unwrap_or(0);
+    let fence = "`".repeat((longest_run + 1).max(3));
+    format!(
+        "{fence}mermaid\n{}\n{fence}\n",
+        diagram.trim_end_matches('\n')
+    )
+}
+
+/// Moves issue lines of a [`fence_diagram`] back onto the bare diagram.
+pub(crate) fn unfence_lines(issues: &mut [PreviewIssue]) {
+    for issue in issues {
+        issue.line = issue.line.map(|line| line.saturating_sub(1).max(1));
+    }
+}
+
+fn command_exists(command: &OsStr) -> bool {
+    let path = Path::new(command);
+    if path.components().count() > 1 {
+        return path.is_file();
+    }
+    env::var_os("PATH")
+        .is_some_and(|paths| env::split_paths(&paths).any(|dir| dir.join(path).is_file()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn globs_split_at_the_first_wildcard() {
+        assert_eq!(
+            split_glob("docs/**/*.md"),
+            (PathBuf::from("docs"), "**/*.md".to_string())
+        );
+        assert_eq!(
+            split_glob("*.mmd"),
+            (PathBuf::from("."), "*.mmd".to_string())
+        );
+        assert_eq!(
+            split_glob("/srv/docs/{a,b}.md"),
+            (PathBuf::from("/srv/docs"), "{a,b}.md".to_string())
+        );
+    }
+
+    #[test]
+    fn diagrams_are_fenced_past_their_backticks() {
+        assert_eq!(
+            fence_diagram("graph TD\n  A[```]-->B\n"),
+            "````mermaid\ngraph TD\n  A[```]-->B\n````\n"
+        );
+        let scanned = scan_diagram("graph TD\n  A-->B", PreviewTarget::Github);
+        assert_eq!(scanned.blocks[0].start_line, 1);
+        assert_eq!(scanned.blocks[0].end_line, 2);
+    }
+}
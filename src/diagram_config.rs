@@ -0,0 +1,479 @@
+use std::sync::OnceLock;
+
+use regex::Regex;
+use serde_json::Value;
+
+use crate::preview_target::PreviewTarget;
+
+const CONFIG_SCHEMA: &str = include_str!("../data/mermaid_config_schema.json");
+
+const FRONT_MATTER_KEYS: &[&str] = &["title", "displayMode", "config"];
+const DIRECTIVE_KEYS: &[&str] = &["init", "initialize", "wrap"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigSourceKind {
+    FrontMatter,
+    Directive,
+}
+
+/// A front matter block or `%%{...}%%` directive inside a diagram. Lines are
+/// 1-based and relative to the block content, both ends inclusive.
+#[derive(Debug, Clone)]
+pub struct ConfigSource {
+    pub kind: ConfigSourceKind,
+    pub start_line: u32,
+    pub end_line: u32,
+    /// YAML text: the front matter body, or the directive wrapped in braces.
+    pub body: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigDiagnostic {
+    pub severity: &'static str,
+    pub code: &'static str,
+    pub message: String,
+    /// 1-based line within the block content.
+    pub line: u32,
+    pub column: Option<u32>,
+}
+
+/// Finds the front matter and every directive in `content`. An unclosed
+/// front matter block is reported as a diagnostic instead of a source.
+pub fn find_config_sources(content: &str) -> (Vec<ConfigSource>, Vec<ConfigDiagnostic>) {
+    let lines = content.lines().collect::<Vec<_>>();
+    let mut sources = Vec::new();
+    let mut diagnostics = Vec::new();
+    let mut idx = 0usize;
+
+    while idx < lines.len() && lines[idx].trim().is_empty() {
+        idx += 1;
+    }
+    if idx < lines.len() && lines[idx].trim() == "---" {
+        let start = idx;
+        match (start + 1..lines.len()).find(|&end| lines[end].trim() == "---") {
+            Some(end) => {
+                sources.push(ConfigSource {
+                    kind: ConfigSourceKind::FrontMatter,
+                    start_line: (start + 1) as u32,
+                    end_line: (end + 1) as u32,
+                    body: lines[start + 1..end].join("\n"),
+                });
+                idx = end + 1;
+            }
+            None => {
+                diagnostics.push(ConfigDiagnostic {
+                    severity: "error",
+                    code: "front_matter_unclosed",
+                    message: "Front matter is missing its closing ---".to_string(),
+                    line: (start + 1) as u32,
+                    column: None,
+                });
+                return (sources, diagnostics);
+            }
+        }
+    }
+
+    while idx < lines.len() {
+        let trimmed = lines[idx].trim();
+        if !trimmed.starts_with("%%{") {
+            idx += 1;
+            continue;
+        }
+        let start = idx;
+        let end = (start..lines.len()).find(|&end| lines[end].contains("}%%"));
+        let Some(end) = end else {
+            diagnostics.push(ConfigDiagnostic {
+                severity: "error",
+                code: "init_directive_unclosed",
+                message: "Directive is missing its closing }%%".to_string(),
+                line: (start + 1) as u32,
+                column: None,
+            });
+            break;
+        };
+        let text = lines[start..=end].join("\n");
+        let inner = text
+            .trim()
+            .trim_start_matches("%%{")
+            .rsplit_once("}%%")
+            .map_or("", |(inner, _)| inner);
+        sources.push(ConfigSource {
+            kind: ConfigSourceKind::Directive,
+            start_line: (start + 1) as u32,
+            end_line: (end + 1) as u32,
+            body: format!("{{{}}}", normalize_directive(inner)),
+        });
+        idx = end + 1;
+    }
+
+    (sources, diagnostics)
+}
+
+/// Validates front matter and directives in `content` against Mermaid's
+/// config schema and the settings `target` ignores.
+pub fn check_diagram_config(content: &str, target: PreviewTarget) -> Vec<ConfigDiagnostic> {
+    let lines = content.lines().collect::<Vec<_>>();
+    let (sources, mut diagnostics) = find_config_sources(content);
+
+    for source in &sources {
+        let source_lines = &lines[source.start_line as usize - 1..source.end_line as usize];
+        let locate = |path: &[String]| locate_key(source_lines, source.start_line, path);
+        let (invalid_code, unknown_code, known_keys, label) = match source.kind {
+            ConfigSourceKind::FrontMatter => (
+                "front_matter_invalid",
+                "front_matter_unknown_key",
+                FRONT_MATTER_KEYS,
+                "front matter",
+            ),
+            ConfigSourceKind::Directive => (
+                "init_directive_invalid",
+                "init_directive_unknown_key",
+                DIRECTIVE_KEYS,
+                "directive",
+            ),
+        };
+
+        let value = match serde_yaml::from_str::<serde_yaml::Value>(&source.body)
+            .map_err(|err| {
+                (
+                    err.location().map(|location| location.line()),
+                    err.to_string(),
+                )
+            })
+            .and_then(|value| serde_json::to_value(value).map_err(|err| (None, err.to_string())))
+        {
+            Ok(value) => value,
+            Err((line, message)) => {
+                let line = match (source.kind, line) {
+                    (ConfigSourceKind::FrontMatter, Some(line)) => source.start_line + line as u32,
+                    _ => source.start_line,
+                };
+                diagnostics.push(ConfigDiagnostic {
+                    severity: "error",
+                    code: invalid_code,
+                    message: format!("Invalid {label}: {message}"),
+                    line,
+                    column: None,
+                });
+                continue;
+            }
+        };
+
+        let Value::Object(entries) = value else {
+            diagnostics.push(ConfigDiagnostic {
+                severity: "error",
+                code: invalid_code,
+                message: format!("The {label} must be a mapping"),
+                line: source.start_line,
+                column: None,
+            });
+            continue;
+        };
+
+        for (key, value) in &entries {
+            let path = vec![key.clone()];
+            if !known_keys.contains(&key.as_str()) {
+                let (line, column) = locate(&path);
+                diagnostics.push(ConfigDiagnostic {
+                    severity: "warning",
+                    code: unknown_code,
+                    message: format!("Unknown {label} key `{key}`"),
+                    line,
+                    column,
+                });
+                continue;
+            }
+            match key.as_str() {
+                "config" | "init" | "initialize" => {
+                    let mut violations = Vec::new();
+                    validate_against_schema(
+                        value,
+                        config_schema(),
+                        &mut path.clone(),
+                        &mut violations,
+                    );
+                    for violation in violations {
+                        let (line, column) = locate(&violation.path);
+                        diagnostics.push(violation.into_diagnostic(line, column));
+                    }
+                    if let Value::Object(config) = value {
+                        for config_key in config.keys() {
+                            if target.ignores_config_key(config_key) {
+                                let (line, column) = locate(&[key.clone(), config_key.clone()]);
+                                diagnostics.push(ConfigDiagnostic {
+                                    severity: "warning",
+                                    code: "config_ignored_by_target",
+                                    message: format!(
+                                        "{} ignores `{config_key}` set inside a diagram",
+                                        target.display_name()
+                                    ),
+                                    line,
+                                    column,
+                                });
+                            }
+                        }
+                    }
+                }
+                "title" | "displayMode" if !value.is_string() => {
+                    let (line, column) = locate(&path);
+                    diagnostics.push(ConfigDiagnostic {
+                        severity: "error",
+                        code: "config_wrong_type",
+                        message: format!("`{key}` must be a string"),
+                        line,
+                        column,
+                    });
+                }
+                _ => {}
+            }
+        }
+    }
+
+    diagnostics.sort_by_key(|diagnostic| (diagnostic.line, diagnostic.column));
+    diagnostics
+}
+
+/// Puts a space after the directive name so `init:{...}` parses as YAML.
+fn normalize_directive(inner: &str) -> String {
+    static NAME: OnceLock<Regex> = OnceLock::new();
+    let name = NAME.get_or_init(|| Regex::new(r"^\s*([A-Za-z]+)\s*:\s*").expect("valid regex"));
+    name.replace(inner.trim(), "$1: ").into_owned()
+}
+
+fn config_schema() -> &'static Value {
+    static SCHEMA: OnceLock<Value> = OnceLock::new();
+    SCHEMA.get_or_init(|| serde_json::from_str(CONFIG_SCHEMA).expect("config schema is valid"))
+}
+
+#[derive(Debug)]
+enum ViolationKind {
+    UnknownKey,
+    WrongType(String),
+    InvalidValue(String),
+}
+
+#[derive(Debug)]
+struct Violation {
+    path: Vec<String>,
+    kind: ViolationKind,
+}
+
+impl Violation {
+    fn into_diagnostic(self, line: u32, column: Option<u32>) -> ConfigDiagnostic {
+        let key = self.path[1..].join(".");
+        let (severity, code, message) = match self.kind {
+            ViolationKind::UnknownKey => (
+                "warning",
+                "config_unknown_key",
+                format!("Unknown Mermaid config key `{key}`"),
+            ),
+            ViolationKind::WrongType(expected) => (
+                "error",
+                "config_wrong_type",
+                format!("Mermaid config `{key}` must be of type {expected}"),
+            ),
+            ViolationKind::InvalidValue(allowed) => (
+                "error",
+                "config_invalid_value",
+                format!("Mermaid config `{key}` must be one of {allowed}"),
+            ),
+        };
+        ConfigDiagnostic {
+            severity,
+            code,
+            message,
+            line,
+            column,
+        }
+    }
+}
+
+/// Checks `value` against the subset of JSON Schema used by the bundled
+/// config schema: `type`, `enum`, `properties` and `additionalProperties`.
+fn validate_against_schema(
+    value: &Value,
+    schema: &Value,
+    path: &mut Vec<String>,
+    violations: &mut Vec<Violation>,
+) {
+    if let Some(expected) = schema.get("type") {
+        let types = match expected {
+            Value::String(name) => vec![name.as_str()],
+            Value::Array(names) => names.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        if !types.is_empty() && !types.iter().any(|name| matches_type(value, name)) {
+            violations.push(Violation {
+                path: path.clone(),
+                kind: ViolationKind::WrongType(types.join(" or ")),
+            });
+            return;
+        }
+    }
+
+    if let Some(Value::Array(allowed)) = schema.get("enum") {
+        if !allowed.contains(value) {
+            let allowed = allowed
+                .iter()
+                .filter(|value| !value.is_null())
+                .map(|value| match value {
+                    Value::String(text) => format!("`{text}`"),
+                    other => format!("`{other}`"),
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+            violations.push(Violation {
+                path: path.clone(),
+                kind: ViolationKind::InvalidValue(allowed),
+            });
+            return;
+        }
+    }
+
+    let Value::Object(entries) = value else {
+        return;
+    };
+    let properties = schema.get("properties").and_then(Value::as_object);
+    let additional = schema.get("additionalProperties");
+    for (key, child) in entries {
+        path.push(key.clone());
+        match properties.and_then(|properties| properties.get(key)) {
+            Some(child_schema) => validate_against_schema(child, child_schema, path, violations),
+            None => match additional {
+                Some(Value::Bool(false)) => violations.push(Violation {
+                    path: path.clone(),
+                    kind: ViolationKind::UnknownKey,
+                }),
+                Some(child_schema @ Value::Object(_)) => {
+                    validate_against_schema(child, child_schema, path, violations)
+                }
+                _ => {}
+            },
+        }
+        path.pop();
+    }
+}
+
+fn matches_type(value: &Value, name: &str) -> bool {
+    match name {
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "boolean" => value.is_boolean(),
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "null" => value.is_null(),
+        _ => true,
+    }
+}
+
+/// Finds the line and column of the last key of `path`, searching for each
+/// segment after the position of its parent. Falls back to the deepest
+/// segment found, then to the start of the source.
+fn locate_key(lines: &[&str], first_line: u32, path: &[String]) -> (u32, Option<u32>) {
+    let mut position: Option<(usize, usize)> = None;
+    for segment in path {
+        let pattern = Regex::new(&format!(
+            r#"(?:^|[^\w-])['"]?({})['"]?\s*:"#,
+            regex::escape(segment)
+        ))
+        .expect("escaped key is a valid regex");
+        let (start_line, start_col) = position.map_or((0, 0), |(line, col)| (line, col + 1));
+        let found = lines
+            .iter()
+            .enumerate()
+            .skip(start_line)
+            .find_map(|(idx, text)| {
+                let from = if idx == start_line {
+                    start_col.min(text.len())
+                } else {
+                    0
+                };
+                let from = (from..=text.len()).find(|&pos| text.is_char_boundary(pos))?;
+                pattern
+                    .captures(&text[from..])
+                    .and_then(|captures| captures.get(1))
+                    .map(|key| (idx, from + key.start()))
+            });
+        match found {
+            Some(found) => position = Some(found),
+            None => break,
+        }
+    }
+
+    match position {
+        Some((idx, col)) => (
+            first_line + idx as u32,
+            Some(lines[idx][..col].chars().count() as u32 + 1),
+        ),
+        None => (first_line, None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn codes(diagnostics: &[ConfigDiagnostic]) -> Vec<&str> {
+        diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.code)
+            .collect()
+    }
+
+    #[test]
+    fn front_matter_reports_bad_keys_at_their_line() {
+        let content = "---\ntitle: Flow\nconfig:\n  theme: midnight\n  flowchart:\n    curve: basis\n    nodeSpace: 10\n  securityLevel: loose\n---\nflowchart TD\nA-->B";
+        let diagnostics = check_diagram_config(content, PreviewTarget::Github);
+        assert_eq!(
+            codes(&diagnostics),
+            vec![
+                "config_invalid_value",
+                "config_unknown_key",
+                "config_ignored_by_target"
+            ]
+        );
+        assert_eq!((diagnostics[0].line, diagnostics[0].column), (4, Some(3)));
+        assert!(diagnostics[0].message.contains("`dark`"));
+        assert_eq!(diagnostics[1].line, 7);
+        assert!(diagnostics[1].message.contains("flowchart.nodeSpace"));
+        assert_eq!(diagnostics[2].line, 8);
+        assert!(diagnostics[2]
+            .message
+            .starts_with("GitHub ignores `securityLevel`"));
+    }
+
+    #[test]
+    fn init_directive_uses_relaxed_json() {
+        let content = "%%{init:{'theme':'dark', 'fontSize': '16px', 'flowchart': {'htmlLabels': false}}}%%\ngraph TD\nA-->B";
+        let diagnostics = check_diagram_config(content, PreviewTarget::Github);
+        assert_eq!(codes(&diagnostics), vec!["config_wrong_type"]);
+        assert_eq!(diagnostics[0].line, 1);
+        assert_eq!(diagnostics[0].column, Some(27));
+    }
+
+    #[test]
+    fn report_syntax_errors_and_unknown_sections() {
+        let content = "---\ntitle: [unclosed\n---\n%%{wrap}%%\n%%{initt: {}}%%\ngraph TD";
+        let diagnostics = check_diagram_config(content, PreviewTarget::Github);
+        assert_eq!(
+            codes(&diagnostics),
+            vec!["front_matter_invalid", "init_directive_unknown_key"]
+        );
+        assert_eq!(diagnostics[1].line, 5);
+
+        let unclosed = check_diagram_config("---\ntitle: x\ngraph TD", PreviewTarget::Github);
+        assert_eq!(codes(&unclosed), vec!["front_matter_unclosed"]);
+    }
+
+    #[test]
+    fn find_front_matter_and_multiline_directive() {
+        let content = "---\ntitle: x\n---\n%%{\n  init: {}\n}%%\ngraph TD";
+        let (sources, diagnostics) = find_config_sources(content);
+        assert!(diagnostics.is_empty());
+        assert_eq!(sources.len(), 2);
+        assert_eq!(sources[0].kind, ConfigSourceKind::FrontMatter);
+        assert_eq!((sources[1].start_line, sources[1].end_line), (4, 6));
+        assert_eq!(sources[1].body, "{init: {}}");
+    }
+}
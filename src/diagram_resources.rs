@@ -0,0 +1,331 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    ffi::OsString,
+    hash::Hash,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use serde_json::json;
+use thiserror::Error;
+
+use crate::{
+    cli_runner::{OutputFormat, RenderError},
+    logging::{log, LogLevel},
+    preview_target::PreviewTarget,
+    preview_validator::{collect_mermaid_blocks, content_hash},
+    renderer_registry::{Renderer, RendererRegistry},
+};
+
+pub const RESOURCE_SCHEME: &str = "mermaid://";
+
+/// Rendered diagrams and registered sources kept per server.
+const CACHE_CAPACITY: usize = 128;
+
+/// RFC 6570 simple expansion: everything but unreserved characters is
+/// escaped, so a file path fills a single `{path}` segment.
+pub(crate) const PATH_SEGMENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// `(uri template, name, description)` for every format.
+pub const RESOURCE_TEMPLATES: &[(&str, &str, &str)] = &[
+    (
+        "mermaid://file/{path}/block/{index}.svg",
+        "mermaid-block-svg",
+        "Mermaid block `index` of the markdown file at `path`, rendered as SVG",
+    ),
+    (
+        "mermaid://file/{path}/block/{index}.png",
+        "mermaid-block-png",
+        "Mermaid block `index` of the markdown file at `path`, rendered as PNG",
+    ),
+    (
+        "mermaid://render/{hash}.svg",
+        "mermaid-render-svg",
+        "A diagram previously validated with validateMermaid, rendered as SVG",
+    ),
+    (
+        "mermaid://render/{hash}.png",
+        "mermaid-render-png",
+        "A diagram previously validated with validateMermaid, rendered as PNG",
+    ),
+];
+
+#[derive(Debug, Error)]
+pub enum ResourceError {
+    #[error("unknown resource {0}")]
+    UnknownUri(String),
+    #[error("{0}")]
+    NotFound(String),
+    #[error("{}", .0.to_error_message())]
+    Render(#[from] RenderError),
+}
+
+/// A resource URI understood by the server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiagramResource {
+    FileBlock {
+        path: String,
+        index: u32,
+        format: OutputFormat,
+    },
+    Render {
+        hash: String,
+        format: OutputFormat,
+    },
+}
+
+impl DiagramResource {
+    pub fn parse(uri: &str) -> Option<DiagramResource> {
+        let rest = uri.strip_prefix(RESOURCE_SCHEME)?;
+        let (rest, extension) = rest.rsplit_once('.')?;
+        let format = match extension {
+            "svg" => OutputFormat::Svg,
+            "png" => OutputFormat::Png,
+            _ => return None,
+        };
+        if let Some(hash) = rest.strip_prefix("render/") {
+            if hash.is_empty() || !hash.chars().all(|ch| ch.is_ascii_hexdigit()) {
+                return None;
+            }
+            return Some(DiagramResource::Render {
+                hash: hash.to_string(),
+                format,
+            });
+        }
+        let (path, index) = rest.strip_prefix("file/")?.rsplit_once("/block/")?;
+        let path = percent_decode_str(path).decode_utf8().ok()?;
+        if path.is_empty() {
+            return None;
+        }
+        Some(DiagramResource::FileBlock {
+            path: path.into_owned(),
+            index: index.parse().ok()?,
+            format,
+        })
+    }
+
+    pub fn uri(&self) -> String {
+        match self {
+            DiagramResource::FileBlock {
+                path,
+                index,
+                format,
+            } => format!(
+                "{RESOURCE_SCHEME}file/{}/block/{index}.{}",
+                utf8_percent_encode(path, PATH_SEGMENT),
+                format.as_str()
+            ),
+            DiagramResource::Render { hash, format } => {
+                format!("{RESOURCE_SCHEME}render/{hash}.{}", format.as_str())
+            }
+        }
+    }
+
+    pub fn format(&self) -> OutputFormat {
+        match self {
+            DiagramResource::FileBlock { format, .. } | DiagramResource::Render { format, .. } => {
+                *format
+            }
+        }
+    }
+}
+
+/// Insertion-ordered map that forgets its oldest entry once full.
+#[derive(Debug)]
+struct BoundedMap<K, V> {
+    entries: HashMap<K, V>,
+    order: VecDeque<K>,
+    capacity: usize,
+}
+
+impl<K: Clone + Eq + Hash, V: Clone> BoundedMap<K, V> {
+    fn new(capacity: usize) -> Self {
+        BoundedMap {
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    fn get(&self, key: &K) -> Option<V> {
+        self.entries.get(key).cloned()
+    }
+
+    fn insert(&mut self, key: K, value: V) {
+        if self.entries.insert(key.clone(), value).is_none() {
+            self.order.push_back(key);
+        }
+        while self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.entries.remove(&oldest);
+            }
+        }
+    }
+}
+
+type RenderKey = (String, OutputFormat, OsString);
+
+/// Caches rendered diagrams by source hash, format and renderer, and keeps
+/// the sources behind `mermaid://render/{hash}` URIs.
+#[derive(Debug)]
+pub struct RenderCache {
+    renders: Mutex<BoundedMap<RenderKey, Arc<Vec<u8>>>>,
+    sources: Mutex<BoundedMap<String, (Arc<str>, Renderer)>>,
+}
+
+impl Default for RenderCache {
+    fn default() -> Self {
+        RenderCache::with_capacity(CACHE_CAPACITY)
+    }
+}
+
+impl RenderCache {
+    pub fn with_capacity(capacity: usize) -> Self {
+        RenderCache {
+            renders: Mutex::new(BoundedMap::new(capacity)),
+            sources: Mutex::new(BoundedMap::new(capacity)),
+        }
+    }
+
+    /// Remembers `diagram` and the renderer that validated it so its render
+    /// URI can be read later. Registering the same source again replaces
+    /// the renderer.
+    pub fn register_source(&self, diagram: &str, renderer: &Renderer) -> String {
+        let hash = content_hash(diagram);
+        if let Ok(mut sources) = self.sources.lock() {
+            sources.insert(hash.clone(), (Arc::from(diagram), renderer.clone()));
+        }
+        hash
+    }
+
+    fn source(&self, hash: &str) -> Option<(Arc<str>, Renderer)> {
+        self.sources.lock().ok()?.get(&hash.to_string())
+    }
+
+    /// Renders `diagram`, reusing an earlier render of the same source.
+    pub async fn render(
+        &self,
+        renderer: &Renderer,
+        diagram: &str,
+        format: OutputFormat,
+        timeout: Duration,
+    ) -> Result<Arc<Vec<u8>>, RenderError> {
+        let key = (content_hash(diagram), format, renderer.command.clone());
+        let cached = self.renders.lock().ok().and_then(|cache| cache.get(&key));
+        log(
+            LogLevel::Debug,
+            "cache",
+            json!({
+                "event": if cached.is_some() { "cacheHit" } else { "cacheMiss" },
+                "hash": key.0,
+                "format": format.as_str(),
+            }),
+        );
+        if let Some(output) = cached {
+            return Ok(output);
+        }
+        let output = Arc::new(renderer.render(diagram, format, timeout).await?);
+        if let Ok(mut cache) = self.renders.lock() {
+            cache.insert(key, output.clone());
+        }
+        Ok(output)
+    }
+
+    /// Renders the diagram behind `resource`. File paths are read as given;
+    /// callers check them against the client's roots first.
+    pub async fn read(
+        &self,
+        resource: &DiagramResource,
+        renderers: &RendererRegistry,
+        timeout: Duration,
+    ) -> Result<Arc<Vec<u8>>, ResourceError> {
+        match resource.clone() {
+            DiagramResource::FileBlock {
+                path,
+                index,
+                format,
+            } => {
+                let markdown = tokio::fs::read_to_string(&path).await.map_err(|err| {
+                    ResourceError::NotFound(format!("Failed to read markdown file {path}: {err}"))
+                })?;
+                let (blocks, _) = collect_mermaid_blocks(&markdown);
+                let block = blocks
+                    .iter()
+                    .find(|block| block.index == index)
+                    .ok_or_else(|| {
+                        ResourceError::NotFound(format!(
+                            "Mermaid block index {index} was not found ({} block(s) in {path})",
+                            blocks.len()
+                        ))
+                    })?;
+                let renderer = renderers.renderer_for_target(PreviewTarget::default());
+                Ok(self
+                    .render(renderer, &block.content, format, timeout)
+                    .await?)
+            }
+            DiagramResource::Render { hash, format } => {
+                let (source, renderer) = self.source(&hash).ok_or_else(|| {
+                    ResourceError::NotFound(format!(
+                        "No diagram with hash {hash}; validate it with validateMermaid first"
+                    ))
+                })?;
+                Ok(self.render(&renderer, &source, format, timeout).await?)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_block_uri_round_trips() {
+        let resource = DiagramResource::FileBlock {
+            path: "/docs/my notes/a.md".to_string(),
+            index: 3,
+            format: OutputFormat::Svg,
+        };
+        let uri = resource.uri();
+        assert_eq!(
+            uri,
+            "mermaid://file/%2Fdocs%2Fmy%20notes%2Fa.md/block/3.svg"
+        );
+        assert_eq!(DiagramResource::parse(&uri), Some(resource));
+    }
+
+    #[test]
+    fn parse_render_uris_and_reject_others() {
+        assert_eq!(
+            DiagramResource::parse("mermaid://render/ab12.png"),
+            Some(DiagramResource::Render {
+                hash: "ab12".to_string(),
+                format: OutputFormat::Png,
+            })
+        );
+        assert_eq!(DiagramResource::parse("mermaid://render/xyz.png"), None);
+        assert_eq!(DiagramResource::parse("mermaid://render/ab12.gif"), None);
+        assert_eq!(
+            DiagramResource::parse("mermaid://file/a.md/block/x.svg"),
+            None
+        );
+        assert_eq!(DiagramResource::parse("file:///a.md"), None);
+    }
+
+    #[test]
+    fn bounded_map_evicts_oldest_entry() {
+        let mut map = BoundedMap::new(2);
+        map.insert("a", 1);
+        map.insert("b", 2);
+        map.insert("a", 3);
+        map.insert("c", 4);
+        assert_eq!(map.get(&"a"), None);
+        assert_eq!(map.get(&"b"), Some(2));
+        assert_eq!(map.get(&"c"), Some(4));
+    }
+}
@@ -0,0 +1,222 @@
+use std::collections::HashMap;
+
+use percent_encoding::{percent_decode_str, utf8_percent_encode};
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::{
+    diagram_resources::{PATH_SEGMENT, RESOURCE_SCHEME},
+    preview_validator::{
+        check_block, collect_mermaid_blocks, content_hash, count_errors, push_missing_blocks_issue,
+        renderer_version_fallback_issue, MermaidBlock, PreviewIssue, ValidationOptions,
+    },
+    renderer_registry::RendererRegistry,
+};
+
+/// `(uri template, name, description)` of the per-file status resource.
+pub const STATUS_RESOURCE_TEMPLATE: (&str, &str, &str) = (
+    "mermaid://status/{path}",
+    "mermaid-file-status",
+    "Validity of every Mermaid block in the markdown file at `path` for GitHub preview; subscribe to be notified when it changes",
+);
+
+pub const STATUS_MIME_TYPE: &str = "application/json";
+
+pub fn status_uri(path: &str) -> String {
+    format!(
+        "{RESOURCE_SCHEME}status/{}",
+        utf8_percent_encode(path, PATH_SEGMENT)
+    )
+}
+
+/// The file path of a `mermaid://status/{path}` URI.
+pub fn parse_status_uri(uri: &str) -> Option<String> {
+    let path = uri.strip_prefix(RESOURCE_SCHEME)?.strip_prefix("status/")?;
+    let path = percent_decode_str(path).decode_utf8().ok()?;
+    if path.is_empty() {
+        return None;
+    }
+    Some(path.into_owned())
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockStatus {
+    pub index: u32,
+    /// Stable id of the block, as in scan results.
+    pub id: String,
+    pub start_line: u32,
+    pub end_line: u32,
+    pub content_hash: String,
+    pub valid: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub renderer_version: Option<String>,
+    pub issues: Vec<PreviewIssue>,
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FileStatus {
+    pub path: String,
+    pub target: String,
+    pub valid: bool,
+    pub error_count: u32,
+    pub mermaid_block_count: u32,
+    pub blocks: Vec<BlockStatus>,
+    /// Issues not tied to a block, such as an unclosed fence.
+    pub issues: Vec<PreviewIssue>,
+}
+
+/// Keeps the last status of each block of one file by content hash and
+/// render options, so a refresh only renders blocks whose source, fence or
+/// options changed. Blocks that merely moved keep their result with line
+/// numbers shifted.
+#[derive(Debug)]
+pub struct StatusTracker {
+    options: ValidationOptions,
+    blocks: HashMap<String, BlockStatus>,
+}
+
+impl StatusTracker {
+    pub fn new(options: ValidationOptions) -> Self {
+        StatusTracker {
+            options,
+            blocks: HashMap::new(),
+        }
+    }
+
+    /// Re-scans `markdown` and validates new or changed blocks. Returns the
+    /// file's status and how many blocks were validated.
+    pub async fn refresh(
+        &mut self,
+        path: &str,
+        markdown: &str,
+        renderers: &RendererRegistry,
+    ) -> (FileStatus, u32) {
+        let (blocks, mut issues) = collect_mermaid_blocks(markdown);
+        push_missing_blocks_issue(&blocks, &mut issues);
+        issues.retain(|issue| issue.block_index.is_none());
+        if !blocks.is_empty() {
+            issues.extend(renderer_version_fallback_issue(renderers, &self.options));
+        }
+
+        let mut validated = 0;
+        let mut current = HashMap::with_capacity(blocks.len());
+        let mut statuses = Vec::with_capacity(blocks.len());
+        for block in &blocks {
+            let key = self.block_key(block);
+            let status = match self.blocks.get(&key) {
+                Some(previous) => BlockStatus {
+                    id: block.id.clone(),
+                    ..moved(previous, block.index, block.start_line, block.end_line)
+                },
+                None => {
+                    validated += 1;
+                    let (issues, renderer_version) =
+                        check_block(block, &self.options, renderers).await;
+                    BlockStatus {
+                        index: block.index,
+                        id: block.id.clone(),
+                        start_line: block.start_line,
+                        end_line: block.end_line,
+                        content_hash: content_hash(&block.content),
+                        valid: count_errors(&issues) == 0,
+                        renderer_version,
+                        issues,
+                    }
+                }
+            };
+            current.insert(key, status.clone());
+            statuses.push(status);
+        }
+        self.blocks = current;
+
+        let error_count = count_errors(&issues)
+            + statuses
+                .iter()
+                .map(|status| count_errors(&status.issues))
+                .sum::<u32>();
+        let status = FileStatus {
+            path: path.to_string(),
+            target: self.options.target.as_str().to_string(),
+            valid: error_count == 0,
+            error_count,
+            mermaid_block_count: blocks.len() as u32,
+            blocks: statuses,
+            issues,
+        };
+        (status, validated)
+    }
+
+    /// Hash of everything a block's result depends on: its source, the
+    /// fence the target sees and the options it is validated with.
+    fn block_key(&self, block: &MermaidBlock) -> String {
+        content_hash(&format!(
+            "{}\n{:?}\n{}\n{:?}\n{}",
+            self.options.target.as_str(),
+            self.options.mermaid_version,
+            block.info_string,
+            block.syntax,
+            block.content
+        ))
+    }
+}
+
+/// `previous` for the same source found as block `index` at `start_line`.
+fn moved(previous: &BlockStatus, index: u32, start_line: u32, end_line: u32) -> BlockStatus {
+    let mut status = previous.clone();
+    let shift = i64::from(start_line) - i64::from(previous.start_line);
+    for issue in &mut status.issues {
+        issue.block_index = Some(index);
+        if let Some(line) = issue.line {
+            issue.line = u32::try_from(i64::from(line) + shift).ok();
+        }
+    }
+    status.index = index;
+    status.start_line = start_line;
+    status.end_line = end_line;
+    status
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_uri_round_trips() {
+        let uri = status_uri("/docs/my notes/a.md");
+        assert_eq!(uri, "mermaid://status/%2Fdocs%2Fmy%20notes%2Fa.md");
+        assert_eq!(
+            parse_status_uri(&uri).as_deref(),
+            Some("/docs/my notes/a.md")
+        );
+        assert_eq!(parse_status_uri("mermaid://status/"), None);
+        assert_eq!(parse_status_uri("mermaid://render/ab12.svg"), None);
+    }
+
+    #[test]
+    fn moved_blocks_shift_issue_lines() {
+        let previous = BlockStatus {
+            index: 1,
+            id: "a#1".to_string(),
+            start_line: 3,
+            end_line: 6,
+            content_hash: "ab".to_string(),
+            valid: false,
+            renderer_version: None,
+            issues: vec![PreviewIssue {
+                severity: "error".to_string(),
+                code: "mermaid_parse_error".to_string(),
+                message: "Parse error".to_string(),
+                line: Some(5),
+                column: None,
+                snippet: None,
+                block_index: Some(1),
+            }],
+        };
+        let status = moved(&previous, 2, 10, 13);
+        assert_eq!(status.index, 2);
+        assert_eq!(status.issues[0].line, Some(12));
+        assert_eq!(status.issues[0].block_index, Some(2));
+    }
+}
@@ -0,0 +1,331 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use futures::{stream, StreamExt};
+use globset::{Glob, GlobBuilder, GlobSet, GlobSetBuilder};
+use ignore::{WalkBuilder, WalkState};
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::{
+    cli_runner::max_concurrent_renders,
+    preview_target::PreviewTarget,
+    preview_validator::{
+        scan_markdown_for_targets, validate_markdown, FileProgress, PreviewIssue,
+        PreviewScanResult, PreviewValidationResult, ValidationOptions,
+    },
+    renderer_registry::RendererRegistry,
+};
+
+/// Files matched when no include glob is given.
+pub const DEFAULT_INCLUDE: &[&str] = &["**/*.md", "**/*.markdown"];
+
+/// Which files of a directory tree to check. Globs are matched against
+/// paths relative to the root, with `/` separators; `*` stays within one
+/// directory and `**` crosses directories.
+#[derive(Debug, Clone, Default)]
+pub struct DirectoryFilter {
+    /// Files to check (default: [`DEFAULT_INCLUDE`]).
+    pub include: Vec<String>,
+    /// Files and directories to skip.
+    pub exclude: Vec<String>,
+}
+
+/// One file of a directory result, with its path relative to the root.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FileReport<T> {
+    pub path: String,
+    #[serde(flatten)]
+    pub result: T,
+}
+
+/// Per-file results of a directory scan or validation, with totals over
+/// the reported files.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DirectoryReport<T> {
+    pub root: String,
+    pub target: String,
+    pub mermaid_block_count: u32,
+    pub error_count: u32,
+    pub valid: bool,
+    pub files: Vec<FileReport<T>>,
+}
+
+/// Lists the files under `root` matching `filter`, skipping hidden files and
+/// whatever `.gitignore`, `.ignore` and git excludes ignore. Directories
+/// are walked in parallel; the result is sorted.
+pub fn find_markdown_files(root: &Path, filter: &DirectoryFilter) -> Result<Vec<PathBuf>, String> {
+    let include = if filter.include.is_empty() {
+        glob_set(DEFAULT_INCLUDE.iter().copied())?
+    } else {
+        glob_set(filter.include.iter().map(String::as_str))?
+    };
+    let exclude = glob_set(filter.exclude.iter().map(String::as_str))?;
+    if !root.is_dir() {
+        return Err(format!("{} is not a directory", root.display()));
+    }
+
+    let found = Mutex::new(Vec::new());
+    let exclude_root = root.to_path_buf();
+    WalkBuilder::new(root)
+        .require_git(false)
+        .filter_entry(move |entry| {
+            relative(&exclude_root, entry.path()).is_none_or(|path| !exclude.is_match(path))
+        })
+        .build_parallel()
+        .run(|| {
+            Box::new(|entry| {
+                let Ok(entry) = entry else {
+                    return WalkState::Continue;
+                };
+                let is_file = entry.file_type().is_some_and(|kind| kind.is_file());
+                if is_file
+                    && relative(root, entry.path()).is_some_and(|path| include.is_match(path))
+                {
+                    found
+                        .lock()
+                        .unwrap_or_else(|poisoned| poisoned.into_inner())
+                        .push(entry.into_path());
+                }
+                WalkState::Continue
+            })
+        });
+
+    let mut files = found
+        .into_inner()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    files.sort();
+    Ok(files)
+}
+
+/// `path` relative to `root` with `/` separators; `None` for the root.
+pub fn relative(root: &Path, path: &Path) -> Option<String> {
+    let relative = path.strip_prefix(root).ok()?;
+    if relative.as_os_str().is_empty() {
+        return None;
+    }
+    Some(
+        relative
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/"),
+    )
+}
+
+/// Scans `files` of the tree at `root` for `target` without rendering.
+pub async fn scan_directory(
+    root: &Path,
+    files: &[PathBuf],
+    target: PreviewTarget,
+) -> DirectoryReport<PreviewScanResult> {
+    // Owned paths keep the futures `Send` for any caller lifetime.
+    let reports = stream::iter(files.to_vec())
+        .map(|path| async move {
+            let result = match tokio::fs::read_to_string(&path).await {
+                Ok(markdown) => {
+                    let mut result = scan_markdown_for_targets(&markdown, target, &[]);
+                    result.error_count -= drop_no_blocks_issue(&mut result.issues);
+                    result
+                }
+                Err(err) => PreviewScanResult {
+                    target: target.as_str().to_string(),
+                    error_count: 1,
+                    mermaid_block_count: 0,
+                    blocks: Vec::new(),
+                    issues: vec![read_issue(&path, err)],
+                    compatibility: Vec::new(),
+                },
+            };
+            file_report(root, &path, result)
+        })
+        .buffered(max_concurrent_renders())
+        .collect::<Vec<_>>()
+        .await;
+    directory_report(root, target, reports, |result| {
+        (result.mermaid_block_count, result.error_count)
+    })
+}
+
+/// Validates `files` of the tree at `root`. Files are validated
+/// concurrently; their renders share the process-wide render limit.
+pub async fn validate_directory(
+    root: &Path,
+    files: &[PathBuf],
+    options: &ValidationOptions,
+    renderers: &RendererRegistry,
+) -> DirectoryReport<PreviewValidationResult> {
+    let target = options.target;
+    // Progress is reported per file; the blocks of concurrent files would
+    // interleave.
+    let file_options = &ValidationOptions {
+        control: options.control.without_progress(),
+        ..options.clone()
+    };
+    let total = files.len() as u32;
+    let reports = stream::iter(files.to_vec())
+        .map(|path| async move {
+            let result = match tokio::fs::read_to_string(&path).await {
+                Ok(markdown) => {
+                    let mut result = validate_markdown(&markdown, file_options, renderers).await;
+                    result.error_count -= drop_no_blocks_issue(&mut result.issues);
+                    result.valid = result.error_count == 0;
+                    result
+                }
+                Err(err) => PreviewValidationResult {
+                    target: target.as_str().to_string(),
+                    valid: false,
+                    error_count: 1,
+                    mermaid_block_count: 0,
+                    renderer_version: None,
+                    issues: vec![read_issue(&path, err)],
+                    compatibility: Vec::new(),
+                },
+            };
+            file_report(root, &path, result)
+        })
+        .buffered(max_concurrent_renders())
+        .zip(stream::iter(1..))
+        .map(|(report, completed)| {
+            options.control.report(FileProgress {
+                path: report.path.clone(),
+                completed,
+                total,
+            });
+            report
+        })
+        .collect::<Vec<_>>()
+        .await;
+    directory_report(root, target, reports, |result| {
+        (result.mermaid_block_count, result.error_count)
+    })
+}
+
+fn glob_set<'a>(globs: impl Iterator<Item = &'a str>) -> Result<GlobSet, String> {
+    let mut builder = GlobSetBuilder::new();
+    for glob in globs {
+        builder.add(parse_glob(glob)?);
+    }
+    builder
+        .build()
+        .map_err(|err| format!("Invalid glob set: {err}"))
+}
+
+fn parse_glob(glob: &str) -> Result<Glob, String> {
+    GlobBuilder::new(glob.trim_start_matches("./"))
+        .literal_separator(true)
+        .build()
+        .map_err(|err| format!("Invalid glob {glob}: {err}"))
+}
+
+/// Most files of a docs tree have no diagram, which is no error there.
+/// Returns how many errors were dropped.
+pub(crate) fn drop_no_blocks_issue(issues: &mut Vec<PreviewIssue>) -> u32 {
+    let before = issues.len();
+    issues.retain(|issue| issue.code != "no_mermaid_blocks");
+    (before - issues.len()) as u32
+}
+
+pub(crate) fn read_issue(path: &Path, err: std::io::Error) -> PreviewIssue {
+    PreviewIssue {
+        severity: "error".to_string(),
+        code: "markdown_read_error".to_string(),
+        message: format!("Failed to read markdown file {}: {err}", path.display()),
+        line: None,
+        column: None,
+        snippet: None,
+        block_index: None,
+    }
+}
+
+fn file_report<T>(root: &Path, path: &Path, result: T) -> FileReport<T> {
+    FileReport {
+        path: relative(root, path).unwrap_or_else(|| path.display().to_string()),
+        result,
+    }
+}
+
+/// Sums the `(blocks, errors)` that `counts` reads from each file result.
+fn directory_report<T>(
+    root: &Path,
+    target: PreviewTarget,
+    files: Vec<FileReport<T>>,
+    counts: impl Fn(&T) -> (u32, u32),
+) -> DirectoryReport<T> {
+    let (mermaid_block_count, error_count) = files
+        .iter()
+        .map(|file| counts(&file.result))
+        .fold((0, 0), |(blocks, errors), (file_blocks, file_errors)| {
+            (blocks + file_blocks, errors + file_errors)
+        });
+    DirectoryReport {
+        root: root.display().to_string(),
+        target: target.as_str().to_string(),
+        mermaid_block_count,
+        error_count,
+        valid: error_count == 0,
+        files,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn walks_respect_globs_and_gitignore() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        for path in [
+            "README.md",
+            "docs/guide.md",
+            "docs/api/ref.markdown",
+            "docs/drafts/wip.md",
+            "build/out.md",
+            "notes.txt",
+            ".hidden/secret.md",
+        ] {
+            let path = root.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "# Doc\n").unwrap();
+        }
+        std::fs::write(root.join(".gitignore"), "build/\n").unwrap();
+
+        let names = |filter: &DirectoryFilter| {
+            find_markdown_files(root, filter)
+                .unwrap()
+                .iter()
+                .map(|path| relative(root, path).unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            names(&DirectoryFilter::default()),
+            vec![
+                "README.md",
+                "docs/api/ref.markdown",
+                "docs/drafts/wip.md",
+                "docs/guide.md"
+            ]
+        );
+        let filter = DirectoryFilter {
+            include: vec!["docs/**/*.md".to_string()],
+            exclude: vec!["docs/drafts".to_string()],
+        };
+        assert_eq!(names(&filter), vec!["docs/guide.md"]);
+        let filter = DirectoryFilter {
+            include: vec!["*.md".to_string()],
+            exclude: Vec::new(),
+        };
+        assert_eq!(names(&filter), vec!["README.md"]);
+
+        let invalid = DirectoryFilter {
+            include: vec!["a[".to_string()],
+            exclude: Vec::new(),
+        };
+        assert!(find_markdown_files(root, &invalid).is_err());
+    }
+}
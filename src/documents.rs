@@ -0,0 +1,180 @@
+use std::{
+    collections::HashMap,
+    io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use schemars::JsonSchema;
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::preview_validator::{content_hash, PreviewScanResult};
+
+/// Reported by every call on a document whose file no longer matches it.
+pub const STALE_WARNING: &str = "document changed, indexes may be stale";
+
+/// Documents one session may keep open at a time.
+pub const MAX_OPEN_DOCUMENTS: usize = 64;
+
+#[derive(Debug, Error)]
+pub enum DocumentError {
+    #[error("Unknown document {0}; open it with openMarkdownDocument")]
+    UnknownHandle(String),
+    #[error(
+        "Too many open documents ({MAX_OPEN_DOCUMENTS}); close some with closeMarkdownDocument"
+    )]
+    TooMany,
+    #[error("Failed to read markdown file {path}: {source}")]
+    Read { path: String, source: io::Error },
+}
+
+/// The content of a markdown file as it was when opened.
+#[derive(Debug, Clone)]
+pub struct Document {
+    pub path: PathBuf,
+    pub markdown: Arc<str>,
+    pub content_hash: String,
+    modified: Option<SystemTime>,
+}
+
+/// Result of `openMarkdownDocument`: the handle and the scan of the
+/// content it refers to.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct OpenedDocument {
+    pub document: String,
+    pub path: String,
+    pub content_hash: String,
+    #[serde(flatten)]
+    pub scan: PreviewScanResult,
+}
+
+/// A document returned by [`DocumentStore::get`], with whether the file on
+/// disk changed since it was opened.
+#[derive(Debug, Clone)]
+pub struct CheckedDocument {
+    pub document: Document,
+    pub stale: bool,
+}
+
+/// Markdown files a session opened once and keeps working on by handle, so
+/// block tools neither re-read the file nor see its indexes shift silently.
+#[derive(Debug, Default)]
+pub struct DocumentStore {
+    documents: Mutex<HashMap<String, Document>>,
+}
+
+impl DocumentStore {
+    /// Reads `path` and returns a new handle for it.
+    pub async fn open(&self, path: PathBuf) -> Result<(String, Document), DocumentError> {
+        let markdown = tokio::fs::read_to_string(&path)
+            .await
+            .map_err(|source| read_error(&path, source))?;
+        let document = Document {
+            content_hash: content_hash(&markdown),
+            markdown: Arc::from(markdown),
+            modified: modified(&path).await,
+            path,
+        };
+        let mut documents = self.lock();
+        if documents.len() >= MAX_OPEN_DOCUMENTS {
+            return Err(DocumentError::TooMany);
+        }
+        let handle = uuid::Uuid::new_v4().simple().to_string();
+        documents.insert(handle.clone(), document.clone());
+        Ok((handle, document))
+    }
+
+    /// Forgets `handle`. Returns whether it was open.
+    pub fn close(&self, handle: &str) -> bool {
+        self.lock().remove(handle).is_some()
+    }
+
+    /// Returns the document behind `handle`. The file's mtime is checked on
+    /// every call and its hash when the mtime moved; a file that changed
+    /// or disappeared makes the document stale until it is reopened.
+    pub async fn get(&self, handle: &str) -> Result<CheckedDocument, DocumentError> {
+        let document = self
+            .lock()
+            .get(handle)
+            .cloned()
+            .ok_or_else(|| DocumentError::UnknownHandle(handle.to_string()))?;
+        let current = modified(&document.path).await;
+        if current.is_some() && current == document.modified {
+            return Ok(CheckedDocument {
+                document,
+                stale: false,
+            });
+        }
+        let unchanged = tokio::fs::read_to_string(&document.path)
+            .await
+            .is_ok_and(|markdown| content_hash(&markdown) == document.content_hash);
+        if unchanged {
+            // Touched but not edited: remember the new mtime.
+            if let Some(stored) = self.lock().get_mut(handle) {
+                stored.modified = current;
+            }
+        }
+        Ok(CheckedDocument {
+            document,
+            stale: !unchanged,
+        })
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Document>> {
+        self.documents
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+async fn modified(path: &Path) -> Option<SystemTime> {
+    tokio::fs::metadata(path).await.ok()?.modified().ok()
+}
+
+fn read_error(path: &Path, source: io::Error) -> DocumentError {
+    DocumentError::Read {
+        path: path.display().to_string(),
+        source,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn documents_turn_stale_only_when_content_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("doc.md");
+        std::fs::write(&path, "# Doc\n").unwrap();
+        let store = DocumentStore::default();
+        let (handle, _) = store.open(path.clone()).await.unwrap();
+        assert!(!store.get(&handle).await.unwrap().stale);
+
+        // Rewriting the same content moves the mtime only.
+        let later = SystemTime::now() + Duration::from_secs(5);
+        std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+        assert!(!store.get(&handle).await.unwrap().stale);
+
+        std::fs::write(&path, "# Changed\n").unwrap();
+        let checked = store.get(&handle).await.unwrap();
+        assert!(checked.stale);
+        assert_eq!(&*checked.document.markdown, "# Doc\n");
+
+        assert!(store.close(&handle));
+        assert!(matches!(
+            store.get(&handle).await,
+            Err(DocumentError::UnknownHandle(_))
+        ));
+    }
+}
@@ -0,0 +1,332 @@
+use std::{env, path::Path, sync::OnceLock};
+
+use regex::Regex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use thiserror::Error;
+
+use crate::{
+    logging::{log, LogLevel},
+    preview_target::MermaidVersion,
+};
+
+const BUILTIN_FEATURES: &str = include_str!("../data/mermaid_features.json");
+
+#[derive(Debug, Error)]
+pub enum FeatureTableError {
+    #[error("failed to read feature table {path}: {message}")]
+    Read { path: String, message: String },
+    #[error("invalid feature table: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("feature {id} has invalid minVersion {version}")]
+    Version { id: String, version: String },
+    #[error("feature {id} has invalid pattern: {message}")]
+    Pattern { id: String, message: String },
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DetectedFeature {
+    pub id: String,
+    pub description: String,
+    pub min_version: String,
+    /// 1-based line within the block where the feature was first seen.
+    pub line: u32,
+}
+
+#[derive(Debug, Deserialize)]
+struct FeatureTableFile {
+    features: Vec<FeatureRuleFile>,
+}
+
+/// One entry of the feature table. A rule matches when any of the given
+/// criteria matches.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FeatureRuleFile {
+    id: String,
+    description: String,
+    min_version: String,
+    #[serde(default)]
+    diagram_type: Option<String>,
+    #[serde(default)]
+    contains: Option<String>,
+    #[serde(default)]
+    pattern: Option<String>,
+    #[serde(default)]
+    front_matter_key: Option<String>,
+}
+
+#[derive(Debug)]
+struct FeatureRule {
+    id: String,
+    description: String,
+    min_version: MermaidVersion,
+    diagram_type: Option<String>,
+    contains: Option<String>,
+    pattern: Option<Regex>,
+    front_matter_key: Option<String>,
+}
+
+/// Mermaid syntax features and the first Mermaid release supporting them.
+#[derive(Debug)]
+pub struct FeatureTable {
+    rules: Vec<FeatureRule>,
+}
+
+impl FeatureTable {
+    pub fn builtin() -> Self {
+        FeatureTable::from_json(BUILTIN_FEATURES).expect("built-in feature table is valid")
+    }
+
+    /// The table used by the validators: `MERMAID_FEATURES_FILE` when set and
+    /// valid, otherwise the built-in table.
+    pub fn shared() -> &'static FeatureTable {
+        static TABLE: OnceLock<FeatureTable> = OnceLock::new();
+        TABLE.get_or_init(|| match env::var_os("MERMAID_FEATURES_FILE") {
+            Some(path) => FeatureTable::from_file(Path::new(&path)).unwrap_or_else(|err| {
+                log(
+                    LogLevel::Warning,
+                    "config",
+                    json!({
+                        "event": "featureTableFallback",
+                        "error": err.to_string(),
+                    }),
+                );
+                FeatureTable::builtin()
+            }),
+            None => FeatureTable::builtin(),
+        })
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, FeatureTableError> {
+        let json = std::fs::read_to_string(path).map_err(|err| FeatureTableError::Read {
+            path: path.display().to_string(),
+            message: err.to_string(),
+        })?;
+        FeatureTable::from_json(&json)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, FeatureTableError> {
+        let file: FeatureTableFile = serde_json::from_str(json)?;
+        let rules = file
+            .features
+            .into_iter()
+            .map(|rule| {
+                let min_version = MermaidVersion::parse(&rule.min_version).ok_or_else(|| {
+                    FeatureTableError::Version {
+                        id: rule.id.clone(),
+                        version: rule.min_version.clone(),
+                    }
+                })?;
+                let pattern = rule
+                    .pattern
+                    .as_deref()
+                    .map(Regex::new)
+                    .transpose()
+                    .map_err(|err| FeatureTableError::Pattern {
+                        id: rule.id.clone(),
+                        message: err.to_string(),
+                    })?;
+                Ok(FeatureRule {
+                    id: rule.id,
+                    description: rule.description,
+                    min_version,
+                    diagram_type: rule.diagram_type,
+                    contains: rule.contains,
+                    pattern,
+                    front_matter_key: rule.front_matter_key,
+                })
+            })
+            .collect::<Result<Vec<_>, FeatureTableError>>()?;
+        Ok(FeatureTable { rules })
+    }
+
+    /// Returns the first Mermaid release supporting the feature `id`.
+    pub fn min_version(&self, id: &str) -> Option<MermaidVersion> {
+        self.rules
+            .iter()
+            .find(|rule| rule.id == id)
+            .map(|rule| rule.min_version)
+    }
+
+    /// Returns `(id, description, min_version)` for every feature `version`
+    /// does not support yet, in table order.
+    pub fn features_newer_than(
+        &self,
+        version: MermaidVersion,
+    ) -> Vec<(&str, &str, MermaidVersion)> {
+        self.rules
+            .iter()
+            .filter(|rule| rule.min_version > version)
+            .map(|rule| {
+                (
+                    rule.id.as_str(),
+                    rule.description.as_str(),
+                    rule.min_version,
+                )
+            })
+            .collect()
+    }
+
+    /// Returns the features used by a block's content, in table order.
+    pub fn detect(&self, content: &str) -> Vec<DetectedFeature> {
+        let lines = classify_lines(content);
+        self.rules
+            .iter()
+            .filter_map(|rule| {
+                let line = lines.iter().find(|line| rule.matches(line))?;
+                Some(DetectedFeature {
+                    id: rule.id.clone(),
+                    description: rule.description.clone(),
+                    min_version: rule.min_version.to_string(),
+                    line: line.number,
+                })
+            })
+            .collect()
+    }
+}
+
+impl FeatureRule {
+    fn matches(&self, line: &ContentLine<'_>) -> bool {
+        match line.kind {
+            LineKind::FrontMatter => self.front_matter_key.as_deref().is_some_and(|key| {
+                line.text
+                    .split_once(':')
+                    .is_some_and(|(name, _)| name == key)
+            }),
+            LineKind::Header => {
+                self.diagram_type.as_deref().is_some_and(|diagram_type| {
+                    line.text.split_whitespace().next() == Some(diagram_type)
+                }) || self.matches_body(line.text)
+            }
+            LineKind::Body => self.matches_body(line.text),
+            LineKind::Other => false,
+        }
+    }
+
+    fn matches_body(&self, text: &str) -> bool {
+        self.contains
+            .as_deref()
+            .is_some_and(|needle| text.contains(needle))
+            || self
+                .pattern
+                .as_ref()
+                .is_some_and(|pattern| pattern.is_match(text))
+    }
+}
+
+/// Returns the newest `min_version` among `features`.
+pub fn min_mermaid_version(features: &[DetectedFeature]) -> Option<String> {
+    features
+        .iter()
+        .filter_map(|feature| MermaidVersion::parse(&feature.min_version))
+        .max()
+        .map(|version| version.to_string())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LineKind {
+    FrontMatter,
+    Header,
+    Body,
+    Other,
+}
+
+#[derive(Debug)]
+struct ContentLine<'a> {
+    number: u32,
+    text: &'a str,
+    kind: LineKind,
+}
+
+/// Splits block content into front matter, the diagram header line, body
+/// lines, and everything else (blank lines, comments, fences).
+fn classify_lines(content: &str) -> Vec<ContentLine<'_>> {
+    let mut lines = Vec::new();
+    let mut in_front_matter = false;
+    let mut seen_content = false;
+    let mut seen_header = false;
+
+    for (idx, text) in content.lines().enumerate() {
+        let trimmed = text.trim();
+        let kind = if in_front_matter {
+            if trimmed == "---" {
+                in_front_matter = false;
+                LineKind::Other
+            } else {
+                LineKind::FrontMatter
+            }
+        } else if trimmed.is_empty() || trimmed.starts_with("%%") {
+            LineKind::Other
+        } else if !seen_content && trimmed == "---" {
+            in_front_matter = true;
+            seen_content = true;
+            LineKind::Other
+        } else if !seen_header {
+            seen_content = true;
+            seen_header = true;
+            LineKind::Header
+        } else {
+            LineKind::Body
+        };
+        lines.push(ContentLine {
+            number: (idx + 1) as u32,
+            text,
+            kind,
+        });
+    }
+
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(features: &[DetectedFeature]) -> Vec<&str> {
+        features.iter().map(|feature| feature.id.as_str()).collect()
+    }
+
+    #[test]
+    fn detect_builtin_features_with_lines() {
+        let content = "---\nconfig:\n  theme: dark\n---\nflowchart TD\n  A@{ shape: rect }\n  B[\"`**bold**`\"]\n";
+        let features = FeatureTable::builtin().detect(content);
+        assert_eq!(
+            ids(&features),
+            vec!["shape-syntax", "markdown-strings", "front-matter-config"]
+        );
+        assert_eq!(features[0].line, 6);
+        assert_eq!(features[2].line, 2);
+        assert_eq!(min_mermaid_version(&features).as_deref(), Some("11.3.0"));
+    }
+
+    #[test]
+    fn detect_diagram_type_features_only_on_header() {
+        let table = FeatureTable::builtin();
+        assert_eq!(
+            ids(&table.detect("%% note\nkanban\n  todo")),
+            vec!["kanban"]
+        );
+        assert!(table.detect("flowchart TD\n  kanban --> x").is_empty());
+    }
+
+    #[test]
+    fn load_custom_table_and_report_errors() {
+        let table = FeatureTable::from_json(
+            r#"{"features":[{"id":"elk","description":"ELK layout","minVersion":"11.0","pattern":"layout:\\s*elk"}]}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            ids(&table.detect("flowchart TD\n%% x\nA --> B %% layout: elk")),
+            vec!["elk"]
+        );
+
+        let error = FeatureTable::from_json(
+            r#"{"features":[{"id":"x","description":"x","minVersion":"next"}]}"#,
+        )
+        .unwrap_err();
+        assert!(matches!(error, FeatureTableError::Version { .. }));
+    }
+}
@@ -0,0 +1,82 @@
+use std::{io, path::Path};
+
+use thiserror::Error;
+
+use crate::preview_validator::content_hash;
+
+#[derive(Debug, Error)]
+pub enum WriteError {
+    #[error("{path} changed since it was read; read it again and retry")]
+    Changed { path: String },
+    #[error("Failed to write markdown file {path}: {source}")]
+    Io { path: String, source: io::Error },
+}
+
+/// Replaces the file at `path`, named `label` in errors, with `contents`
+/// when it still holds `read`, the content the edit was made from.
+pub async fn write_if_unchanged(
+    path: &Path,
+    label: &str,
+    read: &str,
+    contents: &str,
+) -> Result<(), WriteError> {
+    let io_error = |source| WriteError::Io {
+        path: label.to_string(),
+        source,
+    };
+    let current = tokio::fs::read_to_string(path).await.map_err(io_error)?;
+    if content_hash(&current) != content_hash(read) {
+        return Err(WriteError::Changed {
+            path: label.to_string(),
+        });
+    }
+    write_atomically(path, contents).await.map_err(io_error)
+}
+
+/// Writes `contents` to a sibling temporary file with the permissions of
+/// `path`, then renames it over `path`.
+pub async fn write_atomically(path: &Path, contents: &str) -> io::Result<()> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file path"))?;
+    let temp = path.with_file_name(format!(
+        ".{}.{}.tmp",
+        file_name.to_string_lossy(),
+        uuid::Uuid::new_v4().simple()
+    ));
+    let permissions = tokio::fs::metadata(path).await?.permissions();
+    let written = async {
+        tokio::fs::write(&temp, contents).await?;
+        tokio::fs::set_permissions(&temp, permissions).await?;
+        tokio::fs::rename(&temp, path).await
+    }
+    .await;
+    if written.is_err() {
+        let _ = tokio::fs::remove_file(&temp).await;
+    }
+    written
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn files_changed_since_they_were_read_are_left_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("doc.md");
+        std::fs::write(&path, "before").unwrap();
+
+        write_if_unchanged(&path, "doc.md", "before", "after")
+            .await
+            .unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "after");
+
+        let error = write_if_unchanged(&path, "doc.md", "before", "again")
+            .await
+            .unwrap_err();
+        assert!(matches!(error, WriteError::Changed { .. }));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "after");
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+}
@@ -0,0 +1,233 @@
+use std::{fmt::Write, future::Future, time::Duration};
+
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::{
+    block_edit::{contains_fence, fence_indent, indented},
+    modernize::unified_diff,
+    preview_target::PreviewTarget,
+    preview_validator::{
+        collect_mermaid_blocks, replace_block_content, validate_mermaid_block, MermaidBlock,
+        PreviewIssue, ValidationControl, ValidationOptions,
+    },
+    prompts::{fix_block_prompt, push_issues, push_source},
+    renderer_registry::RendererRegistry,
+};
+
+pub const DEFAULT_MAX_ATTEMPTS: u32 = 3;
+pub const MAX_ATTEMPTS_LIMIT: u32 = 10;
+
+#[derive(Debug, Clone)]
+pub struct SuggestFixOptions {
+    pub target: PreviewTarget,
+    /// Installed Mermaid version to validate candidates with (default: the
+    /// target's).
+    pub mermaid_version: Option<String>,
+    pub timeout: Duration,
+    /// Candidates requested before giving up.
+    pub max_attempts: u32,
+    pub control: ValidationControl,
+}
+
+impl SuggestFixOptions {
+    pub fn new(target: PreviewTarget, timeout: Duration) -> Self {
+        SuggestFixOptions {
+            target,
+            mermaid_version: None,
+            timeout,
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            control: ValidationControl::default(),
+        }
+    }
+
+    fn validation(&self) -> ValidationOptions {
+        ValidationOptions {
+            mermaid_version: self.mermaid_version.clone(),
+            control: self.control.clone(),
+            ..ValidationOptions::new(self.target, self.timeout)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FixAttempt {
+    pub attempt: u32,
+    pub valid: bool,
+    /// The candidate diagram source as proposed by the model.
+    pub candidate: String,
+    pub issues: Vec<PreviewIssue>,
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FixSuggestion {
+    pub target: String,
+    pub block_index: u32,
+    /// The block had no errors, so no fix was requested.
+    pub already_valid: bool,
+    pub fixed: bool,
+    pub attempts: Vec<FixAttempt>,
+    /// Unified diff applying the accepted candidate; empty without one.
+    pub diff: String,
+}
+
+/// Asks `sample` for a corrected version of block `block_index` until a
+/// candidate validates or `options.max_attempts` candidates were rejected.
+///
+/// `sample` receives the prompt: the block source, its diagnostics and the
+/// target's rules, followed by the diagnostics of every rejected candidate.
+/// It returns the model's reply; a fenced block in the reply is unwrapped.
+pub async fn suggest_fix<S, Fut>(
+    path: &str,
+    markdown: &str,
+    block_index: u32,
+    options: &SuggestFixOptions,
+    renderers: &RendererRegistry,
+    mut sample: S,
+) -> Result<FixSuggestion, String>
+where
+    S: FnMut(String) -> Fut,
+    Fut: Future<Output = Result<String, String>>,
+{
+    let validation = options.validation();
+    let (blocks, _) = collect_mermaid_blocks(markdown);
+    let block = blocks
+        .iter()
+        .find(|block| block.index == block_index)
+        .ok_or_else(|| {
+            format!(
+                "Mermaid block index {block_index} was not found ({} block(s) in {path})",
+                blocks.len()
+            )
+        })?;
+    let mut suggestion = FixSuggestion {
+        target: options.target.as_str().to_string(),
+        block_index,
+        already_valid: false,
+        fixed: false,
+        attempts: Vec::new(),
+        diff: String::new(),
+    };
+
+    let current = validate_mermaid_block(markdown, block_index, &validation, renderers).await;
+    if current.valid {
+        suggestion.already_valid = true;
+        return Ok(suggestion);
+    }
+
+    let mut prompt = fix_block_prompt(path, markdown, block_index, &validation, renderers).await?;
+    for attempt in 1..=options.max_attempts.max(1) {
+        if options.control.is_cancelled() {
+            return Err("Fix suggestion was cancelled".to_string());
+        }
+        let reply = sample(prompt.clone()).await?;
+        let candidate = extract_candidate(&reply);
+        let (valid, issues, fixed_markdown) =
+            check_candidate(markdown, block, &candidate, &validation, renderers).await;
+        suggestion.attempts.push(FixAttempt {
+            attempt,
+            valid,
+            candidate: candidate.clone(),
+            issues: issues.clone(),
+        });
+        if valid {
+            suggestion.fixed = true;
+            suggestion.diff = unified_diff(path, markdown, &fixed_markdown);
+            break;
+        }
+
+        let _ = writeln!(prompt, "\nAttempt {attempt} was rejected:");
+        push_source(&mut prompt, &candidate);
+        prompt.push_str("Diagnostics:\n");
+        push_issues(&mut prompt, &issues);
+        prompt.push_str("Fix these problems as well and reply with the full corrected diagram.\n");
+    }
+    Ok(suggestion)
+}
+
+/// Validates `candidate` in place of `block`. Returns whether it passes, its
+/// issues and the markdown with the candidate applied.
+async fn check_candidate(
+    markdown: &str,
+    block: &MermaidBlock,
+    candidate: &str,
+    options: &ValidationOptions,
+    renderers: &RendererRegistry,
+) -> (bool, Vec<PreviewIssue>, String) {
+    let rejected = |message: &str| PreviewIssue {
+        severity: "error".to_string(),
+        code: "fix_candidate_rejected".to_string(),
+        message: message.to_string(),
+        line: None,
+        column: None,
+        snippet: None,
+        block_index: Some(block.index),
+    };
+    if candidate.trim().is_empty() {
+        return (
+            false,
+            vec![rejected("The reply contained no diagram")],
+            String::new(),
+        );
+    }
+    let content = indented(candidate, fence_indent(markdown, block));
+    if content.trim() == block.content.trim() {
+        return (
+            false,
+            vec![rejected("The candidate is identical to the broken block")],
+            String::new(),
+        );
+    }
+    if contains_fence(candidate) {
+        return (
+            false,
+            vec![rejected("The candidate must not contain code fences")],
+            String::new(),
+        );
+    }
+
+    let fixed = replace_block_content(markdown, block, &content);
+    let result = validate_mermaid_block(&fixed, block.index, options, renderers).await;
+    (result.valid, result.issues, fixed)
+}
+
+/// The diagram inside a model reply: the first fenced block when there is
+/// one, otherwise the whole reply.
+pub fn extract_candidate(reply: &str) -> String {
+    let mut lines = reply.lines();
+    while let Some(line) = lines.next() {
+        let trimmed = line.trim();
+        let fence = if trimmed.starts_with("```") {
+            "```"
+        } else if trimmed.starts_with("~~~") {
+            "~~~"
+        } else {
+            continue;
+        };
+        let body = lines
+            .by_ref()
+            .take_while(|line| !line.trim().starts_with(fence))
+            .collect::<Vec<_>>();
+        return body.join("\n");
+    }
+    reply.trim().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extract_candidate_unwraps_fenced_replies() {
+        assert_eq!(
+            extract_candidate("Here you go:\n\n```mermaid\nflowchart TD\n  A-->B\n```\nDone."),
+            "flowchart TD\n  A-->B"
+        );
+        assert_eq!(
+            extract_candidate("\nflowchart TD\n  A-->B\n"),
+            "flowchart TD\n  A-->B"
+        );
+    }
+}
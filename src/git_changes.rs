@@ -0,0 +1,313 @@
+use std::{collections::HashSet, io, path::Path};
+
+use git2::{Delta, Diff, DiffFindOptions, DiffOptions, Repository};
+use schemars::JsonSchema;
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::{
+    preview_validator::{
+        block_section, check_block, collect_mermaid_blocks, content_hash, count_errors,
+        BlockProgress, PreviewIssue, ValidationOptions,
+    },
+    renderer_registry::RendererRegistry,
+};
+
+/// What changed blocks are compared against.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChangeBase {
+    /// The index against `HEAD`, with file content read from the index; for
+    /// pre-commit hooks.
+    Staged,
+    /// The working tree, including untracked files, against this revision;
+    /// for branch and pull request checks.
+    Revision(String),
+}
+
+impl ChangeBase {
+    pub fn describe(&self) -> String {
+        match self {
+            ChangeBase::Staged => "staged changes".to_string(),
+            ChangeBase::Revision(revision) => format!("changes since {revision}"),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ChangesError {
+    #[error("{path} is not inside a git repository: {source}")]
+    NotARepository { path: String, source: git2::Error },
+    #[error("The repository at {0} has no working tree")]
+    Bare(String),
+    #[error("Unknown revision {revision}: {source}")]
+    Revision {
+        revision: String,
+        source: git2::Error,
+    },
+    #[error("Failed to read the git repository: {0}")]
+    Git(#[from] git2::Error),
+    #[error("Failed to read {path}: {source}")]
+    Read { path: String, source: io::Error },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum BlockChange {
+    /// No block with the same id existed before.
+    New,
+    /// The block with the same id had other content.
+    Modified,
+}
+
+/// A changed markdown file with its new content and the blocks whose
+/// content is new or modified. Unchanged blocks, including moved ones, are
+/// left out.
+#[derive(Debug, Clone)]
+pub struct ChangedFile {
+    /// Path relative to the repository root, with `/` separators.
+    pub path: String,
+    pub markdown: String,
+    pub blocks: Vec<(u32, BlockChange)>,
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ChangedBlockReport {
+    pub path: String,
+    pub block_index: u32,
+    pub id: String,
+    pub change: BlockChange,
+    pub start_line: u32,
+    pub end_line: u32,
+    pub valid: bool,
+    pub issues: Vec<PreviewIssue>,
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ChangedReport {
+    pub base: String,
+    pub target: String,
+    pub changed_files: u32,
+    pub checked_blocks: u32,
+    pub error_count: u32,
+    pub valid: bool,
+    pub blocks: Vec<ChangedBlockReport>,
+}
+
+/// Markdown files changed in the repository containing `path`, relative to
+/// `base`, with the blocks that are new or modified. Deleted files and
+/// files without changed blocks are left out.
+pub fn changed_markdown_files(
+    path: &Path,
+    base: &ChangeBase,
+) -> Result<Vec<ChangedFile>, ChangesError> {
+    let repo = Repository::discover(path).map_err(|source| ChangesError::NotARepository {
+        path: path.display().to_string(),
+        source,
+    })?;
+    let workdir = repo
+        .workdir()
+        .ok_or_else(|| ChangesError::Bare(repo.path().display().to_string()))?
+        .to_path_buf();
+
+    let mut options = DiffOptions::new();
+    let mut diff = match base {
+        ChangeBase::Staged => {
+            let head = repo.head().ok().and_then(|head| head.peel_to_tree().ok());
+            repo.diff_tree_to_index(head.as_ref(), None, Some(&mut options))?
+        }
+        ChangeBase::Revision(revision) => {
+            let tree = repo
+                .revparse_single(revision)
+                .and_then(|object| object.peel_to_tree())
+                .map_err(|source| ChangesError::Revision {
+                    revision: revision.clone(),
+                    source,
+                })?;
+            options.include_untracked(true).recurse_untracked_dirs(true);
+            repo.diff_tree_to_workdir_with_index(Some(&tree), Some(&mut options))?
+        }
+    };
+    diff.find_similar(Some(DiffFindOptions::new().renames(true)))?;
+
+    let index = repo.index()?;
+    let mut files = Vec::new();
+    for (new_path, old_blob) in markdown_deltas(&diff) {
+        let markdown =
+            match base {
+                ChangeBase::Staged => {
+                    let Some(entry) = index.get_path(Path::new(&new_path), 0) else {
+                        continue;
+                    };
+                    String::from_utf8_lossy(repo.find_blob(entry.id)?.content()).into_owned()
+                }
+                ChangeBase::Revision(_) => std::fs::read_to_string(workdir.join(&new_path))
+                    .map_err(|source| ChangesError::Read {
+                        path: new_path.clone(),
+                        source,
+                    })?,
+            };
+        let old = match old_blob {
+            Some(id) => String::from_utf8_lossy(repo.find_blob(id)?.content()).into_owned(),
+            None => String::new(),
+        };
+        let blocks = changed_blocks(&old, &markdown);
+        if !blocks.is_empty() {
+            files.push(ChangedFile {
+                path: new_path,
+                markdown,
+                blocks,
+            });
+        }
+    }
+    Ok(files)
+}
+
+/// Validates the changed blocks of `files` and reports issues for those
+/// blocks only.
+pub async fn validate_changed_blocks(
+    base: &ChangeBase,
+    files: &[ChangedFile],
+    options: &ValidationOptions,
+    renderers: &RendererRegistry,
+) -> ChangedReport {
+    let mut blocks = Vec::new();
+    let total = files.iter().map(|file| file.blocks.len() as u32).sum();
+    let mut completed = 0;
+    for file in files {
+        let (locations, _) = collect_mermaid_blocks(&file.markdown);
+        for &(index, change) in &file.blocks {
+            completed += 1;
+            let Some(location) = locations.iter().find(|block| block.index == index) else {
+                continue;
+            };
+            // File-level issues, such as an unclosed fence elsewhere, belong
+            // to no changed block, but a missing renderer or a cancellation
+            // fails the block. Blocks after a cancellation are not rendered.
+            let (issues, _) = check_block(location, options, renderers).await;
+            blocks.push(ChangedBlockReport {
+                path: file.path.clone(),
+                block_index: index,
+                id: location.id.clone(),
+                change,
+                start_line: location.start_line,
+                end_line: location.end_line,
+                valid: count_errors(&issues) == 0,
+                issues,
+            });
+            options.control.report(BlockProgress {
+                block_index: index,
+                completed,
+                total,
+            });
+        }
+    }
+
+    let error_count = blocks
+        .iter()
+        .map(|block| count_errors(&block.issues))
+        .sum::<u32>();
+    ChangedReport {
+        base: base.describe(),
+        target: options.target.as_str().to_string(),
+        changed_files: files.len() as u32,
+        checked_blocks: blocks.len() as u32,
+        error_count,
+        valid: error_count == 0,
+        blocks,
+    }
+}
+
+/// New path and old blob of every added, modified, renamed or copied
+/// markdown file in `diff`.
+fn markdown_deltas(diff: &Diff<'_>) -> Vec<(String, Option<git2::Oid>)> {
+    diff.deltas()
+        .filter(|delta| {
+            matches!(
+                delta.status(),
+                Delta::Added
+                    | Delta::Modified
+                    | Delta::Renamed
+                    | Delta::Copied
+                    | Delta::Untracked
+                    | Delta::Typechange
+            )
+        })
+        .filter_map(|delta| {
+            let path = delta.new_file().path()?;
+            let is_markdown = path
+                .extension()
+                .and_then(|extension| extension.to_str())
+                .is_some_and(|extension| {
+                    extension.eq_ignore_ascii_case("md")
+                        || extension.eq_ignore_ascii_case("markdown")
+                });
+            if !is_markdown {
+                return None;
+            }
+            let old = delta.old_file();
+            let old_blob = (old.exists() && !old.id().is_zero()).then(|| old.id());
+            let path = path
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            Some((path, old_blob))
+        })
+        .collect()
+}
+
+/// Blocks of `new` whose content appears in no block of `old`, marked
+/// modified when `old` had a block under the same headings whose content
+/// is gone from `new`.
+fn changed_blocks(old: &str, new: &str) -> Vec<(u32, BlockChange)> {
+    let (old_blocks, _) = collect_mermaid_blocks(old);
+    let (new_blocks, _) = collect_mermaid_blocks(new);
+    let old_hashes = old_blocks
+        .iter()
+        .map(|block| content_hash(&block.content))
+        .collect::<HashSet<_>>();
+    let new_hashes = new_blocks
+        .iter()
+        .map(|block| content_hash(&block.content))
+        .collect::<HashSet<_>>();
+    let mut edited = old_blocks
+        .iter()
+        .filter(|block| !new_hashes.contains(&content_hash(&block.content)))
+        .map(|block| block_section(&block.id))
+        .collect::<Vec<_>>();
+    new_blocks
+        .iter()
+        .filter(|block| !old_hashes.contains(&content_hash(&block.content)))
+        .map(|block| {
+            let section = block_section(&block.id);
+            let change = match edited.iter().position(|old| *old == section) {
+                Some(position) => {
+                    edited.remove(position);
+                    BlockChange::Modified
+                }
+                None => BlockChange::New,
+            };
+            (block.index, change)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_new_and_modified_blocks_are_changed() {
+        let old =
+            "# A\n```mermaid\ngraph TD\n  A-->B\n```\n# B\n```mermaid\ngraph TD\n  C-->D\n```\n";
+        // A's block moved under B unchanged, B's block changed, C is new.
+        let new = "# B\n```mermaid\ngraph TD\n  C-->E\n```\n```mermaid\ngraph TD\n  A-->B\n```\n# C\n```mermaid\ngraph TD\n  F-->G\n```\n";
+        assert_eq!(
+            changed_blocks(old, new),
+            vec![(1, BlockChange::Modified), (3, BlockChange::New)]
+        );
+        assert!(changed_blocks(new, new).is_empty());
+    }
+}
@@ -0,0 +1,272 @@
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    io,
+    sync::{Arc, Mutex},
+};
+
+use axum::{
+    extract::{Query, Request, State},
+    http::{header, StatusCode},
+    middleware::{self, Next},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    routing::{get, post},
+    Json, Router,
+};
+use futures::{stream, Stream, StreamExt};
+use rmcp::{
+    model::ClientJsonRpcMessage,
+    transport::streamable_http_server::{
+        session::local::LocalSessionManager, StreamableHttpServerConfig, StreamableHttpService,
+    },
+    ServiceExt,
+};
+use serde::Deserialize;
+use tokio::{net::TcpListener, sync::mpsc};
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_util::sync::{CancellationToken, PollSender};
+
+use crate::{
+    logging::{log, LogLevel},
+    server::MermaidServer,
+};
+
+/// Streamable HTTP endpoint.
+pub const MCP_PATH: &str = "/mcp";
+/// Legacy HTTP+SSE endpoints for clients without streamable HTTP support.
+pub const SSE_PATH: &str = "/sse";
+pub const MESSAGE_PATH: &str = "/message";
+
+const SSE_CHANNEL_CAPACITY: usize = 64;
+
+#[derive(Debug, Clone, Default)]
+pub struct HttpOptions {
+    /// Token clients must send as `Authorization: Bearer <token>`; `None`
+    /// accepts every request.
+    pub bearer_token: Option<String>,
+    /// Browser origins allowed besides loopback ones, e.g.
+    /// `https://app.example.com`.
+    pub allowed_origins: Vec<String>,
+}
+
+type SseSessions = Arc<Mutex<HashMap<String, mpsc::Sender<ClientJsonRpcMessage>>>>;
+
+#[derive(Clone)]
+struct SseState {
+    server: MermaidServer,
+    sessions: SseSessions,
+    shutdown: CancellationToken,
+}
+
+/// Builds the HTTP routes. Every session gets `server.session()`, so all
+/// sessions share its renderers and caches but keep their own roots.
+/// Cancelling `shutdown` closes every session.
+pub fn router(server: MermaidServer, options: &HttpOptions, shutdown: CancellationToken) -> Router {
+    let streamable = StreamableHttpService::new(
+        {
+            let server = server.clone();
+            move || Ok(server.session())
+        },
+        Arc::new(LocalSessionManager::default()),
+        StreamableHttpServerConfig {
+            cancellation_token: shutdown.child_token(),
+            ..Default::default()
+        },
+    );
+    let state = SseState {
+        server,
+        sessions: SseSessions::default(),
+        shutdown,
+    };
+
+    let router = Router::new()
+        .route_service(MCP_PATH, streamable)
+        .route(SSE_PATH, get(sse_connect))
+        .route(MESSAGE_PATH, post(sse_message))
+        .with_state(state);
+    let router = match &options.bearer_token {
+        Some(token) => router.layer(middleware::from_fn_with_state(
+            Arc::<str>::from(token.as_str()),
+            require_bearer,
+        )),
+        None => router,
+    };
+    let origins = options
+        .allowed_origins
+        .iter()
+        .map(|origin| origin.trim_end_matches('/').to_ascii_lowercase())
+        .collect::<Vec<_>>();
+    router.layer(middleware::from_fn_with_state(
+        Arc::new(origins),
+        require_allowed_origin,
+    ))
+}
+
+/// Serves MCP over HTTP on `listener` until `shutdown` is cancelled, then
+/// closes open sessions and waits for in-flight requests to finish.
+pub async fn serve_http(
+    listener: TcpListener,
+    server: MermaidServer,
+    options: &HttpOptions,
+    shutdown: CancellationToken,
+) -> io::Result<()> {
+    let app = router(server, options, shutdown.clone());
+    axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown.cancelled_owned())
+        .await
+}
+
+/// Removes a legacy SSE session and stops its server once the event stream
+/// is dropped, i.e. when the client disconnects.
+struct SseSessionGuard {
+    id: String,
+    sessions: SseSessions,
+    cancel: CancellationToken,
+}
+
+impl Drop for SseSessionGuard {
+    fn drop(&mut self) {
+        self.cancel.cancel();
+        if let Ok(mut sessions) = self.sessions.lock() {
+            sessions.remove(&self.id);
+        }
+    }
+}
+
+async fn sse_connect(
+    State(state): State<SseState>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let id = uuid::Uuid::new_v4().simple().to_string();
+    let (client_tx, client_rx) = mpsc::channel(SSE_CHANNEL_CAPACITY);
+    let (server_tx, server_rx) = mpsc::channel(SSE_CHANNEL_CAPACITY);
+    if let Ok(mut sessions) = state.sessions.lock() {
+        sessions.insert(id.clone(), client_tx);
+    }
+
+    let cancel = state.shutdown.child_token();
+    let transport = (PollSender::new(server_tx), ReceiverStream::new(client_rx));
+    let server = state.server.session();
+    let session_cancel = cancel.clone();
+    tokio::spawn(async move {
+        match server.serve_with_ct(transport, session_cancel).await {
+            Ok(running) => {
+                let _ = running.waiting().await;
+            }
+            Err(err) => log(
+                LogLevel::Error,
+                "transport",
+                serde_json::json!({
+                    "event": "sseInitializeFailed",
+                    "error": err.to_string(),
+                }),
+            ),
+        }
+    });
+
+    let endpoint = Event::default()
+        .event("endpoint")
+        .data(format!("{MESSAGE_PATH}?sessionId={id}"));
+    let guard = SseSessionGuard {
+        id,
+        sessions: state.sessions.clone(),
+        cancel: cancel.clone(),
+    };
+    let messages = ReceiverStream::new(server_rx).map(move |message| {
+        let _guard = &guard;
+        let data = serde_json::to_string(&message).unwrap_or_default();
+        Ok(Event::default().event("message").data(data))
+    });
+    let events = stream::once(async move { Ok(endpoint) })
+        .chain(messages)
+        .take_until(cancel.cancelled_owned());
+    Sse::new(events).keep_alive(KeepAlive::default())
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MessageQuery {
+    session_id: String,
+}
+
+async fn sse_message(
+    State(state): State<SseState>,
+    Query(query): Query<MessageQuery>,
+    Json(message): Json<ClientJsonRpcMessage>,
+) -> StatusCode {
+    let sender = state
+        .sessions
+        .lock()
+        .ok()
+        .and_then(|sessions| sessions.get(&query.session_id).cloned());
+    let Some(sender) = sender else {
+        return StatusCode::NOT_FOUND;
+    };
+    match sender.send(message).await {
+        Ok(()) => StatusCode::ACCEPTED,
+        Err(_) => StatusCode::GONE,
+    }
+}
+
+async fn require_bearer(State(token): State<Arc<str>>, request: Request, next: Next) -> Response {
+    let authorized = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|given| constant_time_eq(given.as_bytes(), token.as_bytes()));
+    if authorized {
+        next.run(request).await
+    } else {
+        (
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, "Bearer")],
+        )
+            .into_response()
+    }
+}
+
+/// Refuses requests from browser pages on other origins, so that a site
+/// rebinding its DNS name to this host cannot call the server. Requests
+/// without `Origin` do not come from a browser page and pass.
+async fn require_allowed_origin(
+    State(allowed): State<Arc<Vec<String>>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let allowed = match request.headers().get(header::ORIGIN) {
+        None => true,
+        Some(origin) => origin.to_str().is_ok_and(|origin| {
+            is_loopback_origin(origin)
+                || allowed.contains(&origin.trim_end_matches('/').to_ascii_lowercase())
+        }),
+    };
+    if allowed {
+        next.run(request).await
+    } else {
+        (StatusCode::FORBIDDEN, "Origin not allowed").into_response()
+    }
+}
+
+fn is_loopback_origin(origin: &str) -> bool {
+    let Ok(url) = url::Url::parse(origin) else {
+        return false;
+    };
+    match url.host() {
+        Some(url::Host::Domain(domain)) => domain.eq_ignore_ascii_case("localhost"),
+        Some(url::Host::Ipv4(address)) => address.is_loopback(),
+        Some(url::Host::Ipv6(address)) => address.is_loopback(),
+        None => false,
+    }
+}
+
+fn constant_time_eq(left: &[u8], right: &[u8]) -> bool {
+    left.len() == right.len()
+        && left
+            .iter()
+            .zip(right)
+            .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
@@ -0,0 +1,171 @@
+use std::path::Path;
+
+use crate::{
+    command_line::{is_diagram_path, issue_lines, scan_diagram, CheckReport},
+    preview_target::PreviewTarget,
+    preview_validator::{scan_markdown_for_mermaid, MermaidBlockInfo, PreviewIssue},
+};
+
+/// One test case: a Mermaid block, or the file itself for issues outside
+/// any block.
+struct TestCase {
+    name: String,
+    /// First file line of the block.
+    line: Option<u32>,
+    /// Issues attributed to the case; errors fail it.
+    issues: Vec<PreviewIssue>,
+}
+
+/// JUnit XML of `report`: a test suite per file and a test case per
+/// Mermaid block, named by its stable id so history survives edits above
+/// it, with the block's first file line in `line`. Blocks with errors
+/// fail with their diagnostics; warnings go to the case's `system-out`.
+/// Issues outside any block, such as a read error, get a `file` case.
+pub fn junit_xml(report: &CheckReport) -> String {
+    let suites = report
+        .files
+        .iter()
+        .map(|file| {
+            let cases = test_cases(report, &file.path, &file.result.issues);
+            (file.path.as_str(), cases)
+        })
+        .collect::<Vec<_>>();
+    let tests = suites.iter().map(|(_, cases)| cases.len()).sum::<usize>();
+    let failures = suites
+        .iter()
+        .flat_map(|(_, cases)| cases)
+        .filter(|case| case.failed())
+        .count();
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str(&format!(
+        "<testsuites name=\"{}\" tests=\"{tests}\" failures=\"{failures}\" errors=\"0\">\n",
+        env!("CARGO_PKG_NAME")
+    ));
+    for (path, cases) in &suites {
+        let failures = cases.iter().filter(|case| case.failed()).count();
+        xml.push_str(&format!(
+            "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{failures}\" errors=\"0\">\n",
+            escape(path),
+            cases.len()
+        ));
+        for case in cases {
+            case.write(path, &mut xml);
+        }
+        xml.push_str("  </testsuite>\n");
+    }
+    xml.push_str("</testsuites>");
+    xml
+}
+
+impl TestCase {
+    fn failed(&self) -> bool {
+        self.issues.iter().any(|issue| issue.severity == "error")
+    }
+
+    fn write(&self, path: &str, xml: &mut String) {
+        let mut open = format!(
+            "    <testcase classname=\"{path}\" name=\"{}\" file=\"{path}\"",
+            escape(&self.name),
+            path = escape(path)
+        );
+        if let Some(line) = self.line {
+            open.push_str(&format!(" line=\"{line}\""));
+        }
+        if self.issues.is_empty() {
+            xml.push_str(&open);
+            xml.push_str("/>\n");
+            return;
+        }
+        xml.push_str(&open);
+        xml.push_str(">\n");
+        let (errors, others): (Vec<_>, Vec<_>) = self
+            .issues
+            .iter()
+            .cloned()
+            .partition(|issue| issue.severity == "error");
+        if let Some(first) = errors.first() {
+            xml.push_str(&format!(
+                "      <failure message=\"{}\" type=\"{}\">{}</failure>\n",
+                escape(&first.message),
+                escape(&first.code),
+                escape(&issue_lines(path, &errors).join("\n"))
+            ));
+        }
+        if !others.is_empty() {
+            xml.push_str(&format!(
+                "      <system-out>{}</system-out>\n",
+                escape(&issue_lines(path, &others).join("\n"))
+            ));
+        }
+        xml.push_str("    </testcase>\n");
+    }
+}
+
+fn test_cases(report: &CheckReport, path: &str, issues: &[PreviewIssue]) -> Vec<TestCase> {
+    let mut blocks = report
+        .sources
+        .get(path)
+        .map(|source| file_blocks(path, source, &report.target))
+        .unwrap_or_default();
+    if let Some(checked) = report.checked_blocks.get(path) {
+        blocks.retain(|block| checked.contains(&block.index));
+    }
+
+    let mut cases = blocks
+        .iter()
+        .map(|block| TestCase {
+            name: block.id.clone(),
+            line: Some(block.start_line),
+            issues: issues
+                .iter()
+                .filter(|issue| issue.block_index == Some(block.index))
+                .cloned()
+                .collect(),
+        })
+        .collect::<Vec<_>>();
+    let file_issues = issues
+        .iter()
+        .filter(|issue| {
+            issue
+                .block_index
+                .is_none_or(|index| !blocks.iter().any(|block| block.index == index))
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+    if !file_issues.is_empty() {
+        cases.push(TestCase {
+            name: "file".to_string(),
+            line: None,
+            issues: file_issues,
+        });
+    }
+    cases
+}
+
+/// The blocks of a checked file, with lines as reported for it.
+fn file_blocks(path: &str, source: &str, target: &str) -> Vec<MermaidBlockInfo> {
+    if is_diagram_path(Path::new(path)) {
+        scan_diagram(source, PreviewTarget::parse(target).unwrap_or_default()).blocks
+    } else {
+        scan_markdown_for_mermaid(source).blocks
+    }
+}
+
+/// Escapes XML markup and drops characters XML 1.0 cannot carry.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => escaped.push(ch),
+            ch if ch < ' ' => {}
+            ch => escaped.push(ch),
+        }
+    }
+    escaped
+}
@@ -1,4 +1,32 @@
+pub mod block_edit;
+pub mod block_selector;
+pub mod block_source;
 pub mod cli_runner;
+pub mod command_line;
+pub mod diagram_config;
+pub mod diagram_resources;
+pub mod diagram_status;
+pub mod directory;
+pub mod documents;
+pub mod feature_detection;
+pub mod file_write;
+pub mod fix_suggestion;
+pub mod git_changes;
+pub mod http_transport;
+pub mod junit;
+pub mod logging;
+pub mod lsp;
+pub mod modernize;
+pub mod pagination;
+pub mod preview_target;
 pub mod preview_validator;
+pub mod prompts;
+pub mod quick_fix;
+pub mod renderer_registry;
 pub mod response_builder;
+pub mod sarif;
 pub mod server;
+pub mod subscriptions;
+pub mod watch;
+pub mod workflow_commands;
+pub mod workspace_roots;
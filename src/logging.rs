@@ -0,0 +1,242 @@
+use std::{
+    env, fmt,
+    future::Future,
+    sync::{Arc, Mutex, OnceLock},
+};
+
+use rmcp::model::{LoggingLevel, LoggingMessageNotificationParam};
+use serde_json::Value;
+use tokio::sync::mpsc;
+
+/// Minimum level written to stderr (default: info).
+pub const LOG_LEVEL_ENV: &str = "MERMAID_LOG_LEVEL";
+
+/// Syslog severities used by MCP logging, least severe first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LogLevel {
+    Debug,
+    Info,
+    Notice,
+    Warning,
+    Error,
+    Critical,
+    Alert,
+    Emergency,
+}
+
+impl LogLevel {
+    pub const ALL: [LogLevel; 8] = [
+        LogLevel::Debug,
+        LogLevel::Info,
+        LogLevel::Notice,
+        LogLevel::Warning,
+        LogLevel::Error,
+        LogLevel::Critical,
+        LogLevel::Alert,
+        LogLevel::Emergency,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LogLevel::Debug => "debug",
+            LogLevel::Info => "info",
+            LogLevel::Notice => "notice",
+            LogLevel::Warning => "warning",
+            LogLevel::Error => "error",
+            LogLevel::Critical => "critical",
+            LogLevel::Alert => "alert",
+            LogLevel::Emergency => "emergency",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<LogLevel> {
+        let value = value.trim();
+        LogLevel::ALL
+            .into_iter()
+            .find(|level| level.as_str().eq_ignore_ascii_case(value))
+    }
+}
+
+impl fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl From<LoggingLevel> for LogLevel {
+    fn from(level: LoggingLevel) -> Self {
+        match level {
+            LoggingLevel::Debug => LogLevel::Debug,
+            LoggingLevel::Info => LogLevel::Info,
+            LoggingLevel::Notice => LogLevel::Notice,
+            LoggingLevel::Warning => LogLevel::Warning,
+            LoggingLevel::Error => LogLevel::Error,
+            LoggingLevel::Critical => LogLevel::Critical,
+            LoggingLevel::Alert => LogLevel::Alert,
+            LoggingLevel::Emergency => LogLevel::Emergency,
+        }
+    }
+}
+
+impl From<LogLevel> for LoggingLevel {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Debug => LoggingLevel::Debug,
+            LogLevel::Info => LoggingLevel::Info,
+            LogLevel::Notice => LoggingLevel::Notice,
+            LogLevel::Warning => LoggingLevel::Warning,
+            LogLevel::Error => LoggingLevel::Error,
+            LogLevel::Critical => LoggingLevel::Critical,
+            LogLevel::Alert => LoggingLevel::Alert,
+            LogLevel::Emergency => LoggingLevel::Emergency,
+        }
+    }
+}
+
+/// One structured log event. `data` is a JSON object with an `event` field.
+#[derive(Debug, Clone)]
+pub struct LogRecord {
+    pub level: LogLevel,
+    pub logger: &'static str,
+    pub data: Value,
+}
+
+impl From<LogRecord> for LoggingMessageNotificationParam {
+    fn from(record: LogRecord) -> Self {
+        LoggingMessageNotificationParam {
+            level: record.level.into(),
+            logger: Some(record.logger.to_string()),
+            data: record.data,
+        }
+    }
+}
+
+/// The log destination of one MCP session: the level set with
+/// `logging/setLevel` and a channel to the task sending
+/// `notifications/message`.
+#[derive(Debug)]
+pub struct SessionLog {
+    level: Mutex<LogLevel>,
+    sender: Mutex<Option<mpsc::UnboundedSender<LogRecord>>>,
+}
+
+impl Default for SessionLog {
+    fn default() -> Self {
+        SessionLog {
+            level: Mutex::new(LogLevel::Info),
+            sender: Mutex::new(None),
+        }
+    }
+}
+
+tokio::task_local! {
+    static SESSION_LOG: Arc<SessionLog>;
+}
+
+impl SessionLog {
+    pub fn level(&self) -> LogLevel {
+        self.level
+            .lock()
+            .map(|level| *level)
+            .unwrap_or(LogLevel::Info)
+    }
+
+    pub fn set_level(&self, level: LogLevel) {
+        if let Ok(mut current) = self.level.lock() {
+            *current = level;
+        }
+    }
+
+    /// Sends the session's events to `sender` from now on.
+    pub fn connect(&self, sender: mpsc::UnboundedSender<LogRecord>) {
+        if let Ok(mut current) = self.sender.lock() {
+            *current = Some(sender);
+        }
+    }
+
+    fn forward(&self, record: LogRecord) {
+        if record.level < self.level() {
+            return;
+        }
+        if let Some(sender) = self.sender.lock().ok().and_then(|sender| sender.clone()) {
+            let _ = sender.send(record);
+        }
+    }
+
+    /// Runs `future` with the events it logs forwarded to this session.
+    /// Work spawned onto other tasks logs to stderr only.
+    pub async fn scope<F: Future>(self: &Arc<Self>, future: F) -> F::Output {
+        SESSION_LOG.scope(self.clone(), future).await
+    }
+}
+
+static DEFAULT_STDERR_LEVEL: OnceLock<LogLevel> = OnceLock::new();
+
+/// Sets the stderr level used when `MERMAID_LOG_LEVEL` is unset, e.g. to
+/// keep command-line output quiet. Has no effect once an event was logged.
+pub fn set_default_stderr_level(level: LogLevel) {
+    let _ = DEFAULT_STDERR_LEVEL.set(level);
+}
+
+fn stderr_level() -> LogLevel {
+    static LEVEL: OnceLock<LogLevel> = OnceLock::new();
+    *LEVEL.get_or_init(|| {
+        env::var(LOG_LEVEL_ENV)
+            .ok()
+            .and_then(|value| LogLevel::parse(&value))
+            .or_else(|| DEFAULT_STDERR_LEVEL.get().copied())
+            .unwrap_or(LogLevel::Info)
+    })
+}
+
+/// Logs `data` to stderr when `level` reaches `MERMAID_LOG_LEVEL`, and to
+/// the MCP session running the current task when it reaches that session's
+/// level. Stdout stays reserved for the stdio transport.
+pub fn log(level: LogLevel, logger: &'static str, data: Value) {
+    if level >= stderr_level() {
+        eprintln!("[{level}] {logger}: {data}");
+    }
+    let _ = SESSION_LOG.try_with(|session| {
+        session.forward(LogRecord {
+            level,
+            logger,
+            data,
+        })
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn parse_levels_in_severity_order() {
+        assert_eq!(LogLevel::parse("Warning"), Some(LogLevel::Warning));
+        assert_eq!(LogLevel::parse("verbose"), None);
+        assert!(LogLevel::Debug < LogLevel::Info);
+        assert!(LogLevel::Error < LogLevel::Emergency);
+    }
+
+    #[tokio::test]
+    async fn scoped_events_reach_the_session_at_its_level() {
+        let session = Arc::new(SessionLog::default());
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        session.connect(sender);
+        session.set_level(LogLevel::Warning);
+
+        session
+            .scope(async {
+                log(LogLevel::Info, "test", json!({ "event": "quiet" }));
+                log(LogLevel::Error, "test", json!({ "event": "loud" }));
+            })
+            .await;
+        log(LogLevel::Error, "test", json!({ "event": "unscoped" }));
+
+        let record = receiver.try_recv().unwrap();
+        assert_eq!(record.level, LogLevel::Error);
+        assert_eq!(record.data["event"], "loud");
+        assert!(receiver.try_recv().is_err());
+    }
+}
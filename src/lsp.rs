@@ -0,0 +1,591 @@
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::io::{AsyncRead, AsyncWrite};
+use tower_lsp::{
+    jsonrpc::Result,
+    lsp_types::{
+        CodeAction, CodeActionKind, CodeActionOrCommand, CodeActionParams,
+        CodeActionProviderCapability, CodeActionResponse, Diagnostic, DiagnosticSeverity,
+        DidChangeTextDocumentParams, DidCloseTextDocumentParams, DidOpenTextDocumentParams,
+        DidSaveTextDocumentParams, Hover, HoverContents, HoverParams, HoverProviderCapability,
+        InitializeParams, InitializeResult, InitializedParams, MarkupContent, MarkupKind,
+        NumberOrString, Position, PositionEncodingKind, Range, ServerCapabilities, ServerInfo,
+        TextDocumentContentChangeEvent, TextDocumentSyncCapability, TextDocumentSyncKind,
+        TextDocumentSyncOptions, TextDocumentSyncSaveOptions, Url, WorkspaceEdit,
+    },
+    Client, LanguageServer, LspService, Server,
+};
+
+use crate::{
+    command_line::{fence_diagram, is_diagram_path, unfence_lines},
+    preview_target::PreviewTarget,
+    preview_validator::{
+        collect_mermaid_blocks, content_hash, render_block, renderer_unavailable_issue,
+        renderer_version_fallback_issue, scan_markdown_for_targets, target_block_issues,
+        BlockCompatibility, MermaidBlock, MermaidBlockInfo, PreviewIssue, ValidationOptions,
+    },
+    quick_fix::{quick_fix, TextEdit},
+    renderer_registry::RendererRegistry,
+};
+
+/// How long edits must pause before a changed document is re-validated.
+pub const DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// `source` of every published diagnostic.
+const DIAGNOSTIC_SOURCE: &str = "mermaid";
+
+/// An open document.
+struct Document {
+    text: String,
+    version: i32,
+    /// Bumped on every edit; validations of an older generation are dropped.
+    generation: u64,
+    /// `.mmd` and `.mermaid` documents hold one bare diagram.
+    is_diagram: bool,
+    /// Render issues of the last validation by block content hash, with
+    /// lines relative to the block's opening fence.
+    renders: HashMap<String, Vec<PreviewIssue>>,
+}
+
+struct State {
+    options: ValidationOptions,
+    renderers: RendererRegistry,
+    documents: Mutex<HashMap<Url, Document>>,
+}
+
+/// Language server publishing Mermaid diagnostics for markdown and bare
+/// diagram documents. Edits are re-validated after [`DEBOUNCE`], and only
+/// blocks whose content changed since the last validation are rendered.
+#[derive(Clone)]
+pub struct MermaidLanguageServer {
+    client: Client,
+    state: Arc<State>,
+}
+
+/// Serves the language server over `input` and `output` until the client
+/// exits.
+pub async fn serve_lsp<I, O>(
+    input: I,
+    output: O,
+    options: ValidationOptions,
+    renderers: RendererRegistry,
+) where
+    I: AsyncRead + Unpin,
+    O: AsyncWrite,
+{
+    let (service, socket) =
+        LspService::new(|client| MermaidLanguageServer::new(client, options, renderers));
+    Server::new(input, output, socket).serve(service).await;
+}
+
+impl MermaidLanguageServer {
+    pub fn new(client: Client, options: ValidationOptions, renderers: RendererRegistry) -> Self {
+        MermaidLanguageServer {
+            client,
+            state: Arc::new(State {
+                options,
+                renderers,
+                documents: Mutex::new(HashMap::new()),
+            }),
+        }
+    }
+
+    fn documents(&self) -> std::sync::MutexGuard<'_, HashMap<Url, Document>> {
+        self.state
+            .documents
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn generation(&self, uri: &Url) -> Option<u64> {
+        self.documents()
+            .get(uri)
+            .map(|document| document.generation)
+    }
+
+    /// Validates `uri` in the background, after [`DEBOUNCE`] when `debounce`
+    /// is set and no later edit arrived in the meantime.
+    fn schedule(&self, uri: Url, debounce: bool) {
+        let Some(generation) = self.generation(&uri) else {
+            return;
+        };
+        let server = self.clone();
+        tokio::spawn(async move {
+            if debounce {
+                tokio::time::sleep(DEBOUNCE).await;
+                if server.generation(&uri) != Some(generation) {
+                    return;
+                }
+            }
+            server.validate(uri).await;
+        });
+    }
+
+    /// Publishes the scanner's issues and each block's render result. Blocks
+    /// whose content rendered in the last validation reuse that result.
+    async fn validate(&self, uri: Url) {
+        let (text, version, generation, is_diagram, mut previous) = {
+            let documents = self.documents();
+            let Some(document) = documents.get(&uri) else {
+                return;
+            };
+            (
+                document.text.clone(),
+                document.version,
+                document.generation,
+                document.is_diagram,
+                document.renders.clone(),
+            )
+        };
+        let options = &self.state.options;
+        let markdown = document_markdown(&text, is_diagram);
+        let (blocks, mut issues) = collect_mermaid_blocks(&markdown);
+        if !blocks.is_empty() {
+            issues.extend(renderer_version_fallback_issue(
+                &self.state.renderers,
+                options,
+            ));
+        }
+        let renderer = match self
+            .state
+            .renderers
+            .resolve(options.mermaid_version.as_deref(), options.target)
+        {
+            Ok(renderer) => Some(renderer),
+            Err(message) => {
+                issues.push(renderer_unavailable_issue(message));
+                None
+            }
+        };
+
+        let mut renders: HashMap<String, Vec<PreviewIssue>> = HashMap::new();
+        for block in &blocks {
+            issues.extend(target_block_issues(block, options.target));
+            let Some(renderer) = renderer else {
+                continue;
+            };
+            let hash = content_hash(&block.content);
+            let rendered = match previous
+                .remove(&hash)
+                .or_else(|| renders.get(&hash).cloned())
+            {
+                Some(rendered) => rendered,
+                None => {
+                    if self.generation(&uri) != Some(generation) {
+                        return;
+                    }
+                    render_block(block, renderer, options)
+                        .await
+                        .into_iter()
+                        .map(|issue| relative_to_block(issue, block))
+                        .collect()
+                }
+            };
+            issues.extend(rendered.iter().cloned().map(|issue| at_block(issue, block)));
+            renders.insert(hash, rendered);
+        }
+        if is_diagram {
+            unfence_lines(&mut issues);
+        }
+
+        {
+            let mut documents = self.documents();
+            match documents.get_mut(&uri) {
+                Some(document) if document.generation == generation => {
+                    document.renders = renders;
+                }
+                _ => return,
+            }
+        }
+        let diagnostics = issues
+            .iter()
+            .map(|issue| diagnostic(&text, issue))
+            .collect();
+        self.client
+            .publish_diagnostics(uri, diagnostics, Some(version))
+            .await;
+    }
+}
+
+#[tower_lsp::async_trait]
+impl LanguageServer for MermaidLanguageServer {
+    async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+        Ok(InitializeResult {
+            capabilities: ServerCapabilities {
+                position_encoding: Some(PositionEncodingKind::UTF16),
+                text_document_sync: Some(TextDocumentSyncCapability::Options(
+                    TextDocumentSyncOptions {
+                        open_close: Some(true),
+                        change: Some(TextDocumentSyncKind::INCREMENTAL),
+                        save: Some(TextDocumentSyncSaveOptions::Supported(true)),
+                        ..Default::default()
+                    },
+                )),
+                hover_provider: Some(HoverProviderCapability::Simple(true)),
+                code_action_provider: Some(CodeActionProviderCapability::Simple(true)),
+                ..Default::default()
+            },
+            server_info: Some(ServerInfo {
+                name: env!("CARGO_PKG_NAME").to_string(),
+                version: Some(env!("CARGO_PKG_VERSION").to_string()),
+            }),
+        })
+    }
+
+    async fn initialized(&self, _: InitializedParams) {}
+
+    async fn shutdown(&self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn did_open(&self, params: DidOpenTextDocumentParams) {
+        let document = params.text_document;
+        let is_diagram =
+            document.language_id == "mermaid" || is_diagram_path(Path::new(document.uri.path()));
+        self.documents().insert(
+            document.uri.clone(),
+            Document {
+                text: document.text,
+                version: document.version,
+                generation: 0,
+                is_diagram,
+                renders: HashMap::new(),
+            },
+        );
+        self.schedule(document.uri, false);
+    }
+
+    async fn did_change(&self, params: DidChangeTextDocumentParams) {
+        let uri = params.text_document.uri;
+        {
+            let mut documents = self.documents();
+            let Some(document) = documents.get_mut(&uri) else {
+                return;
+            };
+            for change in params.content_changes {
+                apply_change(&mut document.text, change);
+            }
+            document.version = params.text_document.version;
+            document.generation += 1;
+        }
+        self.schedule(uri, true);
+    }
+
+    async fn did_save(&self, params: DidSaveTextDocumentParams) {
+        self.schedule(params.text_document.uri, false);
+    }
+
+    async fn did_close(&self, params: DidCloseTextDocumentParams) {
+        let uri = params.text_document.uri;
+        self.documents().remove(&uri);
+        self.client.publish_diagnostics(uri, Vec::new(), None).await;
+    }
+
+    async fn hover(&self, params: HoverParams) -> Result<Option<Hover>> {
+        let position = params.text_document_position_params;
+        let documents = self.documents();
+        let Some(document) = documents.get(&position.text_document.uri) else {
+            return Ok(None);
+        };
+        let target = self.state.options.target;
+        let markdown = document_markdown(&document.text, document.is_diagram);
+        // Lines of the fenced diagram are one below the document's.
+        let line = position.position.line + 1 + u32::from(document.is_diagram);
+        let scan = scan_markdown_for_targets(&markdown, target, &PreviewTarget::ALL);
+        let Some(block) = scan.blocks.iter().find(|block| {
+            (block.start_line == line && !document.is_diagram)
+                || header_line(&markdown, block) == Some(line)
+        }) else {
+            return Ok(None);
+        };
+        let compatibility = scan
+            .compatibility
+            .iter()
+            .find(|compatibility| compatibility.block_index == block.index);
+        let (blocks, _) = collect_mermaid_blocks(&markdown);
+        let parse_failed = blocks
+            .iter()
+            .find(|candidate| candidate.index == block.index)
+            .and_then(|candidate| document.renders.get(&content_hash(&candidate.content)))
+            .is_some_and(|rendered| {
+                rendered
+                    .iter()
+                    .any(|issue| issue.code == "mermaid_parse_error")
+            });
+
+        let line = position.position.line;
+        let text_line = document.text.lines().nth(line as usize).unwrap_or("");
+        Ok(Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: hover_text(block, compatibility, target, parse_failed),
+            }),
+            range: Some(Range::new(
+                Position::new(line, 0),
+                Position::new(line, utf16_len(text_line)),
+            )),
+        }))
+    }
+
+    async fn code_action(&self, params: CodeActionParams) -> Result<Option<CodeActionResponse>> {
+        let uri = params.text_document.uri;
+        let documents = self.documents();
+        let Some(document) = documents.get(&uri) else {
+            return Ok(None);
+        };
+        if document.is_diagram {
+            return Ok(None);
+        }
+        // Fixable issues come from the scanner, so they are computed on the
+        // current text rather than taken from a possibly stale validation.
+        let text = &document.text;
+        let scan = scan_markdown_for_targets(text, self.state.options.target, &[]);
+        let actions = scan
+            .issues
+            .iter()
+            .filter(|issue| {
+                issue.line.is_some_and(|line| {
+                    (params.range.start.line..=params.range.end.line).contains(&(line - 1))
+                })
+            })
+            .filter_map(|issue| {
+                let fix = quick_fix(text, issue)?;
+                let edits = fix.edits.iter().map(lsp_text_edit).collect();
+                Some(CodeActionOrCommand::CodeAction(CodeAction {
+                    title: fix.description,
+                    kind: Some(CodeActionKind::QUICKFIX),
+                    diagnostics: Some(vec![diagnostic(text, issue)]),
+                    edit: Some(WorkspaceEdit {
+                        changes: Some(HashMap::from([(uri.clone(), edits)])),
+                        ..Default::default()
+                    }),
+                    is_preferred: Some(true),
+                    ..Default::default()
+                }))
+            })
+            .collect::<Vec<_>>();
+        Ok(Some(actions))
+    }
+}
+
+/// The markdown validated for a document: the text itself, or a bare
+/// diagram wrapped in a fence.
+fn document_markdown(text: &str, is_diagram: bool) -> String {
+    if is_diagram {
+        fence_diagram(text)
+    } else {
+        text.to_string()
+    }
+}
+
+fn relative_to_block(mut issue: PreviewIssue, block: &MermaidBlock) -> PreviewIssue {
+    issue.line = issue.line.map(|line| line.saturating_sub(block.start_line));
+    issue.block_index = None;
+    issue
+}
+
+/// Places a [`relative_to_block`] issue back on `block`, on its opening
+/// fence when it has no line.
+fn at_block(mut issue: PreviewIssue, block: &MermaidBlock) -> PreviewIssue {
+    issue.line = Some(issue.line.unwrap_or(0) + block.start_line);
+    issue.block_index = Some(block.index);
+    issue
+}
+
+/// The line declaring the diagram type of `block`, if it has one.
+fn header_line(markdown: &str, block: &MermaidBlockInfo) -> Option<u32> {
+    let diagram_type = block.diagram_type.as_deref()?;
+    let content_lines = block.end_line.saturating_sub(block.start_line + 1) as usize;
+    markdown
+        .lines()
+        .enumerate()
+        .skip(block.start_line as usize)
+        .take(content_lines)
+        .find(|(_, line)| line.split_whitespace().next() == Some(diagram_type))
+        .map(|(idx, _)| idx as u32 + 1)
+}
+
+fn hover_text(
+    block: &MermaidBlockInfo,
+    compatibility: Option<&BlockCompatibility>,
+    target: PreviewTarget,
+    parse_failed: bool,
+) -> String {
+    let mut lines = vec![match &block.diagram_type {
+        Some(diagram_type) => format!(
+            "**{diagram_type}** diagram, block #{} `{}`",
+            block.index, block.id
+        ),
+        None => format!(
+            "Unknown diagram type, block #{} `{}`",
+            block.index, block.id
+        ),
+    }];
+    if let Some(version) = &block.min_mermaid_version {
+        lines.push(String::new());
+        lines.push(format!("Needs Mermaid {version} or newer."));
+    }
+    if let Some(compatibility) = compatibility {
+        lines.push(String::new());
+        lines.push("| Target | Mermaid | Renders |".to_string());
+        lines.push("|---|---|---|".to_string());
+        for entry in &compatibility.targets {
+            let mut issue_codes = entry.issue_codes.clone();
+            if parse_failed && entry.target == target {
+                issue_codes.push("mermaid_parse_error".to_string());
+            }
+            let status = if !issue_codes.is_empty() {
+                format!("no: {}", code_list(&issue_codes))
+            } else if !entry.warning_codes.is_empty() {
+                format!("yes, with {}", code_list(&entry.warning_codes))
+            } else {
+                "yes".to_string()
+            };
+            let name = if entry.target == target {
+                format!("**{}**", entry.target.display_name())
+            } else {
+                entry.target.display_name().to_string()
+            };
+            lines.push(format!("| {name} | {} | {status} |", entry.mermaid_version));
+        }
+    }
+    lines.join("\n")
+}
+
+fn code_list(codes: &[String]) -> String {
+    codes
+        .iter()
+        .map(|code| format!("`{code}`"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn diagnostic(text: &str, issue: &PreviewIssue) -> Diagnostic {
+    let severity = match issue.severity.as_str() {
+        "error" => DiagnosticSeverity::ERROR,
+        "warning" => DiagnosticSeverity::WARNING,
+        _ => DiagnosticSeverity::INFORMATION,
+    };
+    Diagnostic {
+        range: issue_range(text, issue),
+        severity: Some(severity),
+        code: Some(NumberOrString::String(issue.code.clone())),
+        source: Some(DIAGNOSTIC_SOURCE.to_string()),
+        message: issue.message.clone(),
+        ..Default::default()
+    }
+}
+
+/// The range of an issue: from its column, or the line's first
+/// non-blank character, to the end of the line. Issues without a line sit
+/// at the start of the document.
+fn issue_range(text: &str, issue: &PreviewIssue) -> Range {
+    let Some(line) = issue.line.and_then(|line| line.checked_sub(1)) else {
+        return Range::default();
+    };
+    let content = text.lines().nth(line as usize).unwrap_or("");
+    let start = match issue.column {
+        Some(column) => utf16_column(content, column.saturating_sub(1) as usize),
+        None => utf16_len(&content[..content.len() - content.trim_start().len()]),
+    };
+    let end = utf16_len(content.trim_end()).max(start);
+    Range::new(Position::new(line, start), Position::new(line, end))
+}
+
+/// A 1-based [`TextEdit`] as a 0-based LSP edit; both count UTF-16 units.
+fn lsp_text_edit(edit: &TextEdit) -> tower_lsp::lsp_types::TextEdit {
+    tower_lsp::lsp_types::TextEdit {
+        range: Range::new(
+            Position::new(edit.start_line - 1, edit.start_column - 1),
+            Position::new(edit.end_line - 1, edit.end_column - 1),
+        ),
+        new_text: edit.new_text.clone(),
+    }
+}
+
+fn apply_change(text: &mut String, change: TextDocumentContentChangeEvent) {
+    match change.range {
+        Some(range) => {
+            let start = byte_offset(text, range.start);
+            let end = byte_offset(text, range.end).max(start);
+            text.replace_range(start..end, &change.text);
+        }
+        None => *text = change.text,
+    }
+}
+
+/// Byte offset of an LSP position, whose character counts UTF-16 code
+/// units. Positions past the end of a line or the text clamp to it.
+fn byte_offset(text: &str, position: Position) -> usize {
+    let mut line_start = 0;
+    for _ in 0..position.line {
+        match text[line_start..].find('\n') {
+            Some(newline) => line_start += newline + 1,
+            None => return text.len(),
+        }
+    }
+    let line = &text[line_start..];
+    let line = &line[..line.find('\n').unwrap_or(line.len())];
+    let line = line.strip_suffix('\r').unwrap_or(line);
+    let mut units = 0;
+    for (offset, ch) in line.char_indices() {
+        if units >= position.character {
+            return line_start + offset;
+        }
+        units += ch.len_utf16() as u32;
+    }
+    line_start + line.len()
+}
+
+/// UTF-16 length of the first `chars` characters of `line`.
+fn utf16_column(line: &str, chars: usize) -> u32 {
+    line.chars().take(chars).map(char::len_utf16).sum::<usize>() as u32
+}
+
+fn utf16_len(text: &str) -> u32 {
+    text.encode_utf16().count() as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn positions_count_utf16_units() {
+        let text = "# 𝄞 é\r\nA-->B\n";
+        assert_eq!(byte_offset(text, Position::new(0, 4)), 6);
+        assert_eq!(byte_offset(text, Position::new(0, 99)), 9);
+        assert_eq!(byte_offset(text, Position::new(1, 3)), 14);
+        assert_eq!(byte_offset(text, Position::new(5, 0)), text.len());
+
+        let mut edited = text.to_string();
+        apply_change(
+            &mut edited,
+            TextDocumentContentChangeEvent {
+                range: Some(Range::new(Position::new(0, 2), Position::new(0, 4))),
+                range_length: None,
+                text: "x".to_string(),
+            },
+        );
+        assert_eq!(edited, "# x é\r\nA-->B\n");
+
+        let issue = PreviewIssue {
+            severity: "error".to_string(),
+            code: "config_unknown_key".to_string(),
+            message: String::new(),
+            line: Some(1),
+            column: Some(5),
+            snippet: None,
+            block_index: None,
+        };
+        assert_eq!(
+            issue_range(text, &issue),
+            Range::new(Position::new(0, 5), Position::new(0, 6))
+        );
+    }
+}
@@ -1,9 +1,243 @@
-use mermaid_validator::server::MermaidServer;
+use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use mermaid_validator::{
+    cli_runner::{timeout_from_env, OutputFormat},
+    command_line::{
+        check_inputs, check_renderer, diagram_source, expand_inputs, format_for_output,
+        format_report, render_error_status, scan_diagram, scan_text, CheckInput, CheckReport,
+        ExitStatus, ReportFormat,
+    },
+    directory::DirectoryFilter,
+    git_changes::{changed_markdown_files, validate_changed_blocks, ChangeBase},
+    http_transport::{serve_http, HttpOptions, MCP_PATH, SSE_PATH},
+    logging::{set_default_stderr_level, LogLevel},
+    lsp::serve_lsp,
+    preview_target::PreviewTarget,
+    preview_validator::{scan_markdown_for_targets, ValidationOptions},
+    renderer_registry::RendererRegistry,
+    server::MermaidServer,
+    watch::watch_inputs,
+};
 use rmcp::{transport::stdio, ServiceExt};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+};
+use tokio_util::sync::CancellationToken;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Transport {
+    Stdio,
+    Http,
+}
+
+#[derive(Debug, Parser)]
+#[command(
+    version,
+    about = "Mermaid validator MCP server and command-line checker",
+    args_conflicts_with_subcommands = true
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[command(flatten)]
+    serve: ServeArgs,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Run the MCP server (the default).
+    Serve(ServeArgs),
+    /// Validate markdown and .mmd files, directories, globs or stdin (`-`).
+    /// Exits 1 when a diagram is invalid and 3 when the environment fails.
+    Check(CheckArgs),
+    /// Render one diagram to SVG or PNG.
+    Render(RenderArgs),
+    /// List the Mermaid blocks of a file and their rule issues, without
+    /// rendering.
+    Scan(ScanArgs),
+    /// Run a language server over stdio that publishes Mermaid diagnostics
+    /// for markdown and .mmd documents.
+    Lsp(LspArgs),
+    /// Watch files and directories, re-validating changed diagrams and
+    /// printing the ones that start failing or get fixed.
+    Watch(WatchArgs),
+}
+
+#[derive(Debug, Args)]
+struct ServeArgs {
+    /// Transport to serve MCP over.
+    #[arg(long, value_enum, default_value_t = Transport::Stdio)]
+    transport: Transport,
+    /// Address the HTTP transport listens on.
+    #[arg(long, default_value = "127.0.0.1:3000")]
+    bind: SocketAddr,
+    /// Bearer token HTTP clients must send.
+    #[arg(long, env = "MERMAID_HTTP_TOKEN", hide_env_values = true)]
+    auth_token: Option<String>,
+    /// Browser origin allowed to call the HTTP transport besides loopback
+    /// ones; repeatable.
+    #[arg(long = "allow-origin", value_name = "ORIGIN")]
+    allowed_origins: Vec<String>,
+}
+
+#[derive(Debug, Args)]
+struct TargetArgs {
+    /// Preview target whose rules decide validity.
+    #[arg(long, value_parser = parse_target, default_value = "github")]
+    target: PreviewTarget,
+    /// Installed Mermaid version to validate with (default: the target's).
+    #[arg(long)]
+    mermaid_version: Option<String>,
+}
+
+#[derive(Debug, Args)]
+struct CheckArgs {
+    /// Files, directories or globs to check; `-` reads stdin. Defaults to
+    /// the current directory, or the repository with --since/--staged.
+    inputs: Vec<String>,
+    #[command(flatten)]
+    target: TargetArgs,
+    /// Globs of files to check inside directories (default: *.md,
+    /// *.markdown, *.mmd and *.mermaid files).
+    #[arg(long)]
+    include: Vec<String>,
+    /// Globs of files and directories to skip.
+    #[arg(long)]
+    exclude: Vec<String>,
+    /// Name stdin is reported under; a .mmd name reads it as a bare diagram.
+    #[arg(long, default_value = "<stdin>")]
+    stdin_name: String,
+    /// Only check blocks changed since this git revision.
+    #[arg(long, conflicts_with = "staged")]
+    since: Option<String>,
+    /// Only check blocks changed in the git index, reading staged content.
+    #[arg(long)]
+    staged: bool,
+    /// Report format.
+    #[arg(long, value_enum, default_value_t = ReportFormat::Text)]
+    format: ReportFormat,
+}
+
+#[derive(Debug, Args)]
+struct RenderArgs {
+    /// Diagram, fenced block or markdown file to render; `-` reads stdin.
+    #[arg(required_unless_present = "diagram")]
+    input: Option<String>,
+    /// Diagram source instead of an input file; `\n` stands for a newline.
+    #[arg(long, conflicts_with = "input")]
+    diagram: Option<String>,
+    /// Output file; `-` writes to stdout.
+    #[arg(short, long, default_value = "-")]
+    output: String,
+    /// Image format (default: from the output extension, else png).
+    #[arg(long, value_enum)]
+    format: Option<ImageFormat>,
+    #[command(flatten)]
+    target: TargetArgs,
+}
+
+#[derive(Debug, Args)]
+struct ScanArgs {
+    /// Markdown or .mmd file to scan; `-` reads stdin.
+    file: String,
+    /// Preview target whose rules apply.
+    #[arg(long, value_parser = parse_target, default_value = "github")]
+    target: PreviewTarget,
+    /// Report format.
+    #[arg(long, value_enum, default_value_t = ReportFormat::Text)]
+    format: ReportFormat,
+}
+
+#[derive(Debug, Args)]
+struct LspArgs {
+    #[command(flatten)]
+    target: TargetArgs,
+}
+
+#[derive(Debug, Args)]
+struct WatchArgs {
+    /// Files, directories or globs to watch (default: the current
+    /// directory).
+    inputs: Vec<String>,
+    #[command(flatten)]
+    target: TargetArgs,
+    /// Globs of files to watch inside directories (default: *.md,
+    /// *.markdown, *.mmd and *.mermaid files).
+    #[arg(long)]
+    include: Vec<String>,
+    /// Globs of files and directories to skip.
+    #[arg(long)]
+    exclude: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ImageFormat {
+    Svg,
+    Png,
+}
+
+impl From<ImageFormat> for OutputFormat {
+    fn from(format: ImageFormat) -> Self {
+        match format {
+            ImageFormat::Svg => OutputFormat::Svg,
+            ImageFormat::Png => OutputFormat::Png,
+        }
+    }
+}
+
+fn parse_target(value: &str) -> Result<PreviewTarget, String> {
+    PreviewTarget::parse(value).ok_or_else(|| {
+        let names = PreviewTarget::ALL
+            .iter()
+            .map(PreviewTarget::as_str)
+            .collect::<Vec<_>>();
+        format!("unknown target, expected one of {}", names.join(", "))
+    })
+}
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let service = MermaidServer::new()
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let status = match cli.command {
+        None => serve(&cli.serve).await,
+        Some(Command::Serve(args)) => serve(&args).await,
+        Some(command) => {
+            set_default_stderr_level(LogLevel::Warning);
+            match command {
+                Command::Check(args) => check(args).await,
+                Command::Render(args) => render(args).await,
+                Command::Scan(args) => scan(args).await,
+                Command::Lsp(args) => lsp(args).await,
+                Command::Watch(args) => watch(args).await,
+                Command::Serve(_) => unreachable!("handled above"),
+            }
+        }
+    };
+    ExitCode::from(status.code())
+}
+
+async fn serve(args: &ServeArgs) -> ExitStatus {
+    let served = match args.transport {
+        Transport::Stdio => serve_stdio().await,
+        Transport::Http => serve_over_http(args).await,
+    };
+    match served {
+        Ok(()) => ExitStatus::Valid,
+        Err(err) => {
+            eprintln!("Error: {err}");
+            ExitStatus::Environment
+        }
+    }
+}
+
+async fn serve_stdio() -> Result<(), Box<dyn std::error::Error>> {
+    let service = MermaidServer::with_renderers(RendererRegistry::load_from_env().await)
         .serve(stdio())
         .await
         .inspect_err(|err| eprintln!("Error starting server: {err}"))?;
@@ -11,3 +245,245 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     service.waiting().await?;
     Ok(())
 }
+
+async fn serve_over_http(args: &ServeArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let listener = TcpListener::bind(args.bind)
+        .await
+        .inspect_err(|err| eprintln!("Error binding {}: {err}", args.bind))?;
+    let address = listener.local_addr()?;
+    eprintln!("Serving MCP at http://{address}{MCP_PATH} (legacy SSE at {SSE_PATH})");
+
+    let shutdown = CancellationToken::new();
+    tokio::spawn({
+        let shutdown = shutdown.clone();
+        async move {
+            let _ = tokio::signal::ctrl_c().await;
+            eprintln!("Shutting down");
+            shutdown.cancel();
+        }
+    });
+
+    let options = HttpOptions {
+        bearer_token: args.auth_token.clone(),
+        allowed_origins: args.allowed_origins.clone(),
+    };
+    let server = MermaidServer::with_renderers(RendererRegistry::load_from_env().await);
+    serve_http(listener, server, &options, shutdown).await?;
+    Ok(())
+}
+
+async fn check(args: CheckArgs) -> ExitStatus {
+    let renderers = RendererRegistry::load_from_env().await;
+    let options = ValidationOptions {
+        mermaid_version: args.target.mermaid_version.clone(),
+        ..ValidationOptions::new(args.target.target, timeout_from_env())
+    };
+    if let Err(message) = check_renderer(&options, &renderers) {
+        return environment_failure(&message);
+    }
+
+    let base = match (args.staged, args.since.clone()) {
+        (true, _) => Some(ChangeBase::Staged),
+        (false, Some(revision)) => Some(ChangeBase::Revision(revision)),
+        (false, None) => None,
+    };
+    let report = match base {
+        Some(base) => {
+            let path = match args.inputs.as_slice() {
+                [] => PathBuf::from("."),
+                [path] => PathBuf::from(path),
+                _ => return environment_failure("--since and --staged take one repository path"),
+            };
+            let changes = {
+                let base = base.clone();
+                tokio::task::spawn_blocking(move || changed_markdown_files(&path, &base)).await
+            };
+            let files = match changes {
+                Ok(Ok(files)) => files,
+                Ok(Err(err)) => return environment_failure(&err.to_string()),
+                Err(err) => return environment_failure(&err.to_string()),
+            };
+            let changes = validate_changed_blocks(&base, &files, &options, &renderers).await;
+            CheckReport::from_changes(options.target, changes, &files)
+        }
+        None => {
+            let filter = DirectoryFilter {
+                include: args.include,
+                exclude: args.exclude,
+            };
+            let inputs = if args.inputs.is_empty() {
+                vec![".".to_string()]
+            } else {
+                args.inputs
+            };
+            let inputs = match expand_inputs(&inputs, &args.stdin_name, &filter) {
+                Ok(inputs) => inputs,
+                Err(message) => return environment_failure(&message),
+            };
+            check_inputs(&inputs, &options, &renderers).await
+        }
+    };
+
+    println!("{}", format_report(&report, args.format, None));
+    report.exit_status()
+}
+
+async fn render(args: RenderArgs) -> ExitStatus {
+    let source = match (&args.diagram, args.input.as_deref()) {
+        (Some(diagram), _) => diagram.replace("\\n", "\n"),
+        (None, Some(input)) => match read_input(input).await {
+            Ok(source) => source,
+            Err(message) => return environment_failure(&message),
+        },
+        (None, None) => unreachable!("clap requires an input or --diagram"),
+    };
+    let diagram = match diagram_source(&source) {
+        Ok(diagram) => diagram,
+        Err(message) => return invalid(&message),
+    };
+    let renderers = RendererRegistry::load_from_env().await;
+    let renderer =
+        match renderers.resolve(args.target.mermaid_version.as_deref(), args.target.target) {
+            Ok(renderer) => renderer,
+            Err(message) => return environment_failure(&message),
+        };
+    let format = args
+        .format
+        .map(OutputFormat::from)
+        .or_else(|| format_for_output(Path::new(&args.output)))
+        .unwrap_or_default();
+
+    let image = match renderer.render(&diagram, format, timeout_from_env()).await {
+        Ok(image) => image,
+        Err(err) => {
+            eprintln!("{}", err.to_error_message());
+            return render_error_status(&err);
+        }
+    };
+    let written = if args.output == "-" {
+        let mut stdout = tokio::io::stdout();
+        match stdout.write_all(&image).await {
+            Ok(()) => stdout.flush().await,
+            Err(err) => Err(err),
+        }
+    } else {
+        tokio::fs::write(&args.output, &image).await
+    };
+    match written {
+        Ok(()) => ExitStatus::Valid,
+        Err(err) => environment_failure(&format!("Failed to write {}: {err}", args.output)),
+    }
+}
+
+async fn scan(args: ScanArgs) -> ExitStatus {
+    let content = match read_input(&args.file).await {
+        Ok(content) => content,
+        Err(message) => return environment_failure(&message),
+    };
+    let input = if args.file == "-" {
+        CheckInput::Stdin {
+            name: "<stdin>".to_string(),
+        }
+    } else {
+        CheckInput::File(PathBuf::from(&args.file))
+    };
+    let result = if input.is_diagram() {
+        scan_diagram(&content, args.target)
+    } else {
+        scan_markdown_for_targets(&content, args.target, &[])
+    };
+
+    let status = if result.error_count == 0 {
+        ExitStatus::Valid
+    } else {
+        ExitStatus::Invalid
+    };
+    let output = match args.format {
+        ReportFormat::Text => scan_text(&input.name(), &result),
+        ReportFormat::Json => json(&result),
+        format => format_report(
+            &CheckReport::from_scan(&input.name(), result, content),
+            format,
+            None,
+        ),
+    };
+    println!("{output}");
+    status
+}
+
+async fn lsp(args: LspArgs) -> ExitStatus {
+    let options = ValidationOptions {
+        mermaid_version: args.target.mermaid_version,
+        ..ValidationOptions::new(args.target.target, timeout_from_env())
+    };
+    serve_lsp(
+        tokio::io::stdin(),
+        tokio::io::stdout(),
+        options,
+        RendererRegistry::load_from_env().await,
+    )
+    .await;
+    ExitStatus::Valid
+}
+
+async fn watch(args: WatchArgs) -> ExitStatus {
+    let renderers = RendererRegistry::load_from_env().await;
+    let options = ValidationOptions {
+        mermaid_version: args.target.mermaid_version,
+        ..ValidationOptions::new(args.target.target, timeout_from_env())
+    };
+    if let Err(message) = check_renderer(&options, &renderers) {
+        return environment_failure(&message);
+    }
+    let filter = DirectoryFilter {
+        include: args.include,
+        exclude: args.exclude,
+    };
+    let inputs = if args.inputs.is_empty() {
+        vec![".".to_string()]
+    } else {
+        args.inputs
+    };
+
+    let shutdown = CancellationToken::new();
+    tokio::spawn({
+        let shutdown = shutdown.clone();
+        async move {
+            let _ = tokio::signal::ctrl_c().await;
+            shutdown.cancel();
+        }
+    });
+    match watch_inputs(&inputs, &filter, options, &renderers, shutdown).await {
+        Ok(()) => ExitStatus::Valid,
+        Err(message) => environment_failure(&message),
+    }
+}
+
+async fn read_input(input: &str) -> Result<String, String> {
+    if input == "-" {
+        let mut content = String::new();
+        tokio::io::stdin()
+            .read_to_string(&mut content)
+            .await
+            .map_err(|err| format!("Failed to read stdin: {err}"))?;
+        Ok(content)
+    } else {
+        tokio::fs::read_to_string(input)
+            .await
+            .map_err(|err| format!("Failed to read {input}: {err}"))
+    }
+}
+
+fn json<T: serde::Serialize>(value: &T) -> String {
+    serde_json::to_string_pretty(value).expect("reports serialize to JSON")
+}
+
+fn invalid(message: &str) -> ExitStatus {
+    eprintln!("{message}");
+    ExitStatus::Invalid
+}
+
+fn environment_failure(message: &str) -> ExitStatus {
+    eprintln!("Error: {message}");
+    ExitStatus::Environment
+}
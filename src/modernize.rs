@@ -0,0 +1,589 @@
+use std::time::Duration;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_yaml::{Mapping, Value};
+use similar::TextDiff;
+
+use crate::{
+    cli_runner::OutputFormat,
+    diagram_config::{find_config_sources, ConfigSourceKind},
+    feature_detection::FeatureTable,
+    preview_target::PreviewTarget,
+    preview_validator::{
+        collect_mermaid_blocks, detect_diagram_type, replace_block_content, BlockProgress,
+        MermaidBlock, ValidationControl,
+    },
+    renderer_registry::{Renderer, RendererRegistry},
+};
+
+/// Old flowchart node shapes and their `@{ shape: ... }` names, longest
+/// opening delimiter first so `(((` is not read as `((`.
+const SHAPE_DELIMITERS: &[(&str, &[(&str, &str)])] = &[
+    ("(((", &[(")))", "dbl-circ")]),
+    ("((", &[("))", "circle")]),
+    ("([", &[("])", "stadium")]),
+    ("[(", &[(")]", "cyl")]),
+    ("[[", &[("]]", "subproc")]),
+    ("{{", &[("}}", "hex")]),
+    ("[/", &[("/]", "lean-r"), ("\\]", "trap-b")]),
+    ("[\\", &[("\\]", "lean-l"), ("/]", "trap-t")]),
+    (">", &[("]", "odd")]),
+];
+
+/// Flowchart statements whose brackets are not node shapes.
+const NON_NODE_KEYWORDS: &[&str] = &[
+    "subgraph",
+    "end",
+    "click",
+    "class",
+    "classDef",
+    "style",
+    "linkStyle",
+    "direction",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum Codemod {
+    /// `graph TD` becomes `flowchart TD`.
+    GraphToFlowchart,
+    /// `%%{init: ...}%%` directives become front matter `config`.
+    InitToFrontMatter,
+    /// Old node shapes such as `[(db)]` become `db@{ shape: cyl }`.
+    ShapeSyntax,
+}
+
+impl Codemod {
+    pub const ALL: [Codemod; 3] = [
+        Codemod::GraphToFlowchart,
+        Codemod::InitToFrontMatter,
+        Codemod::ShapeSyntax,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Codemod::GraphToFlowchart => "graph-to-flowchart",
+            Codemod::InitToFrontMatter => "init-to-front-matter",
+            Codemod::ShapeSyntax => "shape-syntax",
+        }
+    }
+
+    /// Feature table entry whose `minVersion` gates the codemod.
+    fn required_feature(&self) -> Option<&'static str> {
+        match self {
+            Codemod::GraphToFlowchart => None,
+            Codemod::InitToFrontMatter => Some("front-matter-config"),
+            Codemod::ShapeSyntax => Some("shape-syntax"),
+        }
+    }
+
+    /// Returns the rewritten content, `Ok(None)` when the codemod does not
+    /// apply, or the reason it cannot be applied.
+    pub fn apply(&self, content: &str) -> Result<Option<String>, String> {
+        match self {
+            Codemod::GraphToFlowchart => Ok(graph_to_flowchart(content)),
+            Codemod::InitToFrontMatter => init_to_front_matter(content),
+            Codemod::ShapeSyntax => Ok(shape_syntax(content)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ModernizeOptions {
+    pub target: PreviewTarget,
+    pub codemods: Vec<Codemod>,
+    /// Installed Mermaid version to re-validate with (default: the target's).
+    pub mermaid_version: Option<String>,
+    pub timeout: Duration,
+    pub control: ValidationControl,
+}
+
+impl ModernizeOptions {
+    pub fn new(target: PreviewTarget, timeout: Duration) -> Self {
+        ModernizeOptions {
+            target,
+            codemods: Codemod::ALL.to_vec(),
+            mermaid_version: None,
+            timeout,
+            control: ValidationControl::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SkippedCodemod {
+    pub codemod: Codemod,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockModernization {
+    pub block_index: u32,
+    pub start_line: u32,
+    pub applied: Vec<Codemod>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub skipped: Vec<SkippedCodemod>,
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ModernizeResult {
+    pub target: String,
+    pub changed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub renderer_version: Option<String>,
+    /// Unified diff between the original and the modernized markdown.
+    pub diff: String,
+    pub blocks: Vec<BlockModernization>,
+    /// The modernized markdown.
+    #[serde(skip)]
+    pub markdown: String,
+}
+
+/// Applies `options.codemods` to every Mermaid block of `markdown`.
+///
+/// Each block must render before it is touched, and every codemod is kept
+/// only if the block still renders afterwards. `path` labels the diff.
+/// A cancelled run returns an error rather than a partial result.
+pub async fn modernize_markdown(
+    path: &str,
+    markdown: &str,
+    options: &ModernizeOptions,
+    renderers: &RendererRegistry,
+) -> Result<ModernizeResult, String> {
+    let target = options.target;
+    let renderer = renderers.resolve(options.mermaid_version.as_deref(), target)?;
+    let (blocks, _) = collect_mermaid_blocks(markdown);
+
+    let mut modernized = markdown.to_string();
+    let mut reports = Vec::with_capacity(blocks.len());
+    let total = blocks.len() as u32;
+    // Replace from the last block so earlier line numbers stay valid.
+    for (completed, block) in (1..).zip(blocks.iter().rev()) {
+        let (content, report) = modernize_block(block, options, renderer).await;
+        if options.control.is_cancelled() {
+            return Err("Modernization was cancelled; nothing was changed".to_string());
+        }
+        if content != block.content {
+            modernized = replace_block_content(&modernized, block, &content);
+        }
+        reports.push(report);
+        options.control.report(BlockProgress {
+            block_index: block.index,
+            completed,
+            total,
+        });
+    }
+    reports.reverse();
+
+    let diff = unified_diff(path, markdown, &modernized);
+
+    Ok(ModernizeResult {
+        target: target.as_str().to_string(),
+        changed: modernized != markdown,
+        renderer_version: renderer.version_string(),
+        diff,
+        blocks: reports,
+        markdown: modernized,
+    })
+}
+
+/// Unified diff of a markdown file, labelled `a/{path}` and `b/{path}`.
+pub(crate) fn unified_diff(path: &str, before: &str, after: &str) -> String {
+    TextDiff::from_lines(before, after)
+        .unified_diff()
+        .header(&format!("a/{path}"), &format!("b/{path}"))
+        .to_string()
+}
+
+async fn modernize_block(
+    block: &MermaidBlock,
+    options: &ModernizeOptions,
+    renderer: &Renderer,
+) -> (String, BlockModernization) {
+    let mut report = BlockModernization {
+        block_index: block.index,
+        start_line: block.start_line,
+        applied: Vec::new(),
+        skipped: Vec::new(),
+    };
+    let mut content = block.content.clone();
+
+    let render = renderer.render(&content, OutputFormat::Svg, options.timeout);
+    let Some(rendered) = options.control.run(render).await else {
+        return (content, report);
+    };
+    if let Err(err) = rendered {
+        let reason = format!(
+            "block does not render before modernization: {}",
+            err.to_error_message()
+        );
+        report.skipped = options
+            .codemods
+            .iter()
+            .map(|&codemod| SkippedCodemod {
+                codemod,
+                reason: reason.clone(),
+            })
+            .collect();
+        return (content, report);
+    }
+
+    let target_version = options.target.parsed_mermaid_version();
+    for &codemod in &options.codemods {
+        let candidate = match codemod.apply(&content) {
+            Ok(Some(candidate)) => candidate,
+            Ok(None) => continue,
+            Err(reason) => {
+                report.skipped.push(SkippedCodemod { codemod, reason });
+                continue;
+            }
+        };
+        if let Some(min_version) = codemod
+            .required_feature()
+            .and_then(|feature| FeatureTable::shared().min_version(feature))
+        {
+            if target_version < min_version {
+                report.skipped.push(SkippedCodemod {
+                    codemod,
+                    reason: format!(
+                        "{} ships Mermaid {}, {} needs {}",
+                        options.target.display_name(),
+                        target_version,
+                        codemod.as_str(),
+                        min_version
+                    ),
+                });
+                continue;
+            }
+        }
+        let render = renderer.render(&candidate, OutputFormat::Svg, options.timeout);
+        let Some(rendered) = options.control.run(render).await else {
+            break;
+        };
+        match rendered {
+            Ok(_) => {
+                content = candidate;
+                report.applied.push(codemod);
+            }
+            Err(err) => report.skipped.push(SkippedCodemod {
+                codemod,
+                reason: format!(
+                    "block no longer renders after the change: {}",
+                    err.to_error_message()
+                ),
+            }),
+        }
+    }
+
+    (content, report)
+}
+
+/// Index of the diagram header line, skipping blank lines, front matter and
+/// `%%` comments or directives.
+fn header_line_index(lines: &[&str]) -> Option<usize> {
+    let mut idx = lines.iter().position(|line| !line.trim().is_empty())?;
+    if lines[idx].trim() == "---" {
+        idx += 1 + lines[idx + 1..]
+            .iter()
+            .position(|line| line.trim() == "---")?;
+        idx += 1;
+    }
+    let mut in_directive = false;
+    while idx < lines.len() {
+        let trimmed = lines[idx].trim();
+        if in_directive || trimmed.starts_with("%%{") {
+            in_directive = !trimmed.contains("}%%");
+        } else if !trimmed.is_empty() && !trimmed.starts_with("%%") {
+            return Some(idx);
+        }
+        idx += 1;
+    }
+    None
+}
+
+fn join_lines(lines: &[String]) -> String {
+    lines.join("\n")
+}
+
+fn graph_to_flowchart(content: &str) -> Option<String> {
+    let lines = content.lines().collect::<Vec<_>>();
+    let header = header_line_index(&lines)?;
+    let line = lines[header];
+    let indent = &line[..line.len() - line.trim_start().len()];
+    let rest = line.trim_start().strip_prefix("graph")?;
+    if !(rest.is_empty() || rest.starts_with(char::is_whitespace) || rest.starts_with(';')) {
+        return None;
+    }
+
+    let mut output = lines.iter().map(ToString::to_string).collect::<Vec<_>>();
+    output[header] = format!("{indent}flowchart{rest}");
+    Some(join_lines(&output))
+}
+
+fn init_to_front_matter(content: &str) -> Result<Option<String>, String> {
+    let (sources, diagnostics) = find_config_sources(content);
+    if let Some(diagnostic) = diagnostics.first() {
+        return Err(diagnostic.message.clone());
+    }
+    let directives = sources
+        .iter()
+        .filter(|source| source.kind == ConfigSourceKind::Directive)
+        .collect::<Vec<_>>();
+    if directives.is_empty() {
+        return Ok(None);
+    }
+
+    let mut front_matter = Mapping::new();
+    if let Some(source) = sources
+        .iter()
+        .find(|source| source.kind == ConfigSourceKind::FrontMatter)
+    {
+        front_matter = match serde_yaml::from_str::<Value>(&source.body) {
+            Ok(Value::Mapping(mapping)) => mapping,
+            Ok(Value::Null) => Mapping::new(),
+            _ => return Err("existing front matter is not a YAML mapping".to_string()),
+        };
+    }
+
+    let mut config = match front_matter.remove("config") {
+        Some(Value::Mapping(mapping)) => mapping,
+        None | Some(Value::Null) => Mapping::new(),
+        Some(_) => return Err("front matter `config` is not a mapping".to_string()),
+    };
+    for directive in &directives {
+        let Ok(Value::Mapping(body)) = serde_yaml::from_str::<Value>(&directive.body) else {
+            return Err(format!(
+                "directive on line {} is not valid YAML",
+                directive.start_line
+            ));
+        };
+        for (key, value) in body {
+            match (key.as_str(), value) {
+                (Some("init" | "initialize"), Value::Mapping(init)) => {
+                    merge_mapping(&mut config, init)
+                }
+                (Some(name), _) => {
+                    return Err(format!(
+                        "directive `{name}` on line {} has no front matter equivalent",
+                        directive.start_line
+                    ))
+                }
+                (None, _) => return Err("directive key is not a string".to_string()),
+            }
+        }
+    }
+    front_matter.insert(Value::from("config"), Value::Mapping(config));
+
+    let yaml = serde_yaml::to_string(&front_matter).map_err(|err| err.to_string())?;
+    let lines = content.lines().collect::<Vec<_>>();
+    let indent = lines
+        .iter()
+        .find(|line| !line.trim().is_empty())
+        .map_or("", |line| &line[..line.len() - line.trim_start().len()]);
+
+    let mut output = vec![format!("{indent}---")];
+    output.extend(yaml.lines().map(|line| format!("{indent}{line}")));
+    output.push(format!("{indent}---"));
+    let mut body_started = false;
+    for (idx, line) in lines.iter().enumerate() {
+        let line_no = (idx + 1) as u32;
+        if sources
+            .iter()
+            .any(|source| (source.start_line..=source.end_line).contains(&line_no))
+        {
+            continue;
+        }
+        if !body_started && line.trim().is_empty() {
+            continue;
+        }
+        body_started = true;
+        output.push(line.to_string());
+    }
+    Ok(Some(join_lines(&output)))
+}
+
+/// Deep-merges `overrides` into `base`; values from `overrides` win.
+fn merge_mapping(base: &mut Mapping, overrides: Mapping) {
+    for (key, value) in overrides {
+        match (base.get_mut(&key), value) {
+            (Some(Value::Mapping(existing)), Value::Mapping(nested)) => {
+                merge_mapping(existing, nested)
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+fn shape_syntax(content: &str) -> Option<String> {
+    if !matches!(detect_diagram_type(content), Some("flowchart" | "graph")) {
+        return None;
+    }
+    let lines = content.lines().collect::<Vec<_>>();
+    let header = header_line_index(&lines)?;
+
+    let mut changed = false;
+    let mut output = lines.iter().map(ToString::to_string).collect::<Vec<_>>();
+    for line in output.iter_mut().skip(header + 1) {
+        let trimmed = line.trim_start();
+        let keyword = trimmed.split_whitespace().next().unwrap_or_default();
+        if trimmed.starts_with("%%") || NON_NODE_KEYWORDS.contains(&keyword) {
+            continue;
+        }
+        if let Some(rewritten) = rewrite_shapes(line) {
+            *line = rewritten;
+            changed = true;
+        }
+    }
+    changed.then(|| join_lines(&output))
+}
+
+fn is_id_char(ch: char) -> bool {
+    ch.is_ascii_alphanumeric() || ch == '_'
+}
+
+/// Rewrites every `id<open>label<close>` node on one line.
+fn rewrite_shapes(line: &str) -> Option<String> {
+    let mut output = String::with_capacity(line.len());
+    let mut changed = false;
+    let mut rest = line;
+    let mut previous: Option<char> = None;
+
+    while let Some(ch) = rest.chars().next() {
+        if ch == '"' {
+            // Copy quoted text verbatim.
+            let end = rest[1..].find('"').map_or(rest.len(), |end| end + 2);
+            output.push_str(&rest[..end]);
+            previous = rest[..end].chars().last();
+            rest = &rest[end..];
+            continue;
+        }
+        if is_id_char(ch) && !previous.is_some_and(is_id_char) {
+            let id_len = rest.find(|ch: char| !is_id_char(ch)).unwrap_or(rest.len());
+            let (id, after) = rest.split_at(id_len);
+            if let Some((shape, label, consumed)) = match_shape(after) {
+                output.push_str(&format!(
+                    "{id}@{{ shape: {shape}, label: {} }}",
+                    quote_label(label)
+                ));
+                rest = &after[consumed..];
+                previous = Some('}');
+                changed = true;
+                continue;
+            }
+            output.push_str(id);
+            previous = id.chars().last();
+            rest = after;
+            continue;
+        }
+        output.push(ch);
+        previous = Some(ch);
+        rest = &rest[ch.len_utf8()..];
+    }
+
+    changed.then_some(output)
+}
+
+/// Matches an old shape at the start of `text`, returning the shape name,
+/// the label and the number of bytes consumed.
+fn match_shape(text: &str) -> Option<(&'static str, &str, usize)> {
+    let (open, closings) = SHAPE_DELIMITERS
+        .iter()
+        .find(|(open, _)| text.starts_with(open))?;
+    let body = &text[open.len()..];
+    let search_from = match body.strip_prefix('"') {
+        Some(quoted) => quoted.find('"')? + 2,
+        None => 0,
+    };
+    let (close_at, close, shape) = closings
+        .iter()
+        .filter_map(|(close, shape)| {
+            body[search_from..]
+                .find(close)
+                .map(|at| (search_from + at, *close, *shape))
+        })
+        .min_by_key(|(at, _, _)| *at)?;
+    let label = &body[..close_at];
+    if label.is_empty() {
+        return None;
+    }
+    Some((shape, label, open.len() + close_at + close.len()))
+}
+
+fn quote_label(label: &str) -> String {
+    if label.len() >= 2 && label.starts_with('"') && label.ends_with('"') {
+        label.to_string()
+    } else {
+        format!("\"{}\"", label.replace('"', "#quot;"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn graph_header_becomes_flowchart() {
+        let content = "  %% legend\n  graph LR;\n  A-->B";
+        assert_eq!(
+            Codemod::GraphToFlowchart.apply(content).unwrap().as_deref(),
+            Some("  %% legend\n  flowchart LR;\n  A-->B")
+        );
+        assert_eq!(
+            Codemod::GraphToFlowchart.apply("flowchart TD").unwrap(),
+            None
+        );
+        assert_eq!(Codemod::GraphToFlowchart.apply("gitGraph").unwrap(), None);
+    }
+
+    #[test]
+    fn init_directive_merges_into_front_matter() {
+        let content = "---\ntitle: Demo\nconfig:\n  theme: forest\n  flowchart:\n    curve: basis\n---\n%%{init: {\"theme\": \"dark\", \"flowchart\": {\"htmlLabels\": false}}}%%\nflowchart TD\n  A-->B";
+        let converted = Codemod::InitToFrontMatter.apply(content).unwrap().unwrap();
+        assert_eq!(
+            converted,
+            "---\ntitle: Demo\nconfig:\n  theme: dark\n  flowchart:\n    curve: basis\n    htmlLabels: false\n---\nflowchart TD\n  A-->B"
+        );
+
+        let error = Codemod::InitToFrontMatter
+            .apply("%%{wrap}%%\nsequenceDiagram")
+            .unwrap_err();
+        assert!(error.contains("no front matter equivalent"), "{error}");
+    }
+
+    #[test]
+    fn old_shapes_use_shape_syntax() {
+        let content = "flowchart TD\n  db[(Database)] --> c((Hub)) --> e(((Done)))\n  h{{\"Say \"hi\"\"}} --> s([Go]) --> p[[Sub]]\n  lr[/in/] --> lt[\\out\\] --> tb[/base\\] --> tt[\\top/]\n  o>flag] --> r[Rect] --> d{Choice}\n  subgraph one [[x]]\n  end";
+        let converted = Codemod::ShapeSyntax.apply(content).unwrap().unwrap();
+        let lines = converted.lines().collect::<Vec<_>>();
+        assert_eq!(
+            lines[1],
+            "  db@{ shape: cyl, label: \"Database\" } --> c@{ shape: circle, label: \"Hub\" } --> e@{ shape: dbl-circ, label: \"Done\" }"
+        );
+        assert_eq!(
+            lines[2],
+            "  h@{ shape: hex, label: \"Say \"hi\"\" } --> s@{ shape: stadium, label: \"Go\" } --> p@{ shape: subproc, label: \"Sub\" }"
+        );
+        assert_eq!(
+            lines[3],
+            "  lr@{ shape: lean-r, label: \"in\" } --> lt@{ shape: lean-l, label: \"out\" } --> tb@{ shape: trap-b, label: \"base\" } --> tt@{ shape: trap-t, label: \"top\" }"
+        );
+        assert_eq!(
+            lines[4],
+            "  o@{ shape: odd, label: \"flag\" } --> r[Rect] --> d{Choice}"
+        );
+        assert_eq!(lines[5], "  subgraph one [[x]]");
+
+        assert_eq!(
+            Codemod::ShapeSyntax
+                .apply("sequenceDiagram\n  A->>B: [(x)]")
+                .unwrap(),
+            None
+        );
+    }
+}
@@ -0,0 +1,311 @@
+use base64::Engine;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::preview_validator::{
+    content_hash, count_errors, PreviewScanResult, PreviewValidationResult,
+};
+
+/// How much of a result the text content repeats. Structured content is
+/// always complete for the returned page.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Verbosity {
+    /// Counts and validity only.
+    Summary,
+    /// One line per block or issue.
+    #[default]
+    Normal,
+    /// Everything, including snippets and detected features.
+    Full,
+}
+
+/// The blocks a paginated result covers. Pages are cut by block index, so
+/// a cursor names the same position whatever `limit` the next call uses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockPage {
+    /// First block index on the page (1-based).
+    pub first: u32,
+    /// Last block index on the page; `first - 1` for an empty page.
+    pub last: u32,
+    pub next_cursor: Option<String>,
+}
+
+impl BlockPage {
+    /// Selects up to `limit` blocks of `markdown`, which has `block_count`
+    /// blocks, starting at `cursor` or at the first block. Cursors carry a
+    /// hash of the markdown and are refused once it changed.
+    pub fn select(
+        markdown: &str,
+        block_count: u32,
+        limit: Option<u32>,
+        cursor: Option<&str>,
+    ) -> Result<BlockPage, String> {
+        let (first, last, next_cursor) = select_range(
+            markdown,
+            block_count,
+            limit,
+            cursor,
+            "The markdown changed since this cursor was issued; start again without a cursor",
+        )?;
+        Ok(BlockPage {
+            first,
+            last,
+            next_cursor,
+        })
+    }
+
+    pub fn contains(&self, block_index: u32) -> bool {
+        (self.first..=self.last).contains(&block_index)
+    }
+
+    /// Issues without a block are reported on the first page only.
+    pub fn includes_file_issues(&self) -> bool {
+        self.first == 1
+    }
+
+    pub fn info(&self) -> PageInfo {
+        PageInfo {
+            first_block: self.first,
+            last_block: self.last,
+            returned_blocks: (self.last + 1).saturating_sub(self.first),
+            next_cursor: self.next_cursor.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PageInfo {
+    pub first_block: u32,
+    pub last_block: u32,
+    pub returned_blocks: u32,
+    /// Pass as `cursor` to get the next page; absent on the last page.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+}
+
+/// The files a paginated directory result covers, cut by position in the
+/// sorted file list. Cursors are refused once the list of files changed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilePage {
+    /// First file on the page (1-based).
+    pub first: u32,
+    /// Last file on the page; `first - 1` for an empty page.
+    pub last: u32,
+    /// Files matched in all pages.
+    pub total: u32,
+    pub next_cursor: Option<String>,
+}
+
+impl FilePage {
+    pub fn select(
+        files: &[String],
+        limit: Option<u32>,
+        cursor: Option<&str>,
+    ) -> Result<FilePage, String> {
+        let (first, last, next_cursor) = select_range(
+            &files.join("\n"),
+            files.len() as u32,
+            limit,
+            cursor,
+            "The matched files changed since this cursor was issued; start again without a cursor",
+        )?;
+        Ok(FilePage {
+            first,
+            last,
+            total: files.len() as u32,
+            next_cursor,
+        })
+    }
+
+    /// The items of `all` on this page.
+    pub fn slice<'a, T>(&self, all: &'a [T]) -> &'a [T] {
+        &all[self.first as usize - 1..self.last as usize]
+    }
+
+    pub fn info(&self) -> FilePageInfo {
+        FilePageInfo {
+            first_file: self.first,
+            last_file: self.last,
+            returned_files: (self.last + 1).saturating_sub(self.first),
+            total_files: self.total,
+            next_cursor: self.next_cursor.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FilePageInfo {
+    pub first_file: u32,
+    pub last_file: u32,
+    pub returned_files: u32,
+    pub total_files: u32,
+    /// Pass as `cursor` to get the next page; absent on the last page.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+}
+
+/// A result trimmed to one page, with the page's position.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct Paged<T, P = PageInfo> {
+    #[serde(flatten)]
+    pub result: T,
+    pub page: P,
+}
+
+/// Keeps the blocks, issues and compatibility rows of `page`. Counts and
+/// validity still describe the whole markdown.
+pub fn page_scan(mut result: PreviewScanResult, page: &BlockPage) -> Paged<PreviewScanResult> {
+    result.blocks.retain(|block| page.contains(block.index));
+    result.issues.retain(|issue| match issue.block_index {
+        Some(index) => page.contains(index),
+        None => page.includes_file_issues(),
+    });
+    result
+        .compatibility
+        .retain(|block| page.contains(block.block_index));
+    Paged {
+        result,
+        page: page.info(),
+    }
+}
+
+/// Like [`page_scan`] for a validation result of the page's blocks (see
+/// [`validate_markdown_blocks`]). Only the block count describes the whole
+/// markdown; errors and validity cover the returned page.
+///
+/// [`validate_markdown_blocks`]: crate::preview_validator::validate_markdown_blocks
+pub fn page_validation(
+    mut result: PreviewValidationResult,
+    page: &BlockPage,
+) -> Paged<PreviewValidationResult> {
+    result.issues.retain(|issue| match issue.block_index {
+        Some(index) => page.contains(index),
+        None => page.includes_file_issues(),
+    });
+    result.error_count = count_errors(&result.issues);
+    result.valid = result.error_count == 0;
+    result
+        .compatibility
+        .retain(|block| page.contains(block.block_index));
+    Paged {
+        result,
+        page: page.info(),
+    }
+}
+
+/// First and last 1-based position of a page of up to `limit` of `count`
+/// items, and the cursor of the next page. `key` identifies the paged
+/// content; cursors issued for another key are refused with `changed`.
+fn select_range(
+    key: &str,
+    count: u32,
+    limit: Option<u32>,
+    cursor: Option<&str>,
+    changed: &str,
+) -> Result<(u32, u32, Option<String>), String> {
+    let hash = cursor_hash(key);
+    let first = match cursor {
+        Some(cursor) => {
+            let first = decode_cursor(cursor, &hash, changed)?;
+            if first > count + 1 {
+                return Err(format!("Invalid cursor {cursor}"));
+            }
+            first
+        }
+        None => 1,
+    };
+    let remaining = (count + 1).saturating_sub(first);
+    let taken = limit.map_or(remaining, |limit| limit.max(1).min(remaining));
+    let last = first + taken - 1;
+    let next_cursor = (last < count).then(|| encode_cursor(last + 1, &hash));
+    Ok((first, last, next_cursor))
+}
+
+fn cursor_hash(key: &str) -> String {
+    content_hash(key)[..16].to_string()
+}
+
+fn encode_cursor(block_index: u32, hash: &str) -> String {
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(format!("{block_index}:{hash}"))
+}
+
+fn decode_cursor(cursor: &str, hash: &str, changed: &str) -> Result<u32, String> {
+    let invalid = || format!("Invalid cursor {cursor}");
+    let decoded = base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .decode(cursor)
+        .map_err(|_| invalid())?;
+    let decoded = String::from_utf8(decoded).map_err(|_| invalid())?;
+    let (index, cursor_hash) = decoded.split_once(':').ok_or_else(invalid)?;
+    let index = index.parse::<u32>().map_err(|_| invalid())?;
+    if index == 0 {
+        return Err(invalid());
+    }
+    if cursor_hash != hash {
+        return Err(changed.to_string());
+    }
+    Ok(index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cursors_walk_all_blocks_and_survive_limit_changes() {
+        let markdown = "doc";
+        let page = BlockPage::select(markdown, 5, Some(2), None).unwrap();
+        assert_eq!((page.first, page.last), (1, 2));
+        let cursor = page.next_cursor.unwrap();
+
+        let page = BlockPage::select(markdown, 5, Some(10), Some(&cursor)).unwrap();
+        assert_eq!((page.first, page.last), (3, 5));
+        assert_eq!(page.next_cursor, None);
+        // The same cursor gives the same start every time.
+        let again = BlockPage::select(markdown, 5, Some(1), Some(&cursor)).unwrap();
+        assert_eq!(again.first, 3);
+
+        let all = BlockPage::select(markdown, 0, None, None).unwrap();
+        assert_eq!(all.info().returned_blocks, 0);
+    }
+
+    #[test]
+    fn cursors_are_refused_after_the_markdown_changes() {
+        let page = BlockPage::select("before", 3, Some(1), None).unwrap();
+        let cursor = page.next_cursor.unwrap();
+        let error = BlockPage::select("after", 3, Some(1), Some(&cursor)).unwrap_err();
+        assert!(error.contains("changed since this cursor"));
+        assert!(BlockPage::select("before", 3, None, Some("???")).is_err());
+    }
+
+    #[test]
+    fn cursors_past_the_end_are_invalid() {
+        let files = ["a.md", "b.md"].map(String::from);
+        let hash = cursor_hash(&files.join("\n"));
+        let end = FilePage::select(&files, None, Some(&encode_cursor(3, &hash))).unwrap();
+        assert!(end.slice(&files).is_empty());
+        let past = encode_cursor(4, &hash);
+        let error = FilePage::select(&files, None, Some(&past)).unwrap_err();
+        assert_eq!(error, format!("Invalid cursor {past}"));
+    }
+
+    #[test]
+    fn file_pages_slice_the_file_list() {
+        let files = ["a.md", "b.md", "c.md"].map(String::from);
+        let page = FilePage::select(&files, Some(2), None).unwrap();
+        assert_eq!(page.slice(&files), &files[..2]);
+        let cursor = page.next_cursor.unwrap();
+        let page = FilePage::select(&files, Some(2), Some(&cursor)).unwrap();
+        assert_eq!(page.slice(&files), &files[2..]);
+        assert_eq!(page.info().returned_files, 1);
+
+        let error = FilePage::select(&files[..2], None, Some(&cursor)).unwrap_err();
+        assert!(error.contains("matched files changed"));
+        assert!(FilePage::select(&[], None, None)
+            .unwrap()
+            .slice(&files)
+            .is_empty());
+    }
+}
@@ -0,0 +1,312 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum BlockSyntax {
+    /// A backtick or tilde code fence, e.g. ```` ```mermaid ````.
+    Fence,
+    /// An Azure DevOps wiki colon block, e.g. `::: mermaid`.
+    Colon,
+}
+
+impl BlockSyntax {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BlockSyntax::Fence => "fence",
+            BlockSyntax::Colon => "colon",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum PreviewTarget {
+    #[default]
+    Github,
+    Gitlab,
+    AzureDevops,
+    Obsidian,
+    Mkdocs,
+}
+
+/// A `major.minor.patch` Mermaid release; missing components default to zero.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MermaidVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl MermaidVersion {
+    pub fn parse(value: &str) -> Option<MermaidVersion> {
+        let value = value.trim().trim_start_matches('v');
+        let mut parts = value.split('.');
+        let major = parts.next()?.parse().ok()?;
+        let minor = parts.next().map_or(Some(0), |part| part.parse().ok())?;
+        let patch = parts.next().map_or(Some(0), |part| part.parse().ok())?;
+        if parts.next().is_some() {
+            return None;
+        }
+        Some(MermaidVersion {
+            major,
+            minor,
+            patch,
+        })
+    }
+}
+
+impl std::fmt::Display for MermaidVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// Static description of how a markdown host renders Mermaid.
+#[derive(Debug)]
+pub struct TargetProfile {
+    pub id: &'static str,
+    pub name: &'static str,
+    pub block_syntaxes: &'static [BlockSyntax],
+    pub info_strings: &'static [&'static str],
+    pub diagram_types: &'static [&'static str],
+    pub mermaid_version: &'static str,
+    /// Config keys the host ignores when set inside a diagram.
+    pub ignored_config_keys: &'static [&'static str],
+}
+
+const STANDARD_DIAGRAM_TYPES: &[&str] = &[
+    "flowchart",
+    "graph",
+    "sequenceDiagram",
+    "classDiagram",
+    "classDiagram-v2",
+    "stateDiagram",
+    "stateDiagram-v2",
+    "erDiagram",
+    "journey",
+    "gantt",
+    "pie",
+    "quadrantChart",
+    "requirementDiagram",
+    "gitGraph",
+    "C4Context",
+    "C4Container",
+    "C4Component",
+    "C4Dynamic",
+    "C4Deployment",
+    "mindmap",
+    "timeline",
+    "sankey-beta",
+    "xychart-beta",
+    "block-beta",
+    "packet-beta",
+    "kanban",
+    "architecture-beta",
+    "radar-beta",
+    "treemap-beta",
+];
+
+const AZURE_DEVOPS_DIAGRAM_TYPES: &[&str] = &[
+    "flowchart",
+    "graph",
+    "sequenceDiagram",
+    "classDiagram",
+    "stateDiagram",
+    "stateDiagram-v2",
+    "erDiagram",
+    "journey",
+    "gantt",
+    "pie",
+    "requirementDiagram",
+    "gitGraph",
+    "timeline",
+];
+
+/// Mermaid never lets a diagram override its `secure` keys, so every host
+/// ignores them.
+const SECURE_CONFIG_KEYS: &[&str] = &[
+    "secure",
+    "securityLevel",
+    "startOnLoad",
+    "maxTextSize",
+    "suppressErrorRendering",
+    "maxEdges",
+];
+
+const GITHUB: TargetProfile = TargetProfile {
+    id: "github",
+    name: "GitHub",
+    block_syntaxes: &[BlockSyntax::Fence],
+    info_strings: &["mermaid"],
+    diagram_types: STANDARD_DIAGRAM_TYPES,
+    mermaid_version: "11.4.1",
+    ignored_config_keys: SECURE_CONFIG_KEYS,
+};
+
+const GITLAB: TargetProfile = TargetProfile {
+    id: "gitlab",
+    name: "GitLab",
+    block_syntaxes: &[BlockSyntax::Fence],
+    info_strings: &["mermaid"],
+    diagram_types: STANDARD_DIAGRAM_TYPES,
+    mermaid_version: "10.9.1",
+    ignored_config_keys: SECURE_CONFIG_KEYS,
+};
+
+const AZURE_DEVOPS: TargetProfile = TargetProfile {
+    id: "azure-devops",
+    name: "Azure DevOps wiki",
+    block_syntaxes: &[BlockSyntax::Colon],
+    info_strings: &["mermaid"],
+    diagram_types: AZURE_DEVOPS_DIAGRAM_TYPES,
+    mermaid_version: "10.2.4",
+    ignored_config_keys: SECURE_CONFIG_KEYS,
+};
+
+const OBSIDIAN: TargetProfile = TargetProfile {
+    id: "obsidian",
+    name: "Obsidian",
+    block_syntaxes: &[BlockSyntax::Fence],
+    info_strings: &["mermaid"],
+    diagram_types: STANDARD_DIAGRAM_TYPES,
+    mermaid_version: "11.4.1",
+    ignored_config_keys: SECURE_CONFIG_KEYS,
+};
+
+const MKDOCS: TargetProfile = TargetProfile {
+    id: "mkdocs",
+    name: "MkDocs Material",
+    block_syntaxes: &[BlockSyntax::Fence],
+    info_strings: &["mermaid"],
+    diagram_types: STANDARD_DIAGRAM_TYPES,
+    mermaid_version: "11.4.1",
+    ignored_config_keys: SECURE_CONFIG_KEYS,
+};
+
+impl PreviewTarget {
+    pub const ALL: [PreviewTarget; 5] = [
+        PreviewTarget::Github,
+        PreviewTarget::Gitlab,
+        PreviewTarget::AzureDevops,
+        PreviewTarget::Obsidian,
+        PreviewTarget::Mkdocs,
+    ];
+
+    pub fn profile(&self) -> &'static TargetProfile {
+        match self {
+            PreviewTarget::Github => &GITHUB,
+            PreviewTarget::Gitlab => &GITLAB,
+            PreviewTarget::AzureDevops => &AZURE_DEVOPS,
+            PreviewTarget::Obsidian => &OBSIDIAN,
+            PreviewTarget::Mkdocs => &MKDOCS,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        self.profile().id
+    }
+
+    pub fn display_name(&self) -> &'static str {
+        self.profile().name
+    }
+
+    pub fn mermaid_version(&self) -> &'static str {
+        self.profile().mermaid_version
+    }
+
+    pub fn parsed_mermaid_version(&self) -> MermaidVersion {
+        MermaidVersion::parse(self.mermaid_version()).expect("target versions are valid")
+    }
+
+    pub fn supports_syntax(&self, syntax: BlockSyntax) -> bool {
+        self.profile().block_syntaxes.contains(&syntax)
+    }
+
+    /// Info strings are matched on their first word, which is case-sensitive
+    /// on every supported host.
+    pub fn accepts_info_string(&self, info: &str) -> bool {
+        let first = info.split_whitespace().next().unwrap_or("");
+        self.profile().info_strings.contains(&first)
+    }
+
+    pub fn ignores_config_key(&self, key: &str) -> bool {
+        self.profile().ignored_config_keys.contains(&key)
+    }
+
+    pub fn supports_diagram_type(&self, diagram_type: &str) -> bool {
+        self.profile().diagram_types.contains(&diagram_type)
+    }
+
+    pub fn parse(value: &str) -> Option<PreviewTarget> {
+        PreviewTarget::ALL
+            .into_iter()
+            .find(|target| target.as_str().eq_ignore_ascii_case(value.trim()))
+    }
+}
+
+/// Returns `target` followed by every entry of `targets` not already listed.
+pub fn merge_targets(target: PreviewTarget, targets: &[PreviewTarget]) -> Vec<PreviewTarget> {
+    let mut merged = vec![target];
+    for candidate in targets {
+        if !merged.contains(candidate) {
+            merged.push(*candidate);
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn azure_devops_only_renders_colon_blocks() {
+        let target = PreviewTarget::AzureDevops;
+        assert!(target.supports_syntax(BlockSyntax::Colon));
+        assert!(!target.supports_syntax(BlockSyntax::Fence));
+        assert!(!target.supports_diagram_type("architecture-beta"));
+    }
+
+    #[test]
+    fn info_strings_are_case_sensitive() {
+        assert!(PreviewTarget::Github.accepts_info_string("mermaid"));
+        assert!(PreviewTarget::Github.accepts_info_string("mermaid title=x"));
+        assert!(!PreviewTarget::Github.accepts_info_string("Mermaid"));
+    }
+
+    #[test]
+    fn parse_and_serialize_target_ids() {
+        assert_eq!(
+            PreviewTarget::parse("azure-devops"),
+            Some(PreviewTarget::AzureDevops)
+        );
+        assert_eq!(
+            serde_json::to_value(PreviewTarget::AzureDevops).unwrap(),
+            "azure-devops"
+        );
+        assert!(PreviewTarget::parse("bitbucket").is_none());
+    }
+
+    #[test]
+    fn merge_targets_keeps_primary_first() {
+        let merged = merge_targets(
+            PreviewTarget::Gitlab,
+            &[PreviewTarget::Github, PreviewTarget::Gitlab],
+        );
+        assert_eq!(merged, vec![PreviewTarget::Gitlab, PreviewTarget::Github]);
+    }
+
+    #[test]
+    fn parse_and_order_mermaid_versions() {
+        let old = MermaidVersion::parse("v10.9").unwrap();
+        let new = MermaidVersion::parse("11.4.1").unwrap();
+        assert_eq!(old.to_string(), "10.9.0");
+        assert!(old < new);
+        assert!(MermaidVersion::parse("latest").is_none());
+        assert!(MermaidVersion::parse("1.2.3.4").is_none());
+        for target in PreviewTarget::ALL {
+            target.parsed_mermaid_version();
+        }
+    }
+}
@@ -1,9 +1,21 @@
-use std::time::Duration;
+use std::{collections::HashMap, ffi::OsStr, future::Future, ops::RangeInclusive, time::Duration};
 
 use schemars::JsonSchema;
 use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
 
-use crate::cli_runner::{render_diagram, OutputFormat};
+use crate::{
+    cli_runner::OutputFormat,
+    diagram_config::check_diagram_config,
+    feature_detection::{min_mermaid_version, DetectedFeature, FeatureTable},
+    preview_target::{merge_targets, BlockSyntax, MermaidVersion, PreviewTarget},
+    renderer_registry::{Renderer, RendererRegistry},
+};
+
+/// Hex digits of the content hash in a block id.
+const BLOCK_ID_HASH_LEN: usize = 8;
 
 #[derive(Debug, Clone, Serialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
@@ -25,11 +37,47 @@ pub struct PreviewIssue {
 #[serde(rename_all = "camelCase")]
 pub struct MermaidBlockInfo {
     pub index: u32,
+    /// Stable id: the slugs of the enclosing headings and a prefix of the
+    /// block's content hash, e.g. `architecture/data-flow#3f2a9c1d`.
+    /// Identical blocks under the same headings get `-2`, `-3`, ... appended.
+    pub id: String,
     pub start_line: u32,
     pub end_line: u32,
     pub line_count: u32,
     pub char_count: u32,
     pub first_line: String,
+    pub syntax: BlockSyntax,
+    pub info_string: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub diagram_type: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub features: Vec<DetectedFeature>,
+    /// Oldest Mermaid release supporting every feature the block uses.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_mermaid_version: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TargetCompatibility {
+    pub target: PreviewTarget,
+    /// Mermaid version the target ships.
+    pub mermaid_version: String,
+    /// Mermaid version of the renderer that validated the block, when a
+    /// versioned renderer was used.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub renderer_version: Option<String>,
+    pub compatible: bool,
+    pub issue_codes: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warning_codes: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockCompatibility {
+    pub block_index: u32,
+    pub targets: Vec<TargetCompatibility>,
 }
 
 #[derive(Debug, Clone, Serialize, JsonSchema)]
@@ -40,6 +88,8 @@ pub struct PreviewScanResult {
     pub mermaid_block_count: u32,
     pub blocks: Vec<MermaidBlockInfo>,
     pub issues: Vec<PreviewIssue>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub compatibility: Vec<BlockCompatibility>,
 }
 
 #[derive(Debug, Clone, Serialize, JsonSchema)]
@@ -49,7 +99,13 @@ pub struct PreviewValidationResult {
     pub valid: bool,
     pub error_count: u32,
     pub mermaid_block_count: u32,
+    /// Mermaid version of the renderer used for `target`; absent when the
+    /// unversioned `MERMAID_CLI` renderer was used.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub renderer_version: Option<String>,
     pub issues: Vec<PreviewIssue>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub compatibility: Vec<BlockCompatibility>,
 }
 
 #[derive(Debug, Clone, Serialize, JsonSchema)]
@@ -59,15 +115,121 @@ pub struct BlockValidationResult {
     pub block_index: u32,
     pub found: bool,
     pub valid: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub renderer_version: Option<String>,
     pub issues: Vec<PreviewIssue>,
 }
 
 #[derive(Debug, Clone)]
-struct MermaidBlock {
-    index: u32,
-    start_line: u32,
-    end_line: u32,
-    content: String,
+pub struct ValidationOptions {
+    pub target: PreviewTarget,
+    /// Extra targets for the compatibility matrix.
+    pub matrix_targets: Vec<PreviewTarget>,
+    /// Installed Mermaid version to validate with instead of the target's.
+    pub mermaid_version: Option<String>,
+    pub timeout: Duration,
+    pub control: ValidationControl,
+}
+
+impl ValidationOptions {
+    pub fn new(target: PreviewTarget, timeout: Duration) -> Self {
+        ValidationOptions {
+            target,
+            matrix_targets: Vec::new(),
+            mermaid_version: None,
+            timeout,
+            control: ValidationControl::default(),
+        }
+    }
+}
+
+/// Reported after each block of a multi-block operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockProgress {
+    pub block_index: u32,
+    /// Blocks finished so far, including this one.
+    pub completed: u32,
+    pub total: u32,
+}
+
+/// Reported after each file of a multi-file operation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileProgress {
+    pub path: String,
+    /// Files finished so far, including this one.
+    pub completed: u32,
+    pub total: u32,
+}
+
+/// One finished step of an operation over many blocks or files.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Progress {
+    Block(BlockProgress),
+    File(FileProgress),
+}
+
+impl From<BlockProgress> for Progress {
+    fn from(progress: BlockProgress) -> Self {
+        Progress::Block(progress)
+    }
+}
+
+impl From<FileProgress> for Progress {
+    fn from(progress: FileProgress) -> Self {
+        Progress::File(progress)
+    }
+}
+
+/// Progress reporting and cancellation for operations over many blocks.
+#[derive(Debug, Clone, Default)]
+pub struct ValidationControl {
+    /// Once cancelled, no further block is started and running renderers
+    /// are killed.
+    pub cancel: CancellationToken,
+    /// Receives a [`Progress`] after every block or file.
+    pub progress: Option<mpsc::UnboundedSender<Progress>>,
+}
+
+impl ValidationControl {
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.is_cancelled()
+    }
+
+    /// Runs `future` to completion, or returns `None` as soon as the
+    /// operation is cancelled. Dropping a render kills its mmdc process.
+    pub async fn run<F: Future>(&self, future: F) -> Option<F::Output> {
+        tokio::select! {
+            biased;
+            _ = self.cancel.cancelled() => None,
+            output = future => Some(output),
+        }
+    }
+
+    pub fn report(&self, progress: impl Into<Progress>) {
+        if let Some(sender) = &self.progress {
+            let _ = sender.send(progress.into());
+        }
+    }
+
+    /// The same cancellation without progress, for the steps of an
+    /// operation that reports its own progress.
+    pub fn without_progress(&self) -> Self {
+        ValidationControl {
+            cancel: self.cancel.clone(),
+            progress: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct MermaidBlock {
+    pub(crate) index: u32,
+    pub(crate) id: String,
+    pub(crate) start_line: u32,
+    pub(crate) end_line: u32,
+    pub(crate) syntax: BlockSyntax,
+    pub(crate) info_string: String,
+    pub(crate) content: String,
 }
 
 #[derive(Debug, Clone)]
@@ -75,52 +237,77 @@ struct FenceState<'a> {
     marker: char,
     len: usize,
     is_mermaid: bool,
+    info_string: String,
     start_line: u32,
     content_lines: Vec<&'a str>,
 }
 
+/// Number of mermaid blocks in `markdown`, without validating them.
+pub fn count_mermaid_blocks(markdown: &str) -> u32 {
+    collect_mermaid_blocks(markdown).0.len() as u32
+}
+
 pub fn scan_markdown_for_mermaid(markdown: &str) -> PreviewScanResult {
-    let (blocks, mut issues) = collect_mermaid_blocks(markdown);
+    scan_markdown_for_targets(markdown, PreviewTarget::Github, &[])
+}
 
-    let has_unclosed_mermaid = issues
-        .iter()
-        .any(|issue| issue.code == "mermaid_unclosed_fence");
+/// Scans `markdown` using the rules of `target`. When `matrix_targets` is not
+/// empty, the result also carries a per-block compatibility matrix covering
+/// `target` and every listed target.
+pub fn scan_markdown_for_targets(
+    markdown: &str,
+    target: PreviewTarget,
+    matrix_targets: &[PreviewTarget],
+) -> PreviewScanResult {
+    let (blocks, mut issues) = collect_mermaid_blocks(markdown);
 
-    if blocks.is_empty() && !has_unclosed_mermaid {
-        issues.push(PreviewIssue {
-            severity: "error".to_string(),
-            code: "no_mermaid_blocks".to_string(),
-            message: "No mermaid code block found in markdown".to_string(),
-            line: None,
-            column: None,
-            snippet: None,
-            block_index: None,
-        });
+    push_missing_blocks_issue(&blocks, &mut issues);
+    for block in &blocks {
+        issues.extend(target_block_issues(block, target));
     }
 
     let block_infos = blocks
         .iter()
-        .map(|block| MermaidBlockInfo {
-            index: block.index,
-            start_line: block.start_line,
-            end_line: block.end_line,
-            line_count: block.content.lines().count() as u32,
-            char_count: block.content.chars().count() as u32,
-            first_line: block.content.lines().next().unwrap_or("").to_string(),
+        .map(|block| {
+            let features = FeatureTable::shared().detect(&block.content);
+            MermaidBlockInfo {
+                index: block.index,
+                id: block.id.clone(),
+                start_line: block.start_line,
+                end_line: block.end_line,
+                line_count: block.content.lines().count() as u32,
+                char_count: block.content.chars().count() as u32,
+                first_line: block.content.lines().next().unwrap_or("").to_string(),
+                syntax: block.syntax,
+                info_string: block.info_string.clone(),
+                diagram_type: detect_diagram_type(&block.content).map(str::to_string),
+                min_mermaid_version: min_mermaid_version(&features),
+                features,
+            }
         })
         .collect::<Vec<_>>();
 
-    let error_count = issues
-        .iter()
-        .filter(|issue| issue.severity == "error")
-        .count() as u32;
+    let matrix_targets = if matrix_targets.is_empty() {
+        Vec::new()
+    } else {
+        merge_targets(target, matrix_targets)
+    };
+    let compatibility = build_compatibility(
+        &blocks,
+        &matrix_targets,
+        &vec![vec![false; matrix_targets.len()]; blocks.len()],
+        &vec![None; matrix_targets.len()],
+    );
+
+    let error_count = count_errors(&issues);
 
     PreviewScanResult {
-        target: "github".to_string(),
+        target: target.as_str().to_string(),
         error_count,
         mermaid_block_count: blocks.len() as u32,
         blocks: block_infos,
         issues,
+        compatibility,
     }
 }
 
@@ -128,45 +315,158 @@ pub async fn validate_markdown_for_github(
     markdown: &str,
     timeout: Duration,
 ) -> PreviewValidationResult {
-    let (blocks, mut issues) = collect_mermaid_blocks(markdown);
+    validate_markdown(
+        markdown,
+        &ValidationOptions::new(PreviewTarget::Github, timeout),
+        &RendererRegistry::load_from_env().await,
+    )
+    .await
+}
 
-    let has_unclosed_mermaid = issues
-        .iter()
-        .any(|issue| issue.code == "mermaid_unclosed_fence");
+/// Renders every mermaid block and reports issues for `options.target`.
+/// Each block is rendered once per distinct renderer: the one resolved for
+/// the primary target and, when `options.matrix_targets` is not empty, the
+/// one pinned to each matrix target's Mermaid version.
+pub async fn validate_markdown(
+    markdown: &str,
+    options: &ValidationOptions,
+    renderers: &RendererRegistry,
+) -> PreviewValidationResult {
+    validate_markdown_blocks(markdown, 1..=u32::MAX, options, renderers).await
+}
 
-    if blocks.is_empty() && !has_unclosed_mermaid {
-        issues.push(PreviewIssue {
-            severity: "error".to_string(),
-            code: "no_mermaid_blocks".to_string(),
-            message: "No mermaid code block found in markdown".to_string(),
-            line: None,
-            column: None,
-            snippet: None,
-            block_index: None,
-        });
+/// Like [`validate_markdown`], but renders and reports only the blocks whose
+/// index is in `indexes`. `mermaid_block_count` still counts every block.
+pub async fn validate_markdown_blocks(
+    markdown: &str,
+    indexes: RangeInclusive<u32>,
+    options: &ValidationOptions,
+    renderers: &RendererRegistry,
+) -> PreviewValidationResult {
+    let target = options.target;
+    let (all_blocks, mut issues) = collect_mermaid_blocks(markdown);
+    let block_count = all_blocks.len() as u32;
+
+    push_missing_blocks_issue(&all_blocks, &mut issues);
+    let blocks = all_blocks
+        .into_iter()
+        .filter(|block| indexes.contains(&block.index))
+        .collect::<Vec<_>>();
+
+    let primary = match renderers.resolve(options.mermaid_version.as_deref(), target) {
+        Ok(renderer) => renderer,
+        Err(message) => {
+            issues.push(renderer_unavailable_issue(message));
+            return PreviewValidationResult {
+                target: target.as_str().to_string(),
+                valid: false,
+                error_count: count_errors(&issues),
+                mermaid_block_count: block_count,
+                renderer_version: None,
+                issues,
+                compatibility: Vec::new(),
+            };
+        }
+    };
+    if !blocks.is_empty() {
+        issues.extend(renderer_version_fallback_issue(renderers, options));
     }
 
-    for block in &blocks {
-        match render_diagram(&block.content, OutputFormat::Svg, timeout).await {
-            Ok(_) => {}
-            Err(err) => {
-                let error_message = err.to_error_message();
-                issues.push(build_mermaid_parse_issue(block, &error_message));
+    let matrix_targets = if options.matrix_targets.is_empty() {
+        Vec::new()
+    } else {
+        merge_targets(target, &options.matrix_targets)
+    };
+    let matrix_renderers = matrix_targets
+        .iter()
+        .map(|&matrix_target| {
+            if matrix_target == target {
+                primary
+            } else {
+                renderers.renderer_for_target(matrix_target)
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let control = &options.control;
+    let total = blocks.len() as u32;
+    let mut parse_failed = Vec::with_capacity(blocks.len());
+    let mut cancelled = false;
+    for (completed, block) in (1..).zip(&blocks) {
+        if control.is_cancelled() {
+            cancelled = true;
+            break;
+        }
+        issues.extend(target_block_issues(block, target));
+
+        let mut rendered: Vec<(&OsStr, bool)> = Vec::new();
+        for renderer in std::iter::once(primary).chain(matrix_renderers.iter().copied()) {
+            if rendered
+                .iter()
+                .any(|(command, _)| *command == renderer.command)
+            {
+                continue;
             }
+            let render = renderer.render(&block.content, OutputFormat::Svg, options.timeout);
+            let failed = match control.run(render).await {
+                None => {
+                    cancelled = true;
+                    break;
+                }
+                Some(Ok(_)) => false,
+                Some(Err(err)) => {
+                    if std::ptr::eq(renderer, primary) {
+                        issues.push(build_mermaid_parse_issue(block, &err.to_error_message()));
+                    }
+                    true
+                }
+            };
+            rendered.push((&renderer.command, failed));
+        }
+        if cancelled {
+            break;
         }
+
+        parse_failed.push(
+            matrix_renderers
+                .iter()
+                .map(|renderer| {
+                    rendered
+                        .iter()
+                        .any(|(command, failed)| *failed && *command == renderer.command)
+                })
+                .collect::<Vec<_>>(),
+        );
+        control.report(BlockProgress {
+            block_index: block.index,
+            completed,
+            total,
+        });
+    }
+    if cancelled {
+        issues.push(validation_cancelled_issue(format!(
+            "Validation was cancelled after {} of {total} block(s)",
+            parse_failed.len()
+        )));
     }
 
-    let error_count = issues
+    let renderer_versions = matrix_renderers
         .iter()
-        .filter(|issue| issue.severity == "error")
-        .count() as u32;
+        .map(|renderer| renderer.version_string())
+        .collect::<Vec<_>>();
+    let compatibility =
+        build_compatibility(&blocks, &matrix_targets, &parse_failed, &renderer_versions);
+
+    let error_count = count_errors(&issues);
 
     PreviewValidationResult {
-        target: "github".to_string(),
+        target: target.as_str().to_string(),
         valid: error_count == 0,
         error_count,
-        mermaid_block_count: blocks.len() as u32,
+        mermaid_block_count: block_count,
+        renderer_version: primary.version_string(),
         issues,
+        compatibility,
     }
 }
 
@@ -175,15 +475,33 @@ pub async fn validate_mermaid_block_in_markdown(
     block_index: u32,
     timeout: Duration,
 ) -> BlockValidationResult {
+    validate_mermaid_block(
+        markdown,
+        block_index,
+        &ValidationOptions::new(PreviewTarget::Github, timeout),
+        &RendererRegistry::load_from_env().await,
+    )
+    .await
+}
+
+/// Validates one block by index. `options.matrix_targets` is ignored.
+pub async fn validate_mermaid_block(
+    markdown: &str,
+    block_index: u32,
+    options: &ValidationOptions,
+    renderers: &RendererRegistry,
+) -> BlockValidationResult {
+    let target = options.target;
     let (blocks, issues) = collect_mermaid_blocks(markdown);
 
     let block = blocks.iter().find(|block| block.index == block_index);
     if block.is_none() {
         return BlockValidationResult {
-            target: "github".to_string(),
+            target: target.as_str().to_string(),
             block_index,
             found: false,
             valid: false,
+            renderer_version: None,
             issues: vec![PreviewIssue {
                 severity: "error".to_string(),
                 code: "block_not_found".to_string(),
@@ -201,30 +519,343 @@ pub async fn validate_mermaid_block_in_markdown(
         .into_iter()
         .filter(|issue| issue.block_index.is_none())
         .collect::<Vec<_>>();
-
-    match render_diagram(&block.content, OutputFormat::Svg, timeout).await {
-        Ok(_) => {}
-        Err(err) => block_issues.push(build_mermaid_parse_issue(block, &err.to_error_message())),
-    }
+    block_issues.extend(renderer_version_fallback_issue(renderers, options));
+    let (issues, renderer_version) = check_block(block, options, renderers).await;
+    block_issues.extend(issues);
 
     let valid = !block_issues
         .iter()
         .any(|issue| issue.severity.as_str() == "error");
 
     BlockValidationResult {
-        target: "github".to_string(),
+        target: target.as_str().to_string(),
         block_index,
         found: true,
         valid,
+        renderer_version,
         issues: block_issues,
     }
 }
 
-fn collect_mermaid_blocks(markdown: &str) -> (Vec<MermaidBlock>, Vec<PreviewIssue>) {
+/// The target rules and render result of one block, without the file-level
+/// issues of the markdown around it. Also returns the renderer's version.
+pub(crate) async fn check_block(
+    block: &MermaidBlock,
+    options: &ValidationOptions,
+    renderers: &RendererRegistry,
+) -> (Vec<PreviewIssue>, Option<String>) {
+    let mut issues = target_block_issues(block, options.target);
+
+    let renderer = match renderers.resolve(options.mermaid_version.as_deref(), options.target) {
+        Ok(renderer) => Some(renderer),
+        Err(message) => {
+            issues.push(renderer_unavailable_issue(message));
+            None
+        }
+    };
+
+    if let Some(renderer) = renderer {
+        issues.extend(render_block(block, renderer, options).await);
+    }
+
+    (issues, renderer.and_then(Renderer::version_string))
+}
+
+/// Renders one block with `renderer`: a parse issue when it fails, a
+/// cancellation issue when cancelled, nothing when it renders.
+pub(crate) async fn render_block(
+    block: &MermaidBlock,
+    renderer: &Renderer,
+    options: &ValidationOptions,
+) -> Option<PreviewIssue> {
+    let render = renderer.render(&block.content, OutputFormat::Svg, options.timeout);
+    match options.control.run(render).await {
+        None => Some(validation_cancelled_issue(
+            "Validation was cancelled".to_string(),
+        )),
+        Some(Ok(_)) => None,
+        Some(Err(err)) => Some(build_mermaid_parse_issue(block, &err.to_error_message())),
+    }
+}
+
+pub(crate) fn count_errors(issues: &[PreviewIssue]) -> u32 {
+    issues
+        .iter()
+        .filter(|issue| issue.severity == "error")
+        .count() as u32
+}
+
+pub(crate) fn renderer_unavailable_issue(message: String) -> PreviewIssue {
+    PreviewIssue {
+        severity: "error".to_string(),
+        code: "renderer_unavailable".to_string(),
+        message,
+        line: None,
+        column: None,
+        snippet: None,
+        block_index: None,
+    }
+}
+
+/// A warning, once per file, when no version was requested and versioned
+/// renderers are installed, but none for the Mermaid version `target` ships.
+pub(crate) fn renderer_version_fallback_issue(
+    renderers: &RendererRegistry,
+    options: &ValidationOptions,
+) -> Option<PreviewIssue> {
+    let target = options.target;
+    if options.mermaid_version.is_some() || !renderers.falls_back(target) {
+        return None;
+    }
+    let used = match renderers.renderer_for_target(target).version_string() {
+        Some(version) => format!("Mermaid {version}"),
+        None => "the default MERMAID_CLI renderer".to_string(),
+    };
+    Some(PreviewIssue {
+        severity: "warning".to_string(),
+        code: "renderer_version_fallback".to_string(),
+        message: format!(
+            "No renderer for Mermaid {} ({}) is installed; validated with {used}",
+            target.mermaid_version(),
+            target.display_name()
+        ),
+        line: None,
+        column: None,
+        snippet: None,
+        block_index: None,
+    })
+}
+
+fn validation_cancelled_issue(message: String) -> PreviewIssue {
+    PreviewIssue {
+        severity: "error".to_string(),
+        code: "validation_cancelled".to_string(),
+        message,
+        line: None,
+        column: None,
+        snippet: None,
+        block_index: None,
+    }
+}
+
+pub(crate) fn push_missing_blocks_issue(blocks: &[MermaidBlock], issues: &mut Vec<PreviewIssue>) {
+    let has_unclosed_mermaid = issues
+        .iter()
+        .any(|issue| issue.code == "mermaid_unclosed_fence");
+
+    if blocks.is_empty() && !has_unclosed_mermaid {
+        issues.push(PreviewIssue {
+            severity: "error".to_string(),
+            code: "no_mermaid_blocks".to_string(),
+            message: "No mermaid code block found in markdown".to_string(),
+            line: None,
+            column: None,
+            snippet: None,
+            block_index: None,
+        });
+    }
+}
+
+/// Issues caused by how `target` renders `block`, independent of whether the
+/// diagram itself parses.
+pub(crate) fn target_block_issues(
+    block: &MermaidBlock,
+    target: PreviewTarget,
+) -> Vec<PreviewIssue> {
+    let name = target.display_name();
+    let mut issues = Vec::new();
+    let mut push = |severity: &str, code: &str, message: String| {
+        issues.push(PreviewIssue {
+            severity: severity.to_string(),
+            code: code.to_string(),
+            message,
+            line: Some(block.start_line),
+            column: None,
+            snippet: None,
+            block_index: Some(block.index),
+        });
+    };
+
+    if !target.supports_syntax(block.syntax) {
+        let message = match block.syntax {
+            BlockSyntax::Fence => format!("{name} only renders Mermaid inside ::: mermaid blocks"),
+            BlockSyntax::Colon => format!("{name} does not render ::: mermaid blocks"),
+        };
+        push("error", "mermaid_block_syntax_unsupported", message);
+    } else if !target.accepts_info_string(&block.info_string) {
+        push(
+            "warning",
+            "mermaid_info_string_unsupported",
+            format!(
+                "{name} may not render blocks tagged `{}`; use `mermaid`",
+                block.info_string
+            ),
+        );
+    }
+
+    if let Some(diagram_type) = detect_diagram_type(&block.content) {
+        if is_known_diagram_type(diagram_type) && !target.supports_diagram_type(diagram_type) {
+            push(
+                "error",
+                "unsupported_diagram_type",
+                format!("{name} does not support `{diagram_type}` diagrams"),
+            );
+        }
+    }
+
+    for diagnostic in check_diagram_config(&block.content, target) {
+        issues.push(PreviewIssue {
+            severity: diagnostic.severity.to_string(),
+            code: diagnostic.code.to_string(),
+            message: diagnostic.message,
+            line: Some(block.start_line + diagnostic.line),
+            column: diagnostic.column,
+            snippet: None,
+            block_index: Some(block.index),
+        });
+    }
+
+    let target_version = target.parsed_mermaid_version();
+    for feature in FeatureTable::shared().detect(&block.content) {
+        let too_new = MermaidVersion::parse(&feature.min_version)
+            .is_some_and(|min_version| min_version > target_version);
+        if too_new {
+            issues.push(PreviewIssue {
+                severity: "warning".to_string(),
+                code: "feature_requires_newer_mermaid".to_string(),
+                message: format!(
+                    "{} ({}) requires Mermaid {}+, but {name} ships Mermaid {}",
+                    feature.description,
+                    feature.id,
+                    feature.min_version,
+                    target.mermaid_version()
+                ),
+                line: Some(block.start_line + feature.line),
+                column: None,
+                snippet: None,
+                block_index: Some(block.index),
+            });
+        }
+    }
+
+    issues
+}
+
+/// `parse_failed[block][target]` and `renderer_versions[target]` line up
+/// with `blocks` and `targets`.
+fn build_compatibility(
+    blocks: &[MermaidBlock],
+    targets: &[PreviewTarget],
+    parse_failed: &[Vec<bool>],
+    renderer_versions: &[Option<String>],
+) -> Vec<BlockCompatibility> {
+    if targets.is_empty() {
+        return Vec::new();
+    }
+    blocks
+        .iter()
+        .zip(parse_failed)
+        .map(|(block, parse_failed)| BlockCompatibility {
+            block_index: block.index,
+            targets: targets
+                .iter()
+                .enumerate()
+                .map(|(position, &target)| {
+                    let mut issue_codes = Vec::new();
+                    let mut warning_codes = Vec::new();
+                    for issue in target_block_issues(block, target) {
+                        let codes = if issue.severity == "error" {
+                            &mut issue_codes
+                        } else {
+                            &mut warning_codes
+                        };
+                        if !codes.contains(&issue.code) {
+                            codes.push(issue.code);
+                        }
+                    }
+                    if parse_failed[position] {
+                        issue_codes.push("mermaid_parse_error".to_string());
+                    }
+                    TargetCompatibility {
+                        target,
+                        mermaid_version: target.mermaid_version().to_string(),
+                        renderer_version: renderer_versions[position].clone(),
+                        compatible: issue_codes.is_empty(),
+                        issue_codes,
+                        warning_codes,
+                    }
+                })
+                .collect(),
+        })
+        .collect()
+}
+
+/// Hex SHA-256 of `text`, used to identify diagram sources.
+pub fn content_hash(text: &str) -> String {
+    Sha256::digest(text.as_bytes())
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// Returns the diagram keyword of `content`, skipping blank lines, `%%`
+/// comments and directives, and YAML front matter.
+pub fn detect_diagram_type(content: &str) -> Option<&str> {
+    let mut lines = content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty());
+    let mut line = lines.next()?;
+    if line == "---" {
+        lines.by_ref().find(|line| *line == "---")?;
+        line = lines.next()?;
+    }
+    while line.starts_with("%%") {
+        line = lines.next()?;
+    }
+    line.split_whitespace().next()
+}
+
+fn is_known_diagram_type(diagram_type: &str) -> bool {
+    PreviewTarget::ALL
+        .iter()
+        .any(|target| target.supports_diagram_type(diagram_type))
+}
+
+/// Replaces the content lines of `block` in `markdown`, keeping the fences,
+/// every other line and the document's line endings untouched.
+pub(crate) fn replace_block_content(markdown: &str, block: &MermaidBlock, content: &str) -> String {
+    let newline = if markdown.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    };
+    let mut output = String::with_capacity(markdown.len() + content.len());
+    for (idx, line) in markdown.split_inclusive('\n').enumerate() {
+        let line_no = (idx + 1) as u32;
+        if line_no == block.end_line {
+            for content_line in content.lines() {
+                output.push_str(content_line);
+                output.push_str(newline);
+            }
+        }
+        if line_no > block.start_line && line_no < block.end_line {
+            continue;
+        }
+        output.push_str(line);
+    }
+    output
+}
+
+pub(crate) fn collect_mermaid_blocks(markdown: &str) -> (Vec<MermaidBlock>, Vec<PreviewIssue>) {
     let mut blocks = Vec::new();
     let mut issues = Vec::new();
     let mut fence_state: Option<FenceState<'_>> = None;
     let mut mermaid_index = 0u32;
+    // Slugs of the enclosing headings with their levels, and the blocks
+    // seen so far per heading path and content hash.
+    let mut headings: Vec<(usize, String)> = Vec::new();
+    let mut seen_ids: HashMap<String, u32> = HashMap::new();
 
     for (idx, raw_line) in markdown.lines().enumerate() {
         let line_no = (idx + 1) as u32;
@@ -235,11 +866,27 @@ fn collect_mermaid_blocks(markdown: &str) -> (Vec<MermaidBlock>, Vec<PreviewIssu
                 let closed = fence_state.take().expect("fence state exists");
                 if closed.is_mermaid {
                     mermaid_index += 1;
+                    let path = headings
+                        .iter()
+                        .map(|(_, slug)| slug.as_str())
+                        .filter(|slug| !slug.is_empty())
+                        .collect::<Vec<_>>()
+                        .join("/");
+                    let content = closed.content_lines.join("\n");
+                    let id = format!("{path}#{}", &content_hash(&content)[..BLOCK_ID_HASH_LEN]);
+                    let ordinal = seen_ids.entry(id.clone()).or_default();
+                    *ordinal += 1;
                     blocks.push(MermaidBlock {
                         index: mermaid_index,
+                        id: match *ordinal {
+                            1 => id,
+                            ordinal => format!("{id}-{ordinal}"),
+                        },
                         start_line: closed.start_line,
                         end_line: line_no,
-                        content: closed.content_lines.join("\n"),
+                        syntax: syntax_for_marker(closed.marker),
+                        info_string: closed.info_string,
+                        content,
                     });
                 }
             } else {
@@ -248,11 +895,17 @@ fn collect_mermaid_blocks(markdown: &str) -> (Vec<MermaidBlock>, Vec<PreviewIssu
             continue;
         }
 
-        if let Some((marker, len, is_mermaid)) = parse_fence_start(line) {
+        if let Some((level, text)) = parse_heading(raw_line) {
+            headings.retain(|(outer, _)| *outer < level);
+            headings.push((level, heading_slug(text)));
+            continue;
+        }
+        if let Some((marker, len, info_string)) = parse_fence_start(line) {
             fence_state = Some(FenceState {
                 marker,
                 len,
-                is_mermaid,
+                is_mermaid: is_mermaid_lang(info_string),
+                info_string: info_string.to_string(),
                 start_line: line_no,
                 content_lines: Vec::with_capacity(8),
             });
@@ -260,16 +913,19 @@ fn collect_mermaid_blocks(markdown: &str) -> (Vec<MermaidBlock>, Vec<PreviewIssu
     }
 
     if let Some(unclosed) = fence_state {
-        let (code, message) = if unclosed.is_mermaid {
-            (
+        let (code, message) = match (unclosed.is_mermaid, syntax_for_marker(unclosed.marker)) {
+            (true, BlockSyntax::Fence) => (
                 "mermaid_unclosed_fence",
                 "Mermaid code block is missing closing fence ```",
-            )
-        } else {
-            (
+            ),
+            (true, BlockSyntax::Colon) => (
+                "mermaid_unclosed_fence",
+                "Mermaid block is missing closing marker :::",
+            ),
+            (false, _) => (
                 "markdown_unclosed_fence",
                 "Markdown code fence is missing closing marker",
-            )
+            ),
         };
         issues.push(PreviewIssue {
             severity: "error".to_string(),
@@ -285,10 +941,20 @@ fn collect_mermaid_blocks(markdown: &str) -> (Vec<MermaidBlock>, Vec<PreviewIssu
     (blocks, issues)
 }
 
-fn parse_fence_start(line: &str) -> Option<(char, usize, bool)> {
+fn syntax_for_marker(marker: char) -> BlockSyntax {
+    if marker == ':' {
+        BlockSyntax::Colon
+    } else {
+        BlockSyntax::Fence
+    }
+}
+
+/// Colon blocks only open for `::: mermaid`; other `:::` containers are left
+/// to the surrounding markdown.
+fn parse_fence_start(line: &str) -> Option<(char, usize, &str)> {
     let mut chars = line.chars();
     let marker = chars.next()?;
-    if marker != '`' && marker != '~' {
+    if marker != '`' && marker != '~' && marker != ':' {
         return None;
     }
 
@@ -305,7 +971,52 @@ fn parse_fence_start(line: &str) -> Option<(char, usize, bool)> {
     }
 
     let rest = line[len..].trim();
-    Some((marker, len, is_mermaid_lang(rest)))
+    if marker == ':' && !is_mermaid_lang(rest) {
+        return None;
+    }
+    Some((marker, len, rest))
+}
+
+/// The level and text of an ATX heading such as `## Data flow ##`.
+fn parse_heading(raw_line: &str) -> Option<(usize, &str)> {
+    let line = raw_line.trim_start_matches(' ');
+    if raw_line.len() - line.len() > 3 {
+        return None;
+    }
+    let level = line.bytes().take_while(|&byte| byte == b'#').count();
+    if !(1..=6).contains(&level) {
+        return None;
+    }
+    let rest = &line[level..];
+    if !rest.is_empty() && !rest.starts_with([' ', '\t']) {
+        return None;
+    }
+    let text = rest.trim();
+    let text = match text.trim_end_matches('#') {
+        stripped if stripped.is_empty() || stripped.ends_with([' ', '\t']) => stripped.trim_end(),
+        _ => text,
+    };
+    Some((level, text))
+}
+
+/// The heading path part of a block id.
+pub(crate) fn block_section(id: &str) -> &str {
+    id.rsplit_once('#').map_or(id, |(section, _)| section)
+}
+
+/// The anchor GitHub generates for a heading: lowercase, spaces as `-`,
+/// punctuation other than `-` and `_` dropped.
+pub(crate) fn heading_slug(text: &str) -> String {
+    text.trim()
+        .to_lowercase()
+        .chars()
+        .filter_map(|ch| match ch {
+            ' ' => Some('-'),
+            '-' | '_' => Some(ch),
+            ch if ch.is_alphanumeric() => Some(ch),
+            _ => None,
+        })
+        .collect()
 }
 
 fn is_fence_close(line: &str, marker: char, min_len: usize) -> bool {
@@ -464,8 +1175,11 @@ mod tests {
     fn map_local_error_line_to_markdown_line() {
         let block = MermaidBlock {
             index: 2,
+            id: "#2".to_string(),
             start_line: 20,
             end_line: 24,
+            syntax: BlockSyntax::Fence,
+            info_string: "mermaid".to_string(),
             content: "graph TD\nA-->B".to_string(),
         };
         let issue = build_mermaid_parse_issue(
@@ -484,4 +1198,178 @@ mod tests {
         assert_eq!(scan.blocks[0].line_count, 2);
         assert_eq!(scan.blocks[0].first_line, "graph TD");
     }
+
+    #[test]
+    fn parse_colon_blocks_and_ignore_other_containers() {
+        let markdown = "::: note\ntext\n:::\n\n::: mermaid\ngraph TD\nA-->B\n:::\n";
+        let (blocks, issues) = collect_mermaid_blocks(markdown);
+        assert!(issues.is_empty());
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(blocks[0].syntax, BlockSyntax::Colon);
+        assert_eq!(blocks[0].start_line, 5);
+        assert_eq!(blocks[0].content, "graph TD\nA-->B");
+    }
+
+    #[test]
+    fn detect_diagram_type_skips_front_matter_and_directives() {
+        let content = "---\ntitle: x\n---\n%%{init: {}}%%\n\nsequenceDiagram\nA->>B: hi";
+        assert_eq!(detect_diagram_type(content), Some("sequenceDiagram"));
+        assert_eq!(detect_diagram_type("graph TD\nA-->B"), Some("graph"));
+        assert_eq!(detect_diagram_type("  \n"), None);
+    }
+
+    #[test]
+    fn scan_builds_compatibility_matrix() {
+        let markdown = "```mermaid\narchitecture-beta\n```\n\n::: mermaid\ngraph TD\nA-->B\n:::\n";
+        let scan = scan_markdown_for_targets(
+            markdown,
+            PreviewTarget::Github,
+            &[PreviewTarget::AzureDevops],
+        );
+        assert_eq!(scan.target, "github");
+        assert_eq!(
+            scan.blocks[0].diagram_type.as_deref(),
+            Some("architecture-beta")
+        );
+        assert!(scan
+            .issues
+            .iter()
+            .any(|issue| issue.code == "mermaid_block_syntax_unsupported"
+                && issue.block_index == Some(2)));
+
+        let first = &scan.compatibility[0].targets;
+        assert_eq!(first[0].target, PreviewTarget::Github);
+        assert!(first[0].compatible);
+        assert!(!first[1].compatible);
+        assert!(first[1]
+            .issue_codes
+            .contains(&"unsupported_diagram_type".to_string()));
+
+        let second = &scan.compatibility[1].targets;
+        assert!(!second[0].compatible);
+        assert!(second[1].compatible);
+    }
+
+    #[test]
+    fn warn_when_target_mermaid_is_older_than_feature() {
+        let markdown = "# Doc\n\n```mermaid\nflowchart TD\n  A@{ shape: rect }\n```\n";
+        let gitlab = scan_markdown_for_targets(markdown, PreviewTarget::Gitlab, &[]);
+        let issue = gitlab
+            .issues
+            .iter()
+            .find(|issue| issue.code == "feature_requires_newer_mermaid")
+            .expect("expected feature warning");
+        assert_eq!(issue.severity, "warning");
+        assert_eq!(issue.line, Some(5));
+        assert_eq!(gitlab.error_count, 0);
+        assert_eq!(gitlab.blocks[0].features[0].id, "shape-syntax");
+        assert_eq!(
+            gitlab.blocks[0].min_mermaid_version.as_deref(),
+            Some("11.3.0")
+        );
+
+        let github =
+            scan_markdown_for_targets(markdown, PreviewTarget::Github, &[PreviewTarget::Gitlab]);
+        assert!(github.issues.is_empty());
+        let cells = &github.compatibility[0].targets;
+        assert!(cells[0].warning_codes.is_empty());
+        assert!(cells[1].compatible);
+        assert_eq!(
+            cells[1].warning_codes,
+            vec!["feature_requires_newer_mermaid"]
+        );
+    }
+
+    #[test]
+    fn scan_reports_config_issues_at_markdown_lines() {
+        let markdown =
+            "# Doc\n\n```mermaid\n%%{init: {'theme': 'midnight'}}%%\ngraph TD\nA-->B\n```\n";
+        let scan = scan_markdown_for_mermaid(markdown);
+        let issue = scan
+            .issues
+            .iter()
+            .find(|issue| issue.code == "config_invalid_value")
+            .expect("expected config issue");
+        assert_eq!(issue.line, Some(4));
+        assert_eq!(issue.column, Some(12));
+        assert_eq!(issue.block_index, Some(1));
+        assert_eq!(scan.error_count, 1);
+    }
+
+    #[test]
+    fn block_ids_follow_the_heading_path() {
+        let markdown = "```mermaid\ngraph TD\n```\n# Architecture\n## Data flow ##\n```mermaid\ngraph TD\n```\n```\n# not a heading\n```\n~~~mermaid\ngraph TD\n~~~\n## Deploy: v2!\n```mermaid\ngraph LR\n```\n#hashtag\n# Next\n```mermaid\ngraph TD\n```\n";
+        let (blocks, _) = collect_mermaid_blocks(markdown);
+        let ids = blocks
+            .iter()
+            .map(|block| block.id.as_str())
+            .collect::<Vec<_>>();
+        let td = &content_hash("graph TD")[..BLOCK_ID_HASH_LEN];
+        let lr = &content_hash("graph LR")[..BLOCK_ID_HASH_LEN];
+        assert_eq!(
+            ids,
+            vec![
+                format!("#{td}"),
+                format!("architecture/data-flow#{td}"),
+                format!("architecture/data-flow#{td}-2"),
+                format!("architecture/deploy-v2#{lr}"),
+                format!("next#{td}"),
+            ]
+        );
+    }
+
+    #[test]
+    fn block_ids_survive_blocks_inserted_before_them() {
+        let before = "# Guide\n```mermaid\ngraph TD\n  A-->B\n```\n";
+        let after = "# Guide\n```mermaid\nsequenceDiagram\n  A->>B: hi\n```\n```mermaid\ngraph TD\n  A-->B\n```\n";
+        let (before, _) = collect_mermaid_blocks(before);
+        let (after, _) = collect_mermaid_blocks(after);
+        assert_eq!(after[1].id, before[0].id);
+        assert_ne!(after[0].id, before[0].id);
+    }
+
+    #[test]
+    fn warn_when_the_target_version_is_not_installed() {
+        let mut renderers = RendererRegistry::default();
+        let options = ValidationOptions::new(PreviewTarget::Gitlab, Duration::from_secs(1));
+        assert!(renderer_version_fallback_issue(&renderers, &options).is_none());
+
+        renderers.insert(MermaidVersion::parse("10.2.4").unwrap(), "mmdc-10.2.4");
+        renderers.insert(MermaidVersion::parse("11.4.1").unwrap(), "mmdc-11.4.1");
+        let issue = renderer_version_fallback_issue(&renderers, &options).unwrap();
+        assert_eq!(issue.severity, "warning");
+        assert_eq!(issue.code, "renderer_version_fallback");
+        assert!(issue.message.ends_with("validated with Mermaid 10.2.4"));
+
+        let pinned = ValidationOptions {
+            mermaid_version: Some("10.2.4".to_string()),
+            ..options.clone()
+        };
+        assert!(renderer_version_fallback_issue(&renderers, &pinned).is_none());
+        let github = ValidationOptions::new(PreviewTarget::Github, Duration::from_secs(1));
+        assert!(renderer_version_fallback_issue(&renderers, &github).is_none());
+
+        let mut newer = RendererRegistry::default();
+        newer.insert(MermaidVersion::parse("11.4.1").unwrap(), "mmdc-11.4.1");
+        let issue = renderer_version_fallback_issue(&newer, &options).unwrap();
+        assert!(issue.message.contains("the default MERMAID_CLI renderer"));
+    }
+
+    #[test]
+    fn replace_block_content_keeps_fences_and_line_endings() {
+        let markdown = "intro\r\n  ```mermaid\r\n  graph TD\r\n  A-->B\r\n  ```\r\nend";
+        let (blocks, _) = collect_mermaid_blocks(markdown);
+        let replaced = replace_block_content(markdown, &blocks[0], "  flowchart TD\n  A-->C");
+        assert_eq!(
+            replaced,
+            "intro\r\n  ```mermaid\r\n  flowchart TD\r\n  A-->C\r\n  ```\r\nend"
+        );
+
+        let empty = "```mermaid\n```\n";
+        let (blocks, _) = collect_mermaid_blocks(empty);
+        assert_eq!(
+            replace_block_content(empty, &blocks[0], "graph TD"),
+            "```mermaid\ngraph TD\n```\n"
+        );
+    }
 }
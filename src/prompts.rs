@@ -0,0 +1,237 @@
+use std::fmt::Write;
+
+use crate::{
+    feature_detection::FeatureTable,
+    preview_target::{BlockSyntax, PreviewTarget},
+    preview_validator::{
+        collect_mermaid_blocks, scan_markdown_for_targets, validate_markdown,
+        validate_mermaid_block, PreviewIssue, ValidationOptions,
+    },
+    renderer_registry::RendererRegistry,
+};
+
+/// Text of the `fix-mermaid-block` prompt: the block source, its current
+/// diagnostics and the rules of the validation target.
+pub async fn fix_block_prompt(
+    path: &str,
+    markdown: &str,
+    block_index: u32,
+    options: &ValidationOptions,
+    renderers: &RendererRegistry,
+) -> Result<String, String> {
+    let (blocks, _) = collect_mermaid_blocks(markdown);
+    let block = blocks
+        .iter()
+        .find(|block| block.index == block_index)
+        .ok_or_else(|| {
+            format!(
+                "Mermaid block index {block_index} was not found ({} block(s) in {path})",
+                blocks.len()
+            )
+        })?;
+    let result = validate_mermaid_block(markdown, block_index, options, renderers).await;
+    let target = options.target;
+
+    let mut text = format!(
+        "Fix Mermaid block #{block_index} in {path} (lines {}-{}) so it renders in {} previews.\n\n",
+        block.start_line,
+        block.end_line,
+        target.display_name()
+    );
+    push_source(&mut text, &block.content);
+    if result.valid {
+        text.push_str("\nThe block currently has no errors. Keep its meaning; only fix the warnings below, if any.\n");
+    }
+    text.push_str("\nCurrent diagnostics:\n");
+    push_issues(&mut text, &result.issues);
+    text.push('\n');
+    text.push_str(&target_rules(&[target]));
+    text.push_str(
+        "\nReply with the corrected diagram source only, without the surrounding fence. Keep node ids, labels and layout unless a diagnostic requires changing them.\n",
+    );
+    Ok(text)
+}
+
+/// Text of the `write-mermaid-diagram` prompt.
+pub fn write_diagram_prompt(
+    diagram_type: &str,
+    description: &str,
+    targets: &[PreviewTarget],
+) -> String {
+    let mut text = format!("Write a Mermaid `{diagram_type}` diagram for the following description:\n\n{description}\n\n");
+    let unsupported = targets
+        .iter()
+        .filter(|target| !target.supports_diagram_type(diagram_type))
+        .map(|target| target.display_name())
+        .collect::<Vec<_>>();
+    if !unsupported.is_empty() {
+        let _ = writeln!(
+            text,
+            "Warning: `{diagram_type}` does not render on {}. Suggest a supported diagram type if one fits.\n",
+            unsupported.join(", ")
+        );
+    }
+    text.push_str(&target_rules(targets));
+    text.push_str("\nReply with one Mermaid block using the block syntax above.\n");
+    text
+}
+
+/// Text of the `review-diagrams-in-file` prompt: every block with its
+/// source, diagram type and diagnostics.
+pub async fn review_file_prompt(
+    path: &str,
+    markdown: &str,
+    options: &ValidationOptions,
+    renderers: &RendererRegistry,
+) -> String {
+    let scan = scan_markdown_for_targets(markdown, options.target, &[]);
+    let validation = validate_markdown(markdown, options, renderers).await;
+    let (blocks, _) = collect_mermaid_blocks(markdown);
+    let target = options.target;
+
+    let mut text = format!(
+        "Review the Mermaid diagrams in {path} for {} previews: {} block(s), {} error(s).\n\n",
+        target.display_name(),
+        validation.mermaid_block_count,
+        validation.error_count
+    );
+    text.push_str("For each block, check that it renders, that it matches the surrounding prose, and that labels are clear. Propose concrete fixes.\n");
+
+    let file_issues = validation
+        .issues
+        .iter()
+        .filter(|issue| issue.block_index.is_none())
+        .cloned()
+        .collect::<Vec<_>>();
+    if !file_issues.is_empty() {
+        text.push_str("\nFile-level diagnostics:\n");
+        push_issues(&mut text, &file_issues);
+    }
+
+    for (info, block) in scan.blocks.iter().zip(&blocks) {
+        let _ = write!(
+            text,
+            "\n## Block #{} (lines {}-{}",
+            info.index, info.start_line, info.end_line
+        );
+        if let Some(diagram_type) = &info.diagram_type {
+            let _ = write!(text, ", {diagram_type}");
+        }
+        text.push_str(")\n\n");
+        push_source(&mut text, &block.content);
+        let issues = validation
+            .issues
+            .iter()
+            .filter(|issue| issue.block_index == Some(info.index))
+            .cloned()
+            .collect::<Vec<_>>();
+        text.push_str("\nDiagnostics:\n");
+        push_issues(&mut text, &issues);
+    }
+
+    text.push('\n');
+    text.push_str(&target_rules(&[target]));
+    text
+}
+
+/// Syntax rules for each target: block syntax, Mermaid version, diagram
+/// types, features that are too new and config keys the host ignores.
+pub fn target_rules(targets: &[PreviewTarget]) -> String {
+    let mut text = String::from("Target rules:\n");
+    for target in targets {
+        let profile = target.profile();
+        let _ = writeln!(
+            text,
+            "\n{} (Mermaid {}):",
+            profile.name, profile.mermaid_version
+        );
+        let syntaxes = profile
+            .block_syntaxes
+            .iter()
+            .map(|syntax| match syntax {
+                BlockSyntax::Fence => format!("```{} fenced code block", profile.info_strings[0]),
+                BlockSyntax::Colon => "::: mermaid block closed by :::".to_string(),
+            })
+            .collect::<Vec<_>>();
+        let _ = writeln!(text, "- Block syntax: {}", syntaxes.join(" or "));
+        let _ = writeln!(
+            text,
+            "- Diagram types: {}",
+            profile.diagram_types.join(", ")
+        );
+        let too_new = FeatureTable::shared()
+            .features_newer_than(target.parsed_mermaid_version())
+            .into_iter()
+            .map(|(_, description, min_version)| format!("{description} ({min_version})"))
+            .collect::<Vec<_>>();
+        if !too_new.is_empty() {
+            let _ = writeln!(text, "- Not available yet: {}", too_new.join(", "));
+        }
+        if !profile.ignored_config_keys.is_empty() {
+            let _ = writeln!(
+                text,
+                "- Ignored when set inside a diagram: {}",
+                profile.ignored_config_keys.join(", ")
+            );
+        }
+    }
+    text
+}
+
+pub(crate) fn push_source(text: &mut String, content: &str) {
+    text.push_str("```mermaid\n");
+    text.push_str(content);
+    if !content.ends_with('\n') {
+        text.push('\n');
+    }
+    text.push_str("```\n");
+}
+
+pub(crate) fn push_issues(text: &mut String, issues: &[PreviewIssue]) {
+    if issues.is_empty() {
+        text.push_str("- none\n");
+        return;
+    }
+    for issue in issues {
+        let _ = write!(
+            text,
+            "- [{}] {}: {}",
+            issue.severity, issue.code, issue.message
+        );
+        if let Some(line) = issue.line {
+            let _ = write!(text, " (line {line})");
+        }
+        text.push('\n');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_prompt_lists_rules_and_unsupported_targets() {
+        let text = write_diagram_prompt(
+            "architecture-beta",
+            "Services behind a load balancer",
+            &[PreviewTarget::Github, PreviewTarget::AzureDevops],
+        );
+        assert!(text.contains("Services behind a load balancer"));
+        assert!(text.contains("does not render on Azure DevOps"));
+        assert!(text.contains("GitHub (Mermaid 11.4.1):"));
+        assert!(text.contains("- Block syntax: ::: mermaid block closed by :::"));
+        assert!(text.contains("Ignored when set inside a diagram: secure"));
+    }
+
+    #[test]
+    fn target_rules_list_features_newer_than_target() {
+        let rules = target_rules(&[PreviewTarget::Gitlab]);
+        assert!(rules.contains("- Block syntax: ```mermaid fenced code block"));
+        let too_new = rules
+            .lines()
+            .find(|line| line.starts_with("- Not available yet:"))
+            .unwrap();
+        assert!(too_new.contains("(11.3.0)"));
+        assert!(!too_new.contains("(10.9.0)"));
+    }
+}
@@ -0,0 +1,136 @@
+use crate::preview_validator::PreviewIssue;
+
+/// A replacement of the text between two positions. Lines and columns are
+/// 1-based, columns count UTF-16 code units, and the end is exclusive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextEdit {
+    pub start_line: u32,
+    pub start_column: u32,
+    pub end_line: u32,
+    pub end_column: u32,
+    pub new_text: String,
+}
+
+/// A mechanical fix for one issue.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuickFix {
+    pub description: String,
+    pub edits: Vec<TextEdit>,
+}
+
+/// The fix for `issue` in `markdown`, for issues that have exactly one
+/// obvious fix: retagging a block `mermaid` and closing an unclosed block.
+pub fn quick_fix(markdown: &str, issue: &PreviewIssue) -> Option<QuickFix> {
+    let line_no = issue.line?;
+    let line = markdown.lines().nth(line_no.checked_sub(1)? as usize)?;
+    match issue.code.as_str() {
+        "mermaid_info_string_unsupported" => retag_mermaid(line_no, line),
+        "mermaid_unclosed_fence" => close_fence(markdown, line),
+        _ => None,
+    }
+}
+
+fn retag_mermaid(line_no: u32, line: &str) -> Option<QuickFix> {
+    let (indent, marker, rest) = split_fence(line)?;
+    let spaces = rest.len() - rest.trim_start().len();
+    let info = rest.split_whitespace().next()?;
+    let start = indent.len() + marker.len() + spaces;
+    Some(QuickFix {
+        description: format!("Tag the block `mermaid` instead of `{info}`"),
+        edits: vec![TextEdit {
+            start_line: line_no,
+            start_column: utf16_column(line, start),
+            end_line: line_no,
+            end_column: utf16_column(line, start + info.len()),
+            new_text: "mermaid".to_string(),
+        }],
+    })
+}
+
+fn close_fence(markdown: &str, opening: &str) -> Option<QuickFix> {
+    let (_, marker, _) = split_fence(opening)?;
+    let line_count = markdown.lines().count() as u32;
+    let edit = if markdown.ends_with('\n') || markdown.is_empty() {
+        TextEdit {
+            start_line: line_count + 1,
+            start_column: 1,
+            end_line: line_count + 1,
+            end_column: 1,
+            new_text: format!("{marker}\n"),
+        }
+    } else {
+        let last = markdown.lines().last().unwrap_or("");
+        let column = utf16_column(last, last.len());
+        TextEdit {
+            start_line: line_count,
+            start_column: column,
+            end_line: line_count,
+            end_column: column,
+            new_text: format!("\n{marker}\n"),
+        }
+    };
+    Some(QuickFix {
+        description: format!("Close the block with {marker}"),
+        edits: vec![edit],
+    })
+}
+
+/// Indentation, marker run and the rest of a fence or `:::` line.
+fn split_fence(line: &str) -> Option<(&str, &str, &str)> {
+    let trimmed = line.trim_start();
+    let indent = &line[..line.len() - trimmed.len()];
+    let marker = trimmed
+        .chars()
+        .next()
+        .filter(|ch| matches!(ch, '`' | '~' | ':'))?;
+    let len = trimmed.len() - trimmed.trim_start_matches(marker).len();
+    if len < 3 {
+        return None;
+    }
+    Some((indent, &trimmed[..len], &trimmed[len..]))
+}
+
+/// 1-based UTF-16 column of byte offset `offset` in `line`.
+fn utf16_column(line: &str, offset: usize) -> u32 {
+    line[..offset].encode_utf16().count() as u32 + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn issue(code: &str, line: u32) -> PreviewIssue {
+        PreviewIssue {
+            severity: "warning".to_string(),
+            code: code.to_string(),
+            message: String::new(),
+            line: Some(line),
+            column: None,
+            snippet: None,
+            block_index: Some(1),
+        }
+    }
+
+    #[test]
+    fn retags_and_closes_blocks() {
+        let markdown = "# Ä\n  ``` Mermaid title\ngraph TD\n```\n";
+        let fix = quick_fix(markdown, &issue("mermaid_info_string_unsupported", 2)).unwrap();
+        assert_eq!(
+            fix.edits,
+            vec![TextEdit {
+                start_line: 2,
+                start_column: 7,
+                end_line: 2,
+                end_column: 14,
+                new_text: "mermaid".to_string(),
+            }]
+        );
+
+        let unclosed = "text\n::: mermaid\ngraph TD";
+        let fix = quick_fix(unclosed, &issue("mermaid_unclosed_fence", 2)).unwrap();
+        assert_eq!(fix.edits[0].start_line, 3);
+        assert_eq!(fix.edits[0].start_column, 9);
+        assert_eq!(fix.edits[0].new_text, "\n:::\n");
+        assert!(quick_fix(unclosed, &issue("mermaid_parse_error", 2)).is_none());
+    }
+}
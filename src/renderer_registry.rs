@@ -0,0 +1,260 @@
+use std::{
+    collections::BTreeMap,
+    env,
+    ffi::OsString,
+    io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use serde_json::json;
+
+use crate::{
+    cli_runner::{mermaid_cli_command, render_diagram_with_cli, OutputFormat, RenderError},
+    logging::{log, LogLevel},
+    preview_target::{MermaidVersion, PreviewTarget},
+};
+
+/// Locations checked, in order, inside each `<version>` directory.
+const MMDC_CANDIDATES: &[&str] = &["node_modules/.bin/mmdc", "bin/mmdc", "mmdc"];
+
+/// One mermaid-cli installation. `version` is `None` for the unversioned
+/// renderer configured through `MERMAID_CLI`.
+#[derive(Debug, Clone)]
+pub struct Renderer {
+    pub command: OsString,
+    pub version: Option<MermaidVersion>,
+}
+
+impl Renderer {
+    pub async fn render(
+        &self,
+        diagram: &str,
+        format: OutputFormat,
+        timeout: Duration,
+    ) -> Result<Vec<u8>, RenderError> {
+        render_diagram_with_cli(&self.command, diagram, format, timeout).await
+    }
+
+    pub fn version_string(&self) -> Option<String> {
+        self.version.map(|version| version.to_string())
+    }
+}
+
+/// Renderer installations keyed by the Mermaid version they bundle.
+///
+/// Installations are discovered from `MERMAID_RENDERERS_DIR`, where every
+/// subdirectory named after a version (e.g. `11.4.1/`) holds an `mmdc`
+/// executable. Calls without a matching installation fall back to
+/// `MERMAID_CLI`.
+#[derive(Debug, Clone)]
+pub struct RendererRegistry {
+    default: Renderer,
+    installs: BTreeMap<MermaidVersion, Renderer>,
+}
+
+impl Default for RendererRegistry {
+    fn default() -> Self {
+        RendererRegistry {
+            default: Renderer {
+                command: mermaid_cli_command(),
+                version: None,
+            },
+            installs: BTreeMap::new(),
+        }
+    }
+}
+
+impl RendererRegistry {
+    pub fn from_env() -> Self {
+        let mut registry = RendererRegistry::default();
+        if let Some(dir) = env::var_os("MERMAID_RENDERERS_DIR") {
+            if let Err(err) = registry.discover(Path::new(&dir)) {
+                log(
+                    LogLevel::Warning,
+                    "config",
+                    json!({
+                        "event": "renderersDirUnreadable",
+                        "dir": Path::new(&dir).display().to_string(),
+                        "error": err.to_string(),
+                    }),
+                );
+            }
+        }
+        registry
+    }
+
+    /// Like [`RendererRegistry::from_env`], but scans `MERMAID_RENDERERS_DIR`
+    /// on the blocking pool so that async callers do not stall a worker.
+    pub async fn load_from_env() -> Self {
+        tokio::task::spawn_blocking(RendererRegistry::from_env)
+            .await
+            .unwrap_or_default()
+    }
+
+    /// Registers every `<version>/…/mmdc` found below `dir`. Entries whose
+    /// name is not a version or that contain no executable are skipped.
+    pub fn discover(&mut self, dir: &Path) -> io::Result<()> {
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            let Some(version) = entry.file_name().to_str().and_then(MermaidVersion::parse) else {
+                continue;
+            };
+            if let Some(command) = find_mmdc(&entry.path()) {
+                self.insert(version, command);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn insert(&mut self, version: MermaidVersion, command: impl Into<OsString>) {
+        self.installs.insert(
+            version,
+            Renderer {
+                command: command.into(),
+                version: Some(version),
+            },
+        );
+    }
+
+    pub fn versions(&self) -> Vec<String> {
+        self.installs.keys().map(ToString::to_string).collect()
+    }
+
+    pub fn default_renderer(&self) -> &Renderer {
+        &self.default
+    }
+
+    pub fn renderer_for_version(&self, version: &str) -> Option<&Renderer> {
+        self.installs.get(&MermaidVersion::parse(version)?)
+    }
+
+    /// Picks the installation matching the version `target` ships, else the
+    /// newest one that is not newer than it, else the default renderer.
+    pub fn renderer_for_target(&self, target: PreviewTarget) -> &Renderer {
+        self.installs
+            .range(..=target.parsed_mermaid_version())
+            .next_back()
+            .map(|(_, renderer)| renderer)
+            .unwrap_or(&self.default)
+    }
+
+    /// Whether versioned installations exist but none bundles the version
+    /// `target` ships.
+    pub fn falls_back(&self, target: PreviewTarget) -> bool {
+        !self.installs.is_empty() && !self.installs.contains_key(&target.parsed_mermaid_version())
+    }
+
+    /// Resolves an explicitly requested version, falling back to the
+    /// target's renderer when no version is requested.
+    pub fn resolve(
+        &self,
+        requested: Option<&str>,
+        target: PreviewTarget,
+    ) -> Result<&Renderer, String> {
+        let resolved = match requested {
+            Some(version) => self.renderer_for_version(version).ok_or_else(|| {
+                let available = self.versions();
+                if available.is_empty() {
+                    format!("Mermaid renderer {version} is not installed (no versioned renderers configured)")
+                } else {
+                    format!(
+                        "Mermaid renderer {version} is not installed (available: {})",
+                        available.join(", ")
+                    )
+                }
+            }),
+            None => Ok(self.renderer_for_target(target)),
+        };
+        let data = match &resolved {
+            Ok(renderer) => json!({
+                "event": "resolveRenderer",
+                "target": target.as_str(),
+                "requestedVersion": requested,
+                "command": renderer.command.to_string_lossy(),
+                "version": renderer.version_string(),
+            }),
+            Err(message) => json!({
+                "event": "resolveRendererFailed",
+                "target": target.as_str(),
+                "requestedVersion": requested,
+                "error": message,
+            }),
+        };
+        log(LogLevel::Debug, "config", data);
+        resolved
+    }
+}
+
+fn find_mmdc(dir: &Path) -> Option<PathBuf> {
+    MMDC_CANDIDATES
+        .iter()
+        .map(|candidate| dir.join(candidate))
+        .find(|path| path.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry_with(versions: &[&str]) -> RendererRegistry {
+        let mut registry = RendererRegistry::default();
+        for version in versions {
+            registry.insert(
+                MermaidVersion::parse(version).unwrap(),
+                format!("/opt/mermaid/{version}/mmdc"),
+            );
+        }
+        registry
+    }
+
+    #[test]
+    fn target_uses_newest_install_not_newer_than_its_version() {
+        let registry = registry_with(&["10.2.4", "10.9.1", "11.4.1"]);
+        let gitlab = registry.renderer_for_target(PreviewTarget::Gitlab);
+        assert_eq!(gitlab.version_string().as_deref(), Some("10.9.1"));
+        assert!(!registry.falls_back(PreviewTarget::Gitlab));
+
+        let registry = registry_with(&["10.0.0", "11.0.0"]);
+        let gitlab = registry.renderer_for_target(PreviewTarget::Gitlab);
+        assert_eq!(gitlab.version_string().as_deref(), Some("10.0.0"));
+        assert!(registry.falls_back(PreviewTarget::Gitlab));
+    }
+
+    #[test]
+    fn target_falls_back_to_default_renderer() {
+        let registry = registry_with(&["11.4.1"]);
+        let azure = registry.renderer_for_target(PreviewTarget::AzureDevops);
+        assert!(azure.version.is_none());
+        assert!(registry.falls_back(PreviewTarget::AzureDevops));
+        assert!(!RendererRegistry::default().falls_back(PreviewTarget::AzureDevops));
+    }
+
+    #[test]
+    fn resolve_reports_missing_versions() {
+        let registry = registry_with(&["11.4.1"]);
+        assert!(registry
+            .resolve(Some("11.4"), PreviewTarget::Github)
+            .is_err());
+        let error = registry
+            .resolve(Some("9.0.0"), PreviewTarget::Github)
+            .unwrap_err();
+        assert!(error.contains("available: 11.4.1"));
+    }
+
+    #[test]
+    fn discover_versioned_directories() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("10.9.1/node_modules/.bin")).unwrap();
+        std::fs::write(dir.path().join("10.9.1/node_modules/.bin/mmdc"), "").unwrap();
+        std::fs::create_dir_all(dir.path().join("11.4.1/bin")).unwrap();
+        std::fs::write(dir.path().join("11.4.1/bin/mmdc"), "").unwrap();
+        std::fs::create_dir_all(dir.path().join("latest/bin")).unwrap();
+        std::fs::write(dir.path().join("latest/bin/mmdc"), "").unwrap();
+        std::fs::create_dir_all(dir.path().join("12.0.0")).unwrap();
+
+        let mut registry = RendererRegistry::default();
+        registry.discover(dir.path()).unwrap();
+        assert_eq!(registry.versions(), vec!["10.9.1", "11.4.1"]);
+    }
+}
@@ -1,4 +1,4 @@
-use rmcp::model::{CallToolResult, Content};
+use rmcp::model::{CallToolResult, Content, RawResource};
 
 use crate::cli_runner::OutputFormat;
 
@@ -14,6 +14,20 @@ pub fn valid_result(format: OutputFormat, base64_data: String) -> CallToolResult
     }
 }
 
+/// Like [`valid_result`], but links to the rendered image instead of
+/// embedding it.
+pub fn valid_link_result(link: RawResource) -> CallToolResult {
+    CallToolResult {
+        content: vec![
+            Content::text("Mermaid diagram is valid"),
+            Content::resource_link(link),
+        ],
+        structured_content: None,
+        is_error: None,
+        meta: None,
+    }
+}
+
 pub fn invalid_result(error_message: &str) -> CallToolResult {
     let (main_error, details) = split_error_details(error_message);
     let context = parse_error_context(details.as_deref().unwrap_or(error_message));
@@ -0,0 +1,400 @@
+use std::path::Path;
+
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+use serde::Serialize;
+use url::Url;
+
+use crate::{
+    command_line::CheckReport,
+    preview_validator::PreviewIssue,
+    quick_fix::{quick_fix, TextEdit},
+};
+
+pub const SARIF_VERSION: &str = "2.1.0";
+pub const SARIF_SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+
+/// Base id of artifact URIs relative to the validated directory.
+const ROOT_BASE_ID: &str = "ROOT";
+
+/// Characters escaped in relative artifact URIs.
+const URI_PATH: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+/// What each issue code means, for the rule descriptions of a SARIF log.
+const RULES: &[(&str, &str)] = &[
+    (
+        "mermaid_parse_error",
+        "Mermaid could not parse the diagram.",
+    ),
+    (
+        "mermaid_unclosed_fence",
+        "A Mermaid block is missing its closing fence or marker.",
+    ),
+    (
+        "markdown_unclosed_fence",
+        "A code fence is missing its closing marker, which hides the rest of the file.",
+    ),
+    (
+        "mermaid_block_syntax_unsupported",
+        "The target does not render this kind of Mermaid block (``` fence or ::: mermaid).",
+    ),
+    (
+        "mermaid_info_string_unsupported",
+        "The target may not render a block with this info string; tag it `mermaid`.",
+    ),
+    (
+        "unsupported_diagram_type",
+        "The target does not support this diagram type.",
+    ),
+    (
+        "feature_requires_newer_mermaid",
+        "The diagram uses syntax newer than the Mermaid version the target ships.",
+    ),
+    (
+        "front_matter_unclosed",
+        "The diagram's YAML front matter is missing its closing `---`.",
+    ),
+    (
+        "front_matter_invalid",
+        "The diagram's YAML front matter is not valid YAML.",
+    ),
+    (
+        "front_matter_unknown_key",
+        "The diagram's front matter has a key Mermaid does not read.",
+    ),
+    (
+        "init_directive_unclosed",
+        "An `%%{init: ...}%%` directive is not closed.",
+    ),
+    (
+        "init_directive_invalid",
+        "An `%%{init: ...}%%` directive is not valid JSON.",
+    ),
+    (
+        "init_directive_unknown_key",
+        "An `%%{init: ...}%%` directive has a key Mermaid does not read.",
+    ),
+    (
+        "config_unknown_key",
+        "A diagram configuration key is unknown to Mermaid.",
+    ),
+    (
+        "config_wrong_type",
+        "A diagram configuration value has the wrong type.",
+    ),
+    (
+        "config_invalid_value",
+        "A diagram configuration value is not one of the allowed values.",
+    ),
+    (
+        "config_ignored_by_target",
+        "The target ignores this diagram configuration key.",
+    ),
+    ("no_mermaid_blocks", "The markdown has no Mermaid block."),
+    ("markdown_read_error", "The file could not be read."),
+    (
+        "renderer_unavailable",
+        "No Mermaid renderer is installed for the requested version.",
+    ),
+    (
+        "renderer_version_fallback",
+        "No renderer for the target's Mermaid version is installed; another version was used.",
+    ),
+    (
+        "validation_cancelled",
+        "Validation was cancelled before every block was checked.",
+    ),
+];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SarifLog {
+    #[serde(rename = "$schema")]
+    pub schema: &'static str,
+    pub version: &'static str,
+    pub runs: Vec<Run>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Run {
+    pub tool: Tool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub original_uri_base_ids: Option<serde_json::Map<String, serde_json::Value>>,
+    pub results: Vec<SarifResult>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Tool {
+    pub driver: Driver,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Driver {
+    pub name: &'static str,
+    pub version: &'static str,
+    pub rules: Vec<Rule>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Rule {
+    pub id: String,
+    pub short_description: Message,
+    pub default_configuration: Configuration,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Configuration {
+    pub level: &'static str,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Message {
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SarifResult {
+    pub rule_id: String,
+    pub rule_index: usize,
+    pub level: &'static str,
+    pub message: Message,
+    pub locations: Vec<Location>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub fixes: Vec<Fix>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub properties: Option<ResultProperties>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResultProperties {
+    pub block_index: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Location {
+    pub physical_location: PhysicalLocation,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PhysicalLocation {
+    pub artifact_location: ArtifactLocation,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub region: Option<Region>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArtifactLocation {
+    pub uri: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uri_base_id: Option<&'static str>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Region {
+    pub start_line: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_column: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_line: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_column: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub snippet: Option<Message>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Fix {
+    pub description: Message,
+    pub artifact_changes: Vec<ArtifactChange>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArtifactChange {
+    pub artifact_location: ArtifactLocation,
+    pub replacements: Vec<Replacement>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Replacement {
+    pub deleted_region: Region,
+    pub inserted_content: Message,
+}
+
+/// A SARIF 2.1.0 log of `report`: one rule per issue code and one result
+/// per issue, with fixes where [`quick_fix`] has one. With `root`, file
+/// paths are relative to it; otherwise relative paths are kept as they
+/// are and absolute ones become `file` URIs.
+pub fn sarif_log(report: &CheckReport, root: Option<&Path>) -> SarifLog {
+    let mut rules: Vec<Rule> = Vec::new();
+    let mut results = Vec::new();
+    for file in &report.files {
+        let artifact = artifact_location(&file.path, root.is_some());
+        let source = report.sources.get(&file.path);
+        for issue in &file.result.issues {
+            let rule_index = match rules.iter().position(|rule| rule.id == issue.code) {
+                Some(index) => index,
+                None => {
+                    rules.push(rule(issue));
+                    rules.len() - 1
+                }
+            };
+            let fixes = source
+                .and_then(|source| quick_fix(source, issue))
+                .map(|fix| Fix {
+                    description: Message {
+                        text: fix.description,
+                    },
+                    artifact_changes: vec![ArtifactChange {
+                        artifact_location: artifact.clone(),
+                        replacements: fix.edits.iter().map(replacement).collect(),
+                    }],
+                })
+                .into_iter()
+                .collect();
+            results.push(SarifResult {
+                rule_id: issue.code.clone(),
+                rule_index,
+                level: level(&issue.severity),
+                message: Message {
+                    text: issue.message.clone(),
+                },
+                locations: vec![Location {
+                    physical_location: PhysicalLocation {
+                        artifact_location: artifact.clone(),
+                        region: region(issue, source.map(String::as_str)),
+                    },
+                }],
+                fixes,
+                properties: issue
+                    .block_index
+                    .map(|block_index| ResultProperties { block_index }),
+            });
+        }
+    }
+
+    let original_uri_base_ids = root.and_then(|root| {
+        let uri = Url::from_directory_path(root).ok()?;
+        let mut ids = serde_json::Map::new();
+        ids.insert(
+            ROOT_BASE_ID.to_string(),
+            serde_json::json!({ "uri": uri.to_string() }),
+        );
+        Some(ids)
+    });
+    SarifLog {
+        schema: SARIF_SCHEMA,
+        version: SARIF_VERSION,
+        runs: vec![Run {
+            tool: Tool {
+                driver: Driver {
+                    name: env!("CARGO_PKG_NAME"),
+                    version: env!("CARGO_PKG_VERSION"),
+                    rules,
+                },
+            },
+            original_uri_base_ids,
+            results,
+        }],
+    }
+}
+
+/// The description of an issue code.
+pub fn rule_description(code: &str) -> Option<&'static str> {
+    RULES
+        .iter()
+        .find(|(id, _)| *id == code)
+        .map(|(_, description)| *description)
+}
+
+fn rule(issue: &PreviewIssue) -> Rule {
+    Rule {
+        id: issue.code.clone(),
+        short_description: Message {
+            text: rule_description(&issue.code)
+                .map(str::to_string)
+                .unwrap_or_else(|| issue.code.replace('_', " ")),
+        },
+        default_configuration: Configuration {
+            level: level(&issue.severity),
+        },
+    }
+}
+
+fn level(severity: &str) -> &'static str {
+    match severity {
+        "error" => "error",
+        "warning" => "warning",
+        _ => "note",
+    }
+}
+
+fn artifact_location(path: &str, relative_to_root: bool) -> ArtifactLocation {
+    if relative_to_root {
+        return ArtifactLocation {
+            uri: utf8_percent_encode(path, URI_PATH).to_string(),
+            uri_base_id: Some(ROOT_BASE_ID),
+        };
+    }
+    let uri = Url::from_file_path(path)
+        .map(|url| url.to_string())
+        .unwrap_or_else(|()| {
+            let path = path.replace('\\', "/");
+            utf8_percent_encode(&path, URI_PATH).to_string()
+        });
+    ArtifactLocation {
+        uri,
+        uri_base_id: None,
+    }
+}
+
+/// The issue's line, with the renderer's snippet or else the source line.
+fn region(issue: &PreviewIssue, source: Option<&str>) -> Option<Region> {
+    let line = issue.line?;
+    let snippet = issue.snippet.clone().or_else(|| {
+        source?
+            .lines()
+            .nth(line.checked_sub(1)? as usize)
+            .map(str::to_string)
+    });
+    Some(Region {
+        start_line: line,
+        start_column: issue.column,
+        end_line: None,
+        end_column: None,
+        snippet: snippet.map(|text| Message { text }),
+    })
+}
+
+fn replacement(edit: &TextEdit) -> Replacement {
+    Replacement {
+        deleted_region: Region {
+            start_line: edit.start_line,
+            start_column: Some(edit.start_column),
+            end_line: Some(edit.end_line),
+            end_column: Some(edit.end_column),
+            snippet: None,
+        },
+        inserted_content: Message {
+            text: edit.new_text.clone(),
+        },
+    }
+}
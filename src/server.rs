@@ -1,19 +1,58 @@
+use std::{path::PathBuf, sync::Arc};
+
 use base64::Engine;
 use rmcp::{
-    handler::server::{tool::ToolRouter, wrapper::Parameters},
-    model::{CallToolResult, Content, Implementation, ServerCapabilities, ServerInfo},
-    tool, tool_handler, tool_router, ErrorData as McpError, ServerHandler,
+    handler::server::{prompt::PromptContext, tool::ToolCallContext},
+    handler::server::{router::prompt::PromptRouter, tool::ToolRouter, wrapper::Parameters},
+    model::{
+        AnnotateAble, CallToolRequestParams, CallToolResult, Content, CreateMessageRequestParams,
+        GetPromptRequestParams, GetPromptResult, Implementation, ListPromptsResult,
+        ListResourceTemplatesResult, ListToolsResult, LoggingMessageNotificationParam,
+        PaginatedRequestParams, ProgressNotificationParam, PromptMessage, PromptMessageRole,
+        RawResource, RawResourceTemplate, ReadResourceRequestParams, ReadResourceResult,
+        ResourceContents, Role, SamplingMessage, ServerCapabilities, ServerInfo,
+        SetLevelRequestParams, SubscribeRequestParams, UnsubscribeRequestParams,
+    },
+    prompt, prompt_router,
+    service::{NotificationContext, RequestContext},
+    tool, tool_router, ErrorData as McpError, Peer, RoleServer, ServerHandler,
 };
 use schemars::JsonSchema;
 use serde::Deserialize;
-use serde_json::to_value;
+use serde_json::{to_value, Value};
+use tokio::{sync::mpsc, task::JoinHandle};
 
 use crate::{
-    cli_runner::{render_diagram, timeout_from_env, OutputFormat},
+    block_edit::{edit_mermaid_block, EditBlockOptions},
+    block_selector::BlockSelector,
+    block_source::{mermaid_block_source, NumberedLine},
+    cli_runner::{timeout_from_env, OutputFormat},
+    command_line::{check_inputs, format_report, CheckInput, ReportFormat, DEFAULT_CHECK_INCLUDE},
+    diagram_resources::{DiagramResource, RenderCache, ResourceError, RESOURCE_TEMPLATES},
+    diagram_status::{parse_status_uri, StatusTracker, STATUS_MIME_TYPE, STATUS_RESOURCE_TEMPLATE},
+    directory::{
+        find_markdown_files, relative, scan_directory, validate_directory, DirectoryFilter,
+    },
+    documents::{DocumentStore, OpenedDocument, STALE_WARNING},
+    file_write::write_if_unchanged,
+    fix_suggestion::{suggest_fix, SuggestFixOptions, MAX_ATTEMPTS_LIMIT},
+    git_changes::{changed_markdown_files, validate_changed_blocks, BlockChange, ChangeBase},
+    logging::{LogRecord, SessionLog},
+    modernize::{modernize_markdown, Codemod, ModernizeOptions},
+    pagination::{
+        page_scan, page_validation, BlockPage, FilePage, FilePageInfo, PageInfo, Paged, Verbosity,
+    },
+    preview_target::PreviewTarget,
     preview_validator::{
-        scan_markdown_for_mermaid, validate_markdown_for_github, validate_mermaid_block_in_markdown,
+        count_mermaid_blocks, scan_markdown_for_targets, validate_markdown_blocks,
+        validate_mermaid_block, BlockCompatibility, MermaidBlockInfo, PreviewIssue, Progress,
+        ValidationControl, ValidationOptions,
     },
-    response_builder::{invalid_result, valid_result},
+    prompts::{fix_block_prompt, review_file_prompt, write_diagram_prompt},
+    renderer_registry::RendererRegistry,
+    response_builder::{invalid_result, valid_link_result, valid_result},
+    subscriptions::{Subscriptions, WatchContext},
+    workspace_roots::{allowed_paths_from_env, RootSet},
 };
 
 #[derive(Debug, Deserialize, JsonSchema)]
@@ -22,47 +61,344 @@ pub struct ValidateParams {
     pub diagram: String,
     #[serde(default)]
     pub format: Option<OutputFormat>,
+    /// Installed Mermaid version to render with (default: `MERMAID_CLI`).
+    #[serde(default)]
+    pub mermaid_version: Option<String>,
+    /// Embed the image as base64 instead of returning a resource link
+    /// (default: false).
+    #[serde(default)]
+    pub inline: Option<bool>,
 }
 
 #[derive(Debug, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct ValidatePreviewParams {
     pub markdown: String,
+    /// Preview target whose rules decide validity (default: github).
+    #[serde(default)]
+    pub target: Option<PreviewTarget>,
+    /// Additional targets to include in the per-block compatibility matrix.
+    #[serde(default)]
+    pub targets: Option<Vec<PreviewTarget>>,
+    /// Installed Mermaid version to validate with (default: the target's).
+    #[serde(default)]
+    pub mermaid_version: Option<String>,
+    /// Blocks per page (default: all).
+    #[serde(default)]
+    pub limit: Option<u32>,
+    /// `page.nextCursor` from the previous page.
+    #[serde(default)]
+    pub cursor: Option<String>,
+    /// Detail of the text output: summary, normal or full (default: normal).
+    /// Structured output is always complete.
+    #[serde(default)]
+    pub verbosity: Option<Verbosity>,
 }
 
 #[derive(Debug, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct ScanMermaidBlocksParams {
-    pub file_path: String,
+    /// Markdown file to read; not needed with `document`.
+    #[serde(default)]
+    pub file_path: Option<String>,
+    /// Handle from openMarkdownDocument, used instead of `filePath`.
+    #[serde(default)]
+    pub document: Option<String>,
+    /// Preview target whose rules decide validity (default: github).
+    #[serde(default)]
+    pub target: Option<PreviewTarget>,
+    /// Additional targets to include in the per-block compatibility matrix.
+    #[serde(default)]
+    pub targets: Option<Vec<PreviewTarget>>,
+    /// Blocks per page (default: all).
+    #[serde(default)]
+    pub limit: Option<u32>,
+    /// `page.nextCursor` from the previous page.
+    #[serde(default)]
+    pub cursor: Option<String>,
+    /// Detail of the text output: summary, normal or full (default: normal).
+    /// Structured output is always complete.
+    #[serde(default)]
+    pub verbosity: Option<Verbosity>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MermaidDirectoryParams {
+    /// Directory to walk. Hidden and `.gitignore`d files are skipped.
+    pub root: String,
+    /// Globs of files to check, relative to `root` (default: `**/*.md`, `**/*.markdown`).
+    #[serde(default)]
+    pub include: Option<Vec<String>>,
+    /// Globs of files and directories to skip, relative to `root`.
+    #[serde(default)]
+    pub exclude: Option<Vec<String>>,
+    /// Preview target whose rules decide validity (default: github).
+    #[serde(default)]
+    pub target: Option<PreviewTarget>,
+    /// Installed Mermaid version to validate with (default: the target's).
+    /// Ignored by scanMermaidDirectory.
+    #[serde(default)]
+    pub mermaid_version: Option<String>,
+    /// Files per page (default: all).
+    #[serde(default)]
+    pub limit: Option<u32>,
+    /// `page.nextCursor` from the previous page.
+    #[serde(default)]
+    pub cursor: Option<String>,
+    /// Detail of the text output: summary, normal or full (default: normal).
+    /// Structured output is always complete.
+    #[serde(default)]
+    pub verbosity: Option<Verbosity>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportMermaidReportParams {
+    /// Markdown or .mmd file, or directory to walk. Report paths are
+    /// relative to the directory, or to the file's directory.
+    pub path: String,
+    /// Report format: sarif, junit, github (workflow commands), json or
+    /// text.
+    pub format: ReportFormat,
+    /// Globs of files to check in a directory (default: `**/*.md`,
+    /// `**/*.markdown`, `**/*.mmd`, `**/*.mermaid`).
+    #[serde(default)]
+    pub include: Option<Vec<String>>,
+    /// Globs of files and directories to skip in a directory.
+    #[serde(default)]
+    pub exclude: Option<Vec<String>>,
+    /// Preview target whose rules decide validity (default: github).
+    #[serde(default)]
+    pub target: Option<PreviewTarget>,
+    /// Installed Mermaid version to validate with (default: the target's).
+    #[serde(default)]
+    pub mermaid_version: Option<String>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ValidateMermaidChangesParams {
+    /// Any path inside the git repository to check.
+    pub path: String,
+    /// Revision to compare the working tree against, e.g. `origin/main`.
+    /// Required unless `staged` is set.
+    #[serde(default)]
+    pub base: Option<String>,
+    /// Compare the index against `HEAD` and validate staged content, for
+    /// pre-commit hooks (default: false).
+    #[serde(default)]
+    pub staged: Option<bool>,
+    /// Preview target whose rules decide validity (default: github).
+    #[serde(default)]
+    pub target: Option<PreviewTarget>,
+    /// Installed Mermaid version to validate with (default: the target's).
+    #[serde(default)]
+    pub mermaid_version: Option<String>,
+    /// Detail of the text output: summary, normal or full (default: normal).
+    /// Structured output is always complete.
+    #[serde(default)]
+    pub verbosity: Option<Verbosity>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct GetMermaidBlockParams {
+    /// Markdown file to read; not needed with `document`.
+    #[serde(default)]
+    pub file_path: Option<String>,
+    /// Handle from openMarkdownDocument, used instead of `filePath`.
+    #[serde(default)]
+    pub document: Option<String>,
+    #[serde(flatten)]
+    pub block: BlockSelector,
+    /// Markdown lines to include before and after the block (default: 0, at most 50).
+    #[serde(default)]
+    pub context_lines: Option<u32>,
 }
 
 #[derive(Debug, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct ValidateMermaidBlockParams {
+    /// Markdown file to read; not needed with `document`.
+    #[serde(default)]
+    pub file_path: Option<String>,
+    /// Handle from openMarkdownDocument, used instead of `filePath`.
+    #[serde(default)]
+    pub document: Option<String>,
+    #[serde(flatten)]
+    pub block: BlockSelector,
+    /// Preview target whose rules decide validity (default: github).
+    #[serde(default)]
+    pub target: Option<PreviewTarget>,
+    /// Installed Mermaid version to validate with (default: the target's).
+    #[serde(default)]
+    pub mermaid_version: Option<String>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ModernizeMermaidParams {
+    pub file_path: String,
+    /// Codemods to run, in order (default: all).
+    #[serde(default)]
+    pub transforms: Option<Vec<Codemod>>,
+    /// Preview target whose Mermaid version gates the codemods (default: github).
+    #[serde(default)]
+    pub target: Option<PreviewTarget>,
+    /// Installed Mermaid version to re-validate with (default: the target's).
+    #[serde(default)]
+    pub mermaid_version: Option<String>,
+    /// Write the modernized markdown back to the file (default: false).
+    #[serde(default)]
+    pub write: Option<bool>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SuggestMermaidFixParams {
+    /// Markdown file to read; not needed with `document`.
+    #[serde(default)]
+    pub file_path: Option<String>,
+    /// Handle from openMarkdownDocument, used instead of `filePath`.
+    #[serde(default)]
+    pub document: Option<String>,
+    #[serde(flatten)]
+    pub block: BlockSelector,
+    /// Preview target whose rules decide validity (default: github).
+    #[serde(default)]
+    pub target: Option<PreviewTarget>,
+    /// Installed Mermaid version to validate candidates with (default: the target's).
+    #[serde(default)]
+    pub mermaid_version: Option<String>,
+    /// Candidates to request from the client's model before giving up
+    /// (default: 3, at most 10).
+    #[serde(default)]
+    pub max_attempts: Option<u32>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct EditMermaidBlockParams {
+    pub file_path: String,
+    #[serde(flatten)]
+    pub block: BlockSelector,
+    /// `contentHash` of the block as last read, e.g. from getMermaidBlock.
+    pub expected_hash: String,
+    /// The new diagram source, without fences.
+    pub source: String,
+    /// Preview target whose rules decide validity (default: github).
+    #[serde(default)]
+    pub target: Option<PreviewTarget>,
+    /// Installed Mermaid version to validate with (default: the target's).
+    #[serde(default)]
+    pub mermaid_version: Option<String>,
+    /// Write despite a hash mismatch or an invalid diagram (default: false).
+    #[serde(default)]
+    pub force: Option<bool>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct OpenMarkdownDocumentParams {
     pub file_path: String,
-    pub block_index: u32,
+    /// Preview target whose rules decide validity (default: github).
+    #[serde(default)]
+    pub target: Option<PreviewTarget>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CloseMarkdownDocumentParams {
+    /// Handle from openMarkdownDocument.
+    pub document: String,
+}
+
+/// Prompt arguments are strings on the wire, so numbers and target lists
+/// are parsed by the prompt.
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FixBlockPromptArgs {
+    /// Markdown file containing the block.
+    pub file_path: String,
+    /// Block index as returned by scanMermaidBlocks.
+    pub block_index: String,
+    /// Preview target (default: github).
+    #[serde(default)]
+    pub target: Option<String>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct WriteDiagramPromptArgs {
+    /// Mermaid diagram type, e.g. flowchart or sequenceDiagram.
+    pub diagram_type: String,
+    /// What the diagram should show.
+    pub description: String,
+    /// Comma-separated preview targets the diagram must render on (default: github).
+    #[serde(default)]
+    pub targets: Option<String>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ReviewFilePromptArgs {
+    /// Markdown file to review.
+    pub file_path: String,
+    /// Preview target (default: github).
+    #[serde(default)]
+    pub target: Option<String>,
 }
 
 #[derive(Clone)]
 pub struct MermaidServer {
     tool_router: ToolRouter<Self>,
+    prompt_router: PromptRouter<Self>,
+    renderers: Arc<RendererRegistry>,
+    render_cache: Arc<RenderCache>,
+    /// The client's roots, fetched on first use and dropped when the client
+    /// reports a change. `None` means they need to be (re-)read.
+    roots: Arc<tokio::sync::Mutex<Option<RootSet>>>,
+    allowed_paths: Arc<Vec<PathBuf>>,
+    log: Arc<SessionLog>,
+    subscriptions: Arc<Subscriptions>,
+    documents: Arc<DocumentStore>,
+}
+
+impl Default for MermaidServer {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 #[tool_router]
 impl MermaidServer {
     pub fn new() -> Self {
+        Self::with_renderers(RendererRegistry::from_env())
+    }
+
+    pub fn with_renderers(renderers: RendererRegistry) -> Self {
         Self {
             tool_router: Self::tool_router(),
+            prompt_router: Self::prompt_router(),
+            renderers: Arc::new(renderers),
+            render_cache: Arc::new(RenderCache::default()),
+            roots: Arc::default(),
+            allowed_paths: Arc::new(allowed_paths_from_env()),
+            log: Arc::default(),
+            subscriptions: Arc::default(),
+            documents: Arc::default(),
         }
     }
 
     #[tool(
         name = "validateMermaid",
-        description = "Validates a Mermaid diagram and returns the rendered image (PNG or SVG) if valid"
+        description = "Validates a Mermaid diagram and, if valid, returns a resource link to the rendered image (PNG or SVG), or the image itself with inline: true"
     )]
     async fn validate_mermaid(
         &self,
         params: Parameters<ValidateParams>,
+        context: RequestContext<RoleServer>,
     ) -> Result<CallToolResult, McpError> {
         let params = params.0;
         let format = params.format.unwrap_or_default();
@@ -71,11 +407,42 @@ impl MermaidServer {
             Ok(diagram) => diagram,
             Err(message) => return Ok(invalid_result(&message)),
         };
+        let renderer = match params.mermaid_version.as_deref() {
+            Some(version) => match self.renderers.resolve(Some(version), PreviewTarget::Github) {
+                Ok(renderer) => renderer,
+                Err(message) => return Ok(invalid_result(&message)),
+            },
+            None => self.renderers.default_renderer(),
+        };
 
-        match render_diagram(&diagram, format, timeout).await {
+        let control = ValidationControl {
+            cancel: context.ct,
+            progress: None,
+        };
+        let render = self
+            .render_cache
+            .render(renderer, &diagram, format, timeout);
+        let Some(rendered) = control.run(render).await else {
+            return Ok(invalid_result("Validation was cancelled"));
+        };
+        match rendered {
             Ok(output) => {
-                let encoded = base64::engine::general_purpose::STANDARD.encode(output);
-                Ok(valid_result(format, encoded))
+                let mut result = if params.inline.unwrap_or(false) {
+                    valid_result(
+                        format,
+                        base64::engine::general_purpose::STANDARD.encode(output.as_slice()),
+                    )
+                } else {
+                    let hash = self.render_cache.register_source(&diagram, renderer);
+                    let resource = DiagramResource::Render { hash, format };
+                    valid_link_result(resource_link(&resource, "mermaid-diagram", output.len()))
+                };
+                if let Some(version) = renderer.version_string() {
+                    result
+                        .content
+                        .push(Content::text(format!("Validated with Mermaid {version}")));
+                }
+                Ok(result)
             }
             Err(err) => {
                 let message = err.to_error_message();
@@ -86,45 +453,743 @@ impl MermaidServer {
 
     #[tool(
         name = "validateMermaidPreview",
-        description = "Validates Mermaid preview compatibility for GitHub markdown, including fence issues and parse errors"
+        description = "Validates Mermaid preview compatibility for markdown on a preview target (GitHub by default), including fence issues, parse errors and an optional multi-target compatibility matrix"
     )]
     async fn validate_mermaid_preview(
         &self,
         params: Parameters<ValidatePreviewParams>,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let params = params.0;
+        let timeout = timeout_from_env();
+        let target = params.target.unwrap_or_default();
+        let (control, progress) = request_control(&context);
+        let options = ValidationOptions {
+            matrix_targets: params.targets.unwrap_or_default(),
+            mermaid_version: params.mermaid_version,
+            control,
+            ..ValidationOptions::new(target, timeout)
+        };
+        let page = match BlockPage::select(
+            &params.markdown,
+            count_mermaid_blocks(&params.markdown),
+            params.limit,
+            params.cursor.as_deref(),
+        ) {
+            Ok(page) => page,
+            Err(message) => return Ok(invalid_result(&message)),
+        };
+        let result = validate_markdown_blocks(
+            &params.markdown,
+            page.first..=page.last,
+            &options,
+            &self.renderers,
+        )
+        .await;
+        finish_progress(options.control, progress).await;
+        let result = page_validation(result, &page);
+        let checked = result.page.returned_blocks;
+        let status_text = if result.result.valid {
+            format!(
+                "Mermaid preview is valid for {} ({checked} block(s) checked)",
+                target.display_name(),
+            )
+        } else {
+            format!(
+                "Mermaid preview is invalid for {} ({} error(s), {checked} block(s) checked)",
+                target.display_name(),
+                result.result.error_count,
+            )
+        };
+
+        let verbosity = params.verbosity.unwrap_or_default();
+
+        let mut lines = vec![status_text];
+        if let Some(version) = &result.result.renderer_version {
+            lines.push(format!("Validated with Mermaid {version}"));
+        }
+        if verbosity != Verbosity::Summary {
+            for issue in &result.result.issues {
+                lines.extend(issue_lines(issue, verbosity));
+            }
+            lines.extend(compatibility_lines(&result.result.compatibility));
+        }
+        lines.extend(page_hint(&result.page, result.result.mermaid_block_count));
+
+        Ok(CallToolResult {
+            content: vec![Content::text(lines.join("\n"))],
+            structured_content: Some(
+                to_value(result).map_err(|err| McpError::internal_error(err.to_string(), None))?,
+            ),
+            is_error: Some(false),
+            meta: None,
+        })
+    }
+
+    #[tool(
+        name = "scanMermaidBlocks",
+        description = "Scans a markdown file and returns Mermaid block indexes and locations for preview validation on a target (GitHub by default)"
+    )]
+    async fn scan_mermaid_blocks(
+        &self,
+        params: Parameters<ScanMermaidBlocksParams>,
+        peer: Peer<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let params = params.0;
+        let source = match self
+            .markdown_source(
+                &peer,
+                params.file_path.as_deref(),
+                params.document.as_deref(),
+            )
+            .await
+        {
+            Ok(source) => source,
+            Err(message) => return Ok(invalid_result(&message)),
+        };
+
+        let target = params.target.unwrap_or_default();
+        let targets = params.targets.unwrap_or_default();
+        let result = scan_markdown_for_targets(&source.markdown, target, &targets);
+        let page = match BlockPage::select(
+            &source.markdown,
+            result.mermaid_block_count,
+            params.limit,
+            params.cursor.as_deref(),
+        ) {
+            Ok(page) => page,
+            Err(message) => return Ok(invalid_result(&message)),
+        };
+        let result = page_scan(result, &page);
+        let verbosity = params.verbosity.unwrap_or_default();
+
+        let mut lines = vec![format!(
+            "{} scan complete: {} block(s), {} error(s)",
+            target.display_name(),
+            result.result.mermaid_block_count,
+            result.result.error_count
+        )];
+        if verbosity != Verbosity::Summary {
+            for block in &result.result.blocks {
+                lines.push(format!(
+                    "Block #{} ({}) lines {}-{} ({} lines): {}",
+                    block.index,
+                    block.id,
+                    block.start_line,
+                    block.end_line,
+                    block.line_count,
+                    block.first_line
+                ));
+                if verbosity == Verbosity::Full {
+                    lines.extend(block_detail(block));
+                }
+            }
+            for issue in &result.result.issues {
+                lines.extend(issue_lines(issue, verbosity));
+            }
+            lines.extend(compatibility_lines(&result.result.compatibility));
+        }
+        lines.extend(page_hint(&result.page, result.result.mermaid_block_count));
+
+        Ok(source.warn_if_stale(CallToolResult {
+            content: vec![Content::text(lines.join("\n"))],
+            structured_content: Some(
+                to_value(result).map_err(|err| McpError::internal_error(err.to_string(), None))?,
+            ),
+            is_error: Some(false),
+            meta: None,
+        }))
+    }
+
+    #[tool(
+        name = "scanMermaidDirectory",
+        description = "Scans every markdown file under a directory (include/exclude globs, .gitignore respected) for Mermaid blocks and target rule errors without rendering, with per-file counts, paginated by file"
+    )]
+    async fn scan_mermaid_directory(
+        &self,
+        params: Parameters<MermaidDirectoryParams>,
+        peer: Peer<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let params = params.0;
+        let (root, files, page) = match self.directory_page(&peer, &params).await {
+            Ok(selected) => selected,
+            Err(message) => return Ok(invalid_result(&message)),
+        };
+        let target = params.target.unwrap_or_default();
+        let report = scan_directory(&root, page.slice(&files), target).await;
+        let verbosity = params.verbosity.unwrap_or_default();
+
+        let mut lines = vec![format!(
+            "{} scan of {}: {} file(s), {} block(s), {} error(s)",
+            target.display_name(),
+            params.root,
+            report.files.len(),
+            report.mermaid_block_count,
+            report.error_count
+        )];
+        for file in &report.files {
+            lines.extend(file_lines(
+                &file.path,
+                file.result.mermaid_block_count,
+                file.result.error_count,
+                &file.result.issues,
+                verbosity,
+            ));
+        }
+        lines.extend(file_page_hint(&page.info()));
+
+        Ok(CallToolResult {
+            content: vec![Content::text(lines.join("\n"))],
+            structured_content: Some(
+                to_value(Paged {
+                    result: report,
+                    page: page.info(),
+                })
+                .map_err(|err| McpError::internal_error(err.to_string(), None))?,
+            ),
+            is_error: Some(false),
+            meta: None,
+        })
+    }
+
+    #[tool(
+        name = "validateMermaidDirectory",
+        description = "Validates every Mermaid block in the markdown files under a directory (include/exclude globs, .gitignore respected) for a target, rendering files concurrently within the shared render limit, with per-file error counts, paginated by file"
+    )]
+    async fn validate_mermaid_directory(
+        &self,
+        params: Parameters<MermaidDirectoryParams>,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let params = params.0;
+        let (root, files, page) = match self.directory_page(&context.peer, &params).await {
+            Ok(selected) => selected,
+            Err(message) => return Ok(invalid_result(&message)),
+        };
+        let target = params.target.unwrap_or_default();
+        let (control, progress) = request_control(&context);
+        let options = ValidationOptions {
+            mermaid_version: params.mermaid_version.clone(),
+            control,
+            ..ValidationOptions::new(target, timeout_from_env())
+        };
+        let report = validate_directory(&root, page.slice(&files), &options, &self.renderers).await;
+        finish_progress(options.control, progress).await;
+        let verbosity = params.verbosity.unwrap_or_default();
+
+        let mut lines = vec![format!(
+            "{} validation of {}: {} file(s), {} block(s), {} error(s)",
+            target.display_name(),
+            params.root,
+            report.files.len(),
+            report.mermaid_block_count,
+            report.error_count
+        )];
+        for file in &report.files {
+            lines.extend(file_lines(
+                &file.path,
+                file.result.mermaid_block_count,
+                file.result.error_count,
+                &file.result.issues,
+                verbosity,
+            ));
+        }
+        lines.extend(file_page_hint(&page.info()));
+
+        Ok(CallToolResult {
+            content: vec![Content::text(lines.join("\n"))],
+            structured_content: Some(
+                to_value(Paged {
+                    result: report,
+                    page: page.info(),
+                })
+                .map_err(|err| McpError::internal_error(err.to_string(), None))?,
+            ),
+            is_error: Some(false),
+            meta: None,
+        })
+    }
+
+    #[tool(
+        name = "validateMermaidChanges",
+        description = "Validates only the Mermaid blocks that are new or modified in markdown files changed since a git revision, or in the staged index (read from the index, not the working tree), and reports issues for those blocks only"
+    )]
+    async fn validate_mermaid_changes(
+        &self,
+        params: Parameters<ValidateMermaidChangesParams>,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let params = params.0;
+        let base = match (params.staged.unwrap_or(false), params.base.clone()) {
+            (true, None) => ChangeBase::Staged,
+            (false, Some(revision)) => ChangeBase::Revision(revision),
+            (true, Some(_)) => {
+                return Ok(invalid_result("Pass either base or staged, not both"));
+            }
+            (false, None) => {
+                return Ok(invalid_result("Pass a base revision or set staged"));
+            }
+        };
+        let path = match self.resolve_path(&context.peer, &params.path).await {
+            Ok(path) => path,
+            Err(message) => return Ok(invalid_result(&message)),
+        };
+        let changes = {
+            let base = base.clone();
+            tokio::task::spawn_blocking(move || changed_markdown_files(&path, &base))
+                .await
+                .map_err(|err| McpError::internal_error(err.to_string(), None))?
+        };
+        let files = match changes {
+            Ok(files) => files,
+            Err(err) => return Ok(invalid_result(&err.to_string())),
+        };
+        let target = params.target.unwrap_or_default();
+        let (control, progress) = request_control(&context);
+        let options = ValidationOptions {
+            mermaid_version: params.mermaid_version.clone(),
+            control,
+            ..ValidationOptions::new(target, timeout_from_env())
+        };
+        let report = validate_changed_blocks(&base, &files, &options, &self.renderers).await;
+        finish_progress(options.control, progress).await;
+        let verbosity = params.verbosity.unwrap_or_default();
+
+        let mut lines = vec![format!(
+            "{} validation of {}: {} file(s), {} changed block(s), {} error(s)",
+            target.display_name(),
+            report.base,
+            report.changed_files,
+            report.checked_blocks,
+            report.error_count
+        )];
+        if verbosity != Verbosity::Summary {
+            for block in &report.blocks {
+                let change = match block.change {
+                    BlockChange::New => "new",
+                    BlockChange::Modified => "modified",
+                };
+                lines.push(format!(
+                    "{}: block #{} ({change}, lines {}-{}): {}",
+                    block.path,
+                    block.block_index,
+                    block.start_line,
+                    block.end_line,
+                    if block.valid { "valid" } else { "invalid" }
+                ));
+                for issue in &block.issues {
+                    lines.extend(issue_lines(issue, verbosity));
+                }
+            }
+        }
+
+        Ok(CallToolResult {
+            content: vec![Content::text(lines.join("\n"))],
+            structured_content: Some(
+                to_value(&report).map_err(|err| McpError::internal_error(err.to_string(), None))?,
+            ),
+            is_error: Some(false),
+            meta: None,
+        })
+    }
+
+    #[tool(
+        name = "exportMermaidReport",
+        description = "Validates a markdown or .mmd file, or every such file under a directory, and returns the report for CI: a SARIF 2.1.0 log (one rule per issue code, one result per issue, with fixes where available), JUnit XML (one test case per block), GitHub Actions workflow commands, the JSON report or text"
+    )]
+    async fn export_mermaid_report(
+        &self,
+        params: Parameters<ExportMermaidReportParams>,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let params = params.0;
+        let path = match self.resolve_path(&context.peer, &params.path).await {
+            Ok(path) => path,
+            Err(message) => return Ok(invalid_result(&message)),
+        };
+        let (root, files) = if path.is_dir() {
+            let filter = DirectoryFilter {
+                include: params.include.clone().unwrap_or_else(|| {
+                    DEFAULT_CHECK_INCLUDE
+                        .iter()
+                        .map(|glob| glob.to_string())
+                        .collect()
+                }),
+                exclude: params.exclude.clone().unwrap_or_default(),
+            };
+            let walked = path.clone();
+            let files = tokio::task::spawn_blocking(move || find_markdown_files(&walked, &filter))
+                .await
+                .map_err(|err| McpError::internal_error(err.to_string(), None))?;
+            match files {
+                Ok(files) => (path, files),
+                Err(message) => return Ok(invalid_result(&message)),
+            }
+        } else {
+            let root = path.parent().map(PathBuf::from).unwrap_or_default();
+            (root, vec![path])
+        };
+        let inputs = files.into_iter().map(CheckInput::File).collect::<Vec<_>>();
+        let (control, progress) = request_control(&context);
+        let options = ValidationOptions {
+            mermaid_version: params.mermaid_version.clone(),
+            control,
+            ..ValidationOptions::new(params.target.unwrap_or_default(), timeout_from_env())
+        };
+        let report = check_inputs(&inputs, &options, &self.renderers).await;
+        finish_progress(options.control, progress).await;
+        let report = report.relative_to(&root);
+
+        let output = format_report(&report, params.format, Some(&root));
+        // Reports that are not JSON come with the JSON report as structured
+        // content.
+        let structured = match params.format {
+            ReportFormat::Json | ReportFormat::Sarif => serde_json::from_str(&output),
+            ReportFormat::Text | ReportFormat::Junit | ReportFormat::Github => to_value(&report),
+        }
+        .map_err(|err| McpError::internal_error(err.to_string(), None))?;
+        Ok(CallToolResult {
+            content: vec![Content::text(output)],
+            structured_content: Some(structured),
+            is_error: Some(false),
+            meta: None,
+        })
+    }
+
+    #[tool(
+        name = "openMarkdownDocument",
+        description = "Reads and scans a markdown file once and returns a document handle with its Mermaid block list. Pass the handle as `document` to block tools instead of `filePath`; they warn when the file changed since"
+    )]
+    async fn open_markdown_document(
+        &self,
+        params: Parameters<OpenMarkdownDocumentParams>,
+        peer: Peer<RoleServer>,
     ) -> Result<CallToolResult, McpError> {
         let params = params.0;
+        let path = match self.resolve_path(&peer, &params.file_path).await {
+            Ok(path) => path,
+            Err(message) => return Ok(invalid_result(&message)),
+        };
+        let (handle, document) = match self.documents.open(path).await {
+            Ok(opened) => opened,
+            Err(err) => return Ok(invalid_result(&err.to_string())),
+        };
+        let target = params.target.unwrap_or_default();
+        let result = OpenedDocument {
+            document: handle,
+            path: document.path.display().to_string(),
+            content_hash: document.content_hash.clone(),
+            scan: scan_markdown_for_targets(&document.markdown, target, &[]),
+        };
+
+        let mut content = vec![Content::text(format!(
+            "Opened {} as document {}: {} block(s), {} error(s)",
+            params.file_path,
+            result.document,
+            result.scan.mermaid_block_count,
+            result.scan.error_count
+        ))];
+        for block in &result.scan.blocks {
+            content.push(Content::text(format!(
+                "Block #{} ({}) lines {}-{} ({} lines): {}",
+                block.index,
+                block.id,
+                block.start_line,
+                block.end_line,
+                block.line_count,
+                block.first_line
+            )));
+        }
+
+        Ok(CallToolResult {
+            content,
+            structured_content: Some(
+                to_value(result).map_err(|err| McpError::internal_error(err.to_string(), None))?,
+            ),
+            is_error: Some(false),
+            meta: None,
+        })
+    }
+
+    #[tool(
+        name = "closeMarkdownDocument",
+        description = "Releases a document handle returned by openMarkdownDocument"
+    )]
+    async fn close_markdown_document(
+        &self,
+        params: Parameters<CloseMarkdownDocumentParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let handle = params.0.document;
+        if !self.documents.close(&handle) {
+            return Ok(invalid_result(&format!("Unknown document {handle}")));
+        }
+        Ok(CallToolResult::success(vec![Content::text(format!(
+            "Closed document {handle}"
+        ))]))
+    }
+
+    #[tool(
+        name = "getMermaidBlock",
+        description = "Returns the exact source of one Mermaid block in a markdown file, selected by block index, id or heading, with its fence info string, file line numbers, content hash and optional surrounding markdown lines"
+    )]
+    async fn get_mermaid_block(
+        &self,
+        params: Parameters<GetMermaidBlockParams>,
+        peer: Peer<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let params = params.0;
+        let source = match self
+            .markdown_source(
+                &peer,
+                params.file_path.as_deref(),
+                params.document.as_deref(),
+            )
+            .await
+        {
+            Ok(source) => source,
+            Err(message) => return Ok(invalid_result(&message)),
+        };
+        let block_index = match params.block.resolve(&source.label, &source.markdown) {
+            Ok(index) => index,
+            Err(message) => return Ok(invalid_result(&message)),
+        };
+        let block = match mermaid_block_source(
+            &source.label,
+            &source.markdown,
+            block_index,
+            params.context_lines.unwrap_or(0),
+        ) {
+            Ok(block) => block,
+            Err(message) => return Ok(invalid_result(&message)),
+        };
+
+        let mut lines = vec![format!(
+            "Block #{} ({}) lines {}-{}, info string \"{}\", content hash {}",
+            block.index,
+            block.id,
+            block.start_line,
+            block.end_line,
+            block.info_string,
+            block.content_hash
+        )];
+        let width = block
+            .context_after
+            .last()
+            .map_or(block.end_line, |line| line.line)
+            .to_string()
+            .len();
+        let mut push_numbered = |heading: &str, numbered: &[NumberedLine]| {
+            if !numbered.is_empty() {
+                lines.push(heading.to_string());
+                lines.extend(
+                    numbered
+                        .iter()
+                        .map(|line| format!("{:>width$} | {}", line.line, line.text)),
+                );
+            }
+        };
+        push_numbered("Context before:", &block.context_before);
+        push_numbered("Source:", &block.lines);
+        push_numbered("Context after:", &block.context_after);
+
+        Ok(source.warn_if_stale(CallToolResult {
+            content: vec![Content::text(lines.join("\n"))],
+            structured_content: Some(
+                to_value(block).map_err(|err| McpError::internal_error(err.to_string(), None))?,
+            ),
+            is_error: Some(false),
+            meta: None,
+        }))
+    }
+
+    #[tool(
+        name = "validateMermaidBlock",
+        description = "Validates one Mermaid block in a markdown file, selected by block index, id or heading, using the preview rules of a target (GitHub by default)"
+    )]
+    async fn validate_mermaid_block(
+        &self,
+        params: Parameters<ValidateMermaidBlockParams>,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let params = params.0;
+        let source = match self
+            .markdown_source(
+                &context.peer,
+                params.file_path.as_deref(),
+                params.document.as_deref(),
+            )
+            .await
+        {
+            Ok(source) => source,
+            Err(message) => return Ok(invalid_result(&message)),
+        };
+        let block_index = match params.block.resolve(&source.label, &source.markdown) {
+            Ok(index) => index,
+            Err(message) => return Ok(invalid_result(&message)),
+        };
         let timeout = timeout_from_env();
-        let result = validate_markdown_for_github(&params.markdown, timeout).await;
-        let status_text = if result.valid {
+        let target = params.target.unwrap_or_default();
+        let options = ValidationOptions {
+            mermaid_version: params.mermaid_version,
+            control: ValidationControl {
+                cancel: context.ct,
+                progress: None,
+            },
+            ..ValidationOptions::new(target, timeout)
+        };
+        let result =
+            validate_mermaid_block(&source.markdown, block_index, &options, &self.renderers).await;
+
+        let summary = if result.valid {
+            format!(
+                "Block #{} is valid for {} preview",
+                block_index,
+                target.display_name()
+            )
+        } else {
+            format!(
+                "Block #{} is invalid for {} preview ({} issue(s))",
+                block_index,
+                target.display_name(),
+                result.issues.len()
+            )
+        };
+
+        let mut content = vec![Content::text(summary)];
+        if let Some(version) = &result.renderer_version {
+            content.push(Content::text(format!("Validated with Mermaid {version}")));
+        }
+        if result.valid {
+            let resource = DiagramResource::FileBlock {
+                path: source.path.display().to_string(),
+                index: block_index,
+                format: OutputFormat::Svg,
+            };
+            let name = format!("mermaid-block-{}", block_index);
+            let mut link = RawResource::new(resource.uri(), name);
+            link.mime_type = Some(OutputFormat::Svg.mime_type().to_string());
+            content.push(Content::resource_link(link));
+        }
+        for issue in &result.issues {
+            let mut line = format!("[{}] {}: {}", issue.severity, issue.code, issue.message);
+            if let Some(line_no) = issue.line {
+                if let Some(column_no) = issue.column {
+                    line.push_str(&format!(" at line {line_no}, column {column_no}"));
+                } else {
+                    line.push_str(&format!(" at line {line_no}"));
+                }
+            }
+            content.push(Content::text(line));
+            if let Some(snippet) = &issue.snippet {
+                content.push(Content::text(format!("Snippet: {snippet}")));
+            }
+        }
+
+        Ok(source.warn_if_stale(CallToolResult {
+            content,
+            structured_content: Some(
+                to_value(result).map_err(|err| McpError::internal_error(err.to_string(), None))?,
+            ),
+            is_error: Some(false),
+            meta: None,
+        }))
+    }
+
+    #[tool(
+        name = "modernizeMermaid",
+        description = "Rewrites Mermaid blocks in a markdown file to modern syntax (graph to flowchart, init directives to front matter, @{ shape } nodes), keeping only changes that still render, and returns a unified diff"
+    )]
+    async fn modernize_mermaid(
+        &self,
+        params: Parameters<ModernizeMermaidParams>,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let params = params.0;
+        let path = match self.resolve_path(&context.peer, &params.file_path).await {
+            Ok(path) => path,
+            Err(message) => return Ok(invalid_result(&message)),
+        };
+        let markdown = match tokio::fs::read_to_string(&path).await {
+            Ok(content) => content,
+            Err(err) => {
+                return Ok(invalid_result(&format!(
+                    "Failed to read markdown file {}: {}",
+                    params.file_path, err
+                )))
+            }
+        };
+        let target = params.target.unwrap_or_default();
+        let (control, progress) = request_control(&context);
+        let mut options = ModernizeOptions {
+            mermaid_version: params.mermaid_version,
+            control,
+            ..ModernizeOptions::new(target, timeout_from_env())
+        };
+        if let Some(transforms) = params.transforms {
+            options.codemods = transforms;
+        }
+        let result =
+            modernize_markdown(&params.file_path, &markdown, &options, &self.renderers).await;
+        finish_progress(options.control, progress).await;
+        let result = match result {
+            Ok(result) => result,
+            Err(message) => return Ok(invalid_result(&message)),
+        };
+
+        let written = params.write.unwrap_or(false) && result.changed;
+        if written {
+            if let Err(err) =
+                write_if_unchanged(&path, &params.file_path, &markdown, &result.markdown).await
+            {
+                return Ok(invalid_result(&err.to_string()));
+            }
+        }
+
+        let applied = result
+            .blocks
+            .iter()
+            .map(|block| block.applied.len())
+            .sum::<usize>();
+        let summary = if !result.changed {
             format!(
-                "Mermaid preview is valid for GitHub ({} block(s) checked)",
-                result.mermaid_block_count
+                "No Mermaid blocks to modernize for {}",
+                target.display_name()
             )
-        } else {
+        } else if written {
             format!(
-                "Mermaid preview is invalid for GitHub ({} error(s), {} block(s) checked)",
-                result.error_count, result.mermaid_block_count
+                "Applied {applied} codemod(s) and wrote {}",
+                params.file_path
             )
+        } else {
+            format!("Applied {applied} codemod(s) (dry run, file not written)")
         };
 
-        let mut content = vec![Content::text(status_text)];
-        for issue in &result.issues {
-            let mut line = format!("[{}] {}: {}", issue.severity, issue.code, issue.message);
-            if let Some(block_index) = issue.block_index {
-                line.push_str(&format!(" (block #{block_index})"));
-            }
-            if let Some(line_no) = issue.line {
-                if let Some(column_no) = issue.column {
-                    line.push_str(&format!(" at line {line_no}, column {column_no}"));
-                } else {
-                    line.push_str(&format!(" at line {line_no}"));
-                }
+        let mut content = vec![Content::text(summary)];
+        for block in &result.blocks {
+            if !block.applied.is_empty() {
+                let names = block
+                    .applied
+                    .iter()
+                    .map(Codemod::as_str)
+                    .collect::<Vec<_>>();
+                content.push(Content::text(format!(
+                    "Block #{} applied: {}",
+                    block.block_index,
+                    names.join(", ")
+                )));
             }
-            content.push(Content::text(line));
-            if let Some(snippet) = &issue.snippet {
-                content.push(Content::text(format!("Snippet: {snippet}")));
+            for skipped in &block.skipped {
+                content.push(Content::text(format!(
+                    "Block #{} skipped {}: {}",
+                    block.block_index,
+                    skipped.codemod.as_str(),
+                    skipped.reason
+                )));
             }
         }
+        if result.changed {
+            content.push(Content::text(result.diff.clone()));
+        }
 
         Ok(CallToolResult {
             content,
@@ -137,105 +1202,184 @@ impl MermaidServer {
     }
 
     #[tool(
-        name = "scanMermaidBlocks",
-        description = "Scans a markdown file and returns Mermaid block indexes and locations for GitHub preview validation"
+        name = "suggestMermaidFix",
+        description = "Asks the client's model (via sampling) to fix an invalid Mermaid block in a markdown file, validates each candidate against the target and retries with the new diagnostics, and returns a unified diff of the first valid fix. The file is not written"
     )]
-    async fn scan_mermaid_blocks(
+    async fn suggest_mermaid_fix(
         &self,
-        params: Parameters<ScanMermaidBlocksParams>,
+        params: Parameters<SuggestMermaidFixParams>,
+        context: RequestContext<RoleServer>,
     ) -> Result<CallToolResult, McpError> {
         let params = params.0;
-        let markdown = match tokio::fs::read_to_string(&params.file_path).await {
-            Ok(content) => content,
-            Err(err) => {
-                return Ok(invalid_result(&format!(
-                    "Failed to read markdown file {}: {}",
-                    params.file_path, err
-                )))
-            }
+        let supports_sampling = context
+            .peer
+            .peer_info()
+            .is_some_and(|info| info.capabilities.sampling.is_some());
+        if !supports_sampling {
+            return Ok(invalid_result(
+                "suggestMermaidFix needs a client that supports sampling; use the fix-mermaid-block prompt instead",
+            ));
+        }
+        let source = match self
+            .markdown_source(
+                &context.peer,
+                params.file_path.as_deref(),
+                params.document.as_deref(),
+            )
+            .await
+        {
+            Ok(source) => source,
+            Err(message) => return Ok(invalid_result(&message)),
+        };
+        let block_index = match params.block.resolve(&source.label, &source.markdown) {
+            Ok(index) => index,
+            Err(message) => return Ok(invalid_result(&message)),
+        };
+        let target = params.target.unwrap_or_default();
+        let mut options = SuggestFixOptions {
+            mermaid_version: params.mermaid_version,
+            control: ValidationControl {
+                cancel: context.ct.clone(),
+                progress: None,
+            },
+            ..SuggestFixOptions::new(target, timeout_from_env())
+        };
+        if let Some(max_attempts) = params.max_attempts {
+            options.max_attempts = max_attempts.clamp(1, MAX_ATTEMPTS_LIMIT);
+        }
+        let peer = context.peer.clone();
+        let sample = |prompt: String| {
+            let peer = peer.clone();
+            async move { sample_fix(&peer, prompt).await }
+        };
+        let result = match suggest_fix(
+            &source.label,
+            &source.markdown,
+            block_index,
+            &options,
+            &self.renderers,
+            sample,
+        )
+        .await
+        {
+            Ok(result) => result,
+            Err(message) => return Ok(invalid_result(&message)),
         };
 
-        let result = scan_markdown_for_mermaid(&markdown);
-        let summary = format!(
-            "GitHub scan complete: {} block(s), {} error(s)",
-            result.mermaid_block_count, result.error_count
-        );
-
+        let summary = if result.already_valid {
+            format!(
+                "Block #{} is already valid for {} preview",
+                block_index,
+                target.display_name()
+            )
+        } else if result.fixed {
+            format!(
+                "Found a fix for block #{} after {} attempt(s) (file not written)",
+                block_index,
+                result.attempts.len()
+            )
+        } else {
+            format!(
+                "No valid fix for block #{} after {} attempt(s)",
+                block_index,
+                result.attempts.len()
+            )
+        };
         let mut content = vec![Content::text(summary)];
-        for block in &result.blocks {
+        for attempt in result.attempts.iter().filter(|attempt| !attempt.valid) {
+            let messages = attempt
+                .issues
+                .iter()
+                .map(|issue| format!("{}: {}", issue.code, issue.message))
+                .collect::<Vec<_>>();
             content.push(Content::text(format!(
-                "Block #{} lines {}-{} ({} lines): {}",
-                block.index, block.start_line, block.end_line, block.line_count, block.first_line
+                "Attempt {} rejected: {}",
+                attempt.attempt,
+                messages.join("; ")
             )));
         }
-        for issue in &result.issues {
-            let mut line = format!("[{}] {}: {}", issue.severity, issue.code, issue.message);
-            if let Some(line_no) = issue.line {
-                line.push_str(&format!(" at line {line_no}"));
-            }
-            content.push(Content::text(line));
+        if result.fixed {
+            content.push(Content::text(result.diff.clone()));
         }
 
-        Ok(CallToolResult {
+        Ok(source.warn_if_stale(CallToolResult {
             content,
             structured_content: Some(
                 to_value(result).map_err(|err| McpError::internal_error(err.to_string(), None))?,
             ),
             is_error: Some(false),
             meta: None,
-        })
+        }))
     }
 
     #[tool(
-        name = "validateMermaidBlock",
-        description = "Validates one Mermaid block in a markdown file by block index using GitHub preview rules"
+        name = "editMermaidBlock",
+        description = "Replaces the source of one Mermaid block in a markdown file, keeping its fences, indentation and line endings. The block must still match `expectedHash` and the new diagram must validate for the target, unless `force` is set; the file is written atomically"
     )]
-    async fn validate_mermaid_block(
+    async fn edit_mermaid_block(
         &self,
-        params: Parameters<ValidateMermaidBlockParams>,
+        params: Parameters<EditMermaidBlockParams>,
+        context: RequestContext<RoleServer>,
     ) -> Result<CallToolResult, McpError> {
         let params = params.0;
-        let markdown = match tokio::fs::read_to_string(&params.file_path).await {
-            Ok(content) => content,
-            Err(err) => {
-                return Ok(invalid_result(&format!(
-                    "Failed to read markdown file {}: {}",
-                    params.file_path, err
-                )))
-            }
+        let path = match self.resolve_path(&context.peer, &params.file_path).await {
+            Ok(path) => path,
+            Err(message) => return Ok(invalid_result(&message)),
         };
-        let timeout = timeout_from_env();
-        let result =
-            validate_mermaid_block_in_markdown(&markdown, params.block_index, timeout).await;
+        let target = params.target.unwrap_or_default();
+        let options = EditBlockOptions {
+            validation: ValidationOptions {
+                mermaid_version: params.mermaid_version,
+                control: ValidationControl {
+                    cancel: context.ct,
+                    progress: None,
+                },
+                ..ValidationOptions::new(target, timeout_from_env())
+            },
+            force: params.force.unwrap_or(false),
+        };
+        let result = match edit_mermaid_block(
+            &path,
+            &params.block,
+            &params.expected_hash,
+            &params.source,
+            &options,
+            &self.renderers,
+        )
+        .await
+        {
+            Ok(result) => result,
+            Err(message) => return Ok(invalid_result(&message)),
+        };
+        let block_index = result.block_index;
 
-        let summary = if result.valid {
-            format!("Block #{} is valid for GitHub preview", params.block_index)
-        } else {
+        let mut lines = vec![if !result.written {
             format!(
-                "Block #{} is invalid for GitHub preview ({} issue(s))",
-                params.block_index,
+                "Block #{} was not written: the new diagram is invalid for {} preview ({} issue(s)); pass force to write it anyway",
+                block_index,
+                target.display_name(),
                 result.issues.len()
             )
-        };
-
-        let mut content = vec![Content::text(summary)];
+        } else if result.forced {
+            format!(
+                "Block #{} in {} written with force",
+                block_index, params.file_path
+            )
+        } else {
+            format!(
+                "Block #{} in {} written; it is valid for {} preview",
+                block_index,
+                params.file_path,
+                target.display_name()
+            )
+        }];
         for issue in &result.issues {
-            let mut line = format!("[{}] {}: {}", issue.severity, issue.code, issue.message);
-            if let Some(line_no) = issue.line {
-                if let Some(column_no) = issue.column {
-                    line.push_str(&format!(" at line {line_no}, column {column_no}"));
-                } else {
-                    line.push_str(&format!(" at line {line_no}"));
-                }
-            }
-            content.push(Content::text(line));
-            if let Some(snippet) = &issue.snippet {
-                content.push(Content::text(format!("Snippet: {snippet}")));
-            }
+            lines.extend(issue_lines(issue, Verbosity::Full));
         }
+        lines.push(result.diff.clone());
 
         Ok(CallToolResult {
-            content,
+            content: vec![Content::text(lines.join("\n"))],
             structured_content: Some(
                 to_value(result).map_err(|err| McpError::internal_error(err.to_string(), None))?,
             ),
@@ -245,7 +1389,567 @@ impl MermaidServer {
     }
 }
 
-fn normalize_diagram(input: &str) -> Result<String, String> {
+#[prompt_router]
+impl MermaidServer {
+    #[prompt(
+        name = "fix-mermaid-block",
+        description = "Fix one Mermaid block in a markdown file, with its source and current diagnostics"
+    )]
+    async fn fix_mermaid_block_prompt(
+        &self,
+        Parameters(args): Parameters<FixBlockPromptArgs>,
+        peer: Peer<RoleServer>,
+    ) -> Result<GetPromptResult, McpError> {
+        let block_index = args.block_index.trim().parse::<u32>().map_err(|_| {
+            McpError::invalid_params(
+                format!(
+                    "blockIndex must be a block number, got {}",
+                    args.block_index
+                ),
+                None,
+            )
+        })?;
+        let target = parse_prompt_target(args.target.as_deref())?;
+        let markdown = self.read_prompt_file(&peer, &args.file_path).await?;
+        let options = ValidationOptions::new(target, timeout_from_env());
+        let text = fix_block_prompt(
+            &args.file_path,
+            &markdown,
+            block_index,
+            &options,
+            &self.renderers,
+        )
+        .await
+        .map_err(|message| McpError::invalid_params(message, None))?;
+        Ok(GetPromptResult {
+            description: Some(format!(
+                "Fix Mermaid block #{block_index} in {}",
+                args.file_path
+            )),
+            messages: vec![PromptMessage::new_text(PromptMessageRole::User, text)],
+        })
+    }
+
+    #[prompt(
+        name = "write-mermaid-diagram",
+        description = "Write a new Mermaid diagram that renders on the given preview targets"
+    )]
+    async fn write_mermaid_diagram_prompt(
+        &self,
+        Parameters(args): Parameters<WriteDiagramPromptArgs>,
+    ) -> Result<GetPromptResult, McpError> {
+        let targets = match args.targets.as_deref() {
+            Some(list) => list
+                .split(',')
+                .filter(|name| !name.trim().is_empty())
+                .map(|name| parse_prompt_target(Some(name)))
+                .collect::<Result<Vec<_>, _>>()?,
+            None => Vec::new(),
+        };
+        let targets = if targets.is_empty() {
+            vec![PreviewTarget::default()]
+        } else {
+            targets
+        };
+        let text = write_diagram_prompt(&args.diagram_type, &args.description, &targets);
+        Ok(GetPromptResult {
+            description: Some(format!("Write a Mermaid {} diagram", args.diagram_type)),
+            messages: vec![PromptMessage::new_text(PromptMessageRole::User, text)],
+        })
+    }
+
+    #[prompt(
+        name = "review-diagrams-in-file",
+        description = "Review every Mermaid diagram in a markdown file, with sources and diagnostics"
+    )]
+    async fn review_diagrams_in_file_prompt(
+        &self,
+        Parameters(args): Parameters<ReviewFilePromptArgs>,
+        peer: Peer<RoleServer>,
+    ) -> Result<GetPromptResult, McpError> {
+        let target = parse_prompt_target(args.target.as_deref())?;
+        let markdown = self.read_prompt_file(&peer, &args.file_path).await?;
+        let options = ValidationOptions::new(target, timeout_from_env());
+        let text = review_file_prompt(&args.file_path, &markdown, &options, &self.renderers).await;
+        Ok(GetPromptResult {
+            description: Some(format!("Review Mermaid diagrams in {}", args.file_path)),
+            messages: vec![PromptMessage::new_text(PromptMessageRole::User, text)],
+        })
+    }
+}
+
+impl MermaidServer {
+    /// A server for a new client session: renderers and caches are shared,
+    /// the client's roots, log level, subscriptions and open documents are
+    /// not.
+    pub fn session(&self) -> Self {
+        Self {
+            roots: Arc::default(),
+            log: Arc::default(),
+            subscriptions: Arc::default(),
+            documents: Arc::default(),
+            ..self.clone()
+        }
+    }
+
+    /// Replaces the directories readable outside the client's roots
+    /// (default: `MERMAID_ALLOWED_PATHS`).
+    pub fn with_allowed_paths(mut self, paths: Vec<PathBuf>) -> Self {
+        self.allowed_paths = Arc::new(paths);
+        self
+    }
+
+    /// Returns the session's roots, asking the client for them when they
+    /// are not known yet. Clients without `file://` roots, including those
+    /// without the roots capability, get the server's working directory as
+    /// their only root.
+    async fn root_set(&self, peer: &Peer<RoleServer>) -> Result<RootSet, String> {
+        let mut roots = self.roots.lock().await;
+        if let Some(roots) = roots.as_ref() {
+            return Ok(roots.clone());
+        }
+        let supports_roots = peer
+            .peer_info()
+            .is_some_and(|info| info.capabilities.roots.is_some());
+        let uris = if supports_roots {
+            peer.list_roots()
+                .await
+                .map_err(|err| format!("Failed to list client roots: {err}"))?
+                .roots
+                .into_iter()
+                .map(|root| root.uri)
+                .collect()
+        } else {
+            Vec::new()
+        };
+        let mut set =
+            RootSet::from_uris(uris.iter().map(String::as_str), self.allowed_paths.to_vec());
+        if let Ok(cwd) = std::env::current_dir() {
+            set = set.or_root(cwd);
+        }
+        *roots = Some(set.clone());
+        Ok(set)
+    }
+
+    /// Reads the markdown a block tool works on: the open `document` when
+    /// given, else the file at `file_path`.
+    async fn markdown_source(
+        &self,
+        peer: &Peer<RoleServer>,
+        file_path: Option<&str>,
+        document: Option<&str>,
+    ) -> Result<MarkdownSource, String> {
+        if let Some(handle) = document {
+            let checked = self
+                .documents
+                .get(handle)
+                .await
+                .map_err(|err| err.to_string())?;
+            return Ok(MarkdownSource {
+                label: checked.document.path.display().to_string(),
+                path: checked.document.path,
+                markdown: checked.document.markdown,
+                stale: checked.stale,
+            });
+        }
+        let file_path = file_path.ok_or("Pass filePath or a document handle")?;
+        let path = self.resolve_path(peer, file_path).await?;
+        let markdown = tokio::fs::read_to_string(&path)
+            .await
+            .map_err(|err| format!("Failed to read markdown file {file_path}: {err}"))?;
+        Ok(MarkdownSource {
+            path,
+            label: file_path.to_string(),
+            markdown: Arc::from(markdown),
+            stale: false,
+        })
+    }
+
+    /// Resolves a tool's file path against the client's roots.
+    async fn resolve_path(&self, peer: &Peer<RoleServer>, path: &str) -> Result<PathBuf, String> {
+        self.root_set(peer)
+            .await?
+            .resolve(path)
+            .map_err(|err| err.to_string())
+    }
+
+    /// Resolves `params.root`, walks it and selects the requested page of
+    /// the matched files.
+    async fn directory_page(
+        &self,
+        peer: &Peer<RoleServer>,
+        params: &MermaidDirectoryParams,
+    ) -> Result<(PathBuf, Vec<PathBuf>, FilePage), String> {
+        let root = self.resolve_path(peer, &params.root).await?;
+        let filter = DirectoryFilter {
+            include: params.include.clone().unwrap_or_default(),
+            exclude: params.exclude.clone().unwrap_or_default(),
+        };
+        let walked = root.clone();
+        let files = tokio::task::spawn_blocking(move || find_markdown_files(&walked, &filter))
+            .await
+            .map_err(|err| format!("Directory walk failed: {err}"))??;
+        let names = files
+            .iter()
+            .map(|path| relative(&root, path).unwrap_or_default())
+            .collect::<Vec<_>>();
+        let page = FilePage::select(&names, params.limit, params.cursor.as_deref())?;
+        Ok((root, files, page))
+    }
+
+    async fn read_prompt_file(
+        &self,
+        peer: &Peer<RoleServer>,
+        path: &str,
+    ) -> Result<String, McpError> {
+        let resolved = self
+            .resolve_path(peer, path)
+            .await
+            .map_err(|message| McpError::invalid_params(message, None))?;
+        tokio::fs::read_to_string(&resolved).await.map_err(|err| {
+            McpError::invalid_params(format!("Failed to read markdown file {path}: {err}"), None)
+        })
+    }
+
+    async fn read_diagram_resource(
+        &self,
+        uri: String,
+        peer: &Peer<RoleServer>,
+    ) -> Result<ReadResourceResult, McpError> {
+        if let Some(path) = parse_status_uri(&uri) {
+            return self.read_status_resource(uri, &path, peer).await;
+        }
+        let mut resource = DiagramResource::parse(&uri).ok_or_else(|| {
+            McpError::resource_not_found(ResourceError::UnknownUri(uri.clone()).to_string(), None)
+        })?;
+        if let DiagramResource::FileBlock { path, .. } = &mut resource {
+            let resolved = self
+                .resolve_path(peer, path)
+                .await
+                .map_err(|message| McpError::invalid_params(message, None))?;
+            *path = resolved.display().to_string();
+        }
+        let format = resource.format();
+        let output = self
+            .render_cache
+            .read(&resource, &self.renderers, timeout_from_env())
+            .await
+            .map_err(|err| match err {
+                ResourceError::UnknownUri(_) | ResourceError::NotFound(_) => {
+                    McpError::resource_not_found(err.to_string(), None)
+                }
+                ResourceError::Render(_) => McpError::internal_error(err.to_string(), None),
+            })?;
+        let mime_type = Some(format.mime_type().to_string());
+        let contents = match format {
+            OutputFormat::Svg => ResourceContents::TextResourceContents {
+                uri,
+                mime_type,
+                text: String::from_utf8_lossy(&output).into_owned(),
+                meta: None,
+            },
+            OutputFormat::Png => ResourceContents::BlobResourceContents {
+                uri,
+                mime_type,
+                blob: base64::engine::general_purpose::STANDARD.encode(output.as_slice()),
+                meta: None,
+            },
+        };
+        Ok(ReadResourceResult {
+            contents: vec![contents],
+        })
+    }
+}
+
+impl MermaidServer {
+    /// Returns the diagram status of a file. Subscribed files reuse the
+    /// watch's results, so only blocks changed since are rendered.
+    async fn read_status_resource(
+        &self,
+        uri: String,
+        path: &str,
+        peer: &Peer<RoleServer>,
+    ) -> Result<ReadResourceResult, McpError> {
+        let resolved = self
+            .resolve_path(peer, path)
+            .await
+            .map_err(|message| McpError::invalid_params(message, None))?;
+        let markdown = tokio::fs::read_to_string(&resolved).await.map_err(|err| {
+            McpError::resource_not_found(
+                format!("Failed to read markdown file {path}: {err}"),
+                None,
+            )
+        })?;
+        let display = resolved.display().to_string();
+        let status = match self.subscriptions.tracker(&uri) {
+            Some(tracker) => {
+                let mut tracker = tracker.lock().await;
+                tracker
+                    .refresh(&display, &markdown, &self.renderers)
+                    .await
+                    .0
+            }
+            None => {
+                status_tracker()
+                    .refresh(&display, &markdown, &self.renderers)
+                    .await
+                    .0
+            }
+        };
+        let text = serde_json::to_string_pretty(&status)
+            .map_err(|err| McpError::internal_error(err.to_string(), None))?;
+        Ok(ReadResourceResult {
+            contents: vec![ResourceContents::TextResourceContents {
+                uri,
+                mime_type: Some(STATUS_MIME_TYPE.to_string()),
+                text,
+                meta: None,
+            }],
+        })
+    }
+}
+
+/// Status resources report GitHub preview validity.
+fn status_tracker() -> StatusTracker {
+    StatusTracker::new(ValidationOptions::new(
+        PreviewTarget::default(),
+        timeout_from_env(),
+    ))
+}
+
+/// The markdown behind a block tool call.
+struct MarkdownSource {
+    path: PathBuf,
+    /// The path as the client named it, for messages.
+    label: String,
+    markdown: Arc<str>,
+    /// An open document whose file changed since it was opened.
+    stale: bool,
+}
+
+impl MarkdownSource {
+    /// Puts the stale-document warning first in `result` and adds it to the
+    /// structured content as `warning`.
+    fn warn_if_stale(&self, mut result: CallToolResult) -> CallToolResult {
+        if self.stale {
+            result
+                .content
+                .insert(0, Content::text(format!("Warning: {STALE_WARNING}")));
+            if let Some(Value::Object(structured)) = &mut result.structured_content {
+                structured.insert("warning".to_string(), Value::from(STALE_WARNING));
+            }
+        }
+        result
+    }
+}
+
+/// Ties a tool call's validation to its request: cancelled by
+/// `notifications/cancelled`, and forwarding per-block progress as
+/// `notifications/progress` when the client sent a progress token.
+fn request_control(
+    context: &RequestContext<RoleServer>,
+) -> (ValidationControl, Option<JoinHandle<()>>) {
+    let mut control = ValidationControl {
+        cancel: context.ct.clone(),
+        progress: None,
+    };
+    let Some(token) = context.meta.get_progress_token() else {
+        return (control, None);
+    };
+    let (sender, mut receiver) = mpsc::unbounded_channel::<Progress>();
+    let peer = context.peer.clone();
+    let forwarder = tokio::spawn(async move {
+        while let Some(progress) = receiver.recv().await {
+            let (completed, total, message) = match progress {
+                Progress::Block(block) => (
+                    block.completed,
+                    block.total,
+                    format!("Checked block #{}", block.block_index),
+                ),
+                Progress::File(file) => {
+                    (file.completed, file.total, format!("Checked {}", file.path))
+                }
+            };
+            let _ = peer
+                .notify_progress(ProgressNotificationParam {
+                    progress_token: token.clone(),
+                    progress: f64::from(completed),
+                    total: Some(f64::from(total)),
+                    message: Some(message),
+                })
+                .await;
+        }
+    });
+    control.progress = Some(sender);
+    (control, Some(forwarder))
+}
+
+/// Waits until every progress notification is sent, so none arrives after
+/// the tool result.
+async fn finish_progress(control: ValidationControl, forwarder: Option<JoinHandle<()>>) {
+    drop(control);
+    if let Some(forwarder) = forwarder {
+        let _ = forwarder.await;
+    }
+}
+
+/// Sends one fix prompt to the client's model and returns its text reply.
+async fn sample_fix(peer: &Peer<RoleServer>, prompt: String) -> Result<String, String> {
+    let result = peer
+        .create_message(CreateMessageRequestParams {
+            meta: None,
+            task: None,
+            messages: vec![SamplingMessage {
+                role: Role::User,
+                content: Content::text(prompt),
+            }],
+            model_preferences: None,
+            system_prompt: Some(
+                "You fix Mermaid diagrams. Reply with only the corrected diagram in a ```mermaid code block."
+                    .to_string(),
+            ),
+            include_context: None,
+            temperature: None,
+            max_tokens: 2048,
+            stop_sequences: None,
+            metadata: None,
+        })
+        .await
+        .map_err(|err| format!("Sampling request failed: {err}"))?;
+    result
+        .message
+        .content
+        .as_text()
+        .map(|text| text.text.clone())
+        .ok_or_else(|| "The client's model did not reply with text".to_string())
+}
+
+fn parse_prompt_target(value: Option<&str>) -> Result<PreviewTarget, McpError> {
+    match value.map(str::trim).filter(|value| !value.is_empty()) {
+        Some(name) => PreviewTarget::parse(name).ok_or_else(|| {
+            McpError::invalid_params(format!("Unknown preview target {name}"), None)
+        }),
+        None => Ok(PreviewTarget::default()),
+    }
+}
+
+fn resource_link(resource: &DiagramResource, name: &str, size: usize) -> RawResource {
+    let format = resource.format();
+    let mut link = RawResource::new(resource.uri(), format!("{name}.{}", format.as_str()));
+    link.mime_type = Some(format.mime_type().to_string());
+    link.size = u32::try_from(size).ok();
+    link
+}
+
+/// One line per issue, plus its snippet at full verbosity.
+fn issue_lines(issue: &PreviewIssue, verbosity: Verbosity) -> Vec<String> {
+    let mut line = format!("[{}] {}: {}", issue.severity, issue.code, issue.message);
+    if let Some(block_index) = issue.block_index {
+        line.push_str(&format!(" (block #{block_index})"));
+    }
+    if let Some(line_no) = issue.line {
+        if let Some(column_no) = issue.column {
+            line.push_str(&format!(" at line {line_no}, column {column_no}"));
+        } else {
+            line.push_str(&format!(" at line {line_no}"));
+        }
+    }
+    let mut lines = vec![line];
+    if verbosity == Verbosity::Full {
+        if let Some(snippet) = &issue.snippet {
+            lines.push(format!("Snippet: {snippet}"));
+        }
+    }
+    lines
+}
+
+/// The scan details of a block beyond its location, for full verbosity.
+fn block_detail(block: &MermaidBlockInfo) -> Vec<String> {
+    let mut lines = vec![format!(
+        "  {} {}, info string \"{}\", {} chars",
+        block.diagram_type.as_deref().unwrap_or("unknown diagram"),
+        block.syntax.as_str(),
+        block.info_string,
+        block.char_count
+    )];
+    for feature in &block.features {
+        lines.push(format!(
+            "  uses {} (Mermaid {}+, block line {})",
+            feature.description, feature.min_version, feature.line
+        ));
+    }
+    lines
+}
+
+/// Where a page ends and how to get the next one.
+fn page_hint(page: &PageInfo, block_count: u32) -> Option<String> {
+    let cursor = page.next_cursor.as_ref()?;
+    Some(format!(
+        "Showing blocks {}-{} of {block_count}; pass cursor \"{cursor}\" for more",
+        page.first_block, page.last_block
+    ))
+}
+
+/// One line per file of a directory result, and its issues at full
+/// verbosity; nothing at summary verbosity.
+fn file_lines(
+    path: &str,
+    block_count: u32,
+    error_count: u32,
+    issues: &[PreviewIssue],
+    verbosity: Verbosity,
+) -> Vec<String> {
+    if verbosity == Verbosity::Summary {
+        return Vec::new();
+    }
+    let mut lines = vec![format!(
+        "{path}: {block_count} block(s), {error_count} error(s)"
+    )];
+    if verbosity == Verbosity::Full {
+        for issue in issues {
+            lines.extend(issue_lines(issue, verbosity));
+        }
+    }
+    lines
+}
+
+fn file_page_hint(page: &FilePageInfo) -> Option<String> {
+    let cursor = page.next_cursor.as_ref()?;
+    Some(format!(
+        "Showing files {}-{} of {}; pass cursor \"{cursor}\" for more",
+        page.first_file, page.last_file, page.total_files
+    ))
+}
+
+fn compatibility_lines(compatibility: &[BlockCompatibility]) -> Vec<String> {
+    compatibility
+        .iter()
+        .map(|block| {
+            let cells = block
+                .targets
+                .iter()
+                .map(|cell| {
+                    let name = match &cell.renderer_version {
+                        Some(version) => format!("{}@{version}", cell.target.as_str()),
+                        None => cell.target.as_str().to_string(),
+                    };
+                    if cell.compatible {
+                        format!("{name} ok")
+                    } else {
+                        format!("{name} ({})", cell.issue_codes.join(", "))
+                    }
+                })
+                .collect::<Vec<_>>();
+            format!(
+                "Block #{} compatibility: {}",
+                block.block_index,
+                cells.join("; ")
+            )
+        })
+        .collect()
+}
+
+pub(crate) fn normalize_diagram(input: &str) -> Result<String, String> {
     let trimmed = input.trim();
     if let Some(result) = strip_standalone_fenced_mermaid(trimmed) {
         return result;
@@ -324,19 +2028,176 @@ fn is_fence_start(line: &str) -> bool {
     line.starts_with("```")
 }
 
-#[tool_handler]
 impl ServerHandler for MermaidServer {
     fn get_info(&self) -> ServerInfo {
-        let mut info = ServerInfo::default();
-        info.server_info = Implementation {
-            name: "Mermaid Validator".to_string(),
-            version: "0.6.0".to_string(),
-            title: None,
-            icons: None,
-            website_url: None,
-        };
-        info.capabilities = ServerCapabilities::builder().enable_tools().build();
-        info
+        ServerInfo {
+            server_info: Implementation {
+                name: "Mermaid Validator".to_string(),
+                version: "0.6.0".to_string(),
+                title: None,
+                icons: None,
+                website_url: None,
+            },
+            capabilities: ServerCapabilities::builder()
+                .enable_tools()
+                .enable_prompts()
+                .enable_resources()
+                .enable_resources_subscribe()
+                .enable_logging()
+                .build(),
+            ..Default::default()
+        }
+    }
+
+    // Tool, prompt and resource calls run inside the session's log scope so
+    // renderer and cache events reach the client that caused them.
+    async fn call_tool(
+        &self,
+        request: CallToolRequestParams,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let call = ToolCallContext::new(self, request, context);
+        self.log.scope(self.tool_router.call(call)).await
+    }
+
+    async fn list_tools(
+        &self,
+        _request: Option<PaginatedRequestParams>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListToolsResult, McpError> {
+        Ok(ListToolsResult::with_all_items(self.tool_router.list_all()))
+    }
+
+    async fn get_prompt(
+        &self,
+        request: GetPromptRequestParams,
+        context: RequestContext<RoleServer>,
+    ) -> Result<GetPromptResult, McpError> {
+        let prompt = PromptContext::new(self, request.name, request.arguments, context);
+        self.log.scope(self.prompt_router.get_prompt(prompt)).await
+    }
+
+    async fn list_prompts(
+        &self,
+        _request: Option<PaginatedRequestParams>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListPromptsResult, McpError> {
+        Ok(ListPromptsResult::with_all_items(
+            self.prompt_router.list_all(),
+        ))
+    }
+
+    async fn set_level(
+        &self,
+        request: SetLevelRequestParams,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<(), McpError> {
+        self.log.set_level(request.level.into());
+        Ok(())
+    }
+
+    async fn on_initialized(&self, context: NotificationContext<RoleServer>) {
+        let (sender, mut receiver) = mpsc::unbounded_channel::<LogRecord>();
+        self.log.connect(sender);
+        let peer = context.peer;
+        tokio::spawn(async move {
+            while let Some(record) = receiver.recv().await {
+                let message = LoggingMessageNotificationParam::from(record);
+                if peer.notify_logging_message(message).await.is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    async fn on_roots_list_changed(&self, _context: NotificationContext<RoleServer>) {
+        *self.roots.lock().await = None;
+    }
+
+    async fn list_resource_templates(
+        &self,
+        _request: Option<PaginatedRequestParams>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListResourceTemplatesResult, McpError> {
+        let templates = RESOURCE_TEMPLATES
+            .iter()
+            .map(|(uri_template, name, description)| {
+                let mime_type = if uri_template.ends_with(".png") {
+                    OutputFormat::Png.mime_type()
+                } else {
+                    OutputFormat::Svg.mime_type()
+                };
+                RawResourceTemplate {
+                    uri_template: uri_template.to_string(),
+                    name: name.to_string(),
+                    title: None,
+                    description: Some(description.to_string()),
+                    mime_type: Some(mime_type.to_string()),
+                    icons: None,
+                }
+                .no_annotation()
+            })
+            .chain(std::iter::once({
+                let (uri_template, name, description) = STATUS_RESOURCE_TEMPLATE;
+                RawResourceTemplate {
+                    uri_template: uri_template.to_string(),
+                    name: name.to_string(),
+                    title: None,
+                    description: Some(description.to_string()),
+                    mime_type: Some(STATUS_MIME_TYPE.to_string()),
+                    icons: None,
+                }
+                .no_annotation()
+            }))
+            .collect();
+        Ok(ListResourceTemplatesResult::with_all_items(templates))
+    }
+
+    async fn read_resource(
+        &self,
+        request: ReadResourceRequestParams,
+        context: RequestContext<RoleServer>,
+    ) -> Result<ReadResourceResult, McpError> {
+        self.log
+            .scope(self.read_diagram_resource(request.uri, &context.peer))
+            .await
+    }
+
+    async fn subscribe(
+        &self,
+        request: SubscribeRequestParams,
+        context: RequestContext<RoleServer>,
+    ) -> Result<(), McpError> {
+        let path = parse_status_uri(&request.uri).ok_or_else(|| {
+            McpError::invalid_params(
+                format!(
+                    "Only mermaid://status/{{path}} resources can be subscribed to, got {}",
+                    request.uri
+                ),
+                None,
+            )
+        })?;
+        let resolved = self
+            .resolve_path(&context.peer, &path)
+            .await
+            .map_err(|message| McpError::invalid_params(message, None))?;
+        let watch = WatchContext {
+            peer: context.peer,
+            renderers: self.renderers.clone(),
+            log: self.log.clone(),
+        };
+        self.subscriptions
+            .subscribe(&request.uri, resolved, status_tracker(), watch)
+            .map_err(|message| McpError::internal_error(message, None))
+    }
+
+    async fn unsubscribe(
+        &self,
+        request: UnsubscribeRequestParams,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<(), McpError> {
+        self.subscriptions.unsubscribe(&request.uri);
+        Ok(())
     }
 }
 
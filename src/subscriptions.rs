@@ -0,0 +1,199 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use notify::{RecursiveMode, Watcher};
+use rmcp::{model::ResourceUpdatedNotificationParam, Peer, RoleServer};
+use serde_json::{json, to_value};
+use tokio::{sync::mpsc, task::JoinHandle};
+
+use crate::{
+    diagram_status::StatusTracker,
+    logging::{log, LogLevel, SessionLog},
+    renderer_registry::RendererRegistry,
+};
+
+/// Editors often save in several steps; events closer together than this
+/// are handled as one change.
+const DEBOUNCE: Duration = Duration::from_millis(50);
+
+/// How often an idle watch checks whether its session is gone.
+const CLOSED_POLL: Duration = Duration::from_secs(1);
+
+/// The status resources one session subscribed to. Dropping it, as when the
+/// session ends, stops every watch.
+#[derive(Debug, Default)]
+pub struct Subscriptions {
+    watches: Mutex<HashMap<String, Watch>>,
+}
+
+#[derive(Debug)]
+struct Watch {
+    tracker: Arc<tokio::sync::Mutex<StatusTracker>>,
+    task: JoinHandle<()>,
+}
+
+impl Drop for Watch {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// What a watch needs from its session.
+pub struct WatchContext {
+    pub peer: Peer<RoleServer>,
+    pub renderers: Arc<RendererRegistry>,
+    pub log: Arc<SessionLog>,
+}
+
+impl Subscriptions {
+    /// Watches `path` and sends `notifications/resources/updated` for `uri`
+    /// whenever the file's diagram status changes. Subscribing twice is a
+    /// no-op.
+    pub fn subscribe(
+        &self,
+        uri: &str,
+        path: PathBuf,
+        tracker: StatusTracker,
+        context: WatchContext,
+    ) -> Result<(), String> {
+        let mut watches = self
+            .watches
+            .lock()
+            .map_err(|_| "Subscriptions are unavailable".to_string())?;
+        if watches.contains_key(uri) {
+            return Ok(());
+        }
+
+        // Watch the directory: saving through a temporary file replaces the
+        // file's inode, which would end a watch on the file itself.
+        let dir = path
+            .parent()
+            .map(Path::to_path_buf)
+            .ok_or_else(|| format!("Cannot watch {}", path.display()))?;
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let mut watcher = notify::recommended_watcher(move |event| {
+            let _ = sender.send(event);
+        })
+        .map_err(|err| format!("Failed to watch {}: {err}", path.display()))?;
+        watcher
+            .watch(&dir, RecursiveMode::NonRecursive)
+            .map_err(|err| format!("Failed to watch {}: {err}", path.display()))?;
+
+        let tracker = Arc::new(tokio::sync::Mutex::new(tracker));
+        let log = context.log.clone();
+        let watch = watch_file(
+            uri.to_string(),
+            path,
+            watcher,
+            receiver,
+            tracker.clone(),
+            context,
+        );
+        let task = tokio::spawn(async move { log.scope(watch).await });
+        watches.insert(uri.to_string(), Watch { tracker, task });
+        Ok(())
+    }
+
+    /// Stops watching `uri`. Returns whether it was subscribed.
+    pub fn unsubscribe(&self, uri: &str) -> bool {
+        self.watches
+            .lock()
+            .ok()
+            .and_then(|mut watches| watches.remove(uri))
+            .is_some()
+    }
+
+    /// The tracker of a subscribed `uri`, so reads reuse the watch's results.
+    pub fn tracker(&self, uri: &str) -> Option<Arc<tokio::sync::Mutex<StatusTracker>>> {
+        let watches = self.watches.lock().ok()?;
+        watches.get(uri).map(|watch| watch.tracker.clone())
+    }
+}
+
+async fn watch_file(
+    uri: String,
+    path: PathBuf,
+    // Dropping the watcher stops the events, so the task owns it.
+    _watcher: notify::RecommendedWatcher,
+    mut events: mpsc::UnboundedReceiver<notify::Result<notify::Event>>,
+    tracker: Arc<tokio::sync::Mutex<StatusTracker>>,
+    context: WatchContext,
+) {
+    let display = path.display().to_string();
+    // The status at subscription time is the baseline for change detection.
+    let mut last = match tokio::fs::read_to_string(&path).await {
+        Ok(markdown) => {
+            let (status, _) = tracker
+                .lock()
+                .await
+                .refresh(&display, &markdown, &context.renderers)
+                .await;
+            to_value(status).ok()
+        }
+        Err(_) => None,
+    };
+
+    loop {
+        let event = tokio::select! {
+            event = events.recv() => event,
+            _ = tokio::time::sleep(CLOSED_POLL) => {
+                if context.peer.is_transport_closed() {
+                    break;
+                }
+                continue;
+            }
+        };
+        let Some(event) = event else { break };
+        if !touches(&event, &path) {
+            continue;
+        }
+        tokio::time::sleep(DEBOUNCE).await;
+        while events.try_recv().is_ok() {}
+
+        // A file that is briefly missing during a save is picked up again
+        // by the event that recreates it.
+        let Ok(markdown) = tokio::fs::read_to_string(&path).await else {
+            continue;
+        };
+        let (status, validated) = tracker
+            .lock()
+            .await
+            .refresh(&display, &markdown, &context.renderers)
+            .await;
+        let valid = status.valid;
+        let status = to_value(status).ok();
+        if status == last {
+            continue;
+        }
+        last = status;
+        log(
+            LogLevel::Debug,
+            "subscriptions",
+            json!({
+                "event": "statusChanged",
+                "uri": uri,
+                "valid": valid,
+                "revalidatedBlocks": validated,
+            }),
+        );
+        let notified = context
+            .peer
+            .notify_resource_updated(ResourceUpdatedNotificationParam { uri: uri.clone() })
+            .await;
+        if notified.is_err() {
+            break;
+        }
+    }
+}
+
+fn touches(event: &notify::Result<notify::Event>, path: &Path) -> bool {
+    match event {
+        Ok(event) => !event.kind.is_access() && event.paths.iter().any(|changed| changed == path),
+        // Missed events may hide a change; re-check the file.
+        Err(_) => true,
+    }
+}
@@ -0,0 +1,402 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use notify::{RecursiveMode, Watcher};
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    command_line::{
+        expand_inputs, fence_diagram, is_diagram_path, is_glob, split_glob, unfence_lines,
+        CheckInput,
+    },
+    diagram_status::{FileStatus, StatusTracker},
+    directory::{drop_no_blocks_issue, relative, DirectoryFilter},
+    preview_validator::{block_section, PreviewIssue, ValidationOptions},
+    renderer_registry::RendererRegistry,
+};
+
+/// Editors often save in several steps; events closer together than this
+/// are handled as one change.
+const DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// Name of the pseudo-diagram holding a file's issues outside any block.
+const FILE_DIAGRAM: &str = "file";
+
+/// How a diagram's validity changed since the previous run.
+#[derive(Debug, Clone)]
+pub enum ChangeKind {
+    /// New or valid before, failing now with this first error.
+    Failing(PreviewIssue),
+    /// Failing before, valid now.
+    Fixed,
+    /// Failing before, and gone now.
+    Removed,
+}
+
+#[derive(Debug, Clone)]
+pub struct DiagramChange {
+    pub path: String,
+    /// Block id, or `file` for issues outside any block.
+    pub diagram: String,
+    pub line: Option<u32>,
+    pub kind: ChangeKind,
+}
+
+/// Result of re-checking one file.
+#[derive(Debug, Clone, Default)]
+pub struct Refresh {
+    pub changes: Vec<DiagramChange>,
+    /// Blocks rendered again; the others reused the previous run's result.
+    pub validated: u32,
+}
+
+/// A diagram's status after a run.
+#[derive(Debug, Clone)]
+struct Diagram {
+    id: String,
+    line: Option<u32>,
+    /// First error, when the diagram fails.
+    error: Option<PreviewIssue>,
+}
+
+struct WatchedFile {
+    tracker: StatusTracker,
+    diagrams: Vec<Diagram>,
+    block_count: u32,
+    error_count: u32,
+}
+
+/// The watched files and the last status of each of their diagrams.
+pub struct WatchSession {
+    options: ValidationOptions,
+    files: HashMap<PathBuf, WatchedFile>,
+}
+
+impl WatchSession {
+    pub fn new(options: ValidationOptions) -> Self {
+        WatchSession {
+            options,
+            files: HashMap::new(),
+        }
+    }
+
+    /// Re-checks `path`, reported as `name`. Only blocks whose content or
+    /// render options changed since the last run are rendered; an
+    /// unreadable file is forgotten like a removed one.
+    pub async fn refresh(
+        &mut self,
+        path: &Path,
+        name: &str,
+        renderers: &RendererRegistry,
+    ) -> Refresh {
+        let Ok(content) = tokio::fs::read_to_string(path).await else {
+            return Refresh {
+                changes: self.forget(path, name),
+                validated: 0,
+            };
+        };
+        let is_diagram = is_diagram_path(path);
+        let markdown = if is_diagram {
+            fence_diagram(&content)
+        } else {
+            content
+        };
+        let options = &self.options;
+        let file = self
+            .files
+            .entry(path.to_path_buf())
+            .or_insert_with(|| WatchedFile {
+                tracker: StatusTracker::new(options.clone()),
+                diagrams: Vec::new(),
+                block_count: 0,
+                error_count: 0,
+            });
+        let (mut status, validated) = file.tracker.refresh(name, &markdown, renderers).await;
+        status.error_count -= drop_no_blocks_issue(&mut status.issues);
+        if is_diagram {
+            unfence_status(&mut status);
+        }
+
+        let diagrams = diagrams(&status);
+        let changes = diff(name, &file.diagrams, &diagrams);
+        file.diagrams = diagrams;
+        file.block_count = status.mermaid_block_count;
+        file.error_count = status.error_count;
+        Refresh { changes, validated }
+    }
+
+    /// Stops tracking `path`; its failing diagrams are reported removed.
+    pub fn forget(&mut self, path: &Path, name: &str) -> Vec<DiagramChange> {
+        self.files
+            .remove(path)
+            .map(|file| diff(name, &file.diagrams, &[]))
+            .unwrap_or_default()
+    }
+
+    pub fn is_tracked(&self, path: &Path) -> bool {
+        self.files.contains_key(path)
+    }
+
+    /// Files, blocks and errors as of the last run.
+    pub fn totals(&self) -> (usize, u32, u32) {
+        let blocks = self.files.values().map(|file| file.block_count).sum();
+        let errors = self.files.values().map(|file| file.error_count).sum();
+        (self.files.len(), blocks, errors)
+    }
+}
+
+/// One line per change: `FAIL`, `FIXED` or `REMOVED`, the file line and the
+/// diagram, and for failures the first error.
+pub fn change_lines(changes: &[DiagramChange]) -> Vec<String> {
+    changes
+        .iter()
+        .map(|change| {
+            let line = match &change.kind {
+                ChangeKind::Failing(issue) => issue.line.or(change.line),
+                _ => change.line,
+            };
+            let location = match line {
+                Some(line) => format!("{}:{line}", change.path),
+                None => change.path.clone(),
+            };
+            match &change.kind {
+                ChangeKind::Failing(issue) => format!(
+                    "FAIL    {location} {}: {} [{}]",
+                    change.diagram, issue.message, issue.code
+                ),
+                ChangeKind::Fixed => format!("FIXED   {location} {}", change.diagram),
+                ChangeKind::Removed => format!("REMOVED {location} {}", change.diagram),
+            }
+        })
+        .collect()
+}
+
+/// Checks the files of `inputs` once, then re-checks files as they change
+/// until `shutdown`, printing newly failing and newly fixed diagrams. New
+/// files under watched directories and globs are picked up.
+pub async fn watch_inputs(
+    inputs: &[String],
+    filter: &DirectoryFilter,
+    options: ValidationOptions,
+    renderers: &RendererRegistry,
+    shutdown: CancellationToken,
+) -> Result<(), String> {
+    if inputs.iter().any(|input| input == "-") {
+        return Err("watch cannot read stdin".to_string());
+    }
+    let cwd = std::env::current_dir().map_err(|err| err.to_string())?;
+    let inputs = inputs
+        .iter()
+        .map(|input| cwd.join(input).to_string_lossy().into_owned())
+        .collect::<Vec<_>>();
+    let name = |path: &Path| relative(&cwd, path).unwrap_or_else(|| path.display().to_string());
+
+    let mut files = Vec::new();
+    for input in &inputs {
+        files.extend(input_files(input, filter)?);
+    }
+    let (sender, mut events) = mpsc::unbounded_channel();
+    let mut watcher = notify::recommended_watcher(move |event| {
+        let _ = sender.send(event);
+    })
+    .map_err(|err| format!("Failed to start watching: {err}"))?;
+    for (root, mode) in watch_roots(&inputs) {
+        watcher
+            .watch(&root, mode)
+            .map_err(|err| format!("Failed to watch {}: {err}", root.display()))?;
+    }
+
+    let mut session = WatchSession::new(options);
+    let mut changes = Vec::new();
+    for path in &files {
+        changes.extend(session.refresh(path, &name(path), renderers).await.changes);
+    }
+    print_lines(&change_lines(&changes));
+    let (file_count, blocks, errors) = session.totals();
+    println!("Watching {file_count} file(s): {blocks} block(s), {errors} error(s)");
+
+    loop {
+        let event = tokio::select! {
+            event = events.recv() => event,
+            _ = shutdown.cancelled() => break,
+        };
+        let Some(event) = event else { break };
+        let mut changed = HashSet::new();
+        let mut everything = collect_paths(event, &mut changed);
+        tokio::time::sleep(DEBOUNCE).await;
+        while let Ok(event) = events.try_recv() {
+            everything |= collect_paths(event, &mut changed);
+        }
+
+        // Re-expand the inputs so new and deleted files are noticed.
+        let mut current = Vec::new();
+        for input in &inputs {
+            current.extend(input_files(input, filter).unwrap_or_default());
+        }
+        let mut changes = Vec::new();
+        let mut checked = 0;
+        let mut validated = 0;
+        let removed = files.iter().filter(|path| !current.contains(path)).count();
+        for path in &files {
+            if !current.contains(path) {
+                changes.extend(session.forget(path, &name(path)));
+            }
+        }
+        for path in &current {
+            if everything || changed.contains(path) || !session.is_tracked(path) {
+                let refresh = session.refresh(path, &name(path), renderers).await;
+                checked += 1;
+                validated += refresh.validated;
+                changes.extend(refresh.changes);
+            }
+        }
+        files = current;
+        if checked == 0 && removed == 0 {
+            continue;
+        }
+
+        print_lines(&change_lines(&changes));
+        let (_, blocks, errors) = session.totals();
+        println!(
+            "Re-checked {checked} file(s), re-rendered {validated} block(s): {errors} error(s) in {blocks} block(s)"
+        );
+    }
+    Ok(())
+}
+
+/// The files one input expands to. A missing file or an empty glob is no
+/// error once watching, as files come and go.
+fn input_files(input: &str, filter: &DirectoryFilter) -> Result<Vec<PathBuf>, String> {
+    Ok(expand_inputs(&[input.to_string()], "", filter)?
+        .into_iter()
+        .filter_map(|input| match input {
+            CheckInput::File(path) => Some(path),
+            CheckInput::Stdin { .. } => None,
+        })
+        .collect())
+}
+
+/// Directories to watch: a file's directory, since saving through a
+/// temporary file replaces the file itself, and the trees of directories
+/// and globs.
+fn watch_roots(inputs: &[String]) -> Vec<(PathBuf, RecursiveMode)> {
+    let mut roots: Vec<(PathBuf, RecursiveMode)> = Vec::new();
+    for input in inputs {
+        let path = Path::new(input);
+        let root = if path.is_dir() {
+            (path.to_path_buf(), RecursiveMode::Recursive)
+        } else if !path.exists() && is_glob(input) {
+            (split_glob(input).0, RecursiveMode::Recursive)
+        } else {
+            let dir = path.parent().unwrap_or(Path::new("/")).to_path_buf();
+            (dir, RecursiveMode::NonRecursive)
+        };
+        if !roots.contains(&root) {
+            roots.push(root);
+        }
+    }
+    roots
+}
+
+/// Adds the paths of `event` to `changed`. Returns whether every file must
+/// be re-checked because events may have been missed.
+fn collect_paths(event: notify::Result<notify::Event>, changed: &mut HashSet<PathBuf>) -> bool {
+    match event {
+        Ok(event) => {
+            if !event.kind.is_access() {
+                changed.extend(event.paths);
+            }
+            false
+        }
+        Err(_) => true,
+    }
+}
+
+fn print_lines(lines: &[String]) {
+    for line in lines {
+        println!("{line}");
+    }
+}
+
+/// Moves the lines of a [`fence_diagram`] status back onto the bare
+/// diagram.
+fn unfence_status(status: &mut FileStatus) {
+    unfence_lines(&mut status.issues);
+    for block in &mut status.blocks {
+        unfence_lines(&mut block.issues);
+        block.start_line = 1;
+        block.end_line = block.end_line.saturating_sub(2).max(1);
+    }
+}
+
+fn diagrams(status: &FileStatus) -> Vec<Diagram> {
+    let first_error = |issues: &[PreviewIssue]| {
+        issues
+            .iter()
+            .find(|issue| issue.severity == "error")
+            .cloned()
+    };
+    let mut diagrams = status
+        .blocks
+        .iter()
+        .map(|block| Diagram {
+            id: block.id.clone(),
+            line: Some(block.start_line),
+            error: first_error(&block.issues),
+        })
+        .collect::<Vec<_>>();
+    diagrams.push(Diagram {
+        id: FILE_DIAGRAM.to_string(),
+        line: None,
+        error: first_error(&status.issues),
+    });
+    diagrams
+}
+
+/// Diagrams failing in `current` but not in `previous`, failing in
+/// `previous` but valid in `current`, and failing ones that are gone. An
+/// edited block gets a new id; it is compared with the first unmatched
+/// diagram of `previous` under the same headings.
+fn diff(path: &str, previous: &[Diagram], current: &[Diagram]) -> Vec<DiagramChange> {
+    let mut unmatched = previous
+        .iter()
+        .filter(|diagram| !current.iter().any(|current| current.id == diagram.id))
+        .collect::<Vec<_>>();
+    let change = |diagram: &Diagram, kind| DiagramChange {
+        path: path.to_string(),
+        diagram: diagram.id.clone(),
+        line: diagram.line,
+        kind,
+    };
+    let mut changes = Vec::new();
+    for diagram in current {
+        let before = previous
+            .iter()
+            .find(|previous| previous.id == diagram.id)
+            .or_else(|| {
+                let position = unmatched.iter().position(|previous| {
+                    block_section(&previous.id) == block_section(&diagram.id)
+                })?;
+                Some(unmatched.remove(position))
+            });
+        let failed = before.is_some_and(|before| before.error.is_some());
+        match &diagram.error {
+            Some(error) if !failed => {
+                changes.push(change(diagram, ChangeKind::Failing(error.clone())))
+            }
+            None if failed => changes.push(change(diagram, ChangeKind::Fixed)),
+            _ => {}
+        }
+    }
+    changes.extend(
+        unmatched
+            .into_iter()
+            .filter(|diagram| diagram.error.is_some())
+            .map(|diagram| change(diagram, ChangeKind::Removed)),
+    );
+    changes
+}
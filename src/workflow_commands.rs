@@ -0,0 +1,81 @@
+use crate::{command_line::CheckReport, preview_validator::PreviewIssue};
+
+/// GitHub Actions workflow commands for `report`: an `::error`,
+/// `::warning` or `::notice` per issue at its file line and column, which
+/// Actions turns into pull request annotations, then a summary line.
+pub fn workflow_commands(report: &CheckReport) -> String {
+    let mut lines = report
+        .files
+        .iter()
+        .flat_map(|file| {
+            file.result
+                .issues
+                .iter()
+                .map(|issue| workflow_command(&file.path, issue))
+        })
+        .collect::<Vec<_>>();
+    lines.push(format!(
+        "{} file(s), {} block(s), {} error(s)",
+        report.files.len(),
+        report.mermaid_block_count,
+        report.error_count
+    ));
+    lines.join("\n")
+}
+
+fn workflow_command(path: &str, issue: &PreviewIssue) -> String {
+    let command = match issue.severity.as_str() {
+        "error" => "error",
+        "warning" => "warning",
+        _ => "notice",
+    };
+    let mut properties = vec![format!("file={}", escape_property(path))];
+    if let Some(line) = issue.line {
+        properties.push(format!("line={line}"));
+        if let Some(column) = issue.column {
+            properties.push(format!("col={column}"));
+        }
+    }
+    properties.push(format!("title={}", escape_property(&issue.code)));
+    let mut message = issue.message.clone();
+    if let Some(block_index) = issue.block_index {
+        message.push_str(&format!(" (block #{block_index})"));
+    }
+    format!(
+        "::{command} {}::{}",
+        properties.join(","),
+        escape_data(&message)
+    )
+}
+
+fn escape_data(text: &str) -> String {
+    text.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+fn escape_property(text: &str) -> String {
+    escape_data(text).replace(':', "%3A").replace(',', "%2C")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commands_escape_data_and_properties() {
+        let issue = PreviewIssue {
+            severity: "error".to_string(),
+            code: "mermaid_parse_error".to_string(),
+            message: "Expecting 'SEMI'\ngot 100%".to_string(),
+            line: Some(12),
+            column: Some(4),
+            snippet: None,
+            block_index: Some(2),
+        };
+        assert_eq!(
+            workflow_command("docs/a,b.md", &issue),
+            "::error file=docs/a%2Cb.md,line=12,col=4,title=mermaid_parse_error::Expecting 'SEMI'%0Agot 100%25 (block #2)"
+        );
+    }
+}
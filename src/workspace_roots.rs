@@ -0,0 +1,209 @@
+use std::{
+    env, io,
+    path::{Path, PathBuf},
+};
+
+use thiserror::Error;
+
+/// Extra directories readable outside the client's roots, separated like
+/// `PATH`.
+pub const ALLOWED_PATHS_ENV: &str = "MERMAID_ALLOWED_PATHS";
+
+#[derive(Debug, Error)]
+pub enum RootsError {
+    #[error("{path} is outside the client's roots ({roots})")]
+    OutsideRoots { path: String, roots: String },
+    #[error("Failed to resolve {path}: {source}")]
+    Resolve { path: String, source: io::Error },
+}
+
+/// The directories a session may read: the client's roots plus the
+/// server's allow-list. Paths are canonical so that symlinks cannot escape.
+#[derive(Debug, Clone, Default)]
+pub struct RootSet {
+    roots: Vec<PathBuf>,
+    allowed: Vec<PathBuf>,
+}
+
+impl RootSet {
+    pub fn new(roots: Vec<PathBuf>, allowed: Vec<PathBuf>) -> Self {
+        RootSet {
+            roots: roots.into_iter().map(canonical_or_self).collect(),
+            allowed: allowed.into_iter().map(canonical_or_self).collect(),
+        }
+    }
+
+    /// Builds the set from `file://` root URIs; other schemes are ignored.
+    pub fn from_uris<'a>(uris: impl IntoIterator<Item = &'a str>, allowed: Vec<PathBuf>) -> Self {
+        let roots = uris.into_iter().filter_map(file_uri_to_path).collect();
+        RootSet::new(roots, allowed)
+    }
+
+    /// Uses `root` when the set has no roots of its own.
+    pub fn or_root(self, root: PathBuf) -> Self {
+        if self.roots.is_empty() {
+            RootSet::new(vec![root], self.allowed)
+        } else {
+            self
+        }
+    }
+
+    pub fn roots(&self) -> &[PathBuf] {
+        &self.roots
+    }
+
+    /// Resolves `path` to a canonical path a tool may read or write.
+    /// Relative paths are tried against each root in order; the first root
+    /// containing the path wins, otherwise the first root is used. Without
+    /// roots, only the allow-list is readable.
+    pub fn resolve(&self, path: &str) -> Result<PathBuf, RootsError> {
+        let requested = Path::new(path);
+        let candidate = if requested.is_absolute() {
+            requested.to_path_buf()
+        } else {
+            self.roots
+                .iter()
+                .map(|root| root.join(requested))
+                .find(|joined| joined.exists())
+                .or_else(|| self.roots.first().map(|root| root.join(requested)))
+                .unwrap_or_else(|| requested.to_path_buf())
+        };
+        let resolved = canonicalize_lenient(&candidate).map_err(|source| RootsError::Resolve {
+            path: path.to_string(),
+            source,
+        })?;
+        if self
+            .roots
+            .iter()
+            .chain(&self.allowed)
+            .any(|base| resolved.starts_with(base))
+        {
+            return Ok(resolved);
+        }
+        let roots = self
+            .roots
+            .iter()
+            .map(|root| root.display().to_string())
+            .collect::<Vec<_>>();
+        Err(RootsError::OutsideRoots {
+            path: path.to_string(),
+            roots: if roots.is_empty() {
+                "none".to_string()
+            } else {
+                roots.join(", ")
+            },
+        })
+    }
+}
+
+/// Reads the allow-list from `MERMAID_ALLOWED_PATHS`.
+pub fn allowed_paths_from_env() -> Vec<PathBuf> {
+    env::var_os(ALLOWED_PATHS_ENV)
+        .map(|value| {
+            env::split_paths(&value)
+                .filter(|path| !path.as_os_str().is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+pub fn file_uri_to_path(uri: &str) -> Option<PathBuf> {
+    let url = url::Url::parse(uri).ok()?;
+    if url.scheme() != "file" {
+        return None;
+    }
+    url.to_file_path().ok()
+}
+
+fn canonical_or_self(path: PathBuf) -> PathBuf {
+    path.canonicalize().unwrap_or(path)
+}
+
+/// Canonicalizes `path`, or its parent directory when the file does not
+/// exist yet, so a missing file still resolves through symlinked parents.
+fn canonicalize_lenient(path: &Path) -> io::Result<PathBuf> {
+    match path.canonicalize() {
+        Ok(canonical) => Ok(canonical),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            match (path.parent(), path.file_name()) {
+                (Some(parent), Some(name)) => {
+                    let parent = if parent.as_os_str().is_empty() {
+                        Path::new(".")
+                    } else {
+                        parent
+                    };
+                    Ok(parent.canonicalize()?.join(name))
+                }
+                _ => Err(err),
+            }
+        }
+        Err(err) => Err(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relative_paths_resolve_against_roots() {
+        let first = tempfile::tempdir().unwrap();
+        let second = tempfile::tempdir().unwrap();
+        std::fs::write(second.path().join("doc.md"), "").unwrap();
+        let roots = RootSet::new(
+            vec![first.path().to_path_buf(), second.path().to_path_buf()],
+            Vec::new(),
+        );
+
+        let resolved = roots.resolve("doc.md").unwrap();
+        assert_eq!(
+            resolved,
+            second.path().canonicalize().unwrap().join("doc.md")
+        );
+        let missing = roots.resolve("new.md").unwrap();
+        assert_eq!(missing, first.path().canonicalize().unwrap().join("new.md"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_out_of_roots_are_rejected_unless_allowed() {
+        let root = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        std::fs::write(outside.path().join("secret.md"), "").unwrap();
+        std::os::unix::fs::symlink(outside.path(), root.path().join("link")).unwrap();
+
+        let roots = RootSet::new(vec![root.path().to_path_buf()], Vec::new());
+        let error = roots.resolve("link/secret.md").unwrap_err();
+        assert!(matches!(error, RootsError::OutsideRoots { .. }));
+        let absolute = outside.path().join("secret.md");
+        assert!(roots.resolve(absolute.to_str().unwrap()).is_err());
+
+        let allowed = RootSet::new(
+            vec![root.path().to_path_buf()],
+            vec![outside.path().to_path_buf()],
+        );
+        assert!(allowed.resolve("link/secret.md").is_ok());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn without_roots_only_the_allow_list_is_readable() {
+        let allowed = tempfile::tempdir().unwrap();
+        let roots = RootSet::new(Vec::new(), vec![allowed.path().to_path_buf()]);
+
+        let error = roots.resolve("/etc/passwd").unwrap_err();
+        assert!(matches!(error, RootsError::OutsideRoots { .. }));
+        let inside = allowed.path().join("doc.md");
+        assert!(roots.resolve(inside.to_str().unwrap()).is_ok());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn parse_file_uris_only() {
+        assert_eq!(
+            file_uri_to_path("file:///home/me/my%20docs"),
+            Some(PathBuf::from("/home/me/my docs"))
+        );
+        assert_eq!(file_uri_to_path("https://example.com/docs"), None);
+    }
+}
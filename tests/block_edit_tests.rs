@@ -0,0 +1,120 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::{call, text};
+use rmcp::{service::RunningService, RoleClient};
+
+async fn block_hash(client: &RunningService<RoleClient, ()>, path: &str, index: u32) -> String {
+    let block = call(
+        client,
+        "getMermaidBlock",
+        serde_json::json!({ "filePath": path, "blockIndex": index }),
+    )
+    .await;
+    block.structured_content.unwrap()["contentHash"]
+        .as_str()
+        .unwrap()
+        .to_string()
+}
+
+#[tokio::test]
+async fn edits_keep_fences_indentation_and_line_endings() {
+    let dir = tempfile::tempdir().unwrap();
+    let client = common::connect_with_fake_mmdc(dir.path()).await;
+    let doc = dir.path().join("doc.md");
+    let markdown = "# Doc\r\n\r\n- Step\r\n\r\n  ~~~mermaid\r\n  graph TD\r\n    A-->B\r\n  ~~~\r\n\r\nEnd\r\n";
+    std::fs::write(&doc, markdown).unwrap();
+    let path = doc.to_str().unwrap();
+    let hash = block_hash(&client, path, 1).await;
+
+    let result = call(
+        &client,
+        "editMermaidBlock",
+        serde_json::json!({
+            "filePath": path,
+            "blockIndex": 1,
+            "expectedHash": hash,
+            "source": "flowchart TD\n  A-->C\n\n\n",
+            "mermaidVersion": "11.4.1"
+        }),
+    )
+    .await;
+    let structured = result.structured_content.clone().unwrap();
+    assert_eq!(structured["written"], true, "{}", text(&result));
+    assert_eq!(structured["forced"], false);
+    assert_eq!(
+        std::fs::read_to_string(&doc).unwrap(),
+        "# Doc\r\n\r\n- Step\r\n\r\n  ~~~mermaid\r\n  flowchart TD\r\n    A-->C\r\n\r\n  ~~~\r\n\r\nEnd\r\n"
+    );
+    assert_eq!(
+        structured["contentHash"],
+        block_hash(&client, path, 1).await
+    );
+    assert!(text(&result).contains("+  flowchart TD"));
+    // No temporary file is left next to the document.
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 2);
+}
+
+#[tokio::test]
+async fn edits_are_refused_on_stale_hashes_and_invalid_diagrams() {
+    let dir = tempfile::tempdir().unwrap();
+    let client = common::connect_with_fake_mmdc(dir.path()).await;
+    let doc = dir.path().join("doc.md");
+    let markdown = "```mermaid\nflowchart TD\n  A-->B\n```\n";
+    std::fs::write(&doc, markdown).unwrap();
+    let path = doc.to_str().unwrap();
+    let hash = block_hash(&client, path, 1).await;
+    let edit = |expected_hash: &str, source: &str, force: bool| {
+        serde_json::json!({
+            "filePath": path,
+            "blockIndex": 1,
+            "expectedHash": expected_hash,
+            "source": source,
+            "mermaidVersion": "11.4.1",
+            "force": force
+        })
+    };
+
+    let stale = call(
+        &client,
+        "editMermaidBlock",
+        edit("0000", "flowchart LR\n  A-->B", false),
+    )
+    .await;
+    assert!(text(&stale).contains("changed since it was read"));
+    let fenced = call(
+        &client,
+        "editMermaidBlock",
+        edit(&hash, "flowchart LR\n```\nx", false),
+    )
+    .await;
+    assert!(text(&fenced).contains("must not contain code fences"));
+
+    let invalid = call(
+        &client,
+        "editMermaidBlock",
+        edit(&hash, "flowchart TD\n  A-->BROKEN", false),
+    )
+    .await;
+    let structured = invalid.structured_content.clone().unwrap();
+    assert_eq!(structured["written"], false);
+    assert_eq!(structured["valid"], false);
+    assert_eq!(structured["contentHash"], hash.as_str());
+    assert!(text(&invalid).contains("pass force to write it anyway"));
+    assert_eq!(std::fs::read_to_string(&doc).unwrap(), markdown);
+
+    let forced = call(
+        &client,
+        "editMermaidBlock",
+        edit(&hash, "flowchart TD\n  A-->BROKEN", true),
+    )
+    .await;
+    let structured = forced.structured_content.unwrap();
+    assert_eq!(structured["written"], true);
+    assert_eq!(structured["forced"], true);
+    assert_eq!(
+        std::fs::read_to_string(&doc).unwrap(),
+        "```mermaid\nflowchart TD\n  A-->BROKEN\n```\n"
+    );
+}
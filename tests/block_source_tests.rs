@@ -0,0 +1,94 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::{call, text};
+use mermaid_validator::server::MermaidServer;
+
+#[tokio::test]
+async fn get_mermaid_block_returns_numbered_source_and_context() {
+    let dir = tempfile::tempdir().unwrap();
+    let doc = dir.path().join("doc.md");
+    std::fs::write(
+        &doc,
+        "# Doc\n\n```mermaid\nflowchart TD\n  A-->B\n```\n\nText\n\n~~~mermaid {theme: dark}\nsequenceDiagram\n  A->>B: hi\n~~~\n",
+    )
+    .unwrap();
+    let client =
+        common::connect(MermaidServer::new().with_allowed_paths(vec![std::env::temp_dir()])).await;
+
+    let result = call(
+        &client,
+        "getMermaidBlock",
+        serde_json::json!({ "filePath": doc.to_str().unwrap(), "blockIndex": 2, "contextLines": 2 }),
+    )
+    .await;
+    let structured = result.structured_content.clone().unwrap();
+    assert_eq!(structured["infoString"], "mermaid {theme: dark}");
+    assert_eq!(structured["content"], "sequenceDiagram\n  A->>B: hi");
+    assert_eq!(structured["lines"][0]["line"], 11);
+    assert_eq!(structured["lines"][1]["text"], "  A->>B: hi");
+    assert_eq!(structured["contentHash"].as_str().unwrap().len(), 64);
+    assert_eq!(structured["contextBefore"][0]["text"], "Text");
+    assert!(structured.get("contextAfter").is_none());
+    let source = text(&result);
+    assert!(source.contains("11 | sequenceDiagram"));
+    assert!(source.contains(" 8 | Text"));
+
+    let missing = call(
+        &client,
+        "getMermaidBlock",
+        serde_json::json!({ "filePath": doc.to_str().unwrap(), "blockIndex": 3 }),
+    )
+    .await;
+    assert!(text(&missing).contains("was not found (2 block(s)"));
+}
+
+#[tokio::test]
+async fn block_tools_select_blocks_by_id_heading_or_negative_index() {
+    let dir = tempfile::tempdir().unwrap();
+    let doc = dir.path().join("doc.md");
+    std::fs::write(
+        &doc,
+        "# Architecture\n\n## Data flow\n\n```mermaid\nflowchart TD\n  A-->B\n```\n\n```mermaid\nflowchart LR\n  C-->D\n```\n\n## Deploy\n\n```mermaid\nsequenceDiagram\n  A->>B: hi\n```\n",
+    )
+    .unwrap();
+    let client =
+        common::connect(MermaidServer::new().with_allowed_paths(vec![std::env::temp_dir()])).await;
+    let path = doc.to_str().unwrap();
+
+    let scan = call(
+        &client,
+        "scanMermaidBlocks",
+        serde_json::json!({ "filePath": path }),
+    )
+    .await;
+    let structured = scan.structured_content.clone().unwrap();
+    let data_flow = common::block_id("architecture/data-flow", "flowchart LR\n  C-->D");
+    let deploy = common::block_id("architecture/deploy", "sequenceDiagram\n  A->>B: hi");
+    assert_eq!(structured["blocks"][1]["id"], data_flow.as_str());
+    assert!(text(&scan).contains(&format!("Block #3 ({deploy}) lines 17-20")));
+
+    let get = |selector: serde_json::Value| {
+        let mut arguments = serde_json::json!({ "filePath": path });
+        arguments
+            .as_object_mut()
+            .unwrap()
+            .extend(selector.as_object().unwrap().clone());
+        call(&client, "getMermaidBlock", arguments)
+    };
+    let by_id = get(serde_json::json!({ "blockId": data_flow })).await;
+    assert_eq!(by_id.structured_content.unwrap()["index"], 2);
+    let last = get(serde_json::json!({ "blockIndex": -1 })).await;
+    assert_eq!(last.structured_content.unwrap()["id"], deploy.as_str());
+    let by_heading = get(serde_json::json!({ "heading": "#deploy" })).await;
+    assert_eq!(by_heading.structured_content.unwrap()["index"], 3);
+
+    let ambiguous = get(serde_json::json!({ "heading": "Data flow" })).await;
+    let message = text(&ambiguous);
+    assert!(message.contains("matches 2 Mermaid blocks"));
+    let first = common::block_id("architecture/data-flow", "flowchart TD\n  A-->B");
+    assert!(message.contains(&format!("{first} (block #1, lines 5-8)")));
+    let both = get(serde_json::json!({ "blockIndex": 1, "heading": "Deploy" })).await;
+    assert!(text(&both).contains("Pass exactly one of blockIndex, blockId or heading"));
+}
@@ -0,0 +1,262 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::{
+    io::Write,
+    path::Path,
+    process::{Command, Output, Stdio},
+};
+
+fn run(dir: &Path, mmdc: &Path, args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_mermaid_validator"))
+        .args(args)
+        .current_dir(dir)
+        .env("MERMAID_CLI", mmdc)
+        .env_remove("MERMAID_RENDERERS_DIR")
+        .env_remove("MERMAID_LOG_LEVEL")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+fn fake_mmdc(bin: &Path) -> std::path::PathBuf {
+    common::write_fake_mmdc(bin, "11.4.1", "BROKEN");
+    bin.join("11.4.1/bin/mmdc")
+}
+
+fn write(root: &Path, path: &str, content: &str) {
+    let path = root.join(path);
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(path, content).unwrap();
+}
+
+#[test]
+fn check_reports_issues_and_exit_codes() {
+    let bin = tempfile::tempdir().unwrap();
+    let mmdc = fake_mmdc(bin.path());
+    let tree = tempfile::tempdir().unwrap();
+    let root = tree.path();
+    write(
+        root,
+        "docs/ok.md",
+        "# Ok\n\n```mermaid\nflowchart TD\n  A-->B\n```\n",
+    );
+    write(root, "docs/plain.md", "# No diagrams\n");
+    write(root, "diagrams/bad.mmd", "flowchart TD\n  A-->BROKEN\n");
+
+    let valid = run(root, &mmdc, &["check", "docs"], "");
+    assert_eq!(valid.status.code(), Some(0));
+    assert_eq!(stdout(&valid).trim(), "2 file(s), 1 block(s), 0 error(s)");
+
+    let invalid = run(root, &mmdc, &["check"], "");
+    assert_eq!(invalid.status.code(), Some(1));
+    assert_eq!(
+        stdout(&invalid).trim(),
+        "diagrams/bad.mmd:1: error [mermaid_parse_error] Mermaid parse error (block #1)\n\
+         3 file(s), 2 block(s), 1 error(s)"
+    );
+
+    let globbed = run(root, &mmdc, &["check", "--format", "json", "docs/*.md"], "");
+    let report: serde_json::Value = serde_json::from_slice(&globbed.stdout).unwrap();
+    assert_eq!(report["files"][0]["path"], "docs/ok.md");
+    assert_eq!(report["files"].as_array().unwrap().len(), 2);
+
+    let sarif = run(root, &mmdc, &["check", "--format", "sarif", "diagrams"], "");
+    assert_eq!(sarif.status.code(), Some(1));
+    let log: serde_json::Value = serde_json::from_slice(&sarif.stdout).unwrap();
+    let result = &log["runs"][0]["results"][0];
+    assert_eq!(result["ruleId"], "mermaid_parse_error");
+    assert_eq!(
+        result["locations"][0]["physicalLocation"]["artifactLocation"]["uri"],
+        "diagrams/bad.mmd"
+    );
+
+    let piped = run(
+        root,
+        &mmdc,
+        &["check", "-", "--stdin-name", "piped.mmd"],
+        "flowchart TD\n  A-->B\n",
+    );
+    assert_eq!(piped.status.code(), Some(0));
+
+    let missing = run(root, &mmdc, &["check", "nope.md"], "");
+    assert_eq!(missing.status.code(), Some(3));
+    let no_renderer = run(root, &root.join("no-mmdc"), &["check", "docs"], "");
+    assert_eq!(no_renderer.status.code(), Some(3));
+    assert!(String::from_utf8_lossy(&no_renderer.stderr).contains("mermaid-cli not found"));
+    let usage = run(root, &mmdc, &["check", "--no-such-flag"], "");
+    assert_eq!(usage.status.code(), Some(2));
+}
+
+#[test]
+fn render_and_scan_share_the_library() {
+    let bin = tempfile::tempdir().unwrap();
+    let mmdc = fake_mmdc(bin.path());
+    let tree = tempfile::tempdir().unwrap();
+    let root = tree.path();
+    write(
+        root,
+        "doc.md",
+        "# Flow\n\n```mermaid\nflowchart TD\n  A-->B\n```\n",
+    );
+
+    let rendered = run(root, &mmdc, &["render", "doc.md", "-o", "out.svg"], "");
+    assert_eq!(rendered.status.code(), Some(0));
+    assert_eq!(
+        std::fs::read_to_string(root.join("out.svg")).unwrap(),
+        "<svg/>\n"
+    );
+    let rejected = run(
+        root,
+        &mmdc,
+        &["render", "--diagram", "flowchart TD\\n  A-->BROKEN"],
+        "",
+    );
+    assert_eq!(rejected.status.code(), Some(1));
+
+    let scanned = run(root, &mmdc, &["scan", "doc.md"], "");
+    assert_eq!(scanned.status.code(), Some(0));
+    assert_eq!(
+        stdout(&scanned).trim(),
+        format!(
+            "#1 flowchart lines 3-6 ({}): flowchart TD\n1 block(s), 0 error(s)",
+            common::block_id("flow", "flowchart TD\n  A-->B")
+        )
+    );
+}
+
+#[test]
+fn junit_and_github_reports_use_file_lines() {
+    let bin = tempfile::tempdir().unwrap();
+    let mmdc = fake_mmdc(bin.path());
+    let tree = tempfile::tempdir().unwrap();
+    let root = tree.path();
+    write(
+        root,
+        "docs/guide.md",
+        "# Guide\n\n```mermaid\nflowchart TD\n  A-->B\n```\n\n## Broken\n\n```mermaid\nflowchart TD\n  A-->BROKEN\n```\n",
+    );
+    write(root, "flow.mmd", "flowchart TD\n  A-->B\n");
+
+    let junit = run(root, &mmdc, &["check", "--format", "junit"], "");
+    assert_eq!(junit.status.code(), Some(1));
+    let xml = stdout(&junit);
+    assert!(xml.contains(
+        "<testsuites name=\"mermaid_validator\" tests=\"3\" failures=\"1\" errors=\"0\">"
+    ));
+    assert!(xml.contains(&format!(
+        "<testcase classname=\"docs/guide.md\" name=\"{}\" file=\"docs/guide.md\" line=\"3\"/>",
+        common::block_id("guide", "flowchart TD\n  A-->B")
+    )));
+    assert!(xml.contains(&format!(
+        "<testcase classname=\"docs/guide.md\" name=\"{}\" file=\"docs/guide.md\" line=\"10\">\n      \
+         <failure message=\"Mermaid parse error\" type=\"mermaid_parse_error\">\
+         docs/guide.md:11: error [mermaid_parse_error] Mermaid parse error (block #2)</failure>",
+        common::block_id("guide/broken", "flowchart TD\n  A-->BROKEN")
+    )));
+    assert!(xml.contains(&format!(
+        "<testcase classname=\"flow.mmd\" name=\"{}\" file=\"flow.mmd\" line=\"1\"/>",
+        common::block_id("", "flowchart TD\n  A-->B")
+    )));
+
+    let github = run(root, &mmdc, &["check", "--format", "github"], "");
+    assert_eq!(github.status.code(), Some(1));
+    assert_eq!(
+        stdout(&github).trim(),
+        "::error file=docs/guide.md,line=11,title=mermaid_parse_error::Mermaid parse error (block #2)\n\
+         2 file(s), 3 block(s), 1 error(s)"
+    );
+}
+
+#[test]
+fn watch_prints_newly_failing_and_fixed_diagrams() {
+    use std::{
+        io::{BufRead, BufReader},
+        sync::mpsc,
+        time::Duration,
+    };
+
+    let bin = tempfile::tempdir().unwrap();
+    let mmdc = fake_mmdc(bin.path());
+    let tree = tempfile::tempdir().unwrap();
+    let root = tree.path();
+    write(
+        root,
+        "docs/design.md",
+        "# Flow\n\n```mermaid\nflowchart TD\n  A-->B\n```\n",
+    );
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_mermaid_validator"))
+        .args(["watch", "docs"])
+        .current_dir(root)
+        .env("MERMAID_CLI", &mmdc)
+        .env_remove("MERMAID_RENDERERS_DIR")
+        .env_remove("MERMAID_LOG_LEVEL")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let (sender, lines) = mpsc::channel();
+    let output = child.stdout.take().unwrap();
+    std::thread::spawn(move || {
+        for line in BufReader::new(output).lines() {
+            if sender.send(line.unwrap()).is_err() {
+                break;
+            }
+        }
+    });
+    let next = || lines.recv_timeout(Duration::from_secs(10)).unwrap();
+
+    assert_eq!(next(), "Watching 1 file(s): 1 block(s), 0 error(s)");
+    write(
+        root,
+        "docs/design.md",
+        "# Flow\n\n```mermaid\nflowchart TD\n  A-->BROKEN\n```\n",
+    );
+    assert_eq!(
+        next(),
+        format!(
+            "FAIL    docs/design.md:4 {}: Mermaid parse error [mermaid_parse_error]",
+            common::block_id("flow", "flowchart TD\n  A-->BROKEN")
+        )
+    );
+    assert_eq!(
+        next(),
+        "Re-checked 1 file(s), re-rendered 1 block(s): 1 error(s) in 1 block(s)"
+    );
+
+    write(root, "docs/new.mmd", "flowchart TD\n  A-->B\n");
+    assert_eq!(
+        next(),
+        "Re-checked 1 file(s), re-rendered 1 block(s): 1 error(s) in 2 block(s)"
+    );
+    write(
+        root,
+        "docs/design.md",
+        "# Flow\n\n```mermaid\nflowchart TD\n  A-->C\n```\n",
+    );
+    assert_eq!(
+        next(),
+        format!(
+            "FIXED   docs/design.md:3 {}",
+            common::block_id("flow", "flowchart TD\n  A-->C")
+        )
+    );
+
+    child.kill().unwrap();
+    child.wait().unwrap();
+}
@@ -0,0 +1,126 @@
+// Each test crate uses a different subset of these helpers.
+#![allow(dead_code)]
+
+/// Installs a fake `mmdc` for `version` below `dir` that rejects any input
+/// containing `reject`.
+#[cfg(unix)]
+pub fn write_fake_mmdc(dir: &std::path::Path, version: &str, reject: &str) {
+    use std::os::unix::fs::PermissionsExt;
+
+    let bin = dir.join(version).join("bin");
+    std::fs::create_dir_all(&bin).unwrap();
+    let script = format!(
+        "#!/bin/sh\ninput=$(cat)\ncase \"$input\" in *{reject}*) echo 'Parse error on line 1:' >&2; exit 1;; esac\necho '<svg/>'\n"
+    );
+    let path = bin.join("mmdc");
+    std::fs::write(&path, script).unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+}
+
+/// Like [`write_fake_mmdc`], and appends a line to `log` on each render.
+#[cfg(unix)]
+pub fn write_counting_mmdc(
+    dir: &std::path::Path,
+    version: &str,
+    reject: &str,
+    log: &std::path::Path,
+) {
+    use std::os::unix::fs::PermissionsExt;
+
+    let bin = dir.join(version).join("bin");
+    std::fs::create_dir_all(&bin).unwrap();
+    let script = format!(
+        "#!/bin/sh\ninput=$(cat)\necho render >> '{}'\ncase \"$input\" in *{reject}*) echo 'Parse error on line 1:' >&2; exit 1;; esac\necho '<svg/>'\n",
+        log.display()
+    );
+    let path = bin.join("mmdc");
+    std::fs::write(&path, script).unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+}
+
+/// Number of renders logged by [`write_counting_mmdc`].
+pub fn render_count(log: &std::path::Path) -> usize {
+    std::fs::read_to_string(log)
+        .map(|log| log.lines().count())
+        .unwrap_or(0)
+}
+
+/// Serves `server` over an in-memory pipe and returns a connected client.
+pub async fn connect(
+    server: mermaid_validator::server::MermaidServer,
+) -> rmcp::service::RunningService<rmcp::RoleClient, ()> {
+    use rmcp::ServiceExt;
+
+    let (server_io, client_io) = tokio::io::duplex(64 * 1024);
+    tokio::spawn(async move {
+        if let Ok(service) = server.serve(server_io).await {
+            let _ = service.waiting().await;
+        }
+    });
+    ().serve(client_io).await.unwrap()
+}
+
+/// Installs a fake `mmdc` 11.4.1 below `dir` that rejects `BROKEN`, and
+/// connects to a server using it that may read the temp directory.
+#[cfg(unix)]
+pub async fn connect_with_fake_mmdc(
+    dir: &std::path::Path,
+) -> rmcp::service::RunningService<rmcp::RoleClient, ()> {
+    write_fake_mmdc(dir, "11.4.1", "BROKEN");
+    connect_with_mmdc_in(dir).await
+}
+
+/// Like [`connect_with_fake_mmdc`], and appends a line to `log` on each
+/// render.
+#[cfg(unix)]
+pub async fn connect_with_counting_mmdc(
+    dir: &std::path::Path,
+    log: &std::path::Path,
+) -> rmcp::service::RunningService<rmcp::RoleClient, ()> {
+    write_counting_mmdc(dir, "11.4.1", "BROKEN", log);
+    connect_with_mmdc_in(dir).await
+}
+
+async fn connect_with_mmdc_in(
+    dir: &std::path::Path,
+) -> rmcp::service::RunningService<rmcp::RoleClient, ()> {
+    use mermaid_validator::{renderer_registry::RendererRegistry, server::MermaidServer};
+
+    let mut renderers = RendererRegistry::default();
+    renderers.discover(dir).unwrap();
+    connect(MermaidServer::with_renderers(renderers).with_allowed_paths(vec![std::env::temp_dir()]))
+        .await
+}
+
+/// Calls the tool `name` with `arguments`.
+pub async fn call(
+    client: &rmcp::service::RunningService<rmcp::RoleClient, ()>,
+    name: &'static str,
+    arguments: serde_json::Value,
+) -> rmcp::model::CallToolResult {
+    client
+        .call_tool(rmcp::model::CallToolRequestParams {
+            meta: None,
+            name: name.into(),
+            arguments: arguments.as_object().cloned(),
+            task: None,
+        })
+        .await
+        .unwrap()
+}
+
+/// The text content of `result`, one item per line.
+pub fn text(result: &rmcp::model::CallToolResult) -> String {
+    result
+        .content
+        .iter()
+        .filter_map(|content| content.as_text().map(|text| text.text.clone()))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The id of a block holding `content` under the heading path `section`.
+pub fn block_id(section: &str, content: &str) -> String {
+    let hash = mermaid_validator::preview_validator::content_hash(content);
+    format!("{section}#{}", &hash[..8])
+}
@@ -0,0 +1,115 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::{call, text};
+use mermaid_validator::server::MermaidServer;
+
+fn write_tree(root: &std::path::Path) {
+    let diagram = |body: &str| format!("# Doc\n\n```mermaid\nflowchart TD\n  {body}\n```\n");
+    for (path, content) in [
+        ("README.md", diagram("A-->B")),
+        ("docs/broken.md", diagram("A-->BROKEN")),
+        ("docs/plain.md", "# No diagrams\n".to_string()),
+        ("docs/drafts/wip.md", diagram("A-->BROKEN")),
+        ("vendor/lib.md", diagram("A-->BROKEN")),
+    ] {
+        let path = root.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+    std::fs::write(root.join(".gitignore"), "vendor/\n").unwrap();
+}
+
+#[tokio::test]
+async fn directory_validation_aggregates_files_and_pages() {
+    let bin = tempfile::tempdir().unwrap();
+    let client = common::connect_with_fake_mmdc(bin.path()).await;
+    let tree = tempfile::tempdir().unwrap();
+    write_tree(tree.path());
+    let root = tree.path().to_str().unwrap();
+
+    let result = call(
+        &client,
+        "validateMermaidDirectory",
+        serde_json::json!({
+            "root": root,
+            "exclude": ["docs/drafts"],
+            "mermaidVersion": "11.4.1"
+        }),
+    )
+    .await;
+    let structured = result.structured_content.clone().unwrap();
+    let paths = structured["files"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|file| file["path"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(paths, vec!["README.md", "docs/broken.md", "docs/plain.md"]);
+    assert_eq!(structured["mermaidBlockCount"], 2);
+    assert_eq!(structured["errorCount"], 1);
+    assert_eq!(structured["valid"], false);
+    assert_eq!(structured["files"][1]["errorCount"], 1);
+    assert_eq!(structured["page"]["totalFiles"], 3);
+    assert!(text(&result).contains("docs/broken.md: 1 block(s), 1 error(s)"));
+
+    let first = call(
+        &client,
+        "validateMermaidDirectory",
+        serde_json::json!({ "root": root, "limit": 2, "mermaidVersion": "11.4.1" }),
+    )
+    .await;
+    let structured = first.structured_content.unwrap();
+    assert_eq!(structured["files"].as_array().unwrap().len(), 2);
+    let cursor = structured["page"]["nextCursor"].clone();
+    let rest = call(
+        &client,
+        "validateMermaidDirectory",
+        serde_json::json!({ "root": root, "limit": 2, "cursor": cursor, "mermaidVersion": "11.4.1" }),
+    )
+    .await;
+    let structured = rest.structured_content.unwrap();
+    assert_eq!(structured["files"][0]["path"], "docs/drafts/wip.md");
+    assert_eq!(structured["files"][1]["path"], "docs/plain.md");
+    assert!(structured["page"].get("nextCursor").is_none());
+}
+
+#[tokio::test]
+async fn directory_scan_reports_blocks_without_rendering() {
+    let client =
+        common::connect(MermaidServer::new().with_allowed_paths(vec![std::env::temp_dir()])).await;
+    let tree = tempfile::tempdir().unwrap();
+    write_tree(tree.path());
+
+    let result = call(
+        &client,
+        "scanMermaidDirectory",
+        serde_json::json!({
+            "root": tree.path().to_str().unwrap(),
+            "include": ["docs/**/*.md"],
+            "verbosity": "summary"
+        }),
+    )
+    .await;
+    assert_eq!(
+        text(&result),
+        format!(
+            "GitHub scan of {}: 3 file(s), 2 block(s), 0 error(s)",
+            tree.path().display()
+        )
+    );
+    let structured = result.structured_content.unwrap();
+    assert_eq!(
+        structured["files"][0]["blocks"][0]["id"],
+        common::block_id("doc", "flowchart TD\n  A-->BROKEN").as_str()
+    );
+
+    let missing = call(
+        &client,
+        "scanMermaidDirectory",
+        serde_json::json!({ "root": tree.path().join("nope").to_str().unwrap() }),
+    )
+    .await;
+    assert!(text(&missing).contains("is not a directory"));
+}
@@ -0,0 +1,77 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::{call, text};
+use mermaid_validator::server::MermaidServer;
+
+#[tokio::test]
+async fn document_handles_warn_when_the_file_changes() {
+    let dir = tempfile::tempdir().unwrap();
+    let client = common::connect_with_fake_mmdc(dir.path()).await;
+
+    let doc = dir.path().join("doc.md");
+    let markdown = "# Doc\n\n```mermaid\nflowchart TD\n  A-->B\n```\n\n```mermaid\nflowchart TD\n  A-->BROKEN\n```\n";
+    std::fs::write(&doc, markdown).unwrap();
+
+    let opened = call(
+        &client,
+        "openMarkdownDocument",
+        serde_json::json!({ "filePath": doc.to_str().unwrap() }),
+    )
+    .await;
+    let structured = opened.structured_content.unwrap();
+    assert_eq!(structured["mermaidBlockCount"], 2);
+    assert_eq!(structured["blocks"][1]["startLine"], 8);
+    let handle = structured["document"].as_str().unwrap().to_string();
+
+    let arguments = serde_json::json!({
+        "document": handle,
+        "blockIndex": 2,
+        "mermaidVersion": "11.4.1"
+    });
+    let result = call(&client, "validateMermaidBlock", arguments.clone()).await;
+    let structured = result.structured_content.clone().unwrap();
+    assert_eq!(structured["valid"], false);
+    assert!(structured.get("warning").is_none());
+
+    // The handle keeps the opened content; the edit only raises a warning.
+    std::fs::write(&doc, markdown.replace("BROKEN", "C")).unwrap();
+    let result = call(&client, "validateMermaidBlock", arguments).await;
+    assert!(text(&result).starts_with("Warning: document changed, indexes may be stale"));
+    let structured = result.structured_content.unwrap();
+    assert_eq!(structured["valid"], false);
+    assert_eq!(
+        structured["warning"],
+        "document changed, indexes may be stale"
+    );
+    let scan = call(
+        &client,
+        "scanMermaidBlocks",
+        serde_json::json!({ "document": handle }),
+    )
+    .await;
+    assert!(text(&scan).contains("indexes may be stale"));
+
+    let closed = call(
+        &client,
+        "closeMarkdownDocument",
+        serde_json::json!({ "document": handle }),
+    )
+    .await;
+    assert!(text(&closed).contains("Closed document"));
+    let result = call(
+        &client,
+        "scanMermaidBlocks",
+        serde_json::json!({ "document": handle }),
+    )
+    .await;
+    assert!(text(&result).contains("open it with openMarkdownDocument"));
+}
+
+#[tokio::test]
+async fn block_tools_need_a_file_or_a_document() {
+    let client = common::connect(MermaidServer::new()).await;
+    let result = call(&client, "scanMermaidBlocks", serde_json::json!({})).await;
+    assert!(text(&result).contains("Pass filePath or a document handle"));
+}
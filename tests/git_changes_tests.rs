@@ -0,0 +1,172 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::path::Path;
+
+use git2::{IndexAddOption, Repository, Signature};
+use rmcp::{service::RunningService, RoleClient};
+
+fn diagram(heading: &str, body: &str) -> String {
+    format!("# {heading}\n\n```mermaid\nflowchart TD\n  {body}\n```\n")
+}
+
+fn write(root: &Path, path: &str, content: &str) {
+    let path = root.join(path);
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(path, content).unwrap();
+}
+
+fn stage_all(repo: &Repository) {
+    let mut index = repo.index().unwrap();
+    index.add_all(["*"], IndexAddOption::DEFAULT, None).unwrap();
+    index.write().unwrap();
+}
+
+fn commit_all(repo: &Repository) {
+    stage_all(repo);
+    let mut index = repo.index().unwrap();
+    let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+    let signature = Signature::now("test", "test@example.com").unwrap();
+    let parent = repo.head().ok().and_then(|head| head.peel_to_commit().ok());
+    repo.commit(
+        Some("HEAD"),
+        &signature,
+        &signature,
+        "commit",
+        &tree,
+        &parent.iter().collect::<Vec<_>>(),
+    )
+    .unwrap();
+}
+
+async fn client() -> (tempfile::TempDir, RunningService<RoleClient, ()>) {
+    let bin = tempfile::tempdir().unwrap();
+    let client = common::connect_with_fake_mmdc(bin.path()).await;
+    (bin, client)
+}
+
+#[tokio::test]
+async fn revision_changes_report_only_new_and_modified_blocks() {
+    let (_bin, client) = client().await;
+    let tree = tempfile::tempdir().unwrap();
+    let repo = Repository::init(tree.path()).unwrap();
+    write(
+        tree.path(),
+        "docs/a.md",
+        &(diagram("Old", "O-->BROKEN") + &diagram("Kept", "A-->B")),
+    );
+    write(tree.path(), "docs/same.md", &diagram("Same", "A-->BROKEN"));
+    commit_all(&repo);
+
+    // The old broken block is left alone; only the edited and added ones
+    // count.
+    write(
+        tree.path(),
+        "docs/a.md",
+        &(diagram("Old", "O-->BROKEN") + &diagram("Kept", "A-->BROKEN")),
+    );
+    write(tree.path(), "docs/new.md", &diagram("New", "X-->Y"));
+    write(tree.path(), "notes.txt", "not markdown\n");
+
+    let result = common::call(
+        &client,
+        "validateMermaidChanges",
+        serde_json::json!({
+            "path": tree.path().to_str().unwrap(),
+            "base": "HEAD",
+            "mermaidVersion": "11.4.1"
+        }),
+    )
+    .await;
+    let structured = result.structured_content.unwrap();
+    assert_eq!(structured["base"], "changes since HEAD");
+    assert_eq!(structured["changedFiles"], 2);
+    assert_eq!(structured["checkedBlocks"], 2);
+    assert_eq!(structured["errorCount"], 1);
+    assert_eq!(structured["valid"], false);
+    let blocks = structured["blocks"].as_array().unwrap();
+    assert_eq!(blocks[0]["path"], "docs/a.md");
+    assert_eq!(
+        blocks[0]["id"],
+        common::block_id("kept", "flowchart TD\n  A-->BROKEN").as_str()
+    );
+    assert_eq!(blocks[0]["change"], "modified");
+    assert_eq!(blocks[0]["valid"], false);
+    assert_eq!(blocks[1]["path"], "docs/new.md");
+    assert_eq!(blocks[1]["change"], "new");
+    assert_eq!(blocks[1]["valid"], true);
+}
+
+#[tokio::test]
+async fn staged_changes_read_the_index_not_the_working_tree() {
+    let (_bin, client) = client().await;
+    let tree = tempfile::tempdir().unwrap();
+    let repo = Repository::init(tree.path()).unwrap();
+    write(tree.path(), "README.md", &diagram("Intro", "A-->B"));
+    commit_all(&repo);
+
+    write(tree.path(), "README.md", &diagram("Intro", "A-->C"));
+    stage_all(&repo);
+    // An unstaged edit breaks the diagram, but the commit would not.
+    write(tree.path(), "README.md", &diagram("Intro", "A-->BROKEN"));
+    write(tree.path(), "untracked.md", &diagram("Other", "A-->BROKEN"));
+
+    let result = common::call(
+        &client,
+        "validateMermaidChanges",
+        serde_json::json!({
+            "path": tree.path().to_str().unwrap(),
+            "staged": true,
+            "mermaidVersion": "11.4.1"
+        }),
+    )
+    .await;
+    let structured = result.structured_content.unwrap();
+    assert_eq!(structured["base"], "staged changes");
+    assert_eq!(structured["changedFiles"], 1);
+    assert_eq!(structured["blocks"][0]["path"], "README.md");
+    assert_eq!(structured["blocks"][0]["change"], "modified");
+    assert_eq!(structured["valid"], true);
+
+    let missing = common::call(
+        &client,
+        "validateMermaidChanges",
+        serde_json::json!({ "path": tree.path().to_str().unwrap() }),
+    )
+    .await;
+    assert!(common::text(&missing).contains("Pass a base revision or set staged"));
+}
+
+#[tokio::test]
+async fn uninstalled_versions_fail_the_changed_blocks() {
+    let (_bin, client) = client().await;
+    let tree = tempfile::tempdir().unwrap();
+    let repo = Repository::init(tree.path()).unwrap();
+    // The unclosed fence is a file-level issue outside any changed block.
+    write(tree.path(), "README.md", &diagram("Intro", "A-->B"));
+    commit_all(&repo);
+    write(
+        tree.path(),
+        "README.md",
+        &(diagram("Intro", "A-->C") + "\n```mermaid\nflowchart TD\n"),
+    );
+
+    let result = common::call(
+        &client,
+        "validateMermaidChanges",
+        serde_json::json!({
+            "path": tree.path().to_str().unwrap(),
+            "base": "HEAD",
+            "mermaidVersion": "9.9.9"
+        }),
+    )
+    .await;
+    let structured = result.structured_content.unwrap();
+    assert_eq!(structured["checkedBlocks"], 1);
+    assert_eq!(structured["errorCount"], 1);
+    assert_eq!(structured["valid"], false);
+    let issues = structured["blocks"][0]["issues"].as_array().unwrap();
+    assert_eq!(issues.len(), 1);
+    assert_eq!(issues[0]["code"], "renderer_unavailable");
+}
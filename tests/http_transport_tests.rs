@@ -0,0 +1,198 @@
+use std::{net::SocketAddr, time::Duration};
+
+use futures::StreamExt;
+use mermaid_validator::{
+    http_transport::{serve_http, HttpOptions, MCP_PATH, SSE_PATH},
+    server::MermaidServer,
+};
+use rmcp::{
+    model::CallToolRequestParams,
+    transport::{
+        streamable_http_client::StreamableHttpClientTransportConfig, StreamableHttpClientTransport,
+    },
+    ServiceExt,
+};
+use tokio::{net::TcpListener, task::JoinHandle};
+use tokio_util::sync::CancellationToken;
+
+async fn start_server(
+    bearer_token: Option<&str>,
+) -> (
+    SocketAddr,
+    CancellationToken,
+    JoinHandle<std::io::Result<()>>,
+) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    let shutdown = CancellationToken::new();
+    let options = HttpOptions {
+        bearer_token: bearer_token.map(ToString::to_string),
+        allowed_origins: vec!["https://app.example.com/".to_string()],
+    };
+    let handle = tokio::spawn({
+        let shutdown = shutdown.clone();
+        async move {
+            serve_http(
+                listener,
+                MermaidServer::new().with_allowed_paths(vec![std::env::temp_dir()]),
+                &options,
+                shutdown,
+            )
+            .await
+        }
+    });
+    (address, shutdown, handle)
+}
+
+#[tokio::test]
+async fn streamable_http_serves_concurrent_sessions_and_shuts_down() {
+    let (address, shutdown, handle) = start_server(Some("secret")).await;
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("doc.md");
+    std::fs::write(&file, "```mermaid\nflowchart TD\n  A-->B\n```\n").unwrap();
+
+    let mut clients = Vec::new();
+    for _ in 0..3 {
+        let config =
+            StreamableHttpClientTransportConfig::with_uri(format!("http://{address}{MCP_PATH}"))
+                .auth_header("secret");
+        let transport = StreamableHttpClientTransport::with_client(reqwest::Client::new(), config);
+        clients.push(().serve(transport).await.unwrap());
+    }
+
+    let calls = clients.iter().map(|client| {
+        let arguments = serde_json::json!({ "filePath": file.to_str().unwrap() });
+        client.call_tool(CallToolRequestParams {
+            meta: None,
+            name: "scanMermaidBlocks".into(),
+            arguments: arguments.as_object().cloned(),
+            task: None,
+        })
+    });
+    for result in futures::future::join_all(calls).await {
+        let structured = result.unwrap().structured_content.unwrap();
+        assert_eq!(structured["mermaidBlockCount"], 1);
+    }
+
+    shutdown.cancel();
+    tokio::time::timeout(Duration::from_secs(10), handle)
+        .await
+        .expect("server shuts down while sessions are open")
+        .unwrap()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn http_rejects_missing_or_wrong_bearer_token() {
+    let (address, shutdown, _handle) = start_server(Some("secret")).await;
+    let client = reqwest::Client::new();
+
+    let missing = client
+        .get(format!("http://{address}{SSE_PATH}"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(missing.status(), reqwest::StatusCode::UNAUTHORIZED);
+
+    let wrong = client
+        .post(format!("http://{address}{MCP_PATH}"))
+        .bearer_auth("guess")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(wrong.status(), reqwest::StatusCode::UNAUTHORIZED);
+
+    shutdown.cancel();
+}
+
+#[tokio::test]
+async fn http_rejects_foreign_origins() {
+    let (address, shutdown, _handle) = start_server(None).await;
+    let client = reqwest::Client::new();
+    let status = |origin: &'static str| {
+        let request = client.get(format!("http://{address}{SSE_PATH}"));
+        async move {
+            request
+                .header(reqwest::header::ORIGIN, origin)
+                .send()
+                .await
+                .unwrap()
+                .status()
+        }
+    };
+
+    assert_eq!(
+        status("http://evil.example").await,
+        reqwest::StatusCode::FORBIDDEN
+    );
+    assert_eq!(status("null").await, reqwest::StatusCode::FORBIDDEN);
+    assert!(status("http://localhost:5173").await.is_success());
+    assert!(status("http://127.0.0.1").await.is_success());
+    assert!(status("https://app.example.com").await.is_success());
+
+    shutdown.cancel();
+}
+
+#[tokio::test]
+async fn legacy_sse_transport_answers_initialize() {
+    let (address, shutdown, _handle) = start_server(None).await;
+    let client = reqwest::Client::new();
+
+    let response = client
+        .get(format!("http://{address}{SSE_PATH}"))
+        .send()
+        .await
+        .unwrap();
+    assert!(response.status().is_success());
+    let mut events = response.bytes_stream();
+    let mut buffer = String::new();
+    let mut next_event = async || loop {
+        if let Some(end) = buffer.find("\n\n") {
+            let event = buffer[..end].to_string();
+            buffer.drain(..end + 2);
+            if !event.starts_with(':') {
+                return event;
+            }
+            continue;
+        }
+        let chunk = events.next().await.unwrap().unwrap();
+        buffer.push_str(std::str::from_utf8(&chunk).unwrap());
+    };
+
+    let endpoint = next_event().await;
+    assert!(endpoint.starts_with("event: endpoint\n"), "{endpoint}");
+    let path = endpoint
+        .lines()
+        .nth(1)
+        .unwrap()
+        .trim_start_matches("data: ");
+    assert!(path.starts_with("/message?sessionId="));
+
+    let initialize = serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "initialize",
+        "params": {
+            "protocolVersion": "2024-11-05",
+            "capabilities": {},
+            "clientInfo": { "name": "test", "version": "0.0.0" }
+        }
+    });
+    let accepted = client
+        .post(format!("http://{address}{path}"))
+        .header("content-type", "application/json")
+        .body(initialize.to_string())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(accepted.status(), reqwest::StatusCode::ACCEPTED);
+
+    let message = next_event().await;
+    assert!(message.starts_with("event: message\n"), "{message}");
+    let data = message.lines().nth(1).unwrap().trim_start_matches("data: ");
+    let reply: serde_json::Value = serde_json::from_str(data).unwrap();
+    assert_eq!(reply["id"], 1);
+    assert!(reply["result"]["capabilities"]["tools"].is_object());
+
+    shutdown.cancel();
+}
@@ -0,0 +1,129 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use mermaid_validator::{renderer_registry::RendererRegistry, server::MermaidServer};
+use rmcp::{
+    model::{
+        CallToolRequestParams, LoggingLevel, LoggingMessageNotificationParam, SetLevelRequestParams,
+    },
+    service::NotificationContext,
+    ClientHandler, RoleClient, ServiceExt,
+};
+
+#[derive(Clone, Default)]
+struct LogClient {
+    messages: Arc<Mutex<Vec<LoggingMessageNotificationParam>>>,
+}
+
+impl ClientHandler for LogClient {
+    async fn on_logging_message(
+        &self,
+        params: LoggingMessageNotificationParam,
+        _context: NotificationContext<RoleClient>,
+    ) {
+        self.messages.lock().unwrap().push(params);
+    }
+}
+
+#[tokio::test]
+async fn session_receives_structured_renderer_and_cache_logs() {
+    let dir = tempfile::tempdir().unwrap();
+    common::write_fake_mmdc(dir.path(), "11.4.1", "BROKEN");
+    let mut renderers = RendererRegistry::default();
+    renderers.discover(dir.path()).unwrap();
+
+    let (server_io, client_io) = tokio::io::duplex(64 * 1024);
+    tokio::spawn(async move {
+        if let Ok(service) = MermaidServer::with_renderers(renderers)
+            .serve(server_io)
+            .await
+        {
+            let _ = service.waiting().await;
+        }
+    });
+    let handler = LogClient::default();
+    let client = handler.clone().serve(client_io).await.unwrap();
+    assert!(client.peer_info().unwrap().capabilities.logging.is_some());
+
+    client
+        .set_level(SetLevelRequestParams {
+            meta: None,
+            level: LoggingLevel::Debug,
+        })
+        .await
+        .unwrap();
+    let arguments = serde_json::json!({
+        "diagram": "flowchart TD\n  A-->B",
+        "format": "svg",
+        "mermaidVersion": "11.4.1"
+    });
+    for _ in 0..2 {
+        client
+            .call_tool(CallToolRequestParams {
+                meta: None,
+                name: "validateMermaid".into(),
+                arguments: arguments.as_object().cloned(),
+                task: None,
+            })
+            .await
+            .unwrap();
+    }
+
+    let mut events = Vec::new();
+    for _ in 0..50 {
+        events = handler.messages.lock().unwrap().clone();
+        if events
+            .iter()
+            .any(|message| message.data["event"] == "cacheHit")
+        {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    let find = |name: &str| {
+        events
+            .iter()
+            .find(|message| message.data["event"] == name)
+            .unwrap_or_else(|| panic!("no {name} event in {events:?}"))
+    };
+    assert_eq!(find("resolveRenderer").data["version"], "11.4.1");
+    assert_eq!(find("spawn").level, LoggingLevel::Debug);
+    let exit = find("exit");
+    assert_eq!(exit.logger.as_deref(), Some("renderer"));
+    assert_eq!(exit.data["exitCode"], 0);
+    assert!(exit.data["durationMs"].is_u64());
+    find("cacheMiss");
+    find("cacheHit");
+
+    client
+        .set_level(SetLevelRequestParams {
+            meta: None,
+            level: LoggingLevel::Warning,
+        })
+        .await
+        .unwrap();
+    handler.messages.lock().unwrap().clear();
+    client
+        .call_tool(CallToolRequestParams {
+            meta: None,
+            name: "validateMermaid".into(),
+            arguments: serde_json::json!({
+                "diagram": "flowchart LR\n  C-->D",
+                "format": "svg",
+                "mermaidVersion": "11.4.1"
+            })
+            .as_object()
+            .cloned(),
+            task: None,
+        })
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(handler.messages.lock().unwrap().is_empty());
+}
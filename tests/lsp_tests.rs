@@ -0,0 +1,256 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::time::Duration;
+
+use mermaid_validator::{
+    lsp::serve_lsp, preview_target::PreviewTarget, preview_validator::ValidationOptions,
+    renderer_registry::RendererRegistry,
+};
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader, DuplexStream};
+
+struct Client {
+    writer: DuplexStream,
+    reader: BufReader<DuplexStream>,
+    next_id: u64,
+}
+
+impl Client {
+    async fn start(renderers: RendererRegistry) -> Self {
+        let (client_write, server_read) = tokio::io::duplex(64 * 1024);
+        let (server_write, client_read) = tokio::io::duplex(64 * 1024);
+        let options = ValidationOptions {
+            mermaid_version: Some("11.4.1".to_string()),
+            ..ValidationOptions::new(PreviewTarget::Github, Duration::from_secs(10))
+        };
+        tokio::spawn(serve_lsp(server_read, server_write, options, renderers));
+        let mut client = Client {
+            writer: client_write,
+            reader: BufReader::new(client_read),
+            next_id: 1,
+        };
+        let initialized = client
+            .request("initialize", json!({ "capabilities": {} }))
+            .await;
+        assert_eq!(initialized["capabilities"]["positionEncoding"], "utf-16");
+        client.notify("initialized", json!({})).await;
+        client
+    }
+
+    async fn send(&mut self, message: Value) {
+        let body = message.to_string();
+        let frame = format!("Content-Length: {}\r\n\r\n{body}", body.len());
+        self.writer.write_all(frame.as_bytes()).await.unwrap();
+    }
+
+    async fn receive(&mut self) -> Value {
+        let mut length = 0;
+        loop {
+            let mut header = String::new();
+            self.reader.read_line(&mut header).await.unwrap();
+            let header = header.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            if let Some(value) = header.strip_prefix("Content-Length: ") {
+                length = value.parse().unwrap();
+            }
+        }
+        let mut body = vec![0; length];
+        self.reader.read_exact(&mut body).await.unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    async fn notify(&mut self, method: &str, params: Value) {
+        self.send(json!({ "jsonrpc": "2.0", "method": method, "params": params }))
+            .await;
+    }
+
+    async fn request(&mut self, method: &str, params: Value) -> Value {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.send(json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }))
+            .await;
+        loop {
+            let message = self.receive().await;
+            if message["id"] == id {
+                return message["result"].clone();
+            }
+        }
+    }
+
+    /// The next diagnostics published for `uri`.
+    async fn diagnostics(&mut self, uri: &str) -> Value {
+        let published = async {
+            loop {
+                let message = self.receive().await;
+                if message["method"] == "textDocument/publishDiagnostics"
+                    && message["params"]["uri"] == uri
+                {
+                    return message["params"].clone();
+                }
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(10), published)
+            .await
+            .expect("diagnostics are published")
+    }
+}
+
+fn codes(published: &Value) -> Vec<(String, u64)> {
+    published["diagnostics"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|diagnostic| {
+            (
+                diagnostic["code"].as_str().unwrap().to_string(),
+                diagnostic["range"]["start"]["line"].as_u64().unwrap(),
+            )
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn edits_rerender_only_changed_blocks() {
+    let bin = tempfile::tempdir().unwrap();
+    let log = bin.path().join("renders.log");
+    common::write_counting_mmdc(bin.path(), "11.4.1", "BROKEN", &log);
+    let mut renderers = RendererRegistry::default();
+    renderers.discover(bin.path()).unwrap();
+    let mut client = Client::start(renderers).await;
+
+    let uri = "file:///docs/guide.md";
+    let text = "# Guide\n\n```mermaid\nflowchart TD\n  A-->B\n```\n\n```Mermaid\nflowchart TD\n  𝄞-->BROKEN\n```\n";
+    client
+        .notify(
+            "textDocument/didOpen",
+            json!({ "textDocument": {
+                "uri": uri, "languageId": "markdown", "version": 1, "text": text
+            } }),
+        )
+        .await;
+    let published = client.diagnostics(uri).await;
+    assert_eq!(published["version"], 1);
+    assert_eq!(
+        codes(&published),
+        vec![
+            ("mermaid_info_string_unsupported".to_string(), 7),
+            ("mermaid_parse_error".to_string(), 8),
+        ]
+    );
+    assert_eq!(common::render_count(&log), 2);
+
+    // Replace `BROKEN`, which starts after a surrogate pair.
+    client
+        .notify(
+            "textDocument/didChange",
+            json!({
+                "textDocument": { "uri": uri, "version": 2 },
+                "contentChanges": [{
+                    "range": {
+                        "start": { "line": 9, "character": 7 },
+                        "end": { "line": 9, "character": 13 }
+                    },
+                    "text": "D"
+                }]
+            }),
+        )
+        .await;
+    let published = client.diagnostics(uri).await;
+    assert_eq!(published["version"], 2);
+    assert_eq!(
+        codes(&published),
+        vec![("mermaid_info_string_unsupported".to_string(), 7)]
+    );
+    assert_eq!(common::render_count(&log), 3);
+
+    let actions = client
+        .request(
+            "textDocument/codeAction",
+            json!({
+                "textDocument": { "uri": uri },
+                "range": {
+                    "start": { "line": 7, "character": 0 },
+                    "end": { "line": 7, "character": 0 }
+                },
+                "context": { "diagnostics": [] }
+            }),
+        )
+        .await;
+    let action = &actions[0];
+    assert_eq!(action["kind"], "quickfix");
+    let edit = &action["edit"]["changes"][uri][0];
+    assert_eq!(
+        edit["range"],
+        json!({
+            "start": { "line": 7, "character": 3 },
+            "end": { "line": 7, "character": 10 }
+        })
+    );
+    assert_eq!(edit["newText"], "mermaid");
+
+    let hover = client
+        .request(
+            "textDocument/hover",
+            json!({
+                "textDocument": { "uri": uri },
+                "position": { "line": 3, "character": 2 }
+            }),
+        )
+        .await;
+    let value = hover["contents"]["value"].as_str().unwrap();
+    let id = common::block_id("guide", "flowchart TD\n  A-->B");
+    assert!(value.starts_with(&format!("**flowchart** diagram, block #1 `{id}`")));
+    assert!(value.contains("| **GitHub** |"));
+    let outside = client
+        .request(
+            "textDocument/hover",
+            json!({
+                "textDocument": { "uri": uri },
+                "position": { "line": 4, "character": 2 }
+            }),
+        )
+        .await;
+    assert!(outside.is_null());
+}
+
+#[tokio::test]
+async fn bare_diagrams_report_lines_of_the_document() {
+    let bin = tempfile::tempdir().unwrap();
+    let log = bin.path().join("renders.log");
+    common::write_counting_mmdc(bin.path(), "11.4.1", "BROKEN", &log);
+    let mut renderers = RendererRegistry::default();
+    renderers.discover(bin.path()).unwrap();
+    let mut client = Client::start(renderers).await;
+
+    let uri = "file:///flow.mmd";
+    client
+        .notify(
+            "textDocument/didOpen",
+            json!({ "textDocument": {
+                "uri": uri, "languageId": "plaintext", "version": 1,
+                "text": "flowchart TD\n  A-->BROKEN\n"
+            } }),
+        )
+        .await;
+    let published = client.diagnostics(uri).await;
+    assert_eq!(
+        codes(&published),
+        vec![("mermaid_parse_error".to_string(), 0)]
+    );
+
+    let hover = client
+        .request(
+            "textDocument/hover",
+            json!({
+                "textDocument": { "uri": uri },
+                "position": { "line": 0, "character": 0 }
+            }),
+        )
+        .await;
+    let value = hover["contents"]["value"].as_str().unwrap();
+    assert!(value.contains("| **GitHub** | 11.4.1 | no: `mermaid_parse_error` |"));
+}
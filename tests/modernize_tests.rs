@@ -0,0 +1,70 @@
+mod common;
+
+#[cfg(unix)]
+#[tokio::test]
+async fn modernize_keeps_only_codemods_that_still_render() {
+    use std::time::Duration;
+
+    use mermaid_validator::modernize::{modernize_markdown, Codemod, ModernizeOptions};
+    use mermaid_validator::preview_target::PreviewTarget;
+    use mermaid_validator::renderer_registry::RendererRegistry;
+
+    let dir = tempfile::tempdir().unwrap();
+    common::write_fake_mmdc(dir.path(), "11.4.1", "shape:");
+    let mut renderers = RendererRegistry::default();
+    renderers.discover(dir.path()).unwrap();
+
+    let markdown = "# Doc\r\n\r\n```mermaid\r\ngraph TD\r\n  db[(Store)] --> A\r\n```\r\n\r\n```mermaid\r\nshape: broken\r\n```\r\n";
+    let options = ModernizeOptions::new(PreviewTarget::Github, Duration::from_secs(10));
+    let result = modernize_markdown("docs/doc.md", markdown, &options, &renderers)
+        .await
+        .unwrap();
+
+    assert!(result.changed);
+    assert_eq!(result.renderer_version.as_deref(), Some("11.4.1"));
+    assert_eq!(result.blocks[0].applied, vec![Codemod::GraphToFlowchart]);
+    assert_eq!(result.blocks[0].skipped[0].codemod, Codemod::ShapeSyntax);
+    assert!(result.blocks[0].skipped[0]
+        .reason
+        .contains("no longer renders"));
+    assert!(result.blocks[1].applied.is_empty());
+    assert!(result.blocks[1].skipped[0]
+        .reason
+        .contains("does not render before modernization"));
+    assert_eq!(
+        result.markdown,
+        "# Doc\r\n\r\n```mermaid\r\nflowchart TD\r\n  db[(Store)] --> A\r\n```\r\n\r\n```mermaid\r\nshape: broken\r\n```\r\n"
+    );
+    assert!(result
+        .diff
+        .starts_with("--- a/docs/doc.md\n+++ b/docs/doc.md\n"));
+    assert!(result.diff.contains("+flowchart TD"));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn modernize_skips_shapes_on_older_targets() {
+    use std::time::Duration;
+
+    use mermaid_validator::modernize::{modernize_markdown, Codemod, ModernizeOptions};
+    use mermaid_validator::preview_target::PreviewTarget;
+    use mermaid_validator::renderer_registry::RendererRegistry;
+
+    let dir = tempfile::tempdir().unwrap();
+    common::write_fake_mmdc(dir.path(), "10.9.1", "never-rejected");
+    let mut renderers = RendererRegistry::default();
+    renderers.discover(dir.path()).unwrap();
+
+    let markdown = "```mermaid\nflowchart TD\n  A((Hub))\n```\n";
+    let options = ModernizeOptions::new(PreviewTarget::Gitlab, Duration::from_secs(10));
+    let result = modernize_markdown("doc.md", markdown, &options, &renderers)
+        .await
+        .unwrap();
+
+    assert!(!result.changed);
+    assert!(result.diff.is_empty());
+    assert_eq!(result.blocks[0].skipped[0].codemod, Codemod::ShapeSyntax);
+    assert!(result.blocks[0].skipped[0]
+        .reason
+        .contains("GitLab ships Mermaid 10.9.1"));
+}
@@ -0,0 +1,129 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::{call, text};
+use mermaid_validator::server::MermaidServer;
+
+fn markdown(blocks: usize) -> String {
+    (1..=blocks)
+        .map(|index| format!("## Part {index}\n\n```mermaid\nflowchart TD\n  A{index}-->B\n```\n"))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[tokio::test]
+async fn scan_pages_follow_the_cursor_to_the_end() {
+    let dir = tempfile::tempdir().unwrap();
+    let doc = dir.path().join("doc.md");
+    std::fs::write(&doc, markdown(5)).unwrap();
+    let client =
+        common::connect(MermaidServer::new().with_allowed_paths(vec![std::env::temp_dir()])).await;
+    let path = doc.to_str().unwrap();
+
+    let mut indexes = Vec::new();
+    let mut cursor = serde_json::Value::Null;
+    for _ in 0..5 {
+        let result = call(
+            &client,
+            "scanMermaidBlocks",
+            serde_json::json!({ "filePath": path, "limit": 2, "cursor": cursor }),
+        )
+        .await;
+        assert_eq!(result.content.len(), 1, "text output is one item");
+        let structured = result.structured_content.clone().unwrap();
+        assert_eq!(structured["mermaidBlockCount"], 5);
+        for block in structured["blocks"].as_array().unwrap() {
+            indexes.push(block["index"].as_u64().unwrap());
+            assert!(
+                block["charCount"].is_u64(),
+                "structured blocks are complete"
+            );
+        }
+        cursor = structured["page"]["nextCursor"].clone();
+        if cursor.is_null() {
+            assert!(!text(&result).contains("pass cursor"));
+            break;
+        }
+        assert!(text(&result).contains("pass cursor"));
+    }
+    assert_eq!(indexes, vec![1, 2, 3, 4, 5]);
+
+    let summary = call(
+        &client,
+        "scanMermaidBlocks",
+        serde_json::json!({ "filePath": path, "verbosity": "summary" }),
+    )
+    .await;
+    assert_eq!(
+        text(&summary),
+        "GitHub scan complete: 5 block(s), 0 error(s)"
+    );
+    assert_eq!(
+        summary.structured_content.unwrap()["blocks"]
+            .as_array()
+            .unwrap()
+            .len(),
+        5
+    );
+    let full = call(
+        &client,
+        "scanMermaidBlocks",
+        serde_json::json!({ "filePath": path, "verbosity": "full", "limit": 1 }),
+    )
+    .await;
+    assert!(text(&full).contains("flowchart fence, info string \"mermaid\""));
+}
+
+#[tokio::test]
+async fn preview_pages_render_only_their_blocks_and_refuse_stale_cursors() {
+    let dir = tempfile::tempdir().unwrap();
+    let log = dir.path().join("renders.log");
+    let client = common::connect_with_counting_mmdc(dir.path(), &log).await;
+    let markdown = markdown(3).replace("A2-->B", "A2-->BROKEN");
+
+    let arguments = |cursor: serde_json::Value, markdown: &str| {
+        serde_json::json!({
+            "markdown": markdown,
+            "mermaidVersion": "11.4.1",
+            "limit": 1,
+            "cursor": cursor
+        })
+    };
+    let first = call(
+        &client,
+        "validateMermaidPreview",
+        arguments(serde_json::Value::Null, &markdown),
+    )
+    .await;
+    let structured = first.structured_content.unwrap();
+    assert_eq!(structured["valid"], true);
+    assert_eq!(structured["errorCount"], 0);
+    assert_eq!(structured["mermaidBlockCount"], 3);
+    assert_eq!(structured["issues"].as_array().unwrap().len(), 0);
+    assert_eq!(common::render_count(&log), 1);
+    let cursor = structured["page"]["nextCursor"].clone();
+
+    let second = call(
+        &client,
+        "validateMermaidPreview",
+        arguments(cursor.clone(), &markdown),
+    )
+    .await;
+    let structured = second.structured_content.clone().unwrap();
+    assert_eq!(structured["page"]["firstBlock"], 2);
+    assert_eq!(structured["valid"], false);
+    assert_eq!(structured["errorCount"], 1);
+    assert_eq!(structured["issues"][0]["blockIndex"], 2);
+    assert_eq!(common::render_count(&log), 2);
+    assert!(text(&second).contains("(block #2)"));
+
+    let changed = markdown.replace("Part 1", "Intro");
+    let stale = call(
+        &client,
+        "validateMermaidPreview",
+        arguments(cursor, &changed),
+    )
+    .await;
+    assert!(text(&stale).contains("start again without a cursor"));
+}
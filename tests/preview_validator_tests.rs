@@ -1,3 +1,5 @@
+mod common;
+
 use std::time::Duration;
 
 use mermaid_validator::preview_validator::validate_markdown_for_github;
@@ -39,3 +41,53 @@ async fn preview_reports_parse_error_location() {
     assert!(parse_issue.line.is_some());
     assert!(parse_issue.block_index.is_some());
 }
+
+#[cfg(unix)]
+#[tokio::test]
+async fn preview_matrix_uses_each_targets_renderer_version() {
+    use mermaid_validator::preview_target::PreviewTarget;
+    use mermaid_validator::preview_validator::{validate_markdown, ValidationOptions};
+    use mermaid_validator::renderer_registry::RendererRegistry;
+
+    let dir = tempfile::tempdir().unwrap();
+    common::write_fake_mmdc(dir.path(), "11.4.1", "never-rejected");
+    common::write_fake_mmdc(dir.path(), "10.9.1", "architecture-beta");
+    let mut renderers = RendererRegistry::default();
+    renderers.discover(dir.path()).unwrap();
+
+    let markdown = "```mermaid\narchitecture-beta\n  service api(server)[API]\n```\n";
+    let options = ValidationOptions {
+        matrix_targets: vec![PreviewTarget::Gitlab],
+        ..ValidationOptions::new(PreviewTarget::Github, Duration::from_secs(10))
+    };
+    let result = validate_markdown(markdown, &options, &renderers).await;
+
+    assert!(result.valid, "{:?}", result.issues);
+    assert_eq!(result.renderer_version.as_deref(), Some("11.4.1"));
+    let cells = &result.compatibility[0].targets;
+    assert!(cells[0].compatible);
+    assert_eq!(cells[1].renderer_version.as_deref(), Some("10.9.1"));
+    assert_eq!(cells[1].issue_codes, vec!["mermaid_parse_error"]);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn preview_reports_missing_renderer_version() {
+    use mermaid_validator::preview_target::PreviewTarget;
+    use mermaid_validator::preview_validator::{validate_markdown, ValidationOptions};
+    use mermaid_validator::renderer_registry::RendererRegistry;
+
+    let options = ValidationOptions {
+        mermaid_version: Some("9.4.3".to_string()),
+        ..ValidationOptions::new(PreviewTarget::Github, Duration::from_secs(10))
+    };
+    let result = validate_markdown(
+        "```mermaid\ngraph TD\nA-->B\n```\n",
+        &options,
+        &RendererRegistry::default(),
+    )
+    .await;
+
+    assert!(!result.valid);
+    assert_eq!(result.issues[0].code, "renderer_unavailable");
+}
@@ -0,0 +1,229 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use mermaid_validator::{renderer_registry::RendererRegistry, server::MermaidServer};
+use rmcp::{
+    model::{
+        CallToolRequest, CallToolRequestParams, CancelledNotificationParam, ClientRequest,
+        ProgressNotificationParam, ServerResult,
+    },
+    service::{NotificationContext, PeerRequestOptions, RunningService, ServiceError},
+    ClientHandler, RoleClient, ServiceExt,
+};
+
+#[derive(Clone, Default)]
+struct ProgressClient {
+    progress: Arc<Mutex<Vec<ProgressNotificationParam>>>,
+}
+
+impl ClientHandler for ProgressClient {
+    async fn on_progress(
+        &self,
+        params: ProgressNotificationParam,
+        _context: NotificationContext<RoleClient>,
+    ) {
+        self.progress.lock().unwrap().push(params);
+    }
+}
+
+async fn connect(
+    renderers: RendererRegistry,
+    client: ProgressClient,
+) -> RunningService<RoleClient, ProgressClient> {
+    let (server_io, client_io) = tokio::io::duplex(64 * 1024);
+    tokio::spawn(async move {
+        if let Ok(service) = MermaidServer::with_renderers(renderers)
+            .with_allowed_paths(vec![std::env::temp_dir()])
+            .serve(server_io)
+            .await
+        {
+            let _ = service.waiting().await;
+        }
+    });
+    client.serve(client_io).await.unwrap()
+}
+
+fn tool_request(name: &'static str, arguments: serde_json::Value) -> ClientRequest {
+    ClientRequest::CallToolRequest(CallToolRequest::new(CallToolRequestParams {
+        meta: None,
+        name: name.into(),
+        arguments: arguments.as_object().cloned(),
+        task: None,
+    }))
+}
+
+fn preview_request(markdown: &str) -> ClientRequest {
+    tool_request(
+        "validateMermaidPreview",
+        serde_json::json!({ "markdown": markdown }),
+    )
+}
+
+/// Progress notifications received so far, once `count` have arrived or
+/// after a second.
+async fn received(
+    handler: &ProgressClient,
+    count: usize,
+) -> Vec<(f64, Option<f64>, Option<String>)> {
+    let mut progress = Vec::new();
+    for _ in 0..50 {
+        progress = handler.progress.lock().unwrap().clone();
+        if progress.len() == count {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    progress
+        .into_iter()
+        .map(|param| (param.progress, param.total, param.message))
+        .collect()
+}
+
+/// A fake `mmdc` that logs every call, and records its pid and hangs on
+/// diagrams containing `SLOW`.
+fn write_hanging_mmdc(dir: &Path) {
+    use std::os::unix::fs::PermissionsExt;
+
+    let bin = dir.join("11.4.1").join("bin");
+    std::fs::create_dir_all(&bin).unwrap();
+    let script = format!(
+        "#!/bin/sh\ninput=$(cat)\necho call >> {calls}\ncase \"$input\" in *SLOW*) echo $$ > {pid}; exec sleep 30;; esac\necho '<svg/>'\n",
+        calls = dir.join("mmdc.calls").display(),
+        pid = dir.join("mmdc.pid").display()
+    );
+    let path = bin.join("mmdc");
+    std::fs::write(&path, script).unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+}
+
+fn process_alive(pid: &str) -> bool {
+    std::fs::read_to_string(format!("/proc/{pid}/stat")).is_ok_and(|stat| !stat.contains(") Z "))
+}
+
+#[tokio::test]
+async fn preview_validation_reports_progress_per_block() {
+    let dir = tempfile::tempdir().unwrap();
+    common::write_fake_mmdc(dir.path(), "11.4.1", "BROKEN");
+    let mut renderers = RendererRegistry::default();
+    renderers.discover(dir.path()).unwrap();
+    let handler = ProgressClient::default();
+    let client = connect(renderers, handler.clone()).await;
+
+    let block = "```mermaid\nflowchart TD\n  A-->B\n```\n";
+    let markdown = format!("{block}\n{block}\n{block}");
+    // Requests sent through the peer always carry a progress token.
+    let result = client
+        .send_request(preview_request(&markdown))
+        .await
+        .unwrap();
+    let ServerResult::CallToolResult(result) = result else {
+        panic!("unexpected response {result:?}");
+    };
+    assert_eq!(result.structured_content.unwrap()["valid"], true);
+
+    let steps = received(&handler, 3)
+        .await
+        .into_iter()
+        .map(|(progress, total, _)| (progress, total))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        steps,
+        vec![(1.0, Some(3.0)), (2.0, Some(3.0)), (3.0, Some(3.0))]
+    );
+}
+
+#[tokio::test]
+async fn directory_validation_reports_progress_per_file() {
+    let dir = tempfile::tempdir().unwrap();
+    common::write_fake_mmdc(dir.path(), "11.4.1", "BROKEN");
+    let mut renderers = RendererRegistry::default();
+    renderers.discover(dir.path()).unwrap();
+    let handler = ProgressClient::default();
+    let client = connect(renderers, handler.clone()).await;
+
+    let docs = tempfile::tempdir().unwrap();
+    let block = "```mermaid\nflowchart TD\n  A-->B\n```\n";
+    std::fs::write(docs.path().join("a.md"), format!("{block}\n{block}")).unwrap();
+    std::fs::write(docs.path().join("b.md"), block).unwrap();
+    let result = client
+        .send_request(tool_request(
+            "validateMermaidDirectory",
+            serde_json::json!({ "root": docs.path().to_str().unwrap() }),
+        ))
+        .await
+        .unwrap();
+    let ServerResult::CallToolResult(result) = result else {
+        panic!("unexpected response {result:?}");
+    };
+    assert_eq!(result.structured_content.unwrap()["valid"], true);
+
+    assert_eq!(
+        received(&handler, 2).await,
+        vec![
+            (1.0, Some(2.0), Some("Checked a.md".to_string())),
+            (2.0, Some(2.0), Some("Checked b.md".to_string())),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn cancelled_validation_kills_mmdc_and_stops() {
+    let dir = tempfile::tempdir().unwrap();
+    write_hanging_mmdc(dir.path());
+    let mut renderers = RendererRegistry::default();
+    renderers.discover(dir.path()).unwrap();
+    let client = connect(renderers, ProgressClient::default()).await;
+
+    let markdown =
+        "```mermaid\nflowchart TD\n  SLOW-->B\n```\n\n```mermaid\nflowchart TD\n  A-->B\n```\n";
+    let handle = client
+        .send_cancellable_request(preview_request(markdown), PeerRequestOptions::no_options())
+        .await
+        .unwrap();
+
+    let pid_file = dir.path().join("mmdc.pid");
+    let mut pid = String::new();
+    for _ in 0..250 {
+        if let Ok(content) = std::fs::read_to_string(&pid_file) {
+            if !content.trim().is_empty() {
+                pid = content.trim().to_string();
+                break;
+            }
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert!(!pid.is_empty(), "mmdc started");
+
+    client
+        .notify_cancelled(CancelledNotificationParam {
+            request_id: handle.id.clone(),
+            reason: Some("test".to_string()),
+        })
+        .await
+        .unwrap();
+    // The client stops waiting for the response once it cancels.
+    let response = handle.await_response().await;
+    assert!(matches!(response, Err(ServiceError::Cancelled { .. })));
+
+    let mut alive = true;
+    for _ in 0..100 {
+        alive = process_alive(&pid);
+        if !alive {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert!(!alive, "mmdc {pid} was killed");
+
+    // Give the server time to (wrongly) start the second block.
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    let calls = std::fs::read_to_string(dir.path().join("mmdc.calls")).unwrap();
+    assert_eq!(calls.lines().count(), 1, "no block is started after cancel");
+}
@@ -0,0 +1,91 @@
+mod common;
+
+#[cfg(unix)]
+#[tokio::test]
+async fn prompts_embed_block_source_and_diagnostics() {
+    use mermaid_validator::{renderer_registry::RendererRegistry, server::MermaidServer};
+    use rmcp::model::{GetPromptRequestParams, PromptMessageContent};
+
+    let dir = tempfile::tempdir().unwrap();
+    common::write_fake_mmdc(dir.path(), "11.4.1", "BROKEN");
+    let mut renderers = RendererRegistry::default();
+    renderers.discover(dir.path()).unwrap();
+    let file = dir.path().join("doc.md");
+    std::fs::write(
+        &file,
+        "# Doc\n\n```mermaid\nflowchart TD\n  A-->B\n```\n\n```mermaid\nflowchart TD\n  A-->B\n  BROKEN\n```\n",
+    )
+    .unwrap();
+    let file_path = file.to_str().unwrap();
+
+    let client = common::connect(
+        MermaidServer::with_renderers(renderers).with_allowed_paths(vec![std::env::temp_dir()]),
+    )
+    .await;
+    let prompts = client.list_prompts(None).await.unwrap();
+    let names = prompts
+        .prompts
+        .iter()
+        .map(|prompt| prompt.name.as_str())
+        .collect::<Vec<_>>();
+    assert!(names.contains(&"fix-mermaid-block"));
+    assert!(names.contains(&"write-mermaid-diagram"));
+    assert!(names.contains(&"review-diagrams-in-file"));
+
+    let prompt_text = async |name: &str, arguments: serde_json::Value| {
+        let result = client
+            .get_prompt(GetPromptRequestParams {
+                meta: None,
+                name: name.to_string(),
+                arguments: arguments.as_object().cloned(),
+            })
+            .await
+            .unwrap();
+        match &result.messages[0].content {
+            PromptMessageContent::Text { text } => text.clone(),
+            other => panic!("expected text, got {other:?}"),
+        }
+    };
+
+    let fix = prompt_text(
+        "fix-mermaid-block",
+        serde_json::json!({ "filePath": file_path, "blockIndex": "2" }),
+    )
+    .await;
+    assert!(fix.contains("Fix Mermaid block #2"));
+    assert!(fix.contains("```mermaid\nflowchart TD\n  A-->B\n  BROKEN\n```"));
+    assert!(fix.contains("mermaid_parse_error"));
+    assert!(fix.contains("GitHub (Mermaid 11.4.1):"));
+
+    let review = prompt_text(
+        "review-diagrams-in-file",
+        serde_json::json!({ "filePath": file_path }),
+    )
+    .await;
+    assert!(review.contains("2 block(s), 1 error(s)"), "{review}");
+    assert!(review.contains("## Block #1 (lines 3-6, flowchart)"));
+
+    let write = prompt_text(
+        "write-mermaid-diagram",
+        serde_json::json!({
+            "diagramType": "sequenceDiagram",
+            "description": "Login flow",
+            "targets": "github, gitlab"
+        }),
+    )
+    .await;
+    assert!(write.contains("GitLab (Mermaid 10.9.1):"));
+
+    let bad_index = client
+        .get_prompt(GetPromptRequestParams {
+            meta: None,
+            name: "fix-mermaid-block".to_string(),
+            arguments: serde_json::json!({ "filePath": file_path, "blockIndex": "9" })
+                .as_object()
+                .cloned(),
+        })
+        .await;
+    assert!(bad_index.is_err());
+
+    client.cancel().await.unwrap();
+}
@@ -0,0 +1,72 @@
+mod common;
+
+#[cfg(unix)]
+#[tokio::test]
+async fn validate_returns_resource_links_served_from_cache() {
+    use mermaid_validator::{renderer_registry::RendererRegistry, server::MermaidServer};
+    use rmcp::model::{CallToolRequestParams, ReadResourceRequestParams, ResourceContents};
+
+    let dir = tempfile::tempdir().unwrap();
+    common::write_fake_mmdc(dir.path(), "11.4.1", "never-rejected");
+    let mut renderers = RendererRegistry::default();
+    renderers.discover(dir.path()).unwrap();
+
+    let client = common::connect(MermaidServer::with_renderers(renderers)).await;
+
+    let templates = client.list_resource_templates(None).await.unwrap();
+    assert!(templates
+        .resource_templates
+        .iter()
+        .any(|template| template.uri_template == "mermaid://file/{path}/block/{index}.svg"));
+
+    let arguments = serde_json::json!({
+        "diagram": "flowchart TD\n  A-->B",
+        "format": "svg",
+        "mermaidVersion": "11.4.1"
+    });
+    let result = client
+        .call_tool(CallToolRequestParams {
+            meta: None,
+            name: "validateMermaid".into(),
+            arguments: arguments.as_object().cloned(),
+            task: None,
+        })
+        .await
+        .unwrap();
+    let link = result
+        .content
+        .iter()
+        .find_map(|content| content.as_resource_link())
+        .expect("result links to the rendered image")
+        .clone();
+    assert!(link.uri.starts_with("mermaid://render/"));
+    assert_eq!(link.mime_type.as_deref(), Some("image/svg+xml"));
+    assert!(result
+        .content
+        .iter()
+        .all(|content| content.as_image().is_none()));
+
+    // The render is cached, so the resource stays readable without mmdc.
+    std::fs::remove_dir_all(dir.path().join("11.4.1")).unwrap();
+    let read = client
+        .read_resource(ReadResourceRequestParams {
+            meta: None,
+            uri: link.uri.clone(),
+        })
+        .await
+        .unwrap();
+    match &read.contents[0] {
+        ResourceContents::TextResourceContents { text, .. } => assert!(text.contains("<svg")),
+        other => panic!("expected SVG text, got {other:?}"),
+    }
+
+    let missing = client
+        .read_resource(ReadResourceRequestParams {
+            meta: None,
+            uri: "mermaid://render/00.svg".to_string(),
+        })
+        .await;
+    assert!(missing.is_err());
+
+    client.cancel().await.unwrap();
+}
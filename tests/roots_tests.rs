@@ -0,0 +1,186 @@
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use mermaid_validator::server::MermaidServer;
+use rmcp::{
+    model::{
+        CallToolRequestParams, CallToolResult, ClientCapabilities, ClientInfo, ListRootsResult,
+        Root,
+    },
+    service::{RequestContext, RunningService},
+    ClientHandler, ErrorData as McpError, RoleClient, ServiceExt,
+};
+
+/// A client exposing a changeable list of `file://` roots.
+#[derive(Clone)]
+struct RootsClient {
+    roots: Arc<Mutex<Vec<String>>>,
+}
+
+impl RootsClient {
+    fn set_root(&self, dir: &Path) {
+        let uri = url::Url::from_directory_path(dir).unwrap().to_string();
+        *self.roots.lock().unwrap() = vec![uri];
+    }
+}
+
+impl ClientHandler for RootsClient {
+    async fn list_roots(
+        &self,
+        _context: RequestContext<RoleClient>,
+    ) -> Result<ListRootsResult, McpError> {
+        let roots = self
+            .roots
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|uri| Root {
+                uri: uri.clone(),
+                name: None,
+            })
+            .collect();
+        Ok(ListRootsResult { roots })
+    }
+
+    fn get_info(&self) -> ClientInfo {
+        ClientInfo {
+            capabilities: ClientCapabilities::builder()
+                .enable_roots()
+                .enable_roots_list_changed()
+                .build(),
+            ..Default::default()
+        }
+    }
+}
+
+async fn connect<C: ClientHandler>(
+    server: MermaidServer,
+    client: C,
+) -> RunningService<RoleClient, C> {
+    let (server_io, client_io) = tokio::io::duplex(64 * 1024);
+    tokio::spawn(async move {
+        if let Ok(service) = server.serve(server_io).await {
+            let _ = service.waiting().await;
+        }
+    });
+    client.serve(client_io).await.unwrap()
+}
+
+async fn scan<C: ClientHandler>(
+    client: &RunningService<RoleClient, C>,
+    path: &str,
+) -> CallToolResult {
+    let arguments = serde_json::json!({ "filePath": path });
+    client
+        .call_tool(CallToolRequestParams {
+            meta: None,
+            name: "scanMermaidBlocks".into(),
+            arguments: arguments.as_object().cloned(),
+            task: None,
+        })
+        .await
+        .unwrap()
+}
+
+fn is_rejected(result: &CallToolResult) -> bool {
+    result.content.iter().any(|content| {
+        content
+            .as_text()
+            .is_some_and(|text| text.text.contains("outside the client's roots"))
+    })
+}
+
+#[tokio::test]
+async fn paths_resolve_against_roots_and_follow_root_changes() {
+    let docs = tempfile::tempdir().unwrap();
+    let other = tempfile::tempdir().unwrap();
+    let markdown = "```mermaid\nflowchart TD\n  A-->B\n```\n";
+    std::fs::write(docs.path().join("doc.md"), markdown).unwrap();
+    std::fs::write(other.path().join("other.md"), markdown).unwrap();
+
+    let handler = RootsClient {
+        roots: Arc::default(),
+    };
+    handler.set_root(docs.path());
+    let client = connect(
+        MermaidServer::new().with_allowed_paths(Vec::new()),
+        handler.clone(),
+    )
+    .await;
+
+    let relative = scan(&client, "doc.md").await;
+    assert_eq!(relative.structured_content.unwrap()["mermaidBlockCount"], 1);
+    let outside = other.path().join("other.md");
+    assert!(is_rejected(&scan(&client, outside.to_str().unwrap()).await));
+    assert!(is_rejected(&scan(&client, "../escape.md").await));
+
+    handler.set_root(other.path());
+    client.notify_roots_list_changed().await.unwrap();
+    let mut accepted = false;
+    for _ in 0..50 {
+        if !is_rejected(&scan(&client, outside.to_str().unwrap()).await) {
+            accepted = true;
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert!(accepted, "server re-reads roots after roots/list_changed");
+    let old = docs.path().join("doc.md");
+    assert!(is_rejected(&scan(&client, old.to_str().unwrap()).await));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn symlinks_leaving_roots_need_the_allow_list() {
+    let docs = tempfile::tempdir().unwrap();
+    let shared = tempfile::tempdir().unwrap();
+    std::fs::write(
+        shared.path().join("shared.md"),
+        "```mermaid\nflowchart TD\n  A-->B\n```\n",
+    )
+    .unwrap();
+    std::os::unix::fs::symlink(shared.path(), docs.path().join("shared")).unwrap();
+
+    let handler = RootsClient {
+        roots: Arc::default(),
+    };
+    handler.set_root(docs.path());
+    let strict = connect(
+        MermaidServer::new().with_allowed_paths(Vec::new()),
+        handler.clone(),
+    )
+    .await;
+    assert!(is_rejected(&scan(&strict, "shared/shared.md").await));
+
+    let allowed = MermaidServer::new().with_allowed_paths(vec![shared.path().to_path_buf()]);
+    let client = connect(allowed, handler).await;
+    let result = scan(&client, "shared/shared.md").await;
+    assert_eq!(result.structured_content.unwrap()["mermaidBlockCount"], 1);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn clients_without_roots_are_limited_to_the_working_directory() {
+    let shared = tempfile::tempdir().unwrap();
+    std::fs::write(
+        shared.path().join("shared.md"),
+        "```mermaid\nflowchart TD\n  A-->B\n```\n",
+    )
+    .unwrap();
+
+    let client = connect(
+        MermaidServer::new().with_allowed_paths(vec![shared.path().to_path_buf()]),
+        (),
+    )
+    .await;
+    assert!(is_rejected(&scan(&client, "/etc/passwd").await));
+    assert!(is_rejected(&scan(&client, "../escape.md").await));
+    let readme = scan(&client, "README.md").await;
+    assert_eq!(readme.is_error, Some(false));
+    let shared = shared.path().join("shared.md");
+    let result = scan(&client, shared.to_str().unwrap()).await;
+    assert_eq!(result.structured_content.unwrap()["mermaidBlockCount"], 1);
+}
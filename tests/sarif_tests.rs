@@ -0,0 +1,86 @@
+#![cfg(unix)]
+
+mod common;
+
+use mermaid_validator::{renderer_registry::RendererRegistry, server::MermaidServer};
+use rmcp::model::CallToolRequestParams;
+
+#[tokio::test]
+async fn export_report_returns_a_sarif_log_with_rules_and_fixes() {
+    let bin = tempfile::tempdir().unwrap();
+    common::write_fake_mmdc(bin.path(), "11.4.1", "BROKEN");
+    let mut renderers = RendererRegistry::default();
+    renderers.discover(bin.path()).unwrap();
+    let client = common::connect(
+        MermaidServer::with_renderers(renderers).with_allowed_paths(vec![std::env::temp_dir()]),
+    )
+    .await;
+
+    let tree = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(tree.path().join("docs")).unwrap();
+    std::fs::write(
+        tree.path().join("docs/flow chart.md"),
+        "# Flow\n\n```Mermaid\nflowchart TD\n  A-->BROKEN\n```\n",
+    )
+    .unwrap();
+    std::fs::write(tree.path().join("ok.mmd"), "flowchart TD\n  A-->B\n").unwrap();
+
+    let result = client
+        .call_tool(CallToolRequestParams {
+            meta: None,
+            name: "exportMermaidReport".into(),
+            arguments: serde_json::json!({
+                "path": tree.path().to_str().unwrap(),
+                "format": "sarif",
+                "mermaidVersion": "11.4.1"
+            })
+            .as_object()
+            .cloned(),
+            task: None,
+        })
+        .await
+        .unwrap();
+    let log = result.structured_content.unwrap();
+    assert_eq!(log["version"], "2.1.0");
+    let run = &log["runs"][0];
+    let root_uri = run["originalUriBaseIds"]["ROOT"]["uri"].as_str().unwrap();
+    assert!(root_uri.starts_with("file:///") && root_uri.ends_with('/'));
+
+    let rules = run["tool"]["driver"]["rules"].as_array().unwrap();
+    let rule_ids = rules
+        .iter()
+        .map(|rule| rule["id"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        rule_ids,
+        vec!["mermaid_info_string_unsupported", "mermaid_parse_error"]
+    );
+    assert!(rules[1]["shortDescription"]["text"]
+        .as_str()
+        .unwrap()
+        .contains("could not parse"));
+
+    let results = run["results"].as_array().unwrap();
+    assert_eq!(results.len(), 2);
+    let retag = &results[0];
+    assert_eq!(retag["level"], "warning");
+    let location = &retag["locations"][0]["physicalLocation"];
+    assert_eq!(location["artifactLocation"]["uri"], "docs/flow%20chart.md");
+    assert_eq!(location["artifactLocation"]["uriBaseId"], "ROOT");
+    assert_eq!(location["region"]["startLine"], 3);
+    assert_eq!(location["region"]["snippet"]["text"], "```Mermaid");
+    let replacement = &retag["fixes"][0]["artifactChanges"][0]["replacements"][0];
+    assert_eq!(replacement["deletedRegion"]["startColumn"], 4);
+    assert_eq!(replacement["deletedRegion"]["endColumn"], 11);
+    assert_eq!(replacement["insertedContent"]["text"], "mermaid");
+
+    let parse = &results[1];
+    assert_eq!(parse["ruleIndex"], 1);
+    assert_eq!(parse["level"], "error");
+    assert_eq!(parse["properties"]["blockIndex"], 1);
+    assert_eq!(
+        parse["locations"][0]["physicalLocation"]["region"]["startLine"],
+        4
+    );
+    assert!(parse.get("fixes").is_none());
+}
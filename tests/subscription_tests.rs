@@ -0,0 +1,188 @@
+#![cfg(unix)]
+
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use mermaid_validator::{
+    diagram_status::status_uri, renderer_registry::RendererRegistry, server::MermaidServer,
+};
+use rmcp::{
+    model::{
+        ReadResourceRequestParams, ResourceContents, ResourceUpdatedNotificationParam,
+        SubscribeRequestParams, UnsubscribeRequestParams,
+    },
+    service::{NotificationContext, RunningService},
+    ClientHandler, RoleClient, ServiceExt,
+};
+
+#[derive(Clone, Default)]
+struct UpdateClient {
+    updates: Arc<Mutex<Vec<String>>>,
+}
+
+impl ClientHandler for UpdateClient {
+    async fn on_resource_updated(
+        &self,
+        params: ResourceUpdatedNotificationParam,
+        _context: NotificationContext<RoleClient>,
+    ) {
+        self.updates.lock().unwrap().push(params.uri);
+    }
+}
+
+/// A fake `mmdc` that logs every call and rejects diagrams containing
+/// `BROKEN`.
+fn write_counting_mmdc(dir: &Path) {
+    use std::os::unix::fs::PermissionsExt;
+
+    let bin = dir.join("11.4.1").join("bin");
+    std::fs::create_dir_all(&bin).unwrap();
+    let script = format!(
+        "#!/bin/sh\ninput=$(cat)\necho call >> {calls}\ncase \"$input\" in *BROKEN*) echo 'Parse error on line 1:' >&2; exit 1;; esac\necho '<svg/>'\n",
+        calls = dir.join("mmdc.calls").display()
+    );
+    let path = bin.join("mmdc");
+    std::fs::write(&path, script).unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+}
+
+fn calls(dir: &Path) -> usize {
+    std::fs::read_to_string(dir.join("mmdc.calls"))
+        .map(|calls| calls.lines().count())
+        .unwrap_or(0)
+}
+
+async fn connect(dir: &Path, handler: UpdateClient) -> RunningService<RoleClient, UpdateClient> {
+    write_counting_mmdc(dir);
+    let mut renderers = RendererRegistry::default();
+    renderers.discover(dir).unwrap();
+    let (server_io, client_io) = tokio::io::duplex(64 * 1024);
+    tokio::spawn(async move {
+        if let Ok(service) = MermaidServer::with_renderers(renderers)
+            .with_allowed_paths(vec![std::env::temp_dir()])
+            .serve(server_io)
+            .await
+        {
+            let _ = service.waiting().await;
+        }
+    });
+    handler.serve(client_io).await.unwrap()
+}
+
+async fn read_status(
+    client: &RunningService<RoleClient, UpdateClient>,
+    uri: &str,
+) -> serde_json::Value {
+    let result = client
+        .read_resource(ReadResourceRequestParams {
+            meta: None,
+            uri: uri.to_string(),
+        })
+        .await
+        .unwrap();
+    let ResourceContents::TextResourceContents { text, .. } = &result.contents[0] else {
+        panic!("status is JSON text");
+    };
+    serde_json::from_str(text).unwrap()
+}
+
+async fn wait_for(mut done: impl FnMut() -> bool) -> bool {
+    for _ in 0..150 {
+        if done() {
+            return true;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    false
+}
+
+const VALID: &str = "```mermaid\nflowchart TD\n  A-->B\n```\n";
+
+#[tokio::test]
+async fn subscribed_status_updates_revalidate_only_changed_blocks() {
+    let dir = tempfile::tempdir().unwrap();
+    let doc = dir.path().join("doc.md");
+    let second = "```mermaid\nsequenceDiagram\n  A->>B: hi\n```\n";
+    std::fs::write(&doc, format!("{VALID}\n{second}")).unwrap();
+    let handler = UpdateClient::default();
+    let client = connect(dir.path(), handler.clone()).await;
+    let uri = status_uri(doc.to_str().unwrap());
+
+    client
+        .subscribe(SubscribeRequestParams {
+            meta: None,
+            uri: uri.clone(),
+        })
+        .await
+        .unwrap();
+    let status = read_status(&client, &uri).await;
+    assert_eq!(status["valid"], true);
+    assert_eq!(status["mermaidBlockCount"], 2);
+    assert_eq!(calls(dir.path()), 2);
+
+    let broken = second.replace("hi", "BROKEN");
+    std::fs::write(&doc, format!("{VALID}\n{broken}")).unwrap();
+    assert!(wait_for(|| handler.updates.lock().unwrap().len() == 1).await);
+    assert_eq!(handler.updates.lock().unwrap()[0], uri);
+    let status = read_status(&client, &uri).await;
+    assert_eq!(status["valid"], false);
+    assert_eq!(status["blocks"][0]["valid"], true);
+    assert_eq!(status["blocks"][1]["valid"], false);
+    assert_eq!(calls(dir.path()), 3, "only the edited block is rendered");
+
+    // Moving blocks changes their lines but renders nothing.
+    std::fs::write(&doc, format!("# Title\n\n{VALID}\n{broken}")).unwrap();
+    assert!(wait_for(|| handler.updates.lock().unwrap().len() == 2).await);
+    let status = read_status(&client, &uri).await;
+    assert_eq!(status["blocks"][0]["startLine"], 3);
+    assert_eq!(calls(dir.path()), 3);
+
+    client
+        .unsubscribe(UnsubscribeRequestParams {
+            meta: None,
+            uri: uri.clone(),
+        })
+        .await
+        .unwrap();
+    std::fs::write(&doc, format!("{VALID}\n{second}")).unwrap();
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert_eq!(handler.updates.lock().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn subscriptions_end_with_the_session() {
+    let dir = tempfile::tempdir().unwrap();
+    let doc = dir.path().join("doc.md");
+    std::fs::write(&doc, VALID).unwrap();
+    let client = connect(dir.path(), UpdateClient::default()).await;
+    client
+        .subscribe(SubscribeRequestParams {
+            meta: None,
+            uri: status_uri(doc.to_str().unwrap()),
+        })
+        .await
+        .unwrap();
+    assert!(wait_for(|| calls(dir.path()) == 1).await);
+
+    client.cancel().await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    std::fs::write(&doc, VALID.replace("B", "C")).unwrap();
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert_eq!(calls(dir.path()), 1, "no watch outlives its session");
+}
+
+#[tokio::test]
+async fn only_status_resources_can_be_subscribed() {
+    let dir = tempfile::tempdir().unwrap();
+    let client = connect(dir.path(), UpdateClient::default()).await;
+    let result = client
+        .subscribe(SubscribeRequestParams {
+            meta: None,
+            uri: "mermaid://render/ab12.svg".to_string(),
+        })
+        .await;
+    assert!(result.is_err());
+}
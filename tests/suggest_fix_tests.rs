@@ -0,0 +1,202 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use mermaid_validator::{renderer_registry::RendererRegistry, server::MermaidServer};
+use rmcp::{
+    model::{
+        CallToolRequestParams, CallToolResult, ClientCapabilities, ClientInfo, Content,
+        CreateMessageRequestParams, CreateMessageResult, Role, SamplingMessage,
+    },
+    service::{RequestContext, RunningService},
+    ClientHandler, ErrorData as McpError, RoleClient, ServiceExt,
+};
+
+/// A client whose "model" replies with the next canned answer and records
+/// every prompt it was sent.
+#[derive(Clone)]
+struct SamplingClient {
+    replies: Arc<Mutex<Vec<String>>>,
+    prompts: Arc<Mutex<Vec<String>>>,
+}
+
+impl ClientHandler for SamplingClient {
+    async fn create_message(
+        &self,
+        params: CreateMessageRequestParams,
+        _context: RequestContext<RoleClient>,
+    ) -> Result<CreateMessageResult, McpError> {
+        let prompt = params.messages[0]
+            .content
+            .as_text()
+            .map(|text| text.text.clone())
+            .unwrap_or_default();
+        self.prompts.lock().unwrap().push(prompt);
+        let reply = self.replies.lock().unwrap().remove(0);
+        Ok(CreateMessageResult {
+            model: "mock".to_string(),
+            stop_reason: Some(CreateMessageResult::STOP_REASON_END_TURN.to_string()),
+            message: SamplingMessage {
+                role: Role::Assistant,
+                content: Content::text(reply),
+            },
+        })
+    }
+
+    fn get_info(&self) -> ClientInfo {
+        ClientInfo {
+            capabilities: ClientCapabilities::builder().enable_sampling().build(),
+            ..Default::default()
+        }
+    }
+}
+
+fn server(dir: &std::path::Path) -> MermaidServer {
+    common::write_fake_mmdc(dir, "11.4.1", "BROKEN");
+    let mut renderers = RendererRegistry::default();
+    renderers.discover(dir).unwrap();
+    MermaidServer::with_renderers(renderers).with_allowed_paths(vec![std::env::temp_dir()])
+}
+
+async fn connect(
+    server: MermaidServer,
+    client: SamplingClient,
+) -> RunningService<RoleClient, SamplingClient> {
+    let (server_io, client_io) = tokio::io::duplex(64 * 1024);
+    tokio::spawn(async move {
+        if let Ok(service) = server.serve(server_io).await {
+            let _ = service.waiting().await;
+        }
+    });
+    client.serve(client_io).await.unwrap()
+}
+
+fn suggest_request(path: &std::path::Path, max_attempts: u32) -> CallToolRequestParams {
+    let arguments = serde_json::json!({
+        "filePath": path.to_str().unwrap(),
+        "blockIndex": 1,
+        "mermaidVersion": "11.4.1",
+        "maxAttempts": max_attempts
+    });
+    CallToolRequestParams {
+        meta: None,
+        name: "suggestMermaidFix".into(),
+        arguments: arguments.as_object().cloned(),
+        task: None,
+    }
+}
+
+fn text(result: &CallToolResult) -> String {
+    result
+        .content
+        .iter()
+        .filter_map(|content| content.as_text().map(|text| text.text.clone()))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[tokio::test]
+async fn retries_rejected_candidates_and_returns_a_diff() {
+    let dir = tempfile::tempdir().unwrap();
+    let doc = dir.path().join("doc.md");
+    let markdown = "# Flow\n\n```mermaid\nflowchart TD\n  A-->BROKEN\n```\n";
+    std::fs::write(&doc, markdown).unwrap();
+    let handler = SamplingClient {
+        replies: Arc::new(Mutex::new(vec![
+            "flowchart TD\n  A-->STILL BROKEN".to_string(),
+            "Fixed:\n\n```mermaid\nflowchart TD\n  A-->B\n```\n".to_string(),
+        ])),
+        prompts: Arc::default(),
+    };
+    let client = connect(server(dir.path()), handler.clone()).await;
+
+    let result = client.call_tool(suggest_request(&doc, 3)).await.unwrap();
+    let structured = result.structured_content.clone().unwrap();
+    assert_eq!(structured["fixed"], true, "{}", text(&result));
+    let attempts = structured["attempts"].as_array().unwrap();
+    assert_eq!(attempts.len(), 2);
+    assert_eq!(attempts[0]["valid"], false);
+    assert_eq!(attempts[1]["candidate"], "flowchart TD\n  A-->B");
+    let diff = structured["diff"].as_str().unwrap();
+    assert!(diff.contains("-  A-->BROKEN"), "{diff}");
+    assert!(diff.contains("+  A-->B"), "{diff}");
+    assert!(text(&result).contains("after 2 attempt(s)"));
+
+    let prompts = handler.prompts.lock().unwrap().clone();
+    assert_eq!(prompts.len(), 2);
+    assert!(prompts[0].contains("A-->BROKEN"));
+    assert!(prompts[1].contains("Attempt 1 was rejected"));
+    assert!(prompts[1].contains("STILL BROKEN"));
+    // Suggestions never touch the file.
+    assert_eq!(std::fs::read_to_string(&doc).unwrap(), markdown);
+}
+
+#[tokio::test]
+async fn candidates_keep_the_block_indentation() {
+    let dir = tempfile::tempdir().unwrap();
+    let doc = dir.path().join("doc.md");
+    std::fs::write(
+        &doc,
+        "- Step\n\n  ```mermaid\n  flowchart TD\n    A-->BROKEN\n  ```\n",
+    )
+    .unwrap();
+    let handler = SamplingClient {
+        replies: Arc::new(Mutex::new(vec!["flowchart TD\n  A-->B".to_string()])),
+        prompts: Arc::default(),
+    };
+    let client = connect(server(dir.path()), handler).await;
+
+    let result = client.call_tool(suggest_request(&doc, 1)).await.unwrap();
+    let structured = result.structured_content.unwrap();
+    assert_eq!(structured["fixed"], true);
+    let diff = structured["diff"].as_str().unwrap();
+    assert!(diff.contains("-    A-->BROKEN"), "{diff}");
+    assert!(diff.contains("+    A-->B"), "{diff}");
+    assert!(!diff.contains("+flowchart TD"), "{diff}");
+}
+
+#[tokio::test]
+async fn gives_up_after_max_attempts_and_skips_valid_blocks() {
+    let dir = tempfile::tempdir().unwrap();
+    let broken = dir.path().join("broken.md");
+    std::fs::write(&broken, "```mermaid\nflowchart TD\n  A-->BROKEN\n```\n").unwrap();
+    let valid = dir.path().join("valid.md");
+    std::fs::write(&valid, "```mermaid\nflowchart TD\n  A-->B\n```\n").unwrap();
+    let handler = SamplingClient {
+        replies: Arc::new(Mutex::new(vec![
+            "flowchart TD\n  A-->BROKEN".to_string(),
+            "```mermaid\nflowchart TD\n  BROKEN-->C\n```".to_string(),
+            "unused".to_string(),
+        ])),
+        prompts: Arc::default(),
+    };
+    let client = connect(server(dir.path()), handler.clone()).await;
+
+    let result = client.call_tool(suggest_request(&broken, 2)).await.unwrap();
+    let structured = result.structured_content.unwrap();
+    assert_eq!(structured["fixed"], false);
+    assert_eq!(structured["diff"], "");
+    let attempts = structured["attempts"].as_array().unwrap();
+    assert_eq!(attempts.len(), 2);
+    assert_eq!(
+        attempts[0]["issues"][0]["code"], "fix_candidate_rejected",
+        "an unchanged candidate is rejected without rendering"
+    );
+
+    let result = client.call_tool(suggest_request(&valid, 2)).await.unwrap();
+    assert_eq!(result.structured_content.unwrap()["alreadyValid"], true);
+    assert_eq!(handler.prompts.lock().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn requires_a_client_with_sampling() {
+    let dir = tempfile::tempdir().unwrap();
+    let doc = dir.path().join("doc.md");
+    std::fs::write(&doc, "```mermaid\nflowchart TD\n  A-->BROKEN\n```\n").unwrap();
+    let client = common::connect(server(dir.path())).await;
+
+    let result = client.call_tool(suggest_request(&doc, 3)).await.unwrap();
+    assert!(text(&result).contains("supports sampling"));
+}
@@ -0,0 +1,103 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::time::Duration;
+
+use mermaid_validator::{
+    preview_target::PreviewTarget,
+    preview_validator::ValidationOptions,
+    renderer_registry::RendererRegistry,
+    watch::{change_lines, WatchSession},
+};
+
+#[tokio::test]
+async fn refresh_reports_failing_and_fixed_diagrams() {
+    let bin = tempfile::tempdir().unwrap();
+    let log = bin.path().join("renders.log");
+    common::write_counting_mmdc(bin.path(), "11.4.1", "BROKEN", &log);
+    let mut renderers = RendererRegistry::default();
+    renderers.discover(bin.path()).unwrap();
+    let options = ValidationOptions {
+        mermaid_version: Some("11.4.1".to_string()),
+        ..ValidationOptions::new(PreviewTarget::Github, Duration::from_secs(10))
+    };
+    let mut session = WatchSession::new(options);
+
+    let tree = tempfile::tempdir().unwrap();
+    let path = tree.path().join("design.md");
+    let write = |content: &str| std::fs::write(&path, content).unwrap();
+    write("# Flow\n\n```mermaid\nflowchart TD\n  A-->B\n```\n\n# Broken\n\n```mermaid\nflowchart TD\n  A-->BROKEN\n```\n");
+    let refresh = session.refresh(&path, "design.md", &renderers).await;
+    assert_eq!(refresh.validated, 2);
+    assert_eq!(
+        change_lines(&refresh.changes),
+        vec![format!(
+            "FAIL    design.md:11 {}: Mermaid parse error [mermaid_parse_error]",
+            common::block_id("broken", "flowchart TD\n  A-->BROKEN")
+        )]
+    );
+
+    // Retagging the first block changes its render options, not its
+    // content; the second block is fixed.
+    write("# Flow\n\n```Mermaid\nflowchart TD\n  A-->B\n```\n\n# Broken\n\n```mermaid\nflowchart TD\n  A-->C\n```\n");
+    let refresh = session.refresh(&path, "design.md", &renderers).await;
+    assert_eq!(refresh.validated, 2);
+    assert_eq!(
+        change_lines(&refresh.changes),
+        vec![format!(
+            "FIXED   design.md:10 {}",
+            common::block_id("broken", "flowchart TD\n  A-->C")
+        )]
+    );
+    assert_eq!(common::render_count(&log), 4);
+
+    // Moving a block without changing it reuses its result.
+    write("# Broken\n\n```mermaid\nflowchart TD\n  A-->C\n```\n\n# Flow\n\n```Mermaid\nflowchart TD\n  A-->B\n```\n\n```mermaid\nsequenceDiagram\n  A->>BROKEN: hi\n```\n");
+    let refresh = session.refresh(&path, "design.md", &renderers).await;
+    assert_eq!(refresh.validated, 1);
+    assert_eq!(
+        change_lines(&refresh.changes),
+        vec![format!(
+            "FAIL    design.md:16 {}: Mermaid parse error [mermaid_parse_error]",
+            common::block_id("flow", "sequenceDiagram\n  A->>BROKEN: hi")
+        )]
+    );
+    assert_eq!(session.totals(), (1, 3, 1));
+
+    std::fs::remove_file(&path).unwrap();
+    let refresh = session.refresh(&path, "design.md", &renderers).await;
+    assert_eq!(
+        change_lines(&refresh.changes),
+        vec![format!(
+            "REMOVED design.md:15 {}",
+            common::block_id("flow", "sequenceDiagram\n  A->>BROKEN: hi")
+        )]
+    );
+    assert_eq!(session.totals(), (0, 0, 0));
+}
+
+#[tokio::test]
+async fn bare_diagrams_use_their_own_lines() {
+    let bin = tempfile::tempdir().unwrap();
+    common::write_fake_mmdc(bin.path(), "11.4.1", "BROKEN");
+    let mut renderers = RendererRegistry::default();
+    renderers.discover(bin.path()).unwrap();
+    let options = ValidationOptions {
+        mermaid_version: Some("11.4.1".to_string()),
+        ..ValidationOptions::new(PreviewTarget::Github, Duration::from_secs(10))
+    };
+    let mut session = WatchSession::new(options);
+
+    let tree = tempfile::tempdir().unwrap();
+    let path = tree.path().join("flow.mmd");
+    std::fs::write(&path, "flowchart TD\n  A-->BROKEN\n").unwrap();
+    let refresh = session.refresh(&path, "flow.mmd", &renderers).await;
+    assert_eq!(
+        change_lines(&refresh.changes),
+        vec![format!(
+            "FAIL    flow.mmd:1 {}: Mermaid parse error [mermaid_parse_error]",
+            common::block_id("", "flowchart TD\n  A-->BROKEN")
+        )]
+    );
+}
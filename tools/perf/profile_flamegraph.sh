@@ -29,11 +29,11 @@ echo ""
 
 echo "1. Profiling basic mermaid validation (SVG)"
 echo "   - Creating flamegraph for render_diagram"
-timeout 60 cargo flamegraph --bin mermaid_validator -- --diagram "graph TD\nA-->B" --format svg 2>&1 | tee flamegraphs/basic_validation.log || true
+timeout 60 cargo flamegraph --bin mermaid_validator -- render --diagram "graph TD\nA-->B" --format svg -o /dev/null 2>&1 | tee flamegraphs/basic_validation.log || true
 
 echo ""
 echo "2. Profiling complex mermaid validation"
-timeout 60 cargo flamegraph --bin mermaid_validator -- --diagram "graph TB\nA[Start] --> B{Decision}\nB -->|Yes| C[Process 1]\nB -->|No| D[Process 2]" --format png 2>&1 | tee flamegraphs/complex_validation.log || true
+timeout 60 cargo flamegraph --bin mermaid_validator -- render --diagram "graph TB\nA[Start] --> B{Decision}\nB -->|Yes| C[Process 1]\nB -->|No| D[Process 2]" --format png -o /dev/null 2>&1 | tee flamegraphs/complex_validation.log || true
 
 echo ""
 echo "3. Profiling markdown preview validation"
@@ -51,7 +51,7 @@ X --> Y
 ```
 EOF
 
-timeout 60 cargo flamegraph --bin mermaid_validator -- --markdown-preview-file /tmp/test_markdown.md 2>&1 | tee flamegraphs/markdown_validation.log || true
+timeout 60 cargo flamegraph --bin mermaid_validator -- check /tmp/test_markdown.md 2>&1 | tee flamegraphs/markdown_validation.log || true
 
 echo ""
 echo "4. Running Criterion benchmarks with flamegraph"